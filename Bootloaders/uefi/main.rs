@@ -161,6 +161,37 @@ pub extern "win64" fn efi_main(image_handle: ::uefi::Handle, system_table: &::ue
 
 			(map_key, map)
 			};
+		// Obtain the current time (before handing off, so it's as accurate as possible)
+		let boot_time = {
+			// SAFE: Pointer provided by the firmware
+			let runtime_services = unsafe { &*system_table.runtime_services };
+			// SAFE: POD type (with private padding)
+			let mut t: ::uefi::runtime_services::Time = unsafe { ::core::mem::zeroed() };
+			match (runtime_services.get_time)(&mut t, None)
+			{
+			::uefi::status::SUCCESS => {
+				// - Convert to UTC (time_zone is minutes from UTC, 2047 = unspecified/local time)
+				// > Done via a UNIX timestamp, so the date changes correctly when the offset crosses midnight
+				let tz = if t.time_zone == 2047 { 0 } else { t.time_zone as i16 as i64 };
+				let days = days_from_civil(t.year as i64, t.month as u32, t.day as u32);
+				let local = days * 86400 + t.hour as i64 * 3600 + t.minute as i64 * 60 + t.second as i64;
+				let utc = local - tz * 60;
+				let days = floor_div(utc, 86400);
+				let secs = utc - days * 86400;
+				let (year, month, day) = civil_from_days(days);
+				kernel_proto::DateTime {
+					year: year as u16, month: month as u8, day: day as u8,
+					hour: (secs / 3600) as u8, minute: (secs / 60 % 60) as u8, second: (secs % 60) as u8,
+					_pad: 0,
+					}
+				},
+			e => {
+				loge!(conout, "- get_time failed: {:?}", e);
+				Default::default()
+				},
+			}
+			};
+
 		loge!(conout, "- Exiting boot services");
 		(boot_services.exit_boot_services)(image_handle, map_key).err_or( () ).expect("exit_boot_services");
 
@@ -173,6 +204,8 @@ pub extern "win64" fn efi_main(image_handle: ::uefi::Handle, system_table: &::ue
 			map_addr: map.as_ptr() as usize as u64,
 			map_entnum: map.len() as u32,
 			map_entsz: size_of::<uefi::boot_services::MemoryDescriptor>() as u32,
+
+			boot_time: boot_time,
 			};
 		
 		
@@ -182,6 +215,38 @@ pub extern "win64" fn efi_main(image_handle: ::uefi::Handle, system_table: &::ue
 }
 
 
+/// Integer division rounding towards negative infinity (for positive `b`)
+fn floor_div(a: i64, b: i64) -> i64
+{
+	if a >= 0 { a / b } else { (a - b + 1) / b }
+}
+/// Number of days between 1970-01-01 and the provided date (proleptic Gregorian calendar)
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64
+{
+	// - Count years from March, so the leap day is the last day of the year
+	let y = if month <= 2 { year - 1 } else { year };
+	let era = floor_div(y, 400);
+	let yoe = y - era * 400;
+	let mp = (month as i64 + 9) % 12;
+	let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	era * 146097 + doe - 719468
+}
+/// Inverse of `days_from_civil`, returns (year, month, day)
+fn civil_from_days(days: i64) -> (i64, u32, u32)
+{
+	let z = days + 719468;
+	let era = floor_div(z, 146097);
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+	let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+	let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
+
 #[lang="eh_personality"]
 fn eh_personality() -> ! {
 	loop {}
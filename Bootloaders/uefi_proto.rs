@@ -10,6 +10,25 @@ pub struct Info
 	pub map_addr: u64,
 	pub map_entnum: u32,
 	pub map_entsz: u32,
+
+	/// Wall-clock time when the kernel was started (from `GetTime`)
+	pub boot_time: DateTime,
+}
+
+/// Calendar date and time (UTC), `year == 0` indicates that the time is unavailable
+#[repr(C)]
+#[derive(Copy,Clone,Default)]
+pub struct DateTime
+{
+	pub year: u16,
+	/// 1 - 12
+	pub month: u8,
+	/// 1 - 31
+	pub day: u8,
+	pub hour: u8,
+	pub minute: u8,
+	pub second: u8,
+	pub _pad: u8,
 }
 
 // TODO: Grab this from libuefi
@@ -105,6 +105,8 @@ struct UefiParsed
 	cmdline: &'static str,
 	vidmode: Option<VideoMode>,
 	memmap: &'static [::memory::MemoryMapEnt],
+	/// Wall-clock time at handoff (seconds since the UNIX epoch)
+	boot_time: Option<u64>,
 }
 
 enum BootInfo
@@ -197,6 +199,14 @@ impl BootInfo
 		BootInfo::Uefi(ref i) => i.memmap,
 		}
 	}
+	pub fn boot_time(&self) -> Option<u64>
+	{
+		match *self
+		{
+		BootInfo::Uefi(ref i) => i.boot_time,
+		_ => None,
+		}
+	}
 }
 
 unsafe fn valid_c_str_to_slice(ptr: *const i8) -> Option<&'static str>
@@ -420,6 +430,9 @@ impl MultibootParsed
 			let kernel_start = unsafe { &::arch::imp::v_kernel_end as *const _ as u64 - IDENT_START as u64 };
 			mapbuilder.set_range( 0x100000, kernel_start - 0x10000,
 				::memory::MemoryState::Used, 0 ).ok().unwrap();
+			// - AP startup trampoline (see smp.rs)
+			mapbuilder.set_range( super::smp::AP_TRAMPOLINE_PADDR, ::PAGE_SIZE as u64,
+				::memory::MemoryState::Used, 0 ).ok().unwrap();
 			// - Command line string
 			mapbuilder.set_range( self.cmdline.as_ptr() as u64 - IDENT_START as u64, self.cmdline.len() as u64,
 				::memory::MemoryState::Used, 0 ).ok().unwrap();
@@ -453,6 +466,7 @@ impl UefiParsed
 				vidmode: None,//MultibootParsed::_vidmode(info),
 				//symbol_info: MultibootParsed::_syminfo(info),
 				memmap: &[],
+				boot_time: Self::_boot_time(info),
 			};
 		// - Memory map is initialised afterwards so it gets easy access to used addresses
 		// SAFE: Should only be called before threading is initialised, so no race
@@ -466,6 +480,16 @@ impl UefiParsed
 			::core::str::from_utf8( ::core::slice::from_raw_parts(info.cmdline_ptr, info.cmdline_len) ).expect("UefiParsed::_cmdline")
 		}
 	}
+	fn _boot_time(info: &uefi_proto::Info) -> Option<u64> {
+		let t = &info.boot_time;
+		if t.year == 0 {
+			None
+		}
+		else {
+			log_debug!("boot_time = {:04}-{:02}-{:02} {:02}:{:02}:{:02}", t.year, t.month, t.day, t.hour, t.minute, t.second);
+			Some( ::time::unix_timestamp(t.year as u32, t.month, t.day, t.hour, t.minute, t.second) )
+		}
+	}
 	fn _memmap<'a>(&self, info: &uefi_proto::Info, buf: &'a mut[::memory::MemoryMapEnt]) -> &'a [::memory::MemoryMapEnt] {
 		// TODO: Put this elsewhere
 		struct StrideSlice<T> {
@@ -534,5 +558,11 @@ pub fn get_memory_map() -> &'static[::memory::MemoryMapEnt]
 	get_bootinfo().memmap()
 }
 
+/// Obtain the wall-clock time provided by the bootloader (seconds since the UNIX epoch)
+pub fn get_boot_time() -> Option<u64>
+{
+	get_bootinfo().boot_time()
+}
+
 // vim: ft=rust
 
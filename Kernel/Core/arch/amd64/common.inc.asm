@@ -1,5 +1,5 @@
 
-%define MAX_CPUS	1
+%define MAX_CPUS	8
 %define KSTACK_BASE	0xFFFFA00000000000
 %define INITIAL_KSTACK_SIZE	16
 %define KERNEL_BASE	0xFFFFFFFF80000000
@@ -93,7 +93,28 @@ pub extern "C" fn error_handler(regs: &InterruptRegs)
 			Err(_) => puts("INVAL"),
 			}
 		}
-		todo!("User fault");
+		puts("\n");
+
+		// Report the fault to the parent and terminate the process
+		let kind = match regs.intnum
+			{
+			14 => ::threads::FaultKind::Page,
+			13 => ::threads::FaultKind::Protection,
+			6 => ::threads::FaultKind::InvalidOpcode,
+			_ => ::threads::FaultKind::Other,
+			};
+		let address = if regs.intnum == 14 { get_cr2() as usize } else { 0 };
+		let gprs = [
+			regs.rax as usize, regs.rcx as usize, regs.rdx as usize, regs.rbx as usize,
+			regs.rsp as usize, regs.rbp as usize, regs.rsi as usize, regs.rdi as usize,
+			regs.r8  as usize, regs.r9  as usize, regs.r10 as usize, regs.r11 as usize,
+			regs.r12 as usize, regs.r13 as usize, regs.r14 as usize, regs.r15 as usize,
+			];
+		// - Code is the vector in the high 16 bits, and the error code in the low 16
+		let code = ((regs.intnum << 16) | (regs.errorcode & 0xFFFF)) as u32;
+		let mut info = ::threads::FaultInfo::new(kind, code, address, regs.rip as usize, regs.rsp as usize, gprs);
+		info.walk_frame_pointers(regs.rbp as usize);
+		::threads::fault_process(info);
 	}
 	else
 	{
@@ -23,7 +23,7 @@ struct MADT_DevHeader
 pub struct MADT_LAPIC
 {
 	processor: u8,
-	apic_id: u8,
+	pub apic_id: u8,
 	pub flags: u32,
 }
 #[repr(C,packed)]
@@ -108,6 +108,62 @@ fn get_lapic() -> &'static raw::LAPIC
 	&*s_lapic
 }
 
+/// Initialise the LAPIC for an AP
+pub fn init_ap()
+{
+	s_lapic.init();
+}
+
+/// Returns the LAPIC ID of the current CPU
+#[is_safe(irq)]
+pub fn get_lapic_id() -> u8
+{
+	get_lapic().id()
+}
+
+/// Enumerate the LAPIC IDs of all usable CPUs (from the MADT)
+pub fn enum_cpu_apic_ids() -> Vec<u8>
+{
+	match ::arch::acpi::find::<init::ACPI_MADT>("APIC", 0)
+	{
+	None => Vec::new(),
+	Some(madt) => madt.data().records(madt.data_len()).filter_map(
+		|r| match r {
+			// Flags bit 0: Processor enabled
+			init::MADTDevRecord::DevLAPIC(a) if a.flags & 1 != 0 => Some(a.apic_id),
+			_ => None,
+			}
+		).collect(),
+	}
+}
+
+/// Send an IPI to a specific CPU
+pub fn send_ipi(apic_id: u8, vector: u8)
+{
+	get_lapic().send_ipi(raw::IpiDest::Id(apic_id), raw::IpiMode::Fixed, vector);
+}
+/// Send an IPI to all other CPUs
+pub fn send_ipi_others(vector: u8)
+{
+	get_lapic().send_ipi(raw::IpiDest::AllButSelf, raw::IpiMode::Fixed, vector);
+}
+/// Send an INIT IPI (first step of AP startup)
+pub fn send_init(apic_id: u8)
+{
+	get_lapic().send_ipi(raw::IpiDest::Id(apic_id), raw::IpiMode::Init, 0);
+}
+/// Send a STARTUP IPI, AP starts executing at `vector * 0x1000` in real mode
+pub fn send_startup(apic_id: u8, vector: u8)
+{
+	get_lapic().send_ipi(raw::IpiDest::Id(apic_id), raw::IpiMode::Startup, vector);
+}
+/// Acknowledge an interrupt on this CPU's LAPIC (for interrupts not routed via an IOAPIC)
+#[is_safe(irq)]
+pub fn local_eoi()
+{
+	get_lapic().eoi(0);
+}
+
 ///// Registers a message-signalled interrupt handler.
 //pub fn register_msi(callback: fn (*const()), info: *const ()) -> Result<(uint,::arch::interrupts::ISRHandle),()>
 //{
@@ -151,8 +207,8 @@ pub fn register_irq(global_num: usize, callback: IRQHandler, info: *const() ) ->
 		};
 	
 	// Bind ISR
-	// TODO: Pick a suitable processor, and maybe have separate IDTs (and hence separate ISR lists)
-	let lapic_id = 0u32;
+	// TODO: Pick a suitable processor (currently the BSP), and maybe have separate IDTs (and hence separate ISR lists)
+	let lapic_id = ::arch::imp::smp::get_bsp_apic_id() as u32;
 	let isr_handle = match ::arch::imp::interrupts::bind_free_isr(lapic_irq_handler, info, global_num)
 		{
 		Ok(v) => v,
@@ -14,6 +14,24 @@ pub struct LAPIC
 	timer_isr: ::arch::imp::interrupts::ISRHandle,
 }
 
+/// Target of an IPI
+#[derive(Debug,Copy,Clone)]
+pub enum IpiDest
+{
+	/// Specific LAPIC ID
+	Id(u8),
+	/// All CPUs except the sender
+	AllButSelf,
+}
+/// IPI delivery mode
+#[derive(Debug,Copy,Clone)]
+pub enum IpiMode
+{
+	Fixed,
+	Init,
+	Startup,
+}
+
 pub struct IOAPIC
 {
 	regs: ::sync::Mutex<IOAPICRegs>,
@@ -59,6 +77,7 @@ enum ApicReg
 	ErrStatus = 0x28,	// Error Status
 	LVTCMCI   = 0x2F,	// LVT CMCI Registers (?)
 	ICR       = 0x30,	// Interrupt Command Register (1/2)
+	ICRHigh   = 0x31,	// Interrupt Command Register (2/2, destination)
 	LVTTimer  = 0x32,
 	LVTThermalSensor = 0x33,
 	LVTPermCounters  = 0x34,
@@ -158,6 +177,38 @@ impl LAPIC
 	{
 		self.write_reg(ApicReg::EOI, num as u32);
 	}
+	/// Obtain the ID of this CPU's LAPIC
+	#[is_safe(irq)]
+	pub fn id(&self) -> u8
+	{
+		(self.read_reg(ApicReg::LAPIC_ID) >> 24) as u8
+	}
+
+	/// Send an inter-processor interrupt
+	///
+	/// `dest` is ignored if `shorthand` is not `IpiDest::Id`
+	pub fn send_ipi(&self, dest: IpiDest, mode: IpiMode, vector: u8)
+	{
+		let (dest_id, shorthand) = match dest
+			{
+			IpiDest::Id(id) => (id, 0),
+			IpiDest::AllButSelf => (0, 3),
+			};
+		let mode_bits = match mode
+			{
+			IpiMode::Fixed => 0 << 8,
+			IpiMode::Init => (5 << 8) | (1 << 14),	// INIT, Level Assert
+			IpiMode::Startup => 6 << 8,
+			};
+		// NOTE: Each CPU sees its own LAPIC at this address, so only an IRQ on this CPU could interleave
+		// - The low write triggers the send
+		let _irq = ::sync::hold_interrupts();
+		self.write_reg(ApicReg::ICRHigh, (dest_id as u32) << 24);
+		self.write_reg(ApicReg::ICR, (shorthand << 18) | mode_bits | vector as u32);
+		// Wait for the delivery status bit to clear
+		while self.read_reg(ApicReg::ICR) & (1 << 12) != 0 {
+		}
+	}
 	
 	fn read_reg(&self, reg: ApicReg) -> u32
 	{
@@ -14,8 +14,13 @@ struct HPET
 	mapping_handle: ::memory::virt::AllocHandle,
 	irq_handle: ::arch::imp::hw::apic::IRQHandle,
 	period: u64,
+	/// Counter value currently programmed into comparitor 0
+	next_fire: ::sync::Spinlock<u64>,
 }
 
+/// Maximum time between timer interrupts (in ms), even if no timer is pending
+const MAX_TICK_INTERVAL: u64 = 100;
+
 #[repr(C,packed)]
 struct ACPI_HPET
 {
@@ -45,18 +50,33 @@ pub fn get_timestamp() -> u64
 	if S_INSTANCE.ls_is_valid() {
 		S_INSTANCE.current() / S_INSTANCE.ticks_per_ms()
 	}
+	else if super::pit::is_active() {
+		super::pit::get_timestamp()
+	}
 	else {
 		0
 	}
 }
 
+/// Ensure that the timer interrupt fires at (or before) the provided timestamp
+pub fn request_tick(target_ms: u64)
+{
+	if S_INSTANCE.ls_is_valid() {
+		S_INSTANCE.schedule(target_ms * S_INSTANCE.ticks_per_ms());
+	}
+	else {
+		super::pit::request_tick(target_ms);
+	}
+}
+
 fn init()
 {
 	log_trace!("init()");
 	let hpet = match ::arch::imp::acpi::find::<ACPI_HPET>("HPET", 0)
 		{
 		None => {
-			log_error!("No HPET, in ACPI, no timing avaliable");
+			log_warning!("No HPET in ACPI, falling back to the PIT");
+			super::pit::init();
 			return ;
 			},
 		Some(v) => v,
@@ -85,7 +105,7 @@ fn init()
 		&*S_INSTANCE
 		};
 	
-	inst.oneshot(0, inst.current() + 100*1000 );
+	inst.schedule_next(None);
 }
 
 impl HPET
@@ -96,6 +116,7 @@ impl HPET
 			mapping_handle: mapping,
 			irq_handle: Default::default(),
 			period: 1,
+			next_fire: ::sync::Spinlock::new(!0),
 			};
 		// Enable
 		rv.write_reg(HPETReg::Config as usize, rv.read_reg(HPETReg::Config as usize) | (1 << 0));
@@ -120,7 +141,34 @@ impl HPET
 		let s = unsafe{ &*(sp as *const HPET) };
 		s.write_reg(HPETReg::ISR as usize, s.read_reg(HPETReg::ISR as usize));
 		
-		s.oneshot(0, s.current() + 100*1000 );
+		// Fire expired timers, then program the next interrupt
+		let next = ::time::time_tick();
+		*s.next_fire.lock() = !0;
+		s.schedule_next(next);
+	}
+
+	/// Program the next interrupt for the earliest pending timer (limited to `MAX_TICK_INTERVAL` away)
+	fn schedule_next(&self, next_timer_ms: Option<u64>)
+	{
+		let max = self.current() + MAX_TICK_INTERVAL * self.ticks_per_ms();
+		let next = match next_timer_ms
+			{
+			Some(ms) => ::core::cmp::min(ms * self.ticks_per_ms(), max),
+			None => max,
+			};
+		self.schedule(next);
+	}
+	/// Ensure that an interrupt fires at (or before) the provided counter value
+	fn schedule(&self, value: u64)
+	{
+		let _irq = ::sync::hold_interrupts();
+		let mut lh = self.next_fire.lock();
+		if value < *lh {
+			// - Avoid programming a value that has already passed (the interrupt would never fire)
+			let min = self.current() + self.ticks_per_ms() / 10;
+			*lh = ::core::cmp::max(value, min);
+			self.oneshot(0, *lh);
+		}
 	}
 	
 	fn read_reg(&self, reg: usize) -> u64 {
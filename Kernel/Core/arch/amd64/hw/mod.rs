@@ -6,5 +6,7 @@
 
 pub mod apic;
 pub mod hpet;
+pub mod pit;
+pub mod rtc;
 
 // vim: ft=rust
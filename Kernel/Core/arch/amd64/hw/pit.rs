@@ -0,0 +1,92 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// arch/amd64/hw/pit.rs
+//! Legacy 8254 Programmable Interval Timer (used when there's no HPET)
+//!
+//! Runs channel 0 as a ~1kHz periodic interrupt, counting ticks to provide the timestamp.
+#[allow(unused_imports)]
+use prelude::*;
+
+/// Input clock of the PIT (Hz)
+const BASE_FREQUENCY: u32 = 1193182;
+/// Interrupt rate (Hz), one tick is (close enough to) one millisecond
+const TICK_FREQUENCY: u32 = 1000;
+/// Maximum time between calls to `time_tick` (in ms), even if no timer is pending
+const MAX_TICK_INTERVAL: u64 = 100;
+
+struct State
+{
+	/// Ticks (milliseconds) since the PIT was started
+	ticks: u64,
+	/// Tick at which `time_tick` should next be called
+	next_due: u64,
+}
+
+static S_STATE: ::sync::Spinlock<State> = ::sync::Spinlock::new(State { ticks: 0, next_due: 0 });
+static S_IRQ_HANDLE: ::lib::LazyStatic<::arch::imp::hw::apic::IRQHandle> = lazystatic_init!();
+
+/// Start the PIT, called by the HPET driver when ACPI doesn't report a HPET
+pub fn init()
+{
+	log_notice!("Using the PIT for timing ({}Hz)", TICK_FREQUENCY);
+	let divisor = BASE_FREQUENCY / TICK_FREQUENCY;
+	// SAFE: Nothing else accesses the PIT
+	unsafe {
+		// Channel 0, lo/hi access, mode 2 (rate generator), binary
+		::arch::x86_io::outb(0x43, 0<<6|3<<4|2<<1|0);
+		::arch::x86_io::outb(0x40, (divisor & 0xFF) as u8);
+		::arch::x86_io::outb(0x40, (divisor >> 8) as u8);
+	}
+
+	// NOTE: ISA IRQ 0 is routed to GSI 2 by the (near-universal) ACPI interrupt source override
+	match ::arch::imp::hw::apic::register_irq(2, irq, 0 as *const ())
+	{
+	// SAFE: 'init' is called in a single-threaded context
+	Ok(h) => unsafe { S_IRQ_HANDLE.prep(|| h); },
+	Err(e) => log_error!("Unable to bind PIT interrupt: {:?}", e),
+	}
+}
+
+/// Returns true if the PIT is running (i.e. `init` was called)
+pub fn is_active() -> bool {
+	S_IRQ_HANDLE.ls_is_valid()
+}
+
+/// Milliseconds since the PIT was started
+pub fn get_timestamp() -> u64
+{
+	let _irq = ::sync::hold_interrupts();
+	S_STATE.lock().ticks
+}
+
+/// Ensure that `time_tick` is called at (or before) the provided timestamp
+pub fn request_tick(target_ms: u64)
+{
+	let _irq = ::sync::hold_interrupts();
+	let mut lh = S_STATE.lock();
+	if target_ms < lh.next_due {
+		lh.next_due = target_ms;
+	}
+}
+
+fn irq(_: *const ())
+{
+	let due = {
+		let mut lh = S_STATE.lock();
+		lh.ticks += 1;
+		if lh.ticks >= lh.next_due {
+			// - Pushed out until `time_tick` reports the next timer
+			lh.next_due = lh.ticks + MAX_TICK_INTERVAL;
+			true
+		}
+		else {
+			false
+		}
+		};
+	if due {
+		if let Some(next) = ::time::time_tick() {
+			request_tick(next);
+		}
+	}
+}
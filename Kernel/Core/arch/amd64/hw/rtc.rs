@@ -0,0 +1,135 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// arch/amd64/hw/rtc.rs
+//! CMOS Real-Time Clock
+#[allow(unused_imports)]
+use prelude::*;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+/// Century register (not guaranteed to exist, the ACPI FADT has the definitive location)
+const REG_CENTURY: u8 = 0x32;
+
+#[derive(PartialEq,Copy,Clone)]
+struct RawTime
+{
+	second: u8,
+	minute: u8,
+	hour: u8,
+	day: u8,
+	month: u8,
+	year: u8,
+	century: u8,
+}
+
+/// Maximum number of polls of the update-in-progress flag (an update takes under 2ms)
+const MAX_UPDATE_POLLS: usize = 100_000;
+/// Maximum number of attempts at getting two matching reads
+const MAX_READ_ATTEMPTS: usize = 10;
+
+/// Read the current time from the RTC, returning seconds since the UNIX epoch
+pub fn read() -> Option<u64>
+{
+	// Read until two consecutive reads match (avoids reading during an update)
+	let mut t = match read_raw()
+		{
+		Some(v) => v,
+		None => {
+			log_warning!("RTC update flag stuck, time unavailable");
+			return None;
+			},
+		};
+	let mut attempts = 0;
+	loop
+	{
+		let t2 = match read_raw()
+			{
+			Some(v) => v,
+			None => {
+				log_warning!("RTC update flag stuck, time unavailable");
+				return None;
+				},
+			};
+		if t2 == t {
+			break;
+		}
+		attempts += 1;
+		if attempts == MAX_READ_ATTEMPTS {
+			log_warning!("RTC time unstable, time unavailable");
+			return None;
+		}
+		t = t2;
+	}
+
+	let status_b = read_reg(REG_STATUS_B);
+	let is_binary = status_b & (1 << 2) != 0;
+	let is_24h = status_b & (1 << 1) != 0;
+	let conv = |v: u8| if is_binary { v } else { (v >> 4) * 10 + (v & 0xF) };
+
+	let pm = !is_24h && t.hour & 0x80 != 0;
+	let hour = conv(t.hour & 0x7F);
+	let hour = match (is_24h, pm, hour)
+		{
+		(true, _, h) => h,
+		(false, false, 12) => 0,
+		(false, true, 12) => 12,
+		(false, false, h) => h,
+		(false, true, h) => h + 12,
+		};
+	let century = match conv(t.century)
+		{
+		c @ 19 ... 21 => c as u32,
+		_ => 20,
+		};
+	let year = century * 100 + conv(t.year) as u32;
+	let (month, day) = (conv(t.month), conv(t.day));
+	if month < 1 || month > 12 || day < 1 || day > 31 || hour > 23 || year < 1970 {
+		log_warning!("RTC time invalid: {}-{}-{} {}h", year, month, day, hour);
+		return None;
+	}
+	let (minute, second) = (conv(t.minute), conv(t.second));
+	log_log!("RTC: {:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, hour, minute, second);
+
+	Some( ::time::unix_timestamp(year, month, day, hour, minute, second) )
+}
+
+/// Read the raw time registers, returns `None` if the update-in-progress flag never cleared
+fn read_raw() -> Option<RawTime>
+{
+	// Wait for any in-progress update to complete
+	let mut polls = 0;
+	while read_reg(REG_STATUS_A) & 0x80 != 0 {
+		polls += 1;
+		if polls == MAX_UPDATE_POLLS {
+			return None;
+		}
+	}
+	Some(RawTime {
+		second: read_reg(REG_SECONDS),
+		minute: read_reg(REG_MINUTES),
+		hour: read_reg(REG_HOURS),
+		day: read_reg(REG_DAY),
+		month: read_reg(REG_MONTH),
+		year: read_reg(REG_YEAR),
+		century: read_reg(REG_CENTURY),
+	})
+}
+
+fn read_reg(reg: u8) -> u8
+{
+	let _irq = ::sync::hold_interrupts();
+	// SAFE: CMOS access has no side-effects (other than the selected index), interrupts are held
+	unsafe {
+		::arch::x86_io::outb(0x70, reg);
+		::arch::x86_io::inb(0x71)
+	}
+}
+
+// vim: ft=rust
@@ -167,10 +167,17 @@ BLANKINT i
 %assign i i+1
 %endrep
 [extern irq_handler]
+[extern irq_return_user]
 IRQCommon:
 	API_SAVE
 	mov rdi, rbx
 	call irq_handler
+	; If returning to userland, handle any pending preemption
+	; - Interrupted CS is above the nine saved registers, RBX, and RIP
+	test BYTE [rsp+(9+2)*8], 3
+	jz .to_kernel
+	call irq_return_user
+.to_kernel:
 	API_RESTORE
 	pop rbx
 	iretq
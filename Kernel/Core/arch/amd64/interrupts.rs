@@ -38,6 +38,11 @@ static S_IRQ_HANDLERS_LOCK: ::sync::Spinlock<[IRQHandlersEnt; 256]> = ::sync::Sp
 /// ISR handler called by assembly
 pub extern "C" fn irq_handler(index: usize)
 {
+	// Inter-processor interrupts are handled directly (without the handler lock, as a TLB shootdown
+	// can be requested by a CPU that holds it)
+	if index == super::smp::IPI_WAKE || index == super::smp::IPI_TLB {
+		return super::smp::handle_ipi(index);
+	}
 	let lh = S_IRQ_HANDLERS_LOCK.lock_irqsafe();
 	let ent = (*lh)[index];
 	if let Some(h) = ent.handler {
@@ -45,6 +50,29 @@ pub extern "C" fn irq_handler(index: usize)
 	}
 }
 
+#[no_mangle]
+#[doc(hidden)]
+/// Called by assembly before an IRQ returns to userland, preempts the current thread if requested
+///
+/// Also terminates the thread if its process has been killed
+pub extern "C" fn irq_return_user()
+{
+	let preempt = ::threads::take_preempt_request();
+	if preempt || ::threads::is_killed()
+	{
+		// The interrupted userland state is saved on this thread's kernel stack, so interrupts can be
+		// re-enabled for the task switch
+		// SAFE: Interrupts are disabled again before returning to the assembly
+		unsafe { ::arch::sync::start_interrupts(); }
+		::threads::check_kill();
+		if preempt {
+			::threads::yield_time();
+		}
+		// SAFE: Restores the state expected by the IRQ return path
+		unsafe { ::arch::sync::stop_interrupts(); }
+	}
+}
+
 #[derive(Debug,Copy,Clone)]
 /// Error code for bind_isr
 pub enum BindISRError
@@ -93,6 +121,10 @@ pub fn bind_free_isr(callback: ISRHandler, info: *const(), idx: usize) -> Result
 	let mut lh = S_IRQ_HANDLERS_LOCK.lock();
 	for i in 32 .. lh.len()
 	{
+		// - Skip vectors reserved for IPIs
+		if i == super::smp::IPI_WAKE || i == super::smp::IPI_TLB {
+			continue ;
+		}
 		if lh[i].handler.is_none() {
 			log_trace!("- Using ISR {}", i);
 			lh[i] = IRQHandlersEnt {
@@ -257,6 +257,12 @@ fn invlpg(addr: *mut ()) {
 	}
 }
 
+/// Invalidate a page on all CPUs (for when an existing mapping is removed or restricted)
+fn invlpg_all(addr: *mut ()) {
+	invlpg(addr);
+	super::super::smp::tlb_shootdown(addr as usize);
+}
+
 pub fn can_map_without_alloc(addr: *mut ()) -> bool {
 	// The following only returns PTE::null() if an intermediate step was unallocated
 	! get_page_ent(addr as usize, false, LargeOk::No).is_null()
@@ -285,7 +291,7 @@ pub unsafe fn unmap(addr: *mut ()) -> Option<PAddr>
 		};
 	pte.set( 0, ::memory::virt::ProtectionMode::Unmapped );
 	
-	invlpg(addr);
+	invlpg_all(addr);
 	
 	rv
 }
@@ -298,7 +304,7 @@ pub unsafe fn reprotect(addr: *mut (), prot: ::memory::virt::ProtectionMode)
 	assert!( pte.is_present(), "Reprotecting unmapped page {:p}", addr );
 	let phys = pte.addr();
 	pte.set( phys, prot );
-	invlpg(addr);
+	invlpg_all(addr);
 }
 
 static PF_PRESENT : u64 = 0x001;
@@ -363,6 +369,7 @@ impl PTE
 		ProtectionMode::UserRO   => FLAG_P|FLAG_U|FLAG_NX,
 		ProtectionMode::UserRW   => FLAG_P|FLAG_U|FLAG_NX|FLAG_W,
 		ProtectionMode::UserCOW  => FLAG_P|FLAG_U|FLAG_NX|FLAG_COW,
+		ProtectionMode::UserCOWX => FLAG_P|FLAG_U|FLAG_COW,
 		ProtectionMode::UserRX   => FLAG_P|FLAG_U,
 		ProtectionMode::UserRWX  => FLAG_P|FLAG_U|FLAG_W,
 		}
@@ -405,7 +412,7 @@ impl PTE
 			{
 			0 => ProtectionMode::KernelRX,
 			//0|FLAG_W => ProtectionMode::KernelRWX,
-			U_RX  => ProtectionMode::UserRX,
+			U_RX  => if val & FLAG_COW != 0 { ProtectionMode::UserCOWX } else { ProtectionMode::UserRX },
 			U_RWX => ProtectionMode::UserRWX,
 			U_RO  => if val & FLAG_COW != 0 { ProtectionMode::UserCOW } else { ProtectionMode::UserRO },
 			U_RW  => ProtectionMode::UserRW,
@@ -451,19 +458,48 @@ pub fn handle_page_fault(accessed_address: usize, error_code: u32) -> bool
 		// Poke the main VMM layer
 		//::memory::virt::cow_write(accessed_address);
 
+		let mut rv = Ok( () );
 		// 1. Lock (relevant) address space
 		// SAFE: Changes to address space are transparent
 		::memory::virt::with_lock(accessed_address, || unsafe {
+			// - Another thread may have resolved this fault while the lock was being acquired
+			if ! pte.is_cow() {
+				invlpg( (accessed_address & !0xFFF) as *mut () );
+				return ;
+			}
 			let frame = pte.addr();
 			let pgaddr = (accessed_address as usize) & !PAGE_MASK;
+			// - Executable COW pages (e.g. untouched RWX zero pages) stay executable
+			let mode = if pte.get_perms() == ProtectionMode::UserCOWX { ProtectionMode::UserRWX } else { ProtectionMode::UserRW };
 			// 2. Get the PMM to provide us with a unique copy of that frame (can return the same addr)
-			let newframe = ::memory::phys::make_unique( frame, &*(pgaddr as *const [u8; 4096]) );
-			// 3. Remap to this page as UserRW (because COW is user-only atm)
-			pte.set(newframe, ProtectionMode::UserRW);
-			invlpg( (accessed_address & !0xFFF) as *mut () );
+			// - Demand-zero pages are backed by the shared zero frame, which is always copied
+			match ::memory::phys::make_unique( frame, &*(pgaddr as *const [u8; 4096]) )
+			{
+			Ok(newframe) => {
+				// 3. Remap to this page as writable (because COW is user-only atm)
+				pte.set(newframe, mode);
+				invlpg_all( (accessed_address & !0xFFF) as *mut () );
+				},
+			Err(e) => rv = Err(e),
+			}
 			});
+		// - Out of memory, apply the OOM policy (outside of the lock) and retry the access
+		if let Err(e) = rv {
+			::memory::virt::handle_fault_oom(accessed_address, e, error_code & FAULT_USER != 0);
+		}
 		return true;
 	}
+	//  > Stale TLB entry (another CPU changed the mapping, and the shootdown hasn't reached this CPU yet)
+	if error_code & (FAULT_WRITE|FAULT_LOCKED) == (FAULT_WRITE|FAULT_LOCKED) && !pte.is_null() && pte.is_present() && !pte.is_cow() {
+		match pte.get_perms()
+		{
+		ProtectionMode::KernelRW | ProtectionMode::UserRW | ProtectionMode::UserRWX => {
+			invlpg( (accessed_address & !0xFFF) as *mut () );
+			return true;
+			},
+		_ => {},
+		}
+	}
 	//  > Paged-out pages
 	if error_code & FAULT_LOCKED == 0 && pte.is_reserved() {
 		todo!("Paged - {:#x} pte = {:?}", accessed_address, pte);
@@ -512,19 +548,26 @@ pub unsafe fn temp_unmap<T>(addr: *mut T) {
 	// SAFE: Owned allocation
 	/*unsafe*/ {
 		get_page_ent(addr as usize, false, LargeOk::No).set(0, ProtectionMode::Unmapped);
-		invlpg(addr as *mut ());
+		invlpg_all(addr as *mut ());
 	}
 	S_TEMP_FREE.release();
 }
 
 
-struct NewTable(TempHandle<u64>);
+/// A newly allocated paging structure (and the level it's used at), freed with all entries if dropped
+struct NewTable(TempHandle<u64>, u8);
 impl NewTable {
-	fn new() -> Result<NewTable,::memory::virt::MapError> {
+	fn new(level: u8) -> Result<NewTable,::memory::virt::MapError> {
 		match ::memory::phys::allocate_bare()
 		{
 		Err(::memory::phys::Error) => Err( MapError::OutOfMemory ),
-		Ok(temp_handle) => Ok( NewTable( temp_handle.into() ) ),
+		Ok(temp_handle) => {
+			let mut rv = NewTable( temp_handle.into(), level );
+			for e in rv.iter_mut() {
+				*e = 0;
+			}
+			Ok( rv )
+			},
 		}
 	}
 	fn into_frame(self) -> PAddr {
@@ -539,9 +582,12 @@ impl NewTable {
 }
 impl ::core::ops::Drop for NewTable {
 	fn drop(&mut self) {
-		// TODO: This method needs to recursively free paging structures held by it.
-		// TODO: Need to also know the level it was used at!
-		todo!("NewTable::drop");
+		// Only the user half of a PML4 is owned
+		let count = if self.1 == 4 { 256 } else { 512 };
+		for e in self.0[..count].iter_mut() {
+			drop_table_ent(e, self.1);
+		}
+		::memory::phys::deref_frame( self.0.phys_addr() );
 	}
 }
 impl ::core::ops::Deref for NewTable {
@@ -563,31 +609,47 @@ impl_fmt! {
 }
 impl AddressSpace
 {
-	pub fn new(clone_start: usize, clone_end: usize) -> Result<AddressSpace,::memory::virt::MapError>
+	/// Create a new address space, cloning the user pages in `clone_start .. clone_end`
+	///
+	/// Also returns the number of user pages cloned (for the new process's page quota)
+	pub fn new(clone_start: usize, clone_end: usize) -> Result<(AddressSpace,usize),::memory::virt::MapError>
 	{
 		use super::addresses::FRACTAL_BASE;
 	
 		// Function called when an entry is found to have a table
-		fn opt_clone_page(idx: usize) -> Result<u64, ::memory::virt::MapError>
+		fn opt_clone_page(idx: usize, count: &mut usize) -> Result<u64, ::memory::virt::MapError>
 		{
 			//log_trace!("opt_clone_page(idx={:#x})", idx);
 			
 			// SAFE: Only called when parent table is present
-			let ent = unsafe { get_entry(0, idx, false) };
+			let mut ent = unsafe { get_entry(0, idx, false) };
 			if ! ent.is_reserved()
 			{
 				Ok(0)
 			}
 			else
 			{
-				let p = ent.get_perms();
+				let mut p = ent.get_perms();
 				let frame = match p
 					{
-					ProtectionMode::UserRX | ProtectionMode::UserCOW => {
+					ProtectionMode::UserRX | ProtectionMode::UserRO | ProtectionMode::UserCOW | ProtectionMode::UserCOWX => {
 						let addr = ent.addr();
 						::memory::phys::ref_frame( addr );
 						addr
 						},
+					// Private writable pages are shared copy-on-write (in both the source and the new address space)
+					// - Frames with other references (e.g. write-back file mappings) are still copied, so the sharing isn't broken
+					ProtectionMode::UserRW | ProtectionMode::UserRWX if ::arch::memory::phys::get_multiref_count(ent.addr() / ::PAGE_SIZE as u64) == 0 => {
+						let addr = ent.addr();
+						p = if p == ProtectionMode::UserRWX { ProtectionMode::UserCOWX } else { ProtectionMode::UserCOW };
+						// SAFE: Frame is unchanged, just made read-only (a write will fault and get a private copy)
+						unsafe {
+							ent.set(addr, p);
+						}
+						invlpg_all( (idx << 12) as *mut () );
+						::memory::phys::ref_frame( addr );
+						addr
+						},
 					ProtectionMode::UserRWX | ProtectionMode::UserRW => {
 						// SAFE: We've just determined that this page is mapped in, so we won't crash. Any race is the user's fault (and shouldn't impact the kernel)
 						let src = unsafe { ::core::slice::from_raw_parts((idx << 12) as *const u8, ::PAGE_SIZE) };
@@ -599,10 +661,11 @@ impl AddressSpace
 						},
 					v @ _ => todo!("opt_clone_page - Mode {:?}", v),
 					};
+				*count += 1;
 				Ok( frame | PTE::mode_to_flags(p) )
 			}
 		}
-		fn opt_clone_segment(level: u8, idx: usize, clone_start_pidx: usize, clone_end_pidx: usize) -> Result<u64,::memory::virt::MapError>
+		fn opt_clone_segment(level: u8, idx: usize, clone_start_pidx: usize, clone_end_pidx: usize, count: &mut usize) -> Result<u64,::memory::virt::MapError>
 		{
 			//log_trace!("opt_clone_segment(level={}, idx={}, ...)", level, idx);
 			
@@ -618,7 +681,7 @@ impl AddressSpace
 			}
 			else
 			{
-				let mut ents = try!(NewTable::new());
+				let mut ents = try!(NewTable::new(level));
 				let base = idx << 9;
 				for i in 0 .. 512
 				{
@@ -628,10 +691,10 @@ impl AddressSpace
 					if clone_start_pidx >> level_bits <= this_idx && this_idx << level_bits < clone_end_pidx
 					{
 						ents[i] = if level == 1 {
-								try!(opt_clone_page(this_idx))
+								try!(opt_clone_page(this_idx, count))
 							}
 							else {
-								try!(opt_clone_segment(level-1, this_idx, clone_start_pidx,clone_end_pidx))
+								try!(opt_clone_segment(level-1, this_idx, clone_start_pidx,clone_end_pidx, count))
 							};
 					}
 				}
@@ -647,14 +710,15 @@ impl AddressSpace
 		let clone_end = clone_end >> 12;
 		
 		// - Allocate a new root level
-		let mut ents = try!(NewTable::new());
+		let mut ents = try!(NewTable::new(4));
+		let mut page_count = 0;
 		// TODO: Freeze user state during this
 		for i in 0 .. 256 {
 			const PML4_BITS: usize = 9*3;
 			let pdp_base = i << PML4_BITS;
 			//log_trace!("{:#x} <= {:#x} && {:#x} < {:#x}", clone_start>>PML4_BITS, i, pdp_base, clone_end);
 			if clone_start >> PML4_BITS <= i && pdp_base < clone_end {
-				ents[i] = try!(opt_clone_segment(3, i, clone_start,clone_end));
+				ents[i] = try!(opt_clone_segment(3, i, clone_start,clone_end, &mut page_count));
 			}
 		}
 		// - Alias in kernel shared pages (pretty much all of them really)
@@ -670,7 +734,7 @@ impl AddressSpace
 			}
 		}
 		log_debug!("ents[..256] = {:#x}", ::logging::print_iter(ents[..256].iter()));
-		Ok( AddressSpace( ents.into_frame() ) )
+		Ok( (AddressSpace( ents.into_frame() ), page_count) )
 	}
 	pub fn pid0() -> AddressSpace {
 		// SAFE: Doesn't change while rust code is active
@@ -681,38 +745,38 @@ impl AddressSpace
 		self.0
 	}
 }
-impl ::core::ops::Drop for AddressSpace {
-	fn drop(&mut self) {
-		
-		fn drop_table_ent(table_ent: &mut u64, level: u8) {
-			assert!(1 <= level && level <= 4, "AddressSpace::drop::drop_table_ent - level invalid, {}", level);
-			// SAFE: We have &mut
-			let pte = unsafe { PTE::new(PTEPos::from_level(level), table_ent) };
-			if ! pte.is_reserved() {
-				assert!( *table_ent == 0, "TODO: Handle non-zero non-present table entry" );
-			}
-			else {
-				let addr = pte.addr();
-				if level == 1 {
-					// Level 1, i.e. page table. Just dereference the page
-				}
-				else {
-					// Level 2-4 (PD, PDP, PML4). Recurse
-					// SAFE: All paging tables should be uniquely owned, transmute is valid
-					unsafe {
-						::memory::virt::with_temp(addr, |tab_pg| {
-							let tab: &mut [u64; 512] = ::core::mem::transmute(tab_pg);
-							for e in tab.iter_mut() {
-								drop_table_ent(e, level-1);
-							}
-							});
+/// Release an entry in a paging structure (recursively freeing tables, and dereferencing pages)
+fn drop_table_ent(table_ent: &mut u64, level: u8) {
+	assert!(1 <= level && level <= 4, "drop_table_ent - level invalid, {}", level);
+	// SAFE: We have &mut
+	let pte = unsafe { PTE::new(PTEPos::from_level(level), table_ent) };
+	if ! pte.is_reserved() {
+		assert!( *table_ent == 0, "TODO: Handle non-zero non-present table entry" );
+	}
+	else {
+		let addr = pte.addr();
+		if level == 1 {
+			// Level 1, i.e. page table. Just dereference the page
+		}
+		else {
+			// Level 2-4 (PD, PDP, PML4). Recurse
+			// SAFE: All paging tables should be uniquely owned, transmute is valid
+			unsafe {
+				::memory::virt::with_temp(addr, |tab_pg| {
+					let tab: &mut [u64; 512] = ::core::mem::transmute(tab_pg);
+					for e in tab.iter_mut() {
+						drop_table_ent(e, level-1);
 					}
-				}
-				::memory::phys::deref_frame( addr );
+					});
 			}
-			*table_ent = 0;
 		}
+		::memory::phys::deref_frame( addr );
+	}
+	*table_ent = 0;
+}
 
+impl ::core::ops::Drop for AddressSpace {
+	fn drop(&mut self) {
 		// SAFE: All paging tables should be uniquely owned, transmute is valid
 		unsafe {
 			::memory::virt::with_temp(self.0, |pml4_pg| {
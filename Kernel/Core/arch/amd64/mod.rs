@@ -18,6 +18,7 @@ pub mod boot;
 pub mod sync;
 
 mod tss;
+mod smp;
 
 mod log;
 pub mod x86_io;
@@ -32,7 +33,10 @@ extern "C"
 
 fn init()
 {
-	// None needed, just dependencies
+	// Wall-clock time: prefer the firmware-provided value, falling back to the CMOS RTC
+	if let Some(t) = boot::get_boot_time().or_else(|| hw::rtc::read()) {
+		::time::set_realtime(t * 1000);
+	}
 }
 
 #[inline(always)]
@@ -51,11 +55,18 @@ extern "C" {
 	pub fn drop_to_user(entry: usize, stack: usize, cmdline_len: usize) -> !;
 }
 
+/// `cur_timestamp` counts in milliseconds (HPET/PIT ticks are scaled in `hw::hpet::get_timestamp`)
+pub const TIMESTAMP_PER_MS: u64 = 1;
 /// Return the system timestamp (miliseconds since an arbitary point)
 pub fn cur_timestamp() -> u64
 {
 	hw::hpet::get_timestamp()
 }
+/// Request a timer tick at the specified timestamp
+pub fn request_tick(target: u64)
+{
+	hw::hpet::request_tick(target)
+}
 
 /// Print a backtrace, starting at the current location.
 pub fn print_backtrace()
@@ -0,0 +1,318 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/arch/amd64/smp.rs
+//! Multiprocessor support (AP startup, per-CPU identification, and IPIs)
+//!
+//! Application processors (APs) are started using the INIT-SIPI-SIPI sequence, running a small
+//! real-mode trampoline (copied to `AP_TRAMPOLINE_PADDR`) that enters long mode and jumps to `ap_start64`.
+use prelude::*;
+use core::sync::atomic::{AtomicUsize,AtomicBool,Ordering,ATOMIC_USIZE_INIT,ATOMIC_BOOL_INIT};
+
+module_define!{SMP, [APIC, HPET, TSS], init}
+
+/// Maximum number of supported CPUs
+///
+/// NOTE: MUST match the value in common.inc.asm
+pub const MAX_CPUS: usize = 8;
+
+/// Physical address of the AP startup trampoline (must be page aligned and below 1MiB)
+///
+/// NOTE: Reserved in the boot memory map (see boot.rs)
+pub const AP_TRAMPOLINE_PADDR: u64 = 0x8000;
+
+/// IPI vector used to wake an idle CPU (handler does nothing, `hlt` returns)
+pub const IPI_WAKE: usize = 0x7C;
+/// IPI vector used for TLB shootdown
+pub const IPI_TLB: usize = 0x7D;
+
+/// Data block at the end of the trampoline, populated for each AP
+#[repr(C)]
+struct TrampolineData
+{
+	/// Physical address of the initial PML4 (must be 32-bit)
+	cr3: u32,
+	_pad: u32,
+	/// Initial stack pointer / TLS base for the AP's idle thread
+	tls_base: u64,
+	/// CPU index
+	cpu: u64,
+}
+
+extern "C" {
+	static mut InitialPML4: [u64; 512];
+	static InitialPDP: [u64; 512];
+	static ap_trampoline: [u8; 0];
+	static ap_trampoline_data: [u8; 0];
+	static ap_trampoline_end: [u8; 0];
+}
+
+/// Number of online CPUs
+static S_CPU_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+/// Mapping from LAPIC ID to CPU index (0xFF = not a known CPU)
+// NOTE: Only written by the BSP before the relevant AP is started
+static mut S_APIC_TO_CPU: [u8; 256] = [0xFF; 256];
+/// LAPIC ID for each CPU index
+static mut S_CPU_TO_APIC: [u8; MAX_CPUS] = [0; MAX_CPUS];
+/// Set by an AP once it has finished initialising
+static S_AP_STARTED: AtomicBool = ATOMIC_BOOL_INIT;
+/// Set once the LAPIC ID tables are valid (before the first AP is started)
+static S_SMP_ACTIVE: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Serialises TLB shootdowns
+static S_TLB_LOCK: ::sync::Spinlock<()> = ::sync::Spinlock::new( () );
+/// Address being invalidated by the current shootdown
+static S_TLB_ADDR: AtomicUsize = ATOMIC_USIZE_INIT;
+/// Bitmask of CPUs that have yet to acknowledge the current shootdown
+static S_TLB_PENDING: AtomicUsize = ATOMIC_USIZE_INIT;
+
+fn init()
+{
+	let bsp_apic = super::hw::apic::get_lapic_id();
+	// SAFE: Single-threaded (no APs started yet)
+	unsafe {
+		S_APIC_TO_CPU[bsp_apic as usize] = 0;
+		S_CPU_TO_APIC[0] = bsp_apic;
+	}
+	S_CPU_COUNT.store(1, Ordering::SeqCst);
+
+	let apic_ids: Vec<u8> = super::hw::apic::enum_cpu_apic_ids().into_iter().filter(|&id| id != bsp_apic).collect();
+	if apic_ids.len() == 0 {
+		log_log!("Uniprocessor system (BSP LAPIC {})", bsp_apic);
+		return ;
+	}
+	log_log!("{} APs to start (BSP LAPIC {})", apic_ids.len(), bsp_apic);
+	S_SMP_ACTIVE.store(true, Ordering::SeqCst);
+
+	// SAFE: Single-threaded, and the trampoline page is reserved
+	unsafe {
+		install_trampoline();
+		// Temporarily identity map low memory (the trampoline enables paging while running from it)
+		InitialPML4[0] = (&InitialPDP as *const _ as u64 - super::memory::addresses::IDENT_START as u64) | 3;
+	}
+
+	for apic_id in apic_ids
+	{
+		let cpu = S_CPU_COUNT.load(Ordering::SeqCst);
+		if cpu == MAX_CPUS {
+			log_notice!("More than {} CPUs present, ignoring LAPIC {} and above", MAX_CPUS, apic_id);
+			break ;
+		}
+		if start_ap(cpu, apic_id) {
+			S_CPU_COUNT.store(cpu + 1, Ordering::SeqCst);
+		}
+		else {
+			log_error!("CPU{} (LAPIC {}) failed to start", cpu, apic_id);
+			// SAFE: AP didn't start, so nothing else is reading this
+			unsafe { S_APIC_TO_CPU[apic_id as usize] = 0xFF; }
+		}
+	}
+
+	// SAFE: All APs are now running from the higher half
+	unsafe {
+		InitialPML4[0] = 0;
+	}
+	log_notice!("{} CPUs online", S_CPU_COUNT.load(Ordering::SeqCst));
+}
+
+/// Copy the trampoline code into low memory
+unsafe fn install_trampoline()
+{
+	let start = &ap_trampoline as *const _ as usize;
+	let len = &ap_trampoline_end as *const _ as usize - start;
+	assert!(len <= ::PAGE_SIZE - ::core::mem::size_of::<TrampolineData>());
+	let dst = ::memory::virt::fixed_alloc(AP_TRAMPOLINE_PADDR, 1).expect("AP trampoline not in identity region") as *mut u8;
+	::core::ptr::copy_nonoverlapping(start as *const u8, dst, len);
+}
+/// Obtain a pointer to the trampoline's data block (in the identity mapping)
+fn trampoline_data() -> *mut TrampolineData
+{
+	// SAFE: Only taking addresses
+	let ofs = unsafe { &ap_trampoline_data as *const _ as usize - &ap_trampoline as *const _ as usize };
+	// SAFE: Physical address is within the identity region
+	let base = unsafe { ::memory::virt::fixed_alloc(AP_TRAMPOLINE_PADDR, 1).unwrap() as usize };
+	(base + ofs) as *mut TrampolineData
+}
+
+/// Start a single AP, returns true if it reported in
+fn start_ap(cpu: usize, apic_id: u8) -> bool
+{
+	log_debug!("Starting CPU{} (LAPIC {})", cpu, apic_id);
+	let tls_base = super::threads::prep_ap_idle(cpu);
+
+	// SAFE: The AP isn't running yet, so this is the only accessor
+	unsafe {
+		S_APIC_TO_CPU[apic_id as usize] = cpu as u8;
+		S_CPU_TO_APIC[cpu] = apic_id;
+		*trampoline_data() = TrampolineData {
+			cr3: (&InitialPML4 as *const _ as u64 - super::memory::addresses::IDENT_START as u64) as u32,
+			_pad: 0,
+			tls_base: tls_base as u64,
+			cpu: cpu as u64,
+			};
+	}
+	S_AP_STARTED.store(false, Ordering::SeqCst);
+
+	// INIT, wait 10ms, then STARTUP (twice if the first is ignored)
+	let vector = (AP_TRAMPOLINE_PADDR >> 12) as u8;
+	super::hw::apic::send_init(apic_id);
+	busy_wait_ms(10);
+	for _ in 0 .. 2
+	{
+		super::hw::apic::send_startup(apic_id, vector);
+		// Allow up to 100ms for the AP to report in
+		let end = ::time::ticks() + 100;
+		while ::time::ticks() < end {
+			if S_AP_STARTED.load(Ordering::SeqCst) {
+				return true;
+			}
+			cpu_relax();
+		}
+	}
+	false
+}
+
+fn busy_wait_ms(ms: u64)
+{
+	let end = ::time::ticks() + ms + 1;
+	while ::time::ticks() < end {
+		cpu_relax();
+	}
+}
+
+#[inline(always)]
+fn cpu_relax()
+{
+	// SAFE: Spin-loop hint only
+	unsafe { asm!("pause" : : : "memory" : "volatile"); }
+}
+
+/// Rust entrypoint for APs (called by `ap_start64` on the idle thread's stack)
+#[no_mangle]
+#[doc(hidden)]
+pub extern "C" fn ap_entry(cpu: usize) -> !
+{
+	// Flush the (global) low identity mapping used by the trampoline
+	// SAFE: Just a TLB invalidation
+	unsafe { asm!("invlpg ($0)" : : "r" (AP_TRAMPOLINE_PADDR as usize) : "memory" : "volatile"); }
+
+	super::tss::init_ap(cpu);
+	super::hw::apic::init_ap();
+	log_log!("CPU{} online", cpu);
+	S_AP_STARTED.store(true, Ordering::SeqCst);
+
+	// This thread is the AP's idle thread
+	::threads::idle_thread();
+	unreachable!();
+}
+
+/// Returns the index of the current CPU (0 is the BSP)
+#[is_safe(irq)]
+pub fn cpu_num() -> usize
+{
+	if ! S_SMP_ACTIVE.load(Ordering::Relaxed) {
+		0
+	}
+	else {
+		// SAFE: Table is only written for CPUs that are not yet running
+		match unsafe { S_APIC_TO_CPU[super::hw::apic::get_lapic_id() as usize] }
+		{
+		0xFF => 0,
+		v => v as usize,
+		}
+	}
+}
+/// Returns the LAPIC ID of the BSP (used as the default interrupt target)
+pub fn get_bsp_apic_id() -> u8
+{
+	if S_CPU_COUNT.load(Ordering::Relaxed) == 0 {
+		// Before `init`, only the BSP is running
+		super::hw::apic::get_lapic_id()
+	}
+	else {
+		// SAFE: Entry 0 is written before S_CPU_COUNT is set
+		unsafe { S_CPU_TO_APIC[0] }
+	}
+}
+/// Returns the number of online CPUs
+pub fn cpu_count() -> usize
+{
+	::core::cmp::max(1, S_CPU_COUNT.load(Ordering::Relaxed))
+}
+
+/// Wake the specified CPU (if it is halted in the idle loop)
+pub fn wake_cpu(cpu: usize)
+{
+	if cpu < cpu_count() && cpu != cpu_num() {
+		// SAFE: Table entries for online CPUs are fixed
+		let apic_id = unsafe { S_CPU_TO_APIC[cpu] };
+		super::hw::apic::send_ipi(apic_id, IPI_WAKE as u8);
+	}
+}
+
+/// Invalidate a page's TLB entry on all other CPUs
+///
+/// NOTE: The caller is expected to have already invalidated the local TLB
+///
+/// Other CPUs may have interrupts held (e.g. while spinning on a lock this CPU holds), so they also
+/// acknowledge the request from the spinlock wait loop (see `poll_tlb_shootdown`).
+pub fn tlb_shootdown(addr: usize)
+{
+	let count = cpu_count();
+	if count <= 1 {
+		return ;
+	}
+	let _irq = ::sync::hold_interrupts();
+	let me = cpu_num();
+	// Acquire the shootdown lock, servicing any requests from other CPUs while waiting
+	// - Prevents deadlock when two CPUs attempt a shootdown at the same time
+	let _lh;
+	loop {
+		if let Some(lh) = S_TLB_LOCK.try_lock() {
+			_lh = lh;
+			break ;
+		}
+		service_tlb(me);
+		cpu_relax();
+	}
+	let mask = ((1 << count) - 1) & !(1 << me);
+	S_TLB_ADDR.store(addr, Ordering::SeqCst);
+	S_TLB_PENDING.store(mask, Ordering::SeqCst);
+	super::hw::apic::send_ipi_others(IPI_TLB as u8);
+	while S_TLB_PENDING.load(Ordering::SeqCst) != 0 {
+		cpu_relax();
+	}
+}
+/// Acknowledge a pending shootdown for this CPU (called from spin loops that may run with interrupts held)
+#[inline]
+pub fn poll_tlb_shootdown()
+{
+	if S_TLB_PENDING.load(Ordering::Relaxed) != 0 {
+		service_tlb(cpu_num());
+	}
+}
+/// Handle a pending shootdown for this CPU (if any)
+fn service_tlb(me: usize)
+{
+	if S_TLB_PENDING.load(Ordering::SeqCst) & (1 << me) != 0 {
+		let addr = S_TLB_ADDR.load(Ordering::SeqCst);
+		// SAFE: Just a TLB invalidation
+		unsafe { asm!("invlpg ($0)" : : "r" (addr) : "memory" : "volatile"); }
+		S_TLB_PENDING.fetch_and(!(1 << me), Ordering::SeqCst);
+	}
+}
+
+/// Handle an IPI (called from the low-level IRQ handler)
+#[req_safe(irq)]
+pub fn handle_ipi(vector: usize)
+{
+	match vector
+	{
+	IPI_WAKE => {},
+	IPI_TLB => service_tlb(cpu_num()),
+	_ => log_error!("Unknown IPI vector {:#x}", vector),
+	}
+	super::hw::apic::local_eoi();
+}
+
+// vim: ft=rust
@@ -240,7 +240,20 @@ start64_higher:
 	mov al, 10
 	out dx, al
 	
-	; Bind the 'SYSCALL' handler (and set flags for it)
+	call syscall_init
+	
+	mov rax, InitialPML4
+	mov QWORD [rax], 0
+	; 7. Call rust kmain
+	call kmain
+.dead_loop:
+	cli
+	hlt
+	jmp .dead_loop
+
+; Bind the 'SYSCALL' handler (and set flags for it)
+; - Per-CPU MSRs, so called by both the BSP and APs
+syscall_init:
 	; LSTAR = 0xC000_0082
 	mov rax, syscall_handler
 	mov rdx, rax
@@ -257,11 +270,107 @@ start64_higher:
 	mov edx, 0
 	mov ecx, 0xC0000084
 	wrmsr
-	
-	mov rax, InitialPML4
-	mov QWORD [rax], 0
-	; 7. Call rust kmain
-	call kmain
+	ret
+
+; -------------------------------------------------
+; AP (Application Processor) startup
+; -------------------------------------------------
+; Real-mode trampoline, copied to AP_TRAMPOLINE_BASE by smp.rs
+; - Must be position-dependent on AP_TRAMPOLINE_BASE, hence the TADDR macro
+%define AP_TRAMPOLINE_BASE	0x8000
+%define TADDR(l)	((l) - ap_trampoline + AP_TRAMPOLINE_BASE)
+[section .rodata]
+[BITS 16]
+EXPORT ap_trampoline
+	cli
+	; Normalise CS:IP
+	jmp 0:TADDR(.rm)
+.rm:
+	xor ax, ax
+	mov ds, ax
+	mov dx, 0x3F8
+	mov al, 'A'
+	out dx, al
+	lgdt [TADDR(ap_trampoline_gdtptr)]
+	mov eax, cr0
+	or al, 1
+	mov cr0, eax
+	jmp 0x18:TADDR(.pm)
+[BITS 32]
+.pm:
+	mov ax, 0x10
+	mov ds, ax
+	mov es, ax
+	mov ss, ax
+	; Same feature set as the BSP (see `start`)
+	mov eax, cr4
+	or eax, 0x80|0x20|0x10
+	or ax, (1 << 9)|(1 << 10)
+	mov cr4, eax
+	mov eax, [TADDR(ap_trampoline_data.cr3)]
+	mov cr3, eax
+	; NXE, LME, SCE
+	mov ecx, 0xC0000080
+	rdmsr
+	or eax, (1 << 11)|(1 << 8)|(1 << 0)
+	wrmsr
+	; PG, WP, TS, MP
+	mov eax, cr0
+	or eax, 0x80010000|(1 << 3)|(1 << 1)
+	and ax, ~(1 << 2)
+	mov cr0, eax
+	; - 64-bit code uses the same selector as the kernel GDT (so CS is valid after the reload)
+	jmp 0x08:TADDR(.lm)
+[BITS 64]
+.lm:
+	mov rsp, [TADDR(ap_trampoline_data.tls_base)]
+	mov rdi, [TADDR(ap_trampoline_data.cpu)]
+	mov rax, ap_start64
+	jmp rax
+ALIGN 8
+ap_trampoline_gdt:
+	dd 0, 0
+	dd 0x00000000, 0x00209A00	; 0x08: 64-bit Code
+	dd 0x0000FFFF, 0x00CF9200	; 0x10: 32-bit Data
+	dd 0x0000FFFF, 0x00CF9A00	; 0x18: 32-bit Code
+ap_trampoline_gdtptr:
+	dw	$-ap_trampoline_gdt-1
+	dd	TADDR(ap_trampoline_gdt)
+ALIGN 8
+; NOTE: Layout MUST match `TrampolineData` in smp.rs
+EXPORT ap_trampoline_data
+.cr3:	dd	0
+	dd	0
+.tls_base:	dq	0
+.cpu:	dq	0
+EXPORT ap_trampoline_end
+
+[section .text]
+; RSP = Idle thread's TLS base (top of stack), RDI = CPU index
+EXPORT ap_start64
+	lgdt [rel GDTPtr2]
+	mov ax, 0x10
+	mov ds, ax
+	mov ss, ax
+	mov es, ax
+	mov fs, ax
+	mov gs, ax
+	; Set FS/GS base to the idle thread's TLS block
+	mov r12, rdi
+	mov rax, rsp
+	mov rdx, rax
+	shr rdx, 32
+	mov ecx, 0xC0000100	; FS Base
+	wrmsr
+	mov ecx, 0xC0000101	; GS Base
+	wrmsr
+	; Shared IDT
+	mov rax, IDTPtr
+	lidt [rax]
+	call syscall_init
+	mov rdi, r12
+	[extern ap_entry]
+	call ap_entry
 .dead_loop:
 	cli
 	hlt
@@ -273,6 +382,8 @@ start64_higher:
 ; RSI: New RSP (pointer)
 ; RDX: New FSBASE
 ; RCX: New CR3
+; R8: Outgoing thread's "running" flag (cleared once off its stack)
+; R9: This CPU's TSS RSP0 field
 [section .text.asm.task_switch]
 EXPORT task_switch
 	push rbp
@@ -287,12 +398,13 @@ EXPORT task_switch
 	
 	; Update stack top (RSP0) and TLS base (GS)
 	; TLS base and stack top are the same address.
-	; TODO: This assumes uniprocessor
-	mov [rel TSSes+tss.rsp0], rdx
+	mov [r9], rdx
 	mov rax, rdx
 	shr rdx, 32	; EDX = High
 	mov ecx, 0xC0000101	; GS Base
 	wrmsr
+	; Outgoing thread's state is saved (and no longer referenced), other CPUs can now run it
+	mov BYTE [r8], 0
 	
 	; Restore saved registers and return
 	RESTORE rbx, r12, r13, r14, r15
@@ -312,18 +424,27 @@ EXPORT thread_trampoline
 ; RDI: SP
 ; RDX: Arg
 EXPORT drop_to_user
-	mov rcx, rdi	; Set IP for SYSRET
+	mov r9, rdx	; Save argument (WRMSR uses RDX)
 	pushf
 	cli
 	pop r11	; Set RFLAGS for SYSRET
+	mov r8, [gs:0x18]	; Userland TLS base (see TLSData)
 	swapgs
 	mov ax, 0x23
 	mov ds, ax
 	mov es, ax
 	mov fs, ax
 	mov gs, ax
+	; Loading FS clears the base, so restore the user's TLS base
+	mov eax, r8d
+	shr r8, 32
+	mov edx, r8d
+	mov ecx, 0xC0000100	; FS Base
+	wrmsr
+	mov rcx, rdi	; Set IP for SYSRET
 	mov rsp, rsi	; User's stack
-	mov rax, rdx	; Argument passed in RAX
+	mov rax, r9	; Argument passed in RAX
+	mov rdi, r9	; - and RDI (first argument to thread entrypoints)
 	db 0x48
 	sysret
 
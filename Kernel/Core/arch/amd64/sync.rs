@@ -3,16 +3,19 @@
 //
 // arch/amd64/sync.rs
 //! Low-level synchronisaion primitives
-use core::sync::atomic::{AtomicBool,Ordering};
+use core::sync::atomic::{AtomicUsize,Ordering};
 
 const TRACE_IF: bool = false;
 //const TRACE_IF: bool = true;
 
 /// Lightweight protecting spinlock
+///
+/// NOTE: Does not disable interrupts (use `hold_interrupts` first if the lock is also used by IRQ handlers)
 pub struct Spinlock<T>
 {
 	#[doc(hidden)]
-	pub lock: AtomicBool,
+	/// Owning CPU plus one (zero when unlocked)
+	pub lock: AtomicUsize,
 	#[doc(hidden)]
 	pub value: ::core::cell::UnsafeCell<T>,
 }
@@ -25,7 +28,9 @@ pub struct HeldSpinlock<'lock,T:'lock>
 }
 
 /// A handle for frozen interrupts
-pub struct HeldInterrupts(bool);
+///
+/// Not `Send`: Interrupts are only held on the current CPU, and must be restored on the same CPU
+pub struct HeldInterrupts(bool, ::core::marker::PhantomData<*const ()>);
 
 ///// Handle for a held spinlock that holds interrupts too
 //pub struct HeldSpinlockInt<'lock,T:'lock+Send>
@@ -39,7 +44,7 @@ impl<T> Spinlock<T>
 	/// Create a new spinning lock
 	pub const fn new(val: T) -> Spinlock<T> {
 		Spinlock {
-			lock: ::core::sync::atomic::ATOMIC_BOOL_INIT, //AtomicBool::new(false),
+			lock: ::core::sync::atomic::ATOMIC_USIZE_INIT, //AtomicUsize::new(0),
 			value: ::core::cell::UnsafeCell::new(val),
 		}
 	}
@@ -59,11 +64,27 @@ impl<T> Spinlock<T>
 		HeldSpinlock { lock: self }
 	}
 	/// Attempt to acquire the lock, returning None if it is already held by this CPU
+	///
+	/// If another CPU holds the lock, this spins until it is released.
 	#[is_safe(irq)]
 	pub fn try_lock_cpu(&self) -> Option<HeldSpinlock<T>>
 	{
-		//if self.lock.compare_and_swap(0, cpu_num()+1, Ordering::Acquire) == 0
-		if self.lock.compare_and_swap(false, true, Ordering::Acquire) == false
+		let cpu = cpu_num();
+		loop
+		{
+			match self.lock.compare_and_swap(0, cpu+1, Ordering::Acquire)
+			{
+			0 => return Some( HeldSpinlock { lock: self } ),
+			v if v == cpu+1 => return None,
+			_ => cpu_relax(),
+			}
+		}
+	}
+	/// Attempt to acquire the lock without spinning, returning None if it is held (by any CPU)
+	#[is_safe(irq)]
+	pub fn try_lock(&self) -> Option<HeldSpinlock<T>>
+	{
+		if self.lock.compare_and_swap(0, cpu_num()+1, Ordering::Acquire) == 0
 		{
 			Some( HeldSpinlock { lock: self } )
 		}
@@ -74,16 +95,23 @@ impl<T> Spinlock<T>
 	}
 	
 	fn inner_lock(&self) {
-		//while self.lock.compare_and_swap(0, cpu_num()+1, Ordering::Acquire) != 0
-		while self.lock.compare_and_swap(false, true, Ordering::Acquire) == true
+		let cpu = cpu_num();
+		loop
 		{
+			match self.lock.compare_and_swap(0, cpu+1, Ordering::Acquire)
+			{
+			0 => break,
+			// If this CPU already holds the lock, spinning will never succeed
+			v if v == cpu+1 => panic!("Spinlock {:p} ({}) recursively locked by CPU{}", self, type_name!(T), cpu),
+			_ => cpu_relax(),
+			}
 		}
 		::core::sync::atomic::fence(Ordering::Acquire);
 	}
 	fn inner_release(&self) {
 		//::arch::puts("Spinlock::release()\n");
 		::core::sync::atomic::fence(Ordering::Release);
-		self.lock.store(false, Ordering::Release);
+		self.lock.store(0, Ordering::Release);
 	}
 }
 // Some special functions on non-wrapping spinlocks
@@ -145,7 +173,7 @@ pub fn hold_interrupts() -> HeldInterrupts
 			::arch::puts("hold_interrupts() - IF maintained\n");
 		}
 	}
-	HeldInterrupts(if_set)
+	HeldInterrupts(if_set, ::core::marker::PhantomData)
 }
 
 impl ::core::ops::Drop for HeldInterrupts
@@ -168,6 +196,22 @@ impl ::core::ops::Drop for HeldInterrupts
 	}
 }
 
+/// Index of the current CPU (used to track spinlock ownership)
+#[inline]
+fn cpu_num() -> usize {
+	super::smp::cpu_num()
+}
+/// Spin-loop hint
+///
+/// Also services any pending TLB shootdown, as the spinning CPU may have interrupts held while the lock
+/// holder waits for this CPU to acknowledge a shootdown.
+#[inline(always)]
+fn cpu_relax() {
+	super::smp::poll_tlb_shootdown();
+	// SAFE: No side-effects
+	unsafe { asm!("pause" : : : "memory" : "volatile"); }
+}
+
 pub unsafe fn stop_interrupts() {
 	asm!("cli" : : : : "volatile");
 }
@@ -4,6 +4,8 @@
 // Core/arch/amd64/threads.rs
 //! Architecture-level thread handling (helpers for ::threads).
 use prelude::*;
+use core::sync::atomic::{AtomicBool,Ordering};
+pub use super::smp::MAX_CPUS;
 
 #[derive(Default)]//,Copy,Clone)]
 /// Low-level thread state
@@ -12,10 +14,11 @@ pub struct State
 	cr3: u64,
 	rsp: u64,
 	tlsbase: u64,
+	/// Set while a CPU is executing this thread (prevents another CPU resuming it before its state is saved)
+	running: AtomicBool,
 	// Not strictly part of the CPU state, but it prevents this thread's stack from disappearing
 	stack_handle: Option< ::memory::virt::ArrayHandle<u8> >,
 	// TODO: SSE state 
-	// TODO: Usermode TLS bsae
 }
 
 // TODO: This needs to be 16 byte aligned
@@ -27,11 +30,13 @@ impl Default for SSERegisters {
 extern "C" {
 	static InitialPML4: [u64; 512];
 	static s_tid0_tls_base: u64;
-	fn task_switch(oldrsp: &mut u64, newrsp: &u64, tlsbase: u64, cr3: u64);
+	fn task_switch(oldrsp: &mut u64, newrsp: &u64, tlsbase: u64, cr3: u64, old_running: &AtomicBool, tss_rsp0: *mut u64);
 }
 
 pub static S_IRQS_ENABLED: ::core::sync::atomic::AtomicBool = ::core::sync::atomic::ATOMIC_BOOL_INIT;
-static mut S_IDLE_THREAD: *mut ::threads::Thread = 0 as *mut _;
+/// Per-CPU idle threads
+// NOTE: Each entry is only written before the relevant CPU starts
+static mut S_IDLE_THREADS: [*mut ::threads::Thread; MAX_CPUS] = [0 as *mut _; MAX_CPUS];
 
 #[repr(C)]
 /// Thread-local-storage block
@@ -44,6 +49,8 @@ struct TLSData {
 	stack_top: *const (),
 	// MUST be third (same as above)
 	user_stack: u64,
+	// MUST be fourth (loaded into FS base by drop_to_user)
+	user_tls_base: u64,
 	
 	// Free to reorder these
 	thread_ptr: *mut ::threads::Thread,
@@ -58,7 +65,7 @@ pub fn init_tid0_state() -> State
 {
 	// SAFE: Called in single-threaded context... hopefully (TODO)
 	unsafe {
-		S_IDLE_THREAD = ::core::mem::transmute( ::threads::new_idle_thread(0) );
+		S_IDLE_THREADS[0] = ::core::mem::transmute( ::threads::new_idle_thread(0) );
 	}
 	// SAFE: Just taking the address
 	let cr3 = unsafe { &InitialPML4 as *const _ as u64 - super::memory::addresses::IDENT_START as u64 };
@@ -68,10 +75,34 @@ pub fn init_tid0_state() -> State
 		rsp: 0,
 		// SAFE: Doesn't change outside rust control
 		tlsbase: unsafe { s_tid0_tls_base },
+		// TID0 is the currently executing thread
+		running: AtomicBool::new(true),
 		stack_handle: None,
 		}
 }
 
+/// Prepare the idle thread for an AP, returning the TLS base (and initial stack pointer) for it
+///
+/// The AP starts executing on this thread's stack (see `smp::ap_entry`)
+pub fn prep_ap_idle(cpu: usize) -> usize
+{
+	assert!(cpu > 0 && cpu < MAX_CPUS);
+	let mut thread = ::threads::new_ap_idle_thread(cpu);
+	let stack = ::memory::virt::alloc_stack().into_array::<u8>();
+	let stack_top = &stack[stack.len()-1] as *const _ as usize + 1;
+	let stack_bottom = &stack[0] as *const _ as usize;
+	// SAFE: Stack is valid, and the thread pointer is owned by the TLS block from here on
+	let tls_base = unsafe { prep_tls(stack_top, stack_bottom, &mut *thread as *mut _) };
+	thread.cpu_state.tlsbase = tls_base as u64;
+	thread.cpu_state.running.store(true, Ordering::Relaxed);
+	thread.cpu_state.stack_handle = Some(stack);
+	// SAFE: Written before the AP is started (and never again)
+	unsafe {
+		S_IDLE_THREADS[cpu] = thread.unwrap();
+	}
+	tls_base
+}
+
 impl State
 {
 	/// Construct a new empty CPU state using the provided address space
@@ -81,6 +112,11 @@ impl State
 		rv.cr3 = address_space.get_cr3();
 		rv
 	}
+
+	/// Returns true if a CPU is currently executing (or has yet to finish switching away from) this thread
+	pub fn is_running(&self) -> bool {
+		self.running.load(Ordering::Acquire)
+	}
 }
 
 /// Idle for a short period, called when the CPU has nothing else to do
@@ -112,6 +148,7 @@ pub unsafe extern "C" fn prep_tls(top: usize, _bottom: usize, thread_ptr: *mut :
 		self_ptr: data_ptr,
 		stack_top: tlsblock as *const (),
 		user_stack: 0,
+		user_tls_base: 0,
 		
 		thread_ptr: thread_ptr,
 		thread_ptr_lent: false,
@@ -186,16 +223,34 @@ pub fn start_thread<F: FnOnce()+Send>(thread: &mut ::threads::Thread, code: F)
 	}
 }
 
+/// Obtain the idle thread for the current CPU
 pub fn get_idle_thread() -> ::threads::ThreadPtr
 {
+	let cpu = cpu_num();
 	// TODO: Shared mutability shouldn't be an issue (this thread pointer should not be created twice)
 	// SAFE: Passes a static pointer. `static mut` should be initialised
 	unsafe {
-		assert!(S_IDLE_THREAD != 0 as *mut _);
-		::threads::ThreadPtr::new_static( &mut *S_IDLE_THREAD )
+		assert!(S_IDLE_THREADS[cpu] != 0 as *mut _);
+		::threads::ThreadPtr::new_static( &mut *S_IDLE_THREADS[cpu] )
 	}
 }
 
+/// Returns the index of the current CPU
+#[inline]
+pub fn cpu_num() -> usize {
+	super::smp::cpu_num()
+}
+/// Returns the number of online CPUs
+#[inline]
+pub fn cpu_count() -> usize {
+	super::smp::cpu_count()
+}
+/// Wake a CPU that may be idle (so it can look for work)
+#[inline]
+pub fn wake_cpu(cpu: usize) {
+	super::smp::wake_cpu(cpu)
+}
+
 /// Switch to the passed thread (suspending the current thread until it is rescheduled)
 pub fn switch_to(newthread: ::threads::ThreadPtr)
 {
@@ -213,8 +268,22 @@ pub fn switch_to(newthread: ::threads::ThreadPtr)
 			assert!(flags & 0x200 != 0, "switch_to() with IF clear, RFLAGS = {:#x}", flags);
 		}
 
+		// Switching to the current thread is a no-op
+		if &*newthread as *const _ == borrow_thread() {
+			// SAFE: Valid pointer access
+			unsafe { (*get_tls_ptr()).thread_ptr_lent = false; }
+			::core::mem::forget(newthread);
+			return ;
+		}
+
 		disable_sse();
 		
+		// Wait for the previous CPU to finish switching away from this thread
+		while newthread.cpu_state.running.compare_and_swap(false, true, Ordering::Acquire) {
+			// SAFE: Spin-loop hint
+			unsafe { asm!("pause" : : : "memory" : "volatile"); }
+		}
+		
 		// SAFE: Valid pointer accesses, task_switch trusted
 		unsafe
 		{
@@ -228,7 +297,7 @@ pub fn switch_to(newthread: ::threads::ThreadPtr)
 			
 			assert!( *(outstate.tlsbase as *const usize) != 0, "outstate TLS Base clobbered before switch" );
 			assert!( *(state.tlsbase as *const usize) != 0, "TLS Base clobbered before switch" );
-			task_switch(&mut outstate.rsp, &state.rsp, state.tlsbase, state.cr3);
+			task_switch(&mut outstate.rsp, &state.rsp, state.tlsbase, state.cr3, &outstate.running, super::tss::rsp0_ptr(cpu_num()));
 		}
 		
 		// SAFE: Valid pointer access
@@ -236,10 +305,26 @@ pub fn switch_to(newthread: ::threads::ThreadPtr)
 		{
 			(*get_tls_ptr()).thread_ptr_lent = false;
 			::core::mem::forget(newthread);
+			// Restore this thread's userland TLS base (FS isn't used by the kernel)
+			write_fs_base( (*get_tls_ptr()).user_tls_base );
 		}
 	}
 }
 
+/// Set the current thread's userland TLS base (FS base)
+pub fn set_user_tls_base(base: usize)
+{
+	// SAFE: Valid pointer access, and FS isn't used by the kernel
+	unsafe {
+		(*get_tls_ptr()).user_tls_base = base as u64;
+		write_fs_base(base as u64);
+	}
+}
+unsafe fn write_fs_base(base: u64)
+{
+	asm!("wrmsr" : : "{ecx}" (0xC0000100u32), "{eax}" (base as u32), "{edx}" ((base >> 32) as u32) : : "volatile");
+}
+
 fn get_tls_ptr() -> *mut TLSData {
 	let ret;
 	// SAFE: Just obtains the pointer from %gs
@@ -7,8 +7,7 @@
 // Just a run-of-the-mill module, as it's not needed until the switch to usermode
 module_define!(TSS, [], init);
 
-// NOTE: MUST match the value in common.inc.asm
-const MAX_CPUS: usize = 1;
+use super::smp::MAX_CPUS;
 
 #[repr(C,packed)]
 struct TSS
@@ -28,7 +27,7 @@ struct GDTEnt(u32,u32);
 
 extern "C" {
 	static mut GDT: [GDTEnt; 7+MAX_CPUS*2];
-	static mut TSSes: [TSS; 4];
+	static mut TSSes: [TSS; MAX_CPUS];
 	
 	static s_tid0_tls_base: u64;
 }
@@ -51,6 +50,26 @@ fn init()
 	}
 }
 
+/// Load the task register on an AP
+pub fn init_ap(cpu: usize)
+{
+	assert!(cpu < MAX_CPUS);
+	// SAFE: Just setting the task register (GDT entries populated by `init`)
+	unsafe {
+		asm!("ltr %cx" : : "{ecx}" (7*8 + cpu*16) );
+	}
+}
+
+/// Obtain a pointer to the kernel stack pointer (RSP0) field of a CPU's TSS
+///
+/// Updated by `task_switch` to the top of the incoming thread's stack
+pub fn rsp0_ptr(cpu: usize) -> *mut u64
+{
+	assert!(cpu < MAX_CPUS);
+	// SAFE: Only taking the address
+	unsafe { &mut TSSes[cpu].rsp0 as *mut u64 }
+}
+
 
 impl GDTEnt
 {
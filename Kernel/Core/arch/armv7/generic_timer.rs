@@ -0,0 +1,83 @@
+//
+//
+//
+//! ARM Generic Timer (CP15 physical timer)
+//!
+//! The timer raises PPI 30, which `interrupts` unmasks on the GIC when it's bound
+use sync::Spinlock;
+
+/// Interrupt ID of the non-secure physical timer
+const TIMER_PPI: usize = 30;
+/// Maximum time between timer interrupts (in ms), even if no timer is pending
+const MAX_TICK_INTERVAL: u64 = 100;
+
+/// Compare value currently programmed
+static S_NEXT_FIRE: Spinlock<u64> = Spinlock::new(!0);
+
+pub fn init()
+{
+	if frequency() == 0 {
+		log_error!("Generic timer frequency not set, no timing avaliable");
+		return ;
+	}
+	match super::interrupts::bind_gsi(TIMER_PPI, irq, 0 as *const ())
+	{
+	Ok(_) => {},
+	Err(_) => log_error!("Unable to bind generic timer interrupt"),
+	}
+	schedule_next(None);
+}
+
+/// Counter frequency (Hz), as set by the firmware
+pub fn frequency() -> u64 {
+	let v: u32;
+	// SAFE: Read-only register access
+	unsafe { asm!("mrc p15, 0, $0, c14, c0, 0" : "=r" (v) : : : "volatile"); }
+	v as u64
+}
+/// Current physical count
+pub fn count() -> u64 {
+	let lo: u32;
+	let hi: u32;
+	// SAFE: Read-only register access
+	unsafe { asm!("isb; mrrc p15, 0, $0, $1, c14" : "=r" (lo), "=r" (hi) : : : "volatile"); }
+	(hi as u64) << 32 | lo as u64
+}
+
+/// Ensure that the timer interrupt fires at (or before) the provided count
+pub fn schedule(value: u64)
+{
+	let _irq = ::sync::hold_interrupts();
+	let mut lh = S_NEXT_FIRE.lock();
+	if value < *lh {
+		*lh = value;
+		let (lo, hi) = (value as u32, (value >> 32) as u32);
+		// SAFE: Programs the compare value and enables the timer (interrupt unmasked)
+		unsafe {
+			asm!("mcrr p15, 2, $0, $1, c14" : : "r" (lo), "r" (hi) : : "volatile");
+			asm!("mcr p15, 0, $0, c14, c2, 1" : : "r" (1u32) : : "volatile");
+		}
+	}
+}
+
+fn schedule_next(next_timer_ms: Option<u64>)
+{
+	let per_ms = frequency() / 1000;
+	let max = count() + MAX_TICK_INTERVAL * per_ms;
+	let next = match next_timer_ms
+		{
+		Some(ms) => ::core::cmp::min(ms * per_ms, max),
+		None => max,
+		};
+	schedule(next);
+}
+
+fn irq(_: *const ())
+{
+	// Mask the timer until it's re-programmed
+	// SAFE: Register access
+	unsafe { asm!("mcr p15, 0, $0, c14, c2, 1" : : "r" (2u32) : : "volatile"); }
+	let next = ::time::time_tick();
+	*S_NEXT_FIRE.lock() = !0;
+	schedule_next(next);
+}
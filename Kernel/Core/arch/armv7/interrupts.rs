@@ -1,6 +1,7 @@
 //
 //
 //
+//! ARM Generic Interrupt Controller (GICv2) support
 use lib::Vec;
 use sync::Spinlock;
 use lib::LazyStatic;
@@ -18,23 +19,115 @@ struct Binding {
 }
 unsafe impl Send for Binding {}
 
+/// Memory-mapped GIC register blocks
+struct Gic
+{
+	dist: ::memory::virt::AllocHandle,
+	cpu: ::memory::virt::AllocHandle,
+}
+unsafe impl Sync for Gic {}
+
+// Distributor registers (word offsets)
+const GICD_CTLR: usize = 0x000 / 4;
+const GICD_TYPER: usize = 0x004 / 4;
+const GICD_ISENABLER: usize = 0x100 / 4;
+const GICD_ICENABLER: usize = 0x180 / 4;
+const GICD_IPRIORITYR: usize = 0x400 / 4;
+const GICD_ITARGETSR: usize = 0x800 / 4;
+// CPU interface registers (word offsets)
+const GICC_CTLR: usize = 0x00 / 4;
+const GICC_PMR: usize = 0x04 / 4;
+const GICC_IAR: usize = 0x0C / 4;
+const GICC_EOIR: usize = 0x10 / 4;
+
+/// Interrupt ID returned by GICC_IAR when there is no pending interrupt
+const SPURIOUS_IRQ: u32 = 1023;
+
+/// Default GIC location (QEMU's `virt` machine), used if the FDT doesn't list one
+const DEFAULT_GICD_BASE: u64 = 0x0800_0000;
+const DEFAULT_GICC_BASE: u64 = 0x0801_0000;
+
+static S_GIC: LazyStatic<Gic> = lazystatic_init!();
 static S_IRQS: LazyStatic<Vec< Spinlock<Option<Binding>> >> = lazystatic_init!();
 
 pub fn init() {
-	// TODO: Interrogate the FDT to discover the IRQ controller layout
-	//::arch::int::boot::get_fdt().get_props(&["","intc"])
+	let (dist_base, cpu_base) = find_gic().unwrap_or( (DEFAULT_GICD_BASE, DEFAULT_GICC_BASE) );
+	log_debug!("GIC: Distributor {:#x}, CPU interface {:#x}", dist_base, cpu_base);
+
+	// SAFE: These addresses are only used by this module
+	let gic = unsafe {
+		Gic {
+			dist: ::memory::virt::map_hw_rw(dist_base as ::memory::PAddr, 1, "GIC").expect("Unable to map GIC distributor"),
+			cpu: ::memory::virt::map_hw_rw(cpu_base as ::memory::PAddr, 1, "GIC").expect("Unable to map GIC CPU interface"),
+		}
+		};
+	// - TYPER.ITLinesNumber gives the number of interrupt lines (in blocks of 32)
+	let n_irqs = ::core::cmp::min( 32 * (1 + (gic.dist_read(GICD_TYPER) & 0x1F) as usize), SPURIOUS_IRQ as usize );
+
+	// Start with every interrupt disabled, then enable the distributor and the CPU interface (accepting all priorities)
+	for i in 0 .. n_irqs / 32 {
+		gic.dist_write(GICD_ICENABLER + i, !0);
+	}
+	gic.dist_write(GICD_CTLR, 1);
+	gic.cpu_write(GICC_PMR, 0xFF);
+	gic.cpu_write(GICC_CTLR, 1);
 
 	// SAFE: Called in a single-threaded context
 	unsafe {
-		S_IRQS.prep(|| Vec::from_fn(32, |_| Default::default()));
+		S_GIC.prep(|| gic);
+		S_IRQS.prep(|| Vec::from_fn(n_irqs, |_| Default::default()));
+	}
+}
+
+/// Locate the GIC's register blocks in the FDT
+fn find_gic() -> Option<(u64, u64)>
+{
+	use lib::byteorder::{ByteOrder,BigEndian};
+	let fdt = match super::boot::get_fdt()
+		{
+		Some(v) => v,
+		None => return None,
+		};
+	let root_node = fdt.get_nodes(&[]).next().unwrap();
+	let read_cells = |v: Option<&[u8]>| v.map(|v| BigEndian::read_u32(v)).unwrap_or(1) as usize;
+	let acells = read_cells(root_node.get_prop("#address-cells"));
+	let scells = read_cells(root_node.get_prop("#size-cells"));
+	let read_addr = |reg: &[u8], idx: usize| -> u64 {
+		let ofs = idx * (acells + scells) * 4;
+		match acells
+		{
+		1 => BigEndian::read_u32(&reg[ofs..]) as u64,
+		_ => BigEndian::read_u64(&reg[ofs..]),
+		}
+		};
+
+	for dev in fdt.get_nodes(&[""])
+	{
+		let is_gic = match dev.get_prop("compatible")
+			{
+			Some(c) => c.split(|&b| b == 0).any(|c| c.ends_with(&b"-gic"[..]) || c == &b"arm,gic-400"[..]),
+			None => false,
+			};
+		if is_gic {
+			if let Some(reg) = dev.get_prop("reg") {
+				if reg.len() >= 2 * (acells + scells) * 4 {
+					return Some( (read_addr(reg, 0), read_addr(reg, 1)) );
+				}
+			}
+		}
 	}
+	None
 }
 
 #[linkage="external"]
 #[no_mangle]
 pub extern "C" fn interrupt_handler()
 {
-	let irq = get_active_interrupt();
+	let iar = S_GIC.cpu_read(GICC_IAR);
+	let irq = iar & 0x3FF;
+	if irq == SPURIOUS_IRQ {
+		return ;
+	}
 	if irq as usize >= S_IRQS.len() {
 		// ... No idea!
 	}
@@ -49,15 +142,33 @@ pub extern "C" fn interrupt_handler()
 			{
 			None => {},
 			Some(ref v) => (v.handler)( v.info ),
-					// TODO: Acknowledge the IRQ on the GIC?
 			},
 		}
 	}
+	S_GIC.cpu_write(GICC_EOIR, iar);
 }
 
-fn get_active_interrupt() -> u32
+#[no_mangle]
+#[doc(hidden)]
+/// Called by assembly before an IRQ returns to userland, preempts the current thread if requested
+///
+/// Also terminates the thread if its process has been killed
+pub extern "C" fn irq_return_user()
 {
-	todo!("get_active_interrupt - Query the active interrupt from the GIC");
+	let preempt = ::threads::take_preempt_request();
+	if preempt || ::threads::is_killed()
+	{
+		// The interrupted userland state is saved on this thread's kernel stack, so interrupts can be
+		// re-enabled for the task switch
+		// SAFE: Interrupts are disabled again before returning to the assembly
+		unsafe { ::arch::sync::start_interrupts(); }
+		::threads::check_kill();
+		if preempt {
+			::threads::yield_time();
+		}
+		// SAFE: Restores the state expected by the IRQ return path
+		unsafe { ::arch::sync::stop_interrupts(); }
+	}
 }
 
 pub fn bind_gsi(gsi: usize, handler: fn(*const()), info: *const ()) -> Result<IRQHandle,()> {
@@ -71,13 +182,50 @@ pub fn bind_gsi(gsi: usize, handler: fn(*const()), info: *const ()) -> Result<IR
 			Err( () )
 		}
 		else {
-			// TODO: Enable this interrupt on the GIC?
 			*lh = Some(Binding {
 				handler: handler,
 				info: info,
 				});
+			S_GIC.enable(gsi);
 			Ok( IRQHandle(gsi as u32) )
 		}
 	}
 }
 
+impl Gic
+{
+	/// Route the interrupt to this CPU (at the default priority) and unmask it
+	fn enable(&self, irq: usize)
+	{
+		let shift = (irq % 4) * 8;
+		let p = self.dist_read(GICD_IPRIORITYR + irq / 4);
+		self.dist_write(GICD_IPRIORITYR + irq / 4, (p & !(0xFF << shift)) | (0xA0 << shift));
+		// - Targets are read-only for SGIs and PPIs (which are always local)
+		if irq >= 32 {
+			let t = self.dist_read(GICD_ITARGETSR + irq / 4);
+			self.dist_write(GICD_ITARGETSR + irq / 4, (t & !(0xFF << shift)) | (0x01 << shift));
+		}
+		self.dist_write(GICD_ISENABLER + irq / 32, 1 << (irq % 32));
+	}
+
+	fn dist_read(&self, reg: usize) -> u32 {
+		// SAFE: Hardware access, register index within the mapped page
+		unsafe { ::core::intrinsics::volatile_load( &Self::regs(&self.dist)[reg] ) }
+	}
+	fn dist_write(&self, reg: usize, val: u32) {
+		// SAFE: Hardware access, register index within the mapped page
+		unsafe { ::core::intrinsics::volatile_store( &mut Self::regs(&self.dist)[reg], val ) }
+	}
+	fn cpu_read(&self, reg: usize) -> u32 {
+		// SAFE: Hardware access, register index within the mapped page
+		unsafe { ::core::intrinsics::volatile_load( &Self::regs(&self.cpu)[reg] ) }
+	}
+	fn cpu_write(&self, reg: usize, val: u32) {
+		// SAFE: Hardware access, register index within the mapped page
+		unsafe { ::core::intrinsics::volatile_store( &mut Self::regs(&self.cpu)[reg], val ) }
+	}
+	fn regs(h: &::memory::virt::AllocHandle) -> &mut [u32] {
+		// SAFE: Register block is only accessed with volatile operations
+		unsafe { h.as_int_mut_slice(0, ::PAGE_SIZE / 4) }
+	}
+}
@@ -97,6 +97,7 @@ fn prot_mode_to_flags(mode: ProtectionMode) -> u32 {
 	ProtectionMode::UserRX => 0x232,	// 1,11,0
 	ProtectionMode::UserRWX => 0x032,	// 0,11,0
 	ProtectionMode::UserCOW => 0x223,	// 1,10,1 is a deprecated encoding for ReadOnly, need to find a better encoding
+	ProtectionMode::UserCOWX => 0x222,	// 1,10,0 (as above, but executable)
 	}
 }
 fn flags_to_prot_mode(flags: u32) -> ProtectionMode {
@@ -111,6 +112,7 @@ fn flags_to_prot_mode(flags: u32) -> ProtectionMode {
 	0x232 => ProtectionMode::UserRX,
 	0x032 => ProtectionMode::UserRWX,
 	0x223 => ProtectionMode::UserCOW,
+	0x222 => ProtectionMode::UserCOWX,
 	v @ _ => todo!("Unknown mode value {:#x}", v),
 	}
 }
@@ -623,7 +625,10 @@ impl AddressSpace
 		}
 		AddressSpace( get_phys(&kernel_table0) )
 	}
-	pub fn new(clone_start: usize, clone_end: usize) -> Result<AddressSpace,::memory::virt::MapError> {
+	/// Create a new address space, cloning the user pages in `clone_start .. clone_end`
+	///
+	/// Also returns the number of user pages cloned (for the new process's page quota)
+	pub fn new(clone_start: usize, clone_end: usize) -> Result<(AddressSpace,usize),::memory::virt::MapError> {
 		assert!( clone_start % ::PAGE_SIZE == 0 );
 		assert!( clone_end % ::PAGE_SIZE == 0 );
 		// 1. Allocate a new root-level table for the user code
@@ -644,6 +649,7 @@ impl AddressSpace
 		let start_pidx = clone_start / ::PAGE_SIZE;
 		let end_pidx = clone_end / ::PAGE_SIZE;
 		
+		let mut page_count = 0;
 		for page in start_pidx .. end_pidx
 		{
 			let ofs = page*2 - 2040*256;
@@ -654,8 +660,32 @@ impl AddressSpace
 			match flags_to_prot_mode(mode_flags)
 			{
 			ProtectionMode::Unmapped => {},
-			ProtectionMode::UserCOW => todo!("Clone when COW"),
-			ProtectionMode::UserRW | ProtectionMode::UserRO | ProtectionMode::UserRX => {
+			// Read-only and copy-on-write pages share the frame
+			ProtectionMode::UserCOW | ProtectionMode::UserCOWX | ProtectionMode::UserRO | ProtectionMode::UserRX => {
+				let paddr = (src_slot_0_val & !PAGE_MASK_U32) as ::arch::memory::PAddr;
+				::memory::phys::ref_frame(paddr);
+				dst_slots[0] = src_slot_0_val;
+				dst_slots[1] = user_last_table()[ofs+1].load(Ordering::Relaxed);
+				page_count += 1;
+				},
+			// Private writable pages are shared copy-on-write (in both the source and the new address space)
+			// - Frames with other references (e.g. write-back file mappings) are still copied, so the sharing isn't broken
+			mode @ ProtectionMode::UserRW | mode @ ProtectionMode::UserRWX
+				if ::arch::memory::phys::get_multiref_count( (src_slot_0_val & !PAGE_MASK_U32) as u64 / ::PAGE_SIZE as u64 ) == 0 =>
+			{
+				let paddr = (src_slot_0_val & !PAGE_MASK_U32) as ::arch::memory::PAddr;
+				let flags = prot_mode_to_flags( if mode == ProtectionMode::UserRWX { ProtectionMode::UserCOWX } else { ProtectionMode::UserCOW } );
+				// Frame is unchanged, just made read-only (a write will fault and get a private copy)
+				user_last_table()[ofs+0].store( (paddr + 0x0000) | flags, Ordering::SeqCst );
+				user_last_table()[ofs+1].store( (paddr + 0x1000) | flags, Ordering::SeqCst );
+				tlbimva( (page * ::PAGE_SIZE) as *mut () );
+				tlbimva( (page * ::PAGE_SIZE + 0x1000) as *mut () );
+				::memory::phys::ref_frame(paddr);
+				dst_slots[0] = (paddr + 0x0000) | flags;
+				dst_slots[1] = (paddr + 0x1000) | flags;
+				page_count += 1;
+				},
+			ProtectionMode::UserRW | ProtectionMode::UserRWX => {
 				let src_ptr = (page * ::PAGE_SIZE) as *const u8;
 				// SAFE: Memory is valid (TODO: What if this changes? Shouldn't cause errors, just inconsistent user data)
 				let src = unsafe { ::core::slice::from_raw_parts(src_ptr, ::PAGE_SIZE) };
@@ -664,6 +694,7 @@ impl AddressSpace
 				dst_slots[0] = (data.phys_addr() as u32 + 0x0000) | mode_flags;
 				dst_slots[1] = (data.phys_addr() as u32 + 0x1000) | mode_flags;
 				log_trace!("- Clone @{:p} = {:#x}", src_ptr, data.phys_addr());
+				page_count += 1;
 				},
 			mode @ _ => {
 				log_warning!("TODO: Other protection modes: {:?}", mode)
@@ -671,7 +702,7 @@ impl AddressSpace
 			}
 		}
 
-		Ok( AddressSpace( new_root.phys_addr() ) )
+		Ok( (AddressSpace( new_root.phys_addr() ), page_count) )
 	}
 
 	pub fn get_ttbr0(&self) -> u32 { self.0 }
@@ -700,23 +731,35 @@ pub fn data_abort_handler(pc: u32, reg_state: &AbortRegs, dfar: u32, dfsr: u32)
 	//log_debug!("R 0 {:08x}  R 1 {:08x}  R 2 {:08x}  R 3 {:08x}  R 4 {:08x}  R 5 {:08x}}  R 6 {:08x}", reg_state.gprs[0]);
 	
 	let mut ent = PageEntry::get(dfar as usize as *const ());
-	if ent.mode() == ProtectionMode::UserCOW {
+	let mode = ent.mode();
+	if mode == ProtectionMode::UserCOW || mode == ProtectionMode::UserCOWX {
+		// - Executable COW pages (e.g. untouched RWX zero pages) stay executable
+		let new_mode = if mode == ProtectionMode::UserCOWX { ProtectionMode::UserRWX } else { ProtectionMode::UserRW };
 		// 1. Lock (relevant) address space
 		// SAFE: Changes to address space are transparent
+		let mut rv = Ok( () );
 		::memory::virt::with_lock(dfar as usize, || unsafe {
 			let frame = ent.phys_addr();
 			// 2. Get the PMM to provide us with a unique copy of that frame (can return the same addr)
-			let newframe = ::memory::phys::make_unique( frame, &*(((dfar as usize) & !PAGE_MASK) as *const [u8; PAGE_SIZE]) );
-			// 3. Remap to this page as UserRW (because COW is user-only atm)
-			ent.set(newframe, ProtectionMode::UserRW);
-			log_debug!("- COW frame copied");
+			match ::memory::phys::make_unique( frame, &*(((dfar as usize) & !PAGE_MASK) as *const [u8; PAGE_SIZE]) )
+			{
+			Ok(newframe) => {
+				// 3. Remap to this page as writable (because COW is user-only atm)
+				ent.set(newframe, new_mode);
+				log_debug!("- COW frame copied");
+				},
+			Err(e) => rv = Err(e),
+			}
 			});
+		if let Err(e) = rv {
+			::memory::virt::handle_fault_oom(dfar as usize, e, pc < 0x8000_0000);
+		}
 
 		return ;
 	}
 	
 	if pc < 0x8000_0000 {
-		loop {}
+		user_fault(::threads::FaultKind::Page, dfsr, dfar as usize, pc, reg_state);
 	}
 	else {
 		let rs = ::arch::imp::aeabi_unwind::UnwindState::from_regs([
@@ -729,6 +772,20 @@ pub fn data_abort_handler(pc: u32, reg_state: &AbortRegs, dfar: u32, dfsr: u32)
 		panic!("Kernel data abort by {:#x} at {:#x}", pc, dfar);
 	}
 }
+/// Report a fault from user mode to the parent process, and terminate the process
+fn user_fault(kind: ::threads::FaultKind, code: u32, address: usize, pc: u32, reg_state: &AbortRegs) -> ! {
+	let g = &reg_state.gprs;
+	let regs = [
+		g[ 0] as usize, g[1] as usize, g[ 2] as usize, g[ 3] as usize,
+		g[ 4] as usize, g[5] as usize, g[ 6] as usize, g[ 7] as usize,
+		g[ 8] as usize, g[9] as usize, g[10] as usize, g[11] as usize,
+		g[12] as usize, reg_state.sp as usize, reg_state.lr as usize, pc as usize,
+		];
+	let mut info = ::threads::FaultInfo::new(kind, code, address, pc as usize, reg_state.sp as usize, regs);
+	// R11 is the frame pointer
+	info.walk_frame_pointers(g[11] as usize);
+	::threads::fault_process(info);
+}
 fn fsr_name(ifsr: u32) -> &'static str {
 	match ifsr & 0x40F
 	{
@@ -759,6 +816,9 @@ pub fn prefetch_abort_handler(pc: u32, reg_state: &AbortRegs, ifsr: u32) {
 		let mode = ent.mode();
 		log_debug!("- {:#x} {:?}", ent.phys_addr(), mode);
 	}
+	if pc < 0x8000_0000 {
+		user_fault(::threads::FaultKind::Page, ifsr, pc as usize, pc, reg_state);
+	}
 	//log_debug!("Registers:");
 	//log_debug!("R 0 {:08x}  R 1 {:08x}  R 2 {:08x}  R 3 {:08x}  R 4 {:08x}  R 5 {:08x}}  R 6 {:08x}", reg_state.gprs[0]);
 	
@@ -774,6 +834,9 @@ pub fn prefetch_abort_handler(pc: u32, reg_state: &AbortRegs, ifsr: u32) {
 #[no_mangle]
 pub fn ud_abort_handler(pc: u32, reg_state: &AbortRegs) {
 	log_warning!("Undefined instruction abort at {:#x} - LR={:#x}", pc, reg_state.lr);
+	if pc < 0x8000_0000 {
+		user_fault(::threads::FaultKind::InvalidOpcode, 0, 0, pc, reg_state);
+	}
 		
 	let rs = ::arch::imp::aeabi_unwind::UnwindState::from_regs([
 		reg_state.gprs[ 0], reg_state.gprs[1], reg_state.gprs[ 2], reg_state.gprs[ 3],
@@ -20,6 +20,7 @@ mod fdt;
 mod fdt_devices;
 
 mod aeabi_unwind;
+mod generic_timer;
 
 #[inline(always)]
 pub fn checkmark() {
@@ -40,6 +41,7 @@ extern "C" {
 fn init()
 {
 	interrupts::init();
+	generic_timer::init();
 }
 
 #[no_mangle]
@@ -106,8 +108,24 @@ pub fn puth(v: u64) {
 	}
 }
 
+/// `cur_timestamp` counts in milliseconds (the generic timer count is scaled by its frequency)
+pub const TIMESTAMP_PER_MS: u64 = 1;
+/// Return the system timestamp (miliseconds since an arbitary point), using the generic timer's physical count
 pub fn cur_timestamp() -> u64 {
-	0
+	let freq = generic_timer::frequency();
+	if freq == 0 {
+		0
+	}
+	else {
+		generic_timer::count() / (freq / 1000)
+	}
+}
+/// Request a timer tick at the specified timestamp
+pub fn request_tick(target: u64) {
+	let freq = generic_timer::frequency();
+	if freq != 0 {
+		generic_timer::schedule(target * (freq / 1000));
+	}
 }
 
 pub fn print_backtrace() {
@@ -80,16 +80,29 @@ impl<'a, T: 'a> ::core::ops::Drop for HeldSpinlock<'a, T> {
 }
 
 
-pub struct HeldInterrupts;
+/// Handle for masked IRQs, restores the previous mask state when dropped
+pub struct HeldInterrupts(bool);
 pub fn hold_interrupts() -> HeldInterrupts {
-	HeldInterrupts
+	let cpsr: u32;
+	// SAFE: Reads CPSR and masks IRQs
+	unsafe { asm!("mrs $0, cpsr; cpsid i" : "=r" (cpsr) : : "memory" : "volatile"); }
+	// - CPSR.I set means IRQs were already masked
+	HeldInterrupts(cpsr & (1 << 7) == 0)
 }
 impl ::core::ops::Drop for HeldInterrupts {
 	fn drop(&mut self) {
+		if self.0 {
+			// SAFE: IRQs were enabled when this handle was created
+			unsafe { asm!("cpsie i" : : : "memory" : "volatile"); }
+		}
 	}
 }
 pub fn stop_interrupts() {
+	// SAFE: Masks IRQs
+	unsafe { asm!("cpsid i" : : : "memory" : "volatile"); }
 }
 pub fn start_interrupts() {
+	// SAFE: Unmasks IRQs
+	unsafe { asm!("cpsie i" : : : "memory" : "volatile"); }
 }
 
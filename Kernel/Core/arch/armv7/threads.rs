@@ -4,6 +4,8 @@
 pub struct State {
 	sp: usize,
 	ttbr0: u32,
+	/// Userland TLS base (TPIDRURO)
+	user_tls_base: usize,
 	stack_handle: Option< ::memory::virt::ArrayHandle<u8> >,
 }
 
@@ -13,11 +15,19 @@ impl State
 		State {
 			sp: 0,
 			ttbr0: address_space.get_ttbr0(),
+			user_tls_base: 0,
 			stack_handle: None,
 		}
 	}
+	/// Uniprocessor only: a thread is never running on another CPU
+	pub fn is_running(&self) -> bool {
+		false
+	}
 }
 
+/// Only a single CPU is supported
+pub const MAX_CPUS: usize = 1;
+
 pub fn init_tid0_state() -> State {
 	extern "C" {
 		static kernel_table0: ::Void;
@@ -82,13 +92,36 @@ pub fn switch_to(thread: ::threads::ThreadPtr) {
 		let new_ttbr0 = thread.cpu_state.ttbr0;
 		log_trace!("Switching to SP={:#x},TTBR0={:#x}", new_sp, new_ttbr0);
 		task_switch(&mut outstate.sp, new_sp, new_ttbr0, thread.into_usize());
+		// Restore this thread's userland TLS base
+		write_tpidruro( (*borrow_thread()).cpu_state.user_tls_base );
+	}
+}
+/// Set the current thread's userland TLS base (TPIDRURO)
+pub fn set_user_tls_base(base: usize) {
+	// SAFE: Current thread pointer is valid, and the register is only read by userland
+	unsafe {
+		(*borrow_thread_mut()).cpu_state.user_tls_base = base;
+		write_tpidruro(base);
 	}
 }
+unsafe fn write_tpidruro(v: usize) {
+	asm!("mcr p15,0, $0, c13,c0,3" : : "r" (v));
+}
+pub fn cpu_num() -> usize {
+	0
+}
+pub fn cpu_count() -> usize {
+	1
+}
+pub fn wake_cpu(_cpu: usize) {
+}
+
 pub fn idle() {
 	log_trace!("idle");
 	// SAFE: Calls 'wait for interrupt'
+	// - A pending IRQ wakes WFI even while masked, and is taken once IRQs are re-enabled
 	unsafe {
-		asm!("wfi" : : : : "volatile");
+		asm!("wfi; cpsie i" : : : "memory" : "volatile");
 	}
 }
 
@@ -257,6 +257,9 @@ pub mod threads {
 
 	pub type State = imp::State;
 
+	/// Maximum number of CPUs supported by the architecture
+	pub const MAX_CPUS: usize = imp::MAX_CPUS;
+
 	#[inline]
 	pub fn init_tid0_state() -> State {
 		imp::init_tid0_state()
@@ -292,6 +295,27 @@ pub mod threads {
 	pub fn start_thread<F: FnOnce()+Send+'static>(thread: &mut ::threads::Thread, code: F) {
 		imp::start_thread(thread, code)
 	}
+	/// Set the userland thread-local storage base for the current thread (FS base on amd64, TPIDRURO on ARM)
+	#[inline]
+	pub fn set_user_tls_base(base: usize) {
+		imp::set_user_tls_base(base)
+	}
+
+	/// Index of the current CPU (zero for the boot CPU)
+	#[inline]
+	pub fn cpu_num() -> usize {
+		imp::cpu_num()
+	}
+	/// Number of CPUs online
+	#[inline]
+	pub fn cpu_count() -> usize {
+		imp::cpu_count()
+	}
+	/// Wake another CPU from `idle` (e.g. when a thread is made runnable)
+	#[inline]
+	pub fn wake_cpu(cpu: usize) {
+		imp::wake_cpu(cpu)
+	}
 }
 
 /// x86 IO bus accesses
@@ -322,10 +346,17 @@ pub fn puth(v: u64) {
 	imp::puth(v)
 }
 
+/// Number of `cur_timestamp` units in a millisecond
+pub const TIMESTAMP_PER_MS: u64 = imp::TIMESTAMP_PER_MS;
 #[inline]
 pub fn cur_timestamp() -> u64 {
 	imp::cur_timestamp()
 }
+/// Request that `time::time_tick` be called once the timestamp reaches `target` (or earlier)
+#[inline]
+pub fn request_tick(target: u64) {
+	imp::request_tick(target)
+}
 #[inline]
 pub fn print_backtrace() {
 	imp::print_backtrace()
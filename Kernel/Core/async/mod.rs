@@ -178,6 +178,8 @@ pub enum WaitError
 
 /// Wait on the provided list of Waiter trait objects
 ///
+/// Returns the number of waiters that completed (zero if the timeout, in ms, elapsed), or `None` if there
+/// were no incomplete waiters.
 pub fn wait_on_list(waiters: &mut [&mut Waiter], timeout: Option<u64>) -> Option<usize>
 {
 	log_trace!("wait_on_list(waiters = {:?}, timeout = {:?})", waiters, timeout);
@@ -186,10 +188,6 @@ pub fn wait_on_list(waiters: &mut [&mut Waiter], timeout: Option<u64>) -> Option
 		panic!("wait_on_list - Nothing to wait on");
 	}
 	
-	if timeout.is_some() {
-		todo!("Support timeouts in wait_on_list");
-	}
-	
 	// Wait on primitives from the waiters, returning the indexes of those that need a state advance
 	
 	// - If there are no incomplete waiters, return None
@@ -204,6 +202,7 @@ pub fn wait_on_list(waiters: &mut [&mut Waiter], timeout: Option<u64>) -> Option
 		.fold(false, |v,x| v | !x.get_waiter().bind_signal( &mut obj) )
 		// ^ doesn't use .any() becuase of unbind_signal below
 		;
+	let timer = timeout.map(|ms| ::time::Timer::after(ms, &obj));
 	
 	if force_poll
 	{
@@ -212,6 +211,9 @@ pub fn wait_on_list(waiters: &mut [&mut Waiter], timeout: Option<u64>) -> Option
 		// While none of the active waiters returns true from poll()
 		while !waiters.iter_mut().filter(|x| !x.is_complete()).fold(false, |r,e| r || e.get_waiter().poll())
 		{
+			if timer.as_ref().map(|t| t.is_expired()).unwrap_or(false) {
+				break;
+			}
 			n_passes += 1;
 			// TODO: Take a short nap
 		}
@@ -227,6 +229,7 @@ pub fn wait_on_list(waiters: &mut [&mut Waiter], timeout: Option<u64>) -> Option
 	for ent in waiters.iter_mut().filter(|x| !x.is_complete()) {
 		ent.get_waiter().unbind_signal();
 	}
+	::core::mem::drop(timer);
 	::core::mem::drop(obj);
 	
 	// Run completion handlers (via .is_ready and .complete), counting the number of changed waiters
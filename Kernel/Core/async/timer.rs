@@ -5,12 +5,11 @@
 //! Asynchronous Timer.
 //! 
 //! An async timer type, firing after the specified duration has elapsed
-//!
-//! TODO: Fix to actually be usable
 
 pub struct Waiter
 {
 	expiry_ticks: u64,
+	timer: Option<::time::Timer>,
 }
 
 impl Waiter
@@ -19,6 +18,7 @@ impl Waiter
 	{
 		Waiter {
 			expiry_ticks: ::time::ticks() + duration_ms,
+			timer: None,
 		}
 	}
 }
@@ -34,12 +34,12 @@ impl super::PrimitiveWaiter for Waiter {
 	fn run_completion(&mut self) {
 		// no action
 	}
-	fn bind_signal(&mut self, _sleeper: &mut ::threads::SleepObject) -> bool {
-		todo!("timer::Waiter::bind_signal()")
-		//::time::bind_signal(_sleeper, self.expiry_ticks)
+	fn bind_signal(&mut self, sleeper: &mut ::threads::SleepObject) -> bool {
+		self.timer = Some( ::time::Timer::new(self.expiry_ticks, sleeper) );
+		true
 	}
 	fn unbind_signal(&mut self) {
-		todo!("timer::Waiter::unbind_signal()")
+		self.timer = None;
 	}
 }
 
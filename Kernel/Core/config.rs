@@ -88,6 +88,8 @@ def_config_set! {
 		Loader @ "LOADER" = "/sysroot/bin/loader",
 //		/// Startup - Init executable (first userland process)
 		Init @ "INIT" = "/sysroot/bin/init",
+//		/// Scheduler - Base time slice length (ms), see `threads::Priority::time_slice`
+		TimeSlice @ "TIMESLICE" = "10",
 	}
 }
 
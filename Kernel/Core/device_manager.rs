@@ -105,6 +105,7 @@ impl_from! {
 		match v
 		{
 		::memory::virt::MapError::OutOfMemory => DriverBindError::OutOfMemory,
+		::memory::virt::MapError::QuotaExceeded => DriverBindError::OutOfMemory,
 		::memory::virt::MapError::RangeInUse => DriverBindError::Bug("Memory map range collision"),
 		}
 	}
@@ -49,7 +49,7 @@ pub fn init() {
 	// SAFE: Called in a single-threaded context
 	unsafe {
 		S_IRQ_WORKER_SIGNAL.prep(|| ::threads::SleepObject::new("IRQ Worker"));
-		S_IRQ_WORKER.prep(|| ::threads::WorkerThread::new("IRQ Worker", irq_worker));
+		S_IRQ_WORKER.prep(|| ::threads::WorkerThread::with_priority("IRQ Worker", ::threads::Priority::Realtime, irq_worker));
 	}
 }
 
@@ -88,6 +88,20 @@ impl<T> RingBuf<T>
 		}
 	}
 	
+	/// Borrow the item at the front of the buffer
+	pub fn front(&self) -> Option<&T>
+	{
+		if self.len == 0
+		{
+			None
+		}
+		else
+		{
+			// SAFE: Pointer is valid, and the data is initialised
+			Some( unsafe { &*self.data.get_ptr(self.start) } )
+		}
+	}
+
 	/// Pop an item from the front of the buffer
 	pub fn pop_front(&mut self) -> Option<T>
 	{
@@ -124,6 +124,28 @@ impl<K: Ord, V> VecMap<K,V>
 			ents: &mut *self.ents,
 		}
 	}
+
+	/// Number of entries in the map
+	pub fn len(&self) -> usize {
+		self.ents.len()
+	}
+	/// Remove all entries for which the predicate returns false
+	pub fn retain<F: FnMut(&K, &mut V)->bool>(&mut self, mut f: F) {
+		let mut i = 0;
+		while i < self.ents.len()
+		{
+			let keep = {
+				let e = &mut self.ents[i];
+				f(&e.0, &mut e.1)
+				};
+			if keep {
+				i += 1;
+			}
+			else {
+				self.ents.remove(i);
+			}
+		}
+	}
 }
 impl<K: Ord,V> Default for VecMap<K, V> {
 	fn default() -> Self {
@@ -1,20 +1,24 @@
 //
 //
 //
-//! 
-use super::{ZERO_ALLOC, Error};
-use arch::memory::addresses;
+//! Block heap (used for allocations too large for the slab allocator)
+use super::{ZERO_ALLOC, Error, REDZONE_SIZE};
 
 // Curse no CTFE
 //const HEADERS_SIZE: usize = ::core::mem::size_of::<HeapHead>() + ::core::mem::size_of::<HeapFoot>();
 const MAGIC: u32 = 0x71ff11A1;
 
-// TODO: Store the limits in the definition
 pub struct HeapDef
 {
+	/// Limits of the heap's address range
+	base: usize,
+	limit: usize,
 	start: *mut HeapHead,
 	last_foot: *mut HeapFoot,
 	first_free: *mut HeapHead,
+	/// Number (and total size) of allocated blocks
+	used_blocks: usize,
+	used_bytes: usize,
 }
 unsafe impl ::core::marker::Send for HeapDef {}
 
@@ -28,6 +32,8 @@ enum HeapState
 struct HeapHead
 {
 	magic: u32,
+	/// Allocation tag (see `tracker`)
+	tag: u8,
 	size: usize,
 	state: HeapState,
 }
@@ -45,19 +51,49 @@ struct HeapFoot
 
 impl HeapDef
 {
-	/// Construct a new heap instance
-	pub const fn new() -> HeapDef {
+	/// Construct a new heap instance covering the specified address range
+	pub const fn new(base: usize, limit: usize) -> HeapDef {
 		HeapDef {
+			base: base,
+			limit: limit,
 			start: 0 as *mut _,
 			last_foot: 0 as *mut _,
 			first_free: 0 as *mut _,
+			used_blocks: 0,
+			used_bytes: 0,
 			}
 	}
 
+	/// Returns true if the pointer is within this heap's address range
+	pub fn contains(&self, ptr: *const ()) -> bool {
+		self.base <= ptr as usize && (ptr as usize) < self.limit
+	}
+
+	/// Statistics: (bytes reserved, blocks allocated, bytes allocated)
+	pub fn stats(&self) -> (usize, usize, usize) {
+		let reserved = if self.start.is_null() { 0 } else { self.last_foot as usize + ::core::mem::size_of::<HeapFoot>() - self.base };
+		(reserved, self.used_blocks, self.used_bytes)
+	}
+
+	/// Obtain information on an allocation: (requested size, usable size, tag)
+	pub unsafe fn alloc_info(&self, ptr: *mut ()) -> (usize, usize, u8)
+	{
+		let headers_size = ::core::mem::size_of::<HeapHead>() + ::core::mem::size_of::<HeapFoot>();
+		let headptr = (ptr as *mut HeapHead).offset(-1);
+		assert!(self.contains(headptr as *const ()), "Pointer {:p} not within the heap", ptr);
+		let headref = &mut *headptr;
+		headref.validate().unwrap();
+		match headref.state
+		{
+		HeapState::Used(size) => (size, headref.size() - headers_size, headref.tag),
+		HeapState::Free(..) => panic!("Allocation info requested for a free block ({:p})", ptr),
+		}
+	}
+
 	/// Allocate arbitary bytes from the heap
 	/// 
 	// TODO: Is this actually unsafe?
-	pub unsafe fn allocate(&mut self, size: usize, align: usize) -> Result<*mut (), Error>
+	pub unsafe fn allocate(&mut self, size: usize, align: usize, tag: u8) -> Result<*mut (), Error>
 	{
 		// TODO: Have different pools for different alignments
 		
@@ -70,7 +106,7 @@ impl HeapDef
 		let headers_size = ::core::mem::size_of::<HeapHead>() + ::core::mem::size_of::<HeapFoot>();
 		
 		// 1. Round size up to closest heap block size
-		let blocksize = ::lib::num::round_up(size + headers_size, 32);
+		let blocksize = ::lib::num::round_up(size + headers_size + REDZONE_SIZE, 32);
 		log_debug!("allocate(size={},align={}) blocksize={}", size, align, blocksize);
 
 		// 2. Locate a free location
@@ -135,6 +171,9 @@ impl HeapDef
 			}
 			// Return newly allocated block
 			fb.state = HeapState::Used(size);
+			fb.tag = tag;
+			self.used_blocks += 1;
+			self.used_bytes += fb.size();
 			log_debug!("Returning block {:p} (Freelist)", fb);
 			return Ok( fb.data() );
 		}
@@ -157,7 +196,10 @@ impl HeapDef
 			self.first_free = block.next();
 		}
 		
-		block.state = HeapState::Used(size);	
+		block.state = HeapState::Used(size);
+		block.tag = tag;
+		self.used_blocks += 1;
+		self.used_bytes += block.size();
 	
 		log_trace!("Returning block {:p} (new)", block);
 		Ok( block.data() )
@@ -175,13 +217,12 @@ impl HeapDef
 		
 		let headptr = {
 			let hp = (ptr as *mut HeapHead).offset(-1);
-			assert!( (hp as usize) >= self.start as usize );
-			assert!( (hp as usize) < self.last_foot as usize );
+			assert!( self.contains(hp as *const ()) );
 			&mut *hp
 			};
 
 		// If the new size fits within the old block, update the cached size and return true
-		if size + headers_size <= headptr.size()
+		if size + headers_size + REDZONE_SIZE <= headptr.size()
 		{
 			headptr.state = HeapState::Used(size);
 			true
@@ -204,8 +245,7 @@ impl HeapDef
 		
 		let headptr = {
 			let hp = (ptr as *mut HeapHead).offset(-1);
-			assert!( (hp as usize) >= self.start as usize );
-			assert!( (hp as usize) < self.last_foot as usize );
+			assert!( self.contains(hp as *const ()) );
 			&mut *hp
 			};
 
@@ -231,7 +271,7 @@ impl HeapDef
 
 		let mut no_add = false;
 		let headptr = (ptr as *mut HeapHead).offset(-1);
-		assert!(headptr as usize >= addresses::HEAP_START);
+		assert!(self.contains(headptr as *const ()), "Pointer {:p} not within the heap", ptr);
 		
 		{
 			let headref = &mut *headptr;
@@ -246,9 +286,12 @@ impl HeapDef
 					headref, headref.state, size );
 			}
 			
+			self.used_blocks -= 1;
+			self.used_bytes -= headref.size();
+			
 			// Merge left and right
 			// 1. Left:
-			if headptr as usize != addresses::HEAP_START
+			if headptr as usize != self.base
 			{
 				if let HeapState::Free(_) = (*headref.prev()).state
 				{
@@ -282,7 +325,7 @@ impl HeapDef
 		//log_debug!("self.{{start = {:p}, last_foot = {:?}}}", self.start, self.last_foot);
 		let use_prev =
 			if self.start.is_null() {
-				let base = addresses::HEAP_START;
+				let base = self.base;
 				self.start = base as *mut HeapHead;
 				// note: Evil hack, set last_foot to invalid memory (it's only used for .next_head())
 				self.last_foot = (base as *mut HeapFoot).offset(-1);
@@ -305,10 +348,10 @@ impl HeapDef
 		//log_trace!("last_foot = {:p}", self.last_foot);
 		assert!(n_pages > 0);
 
-		if last_foot.next_head() as usize == addresses::HEAP_END {
+		if last_foot.next_head() as usize == self.limit {
 			return Err( Error::OutOfReservation );
 		}
-		if last_foot.next_head() as usize + n_pages * ::PAGE_SIZE > addresses::HEAP_END {
+		if last_foot.next_head() as usize + n_pages * ::PAGE_SIZE > self.limit {
 			return Err( Error::OutOfReservation );
 		}
 
@@ -433,6 +476,7 @@ impl HeapHead
 	{
 		*self = HeapHead {
 			magic: MAGIC,
+			tag: 0,
 			state: state,
 			size: size,
 			};
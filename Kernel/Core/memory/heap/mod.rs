@@ -3,23 +3,25 @@
 //
 // Core/memory/heap.rs
 //! Dynamic memory manager
+//!
+//! Small allocations are served by a size-class (slab) allocator with per-CPU caches, larger allocations
+//! use a block heap. The heap's address range is split evenly between the two.
+//!
+//! Building with `HEAP_DEBUG=1` (the `heap_debug` cfg) enables red-zones after each allocation, and poisoning
+//! of allocated and freed memory.
 use core::ptr::Unique;
-
-// TODO: Rewrite this to correctly use the size information avaliable
+use arch::memory::addresses;
 
 use self::heapdef::HeapDef;
 
+pub use self::tracker::{set_tag, TagGuard};
+
 mod heapdef;
+mod slab;
+mod tracker;
 
 // --------------------------------------------------------
 // Types
-#[derive(Copy,Clone)]
-pub enum HeapId
-{
-	Local,	// Inaccessible outside of process
-	Global,	// Global allocations
-}
-
 #[derive(Debug)]
 pub enum Error
 {
@@ -47,8 +49,22 @@ impl<T> !::lib::POD for ArrayAlloc<T> {}
 
 pub const ZERO_ALLOC: *mut () = 1 as *mut _;
 
-//static S_LOCAL_HEAP: ::sync::Mutex<HeapDef> = mutex_init!(HeapDef{head:None});
-static S_GLOBAL_HEAP: ::sync::Mutex<HeapDef> = ::sync::Mutex::new(HeapDef::new());
+/// Split between the block heap (below) and the slabs (above)
+const SLAB_START: usize = addresses::HEAP_START + (addresses::HEAP_END - addresses::HEAP_START) / 2;
+const SLAB_END: usize = addresses::HEAP_END;
+
+/// Bytes reserved after each allocation for overrun detection
+#[cfg(heap_debug)]
+const REDZONE_SIZE: usize = 16;
+#[cfg(not(heap_debug))]
+const REDZONE_SIZE: usize = 0;
+
+/// Fill bytes for red-zones, newly allocated memory, and freed memory (when `heap_debug` is set)
+const REDZONE_BYTE: u8 = 0xFD;
+const POISON_ALLOC: u8 = 0xCD;
+const POISON_FREE: u8 = 0xDD;
+
+static S_GLOBAL_HEAP: ::sync::Mutex<HeapDef> = ::sync::Mutex::new(HeapDef::new(addresses::HEAP_START, SLAB_START));
 
 // --------------------------------------------------------
 // Code
@@ -56,12 +72,22 @@ pub fn init()
 {
 }
 
+/// Log heap statistics (size class usage, block heap usage, and live allocations by module)
+pub fn dump_stats()
+{
+	log_log!("Heap statistics:");
+	slab::dump_stats();
+	let (reserved, blocks, bytes) = S_GLOBAL_HEAP.lock().stats();
+	log_log!("Block heap: {} KiB reserved, {} bytes in {} blocks", reserved / 1024, bytes, blocks);
+	tracker::dump();
+}
+
 // Used by Box<T>
 #[lang="exchange_malloc"]
 #[inline]
 unsafe fn exchange_malloc(size: usize, align: usize) -> *mut u8
 {
-	match allocate(HeapId::Global, size, align)
+	match allocate(size, align)
 	{
 	Some(x) => x as *mut u8,
 	None => panic!("exchange_malloc({}, {}) out of memory", size, align),
@@ -71,20 +97,20 @@ unsafe fn exchange_malloc(size: usize, align: usize) -> *mut u8
 #[inline]
 unsafe fn exchange_free(ptr: *mut u8, size: usize, align: usize)
 {
-	S_GLOBAL_HEAP.lock().deallocate(ptr as *mut (), size, align)
+	deallocate(ptr as *mut (), size, align)
 }
 #[lang = "box_free"]
 #[inline]
 unsafe fn box_free<T>(ptr: *mut T) {
 	let size = ::core::mem::size_of::<T>();
 	if size != 0 {
-		S_GLOBAL_HEAP.lock().deallocate(ptr as *mut (), size, ::core::mem::align_of::<T>());
+		deallocate(ptr as *mut (), size, ::core::mem::align_of::<T>());
 	}
 }
 
 // Used by libgcc and ACPICA
 #[no_mangle] pub unsafe extern "C" fn malloc(size: usize) -> *mut () {
-	allocate(HeapId::Global, size, 16).unwrap()
+	allocate(size, 16).unwrap()
 } 
 #[no_mangle] pub unsafe extern "C" fn free(ptr: *mut ()) {
 	if !ptr.is_null() {
@@ -95,7 +121,7 @@ unsafe fn box_free<T>(ptr: *mut T) {
 // Used by kernel internals
 pub unsafe fn alloc<T>(value: T) -> *mut T
 {
-	let ret = match allocate(HeapId::Global, ::core::mem::size_of::<T>(), ::core::mem::align_of::<T>())
+	let ret = match allocate(::core::mem::size_of::<T>(), ::core::mem::align_of::<T>())
 		{
 		Some(v) => v as *mut T,
 		None => panic!("Out of memory")
@@ -104,7 +130,7 @@ pub unsafe fn alloc<T>(value: T) -> *mut T
 	ret
 }
 pub unsafe fn alloc_raw(size: usize, align: usize) -> *mut () {
-	match allocate(HeapId::Global, size, align)
+	match allocate(size, align)
 	{
 	Some(v) => v,
 	None => panic!("Out of memory")
@@ -143,7 +169,7 @@ impl<T> ArrayAlloc<T>
 			}
 			else
 			{
-				let ptr = match allocate(HeapId::Global, ::core::mem::size_of::<T>() * count, ::core::mem::align_of::<T>())
+				let ptr = match allocate(::core::mem::size_of::<T>() * count, ::core::mem::align_of::<T>())
 					{
 					Some(v) => v as *mut T,
 					None => panic!("Out of memory when allocating array of {} elements", count)
@@ -248,36 +274,143 @@ impl<T> ::core::ops::Drop for ArrayAlloc<T>
 }
 
 // Main entrypoints
-/// Allocate memory from the specified heap
-unsafe fn allocate(heap: HeapId, size: usize, align: usize) -> Option<*mut ()>
+/// Allocate memory (from the slabs if small enough, otherwise from the block heap)
+unsafe fn allocate(size: usize, align: usize) -> Option<*mut ()>
 {
-	match heap
-	{
-	HeapId::Global => match S_GLOBAL_HEAP.lock().allocate(size, align)
+	if size == 0 {
+		return Some(ZERO_ALLOC);
+	}
+	let tag = tracker::current();
+	let rv = match slab::class_for(size + REDZONE_SIZE, align)
 		{
-		Ok(v) => Some(v),
-		Err(e) => {
-			log_error!("Unable to allocate: {:?}", e);
-			None
+		Some(class) => slab::allocate(class).map(|ptr| {
+			check_poison(ptr, slab::class_size(class));
+			slab::set_tag(ptr, tag);
+			(ptr, slab::class_size(class))
+			}),
+		None => {
+			let mut lh = S_GLOBAL_HEAP.lock();
+			lh.allocate(size, align, tag).map(|ptr| (ptr, lh.alloc_info(ptr).1))
 			},
+		};
+	match rv
+	{
+	Ok( (ptr, capacity) ) => {
+		tracker::record_alloc(tag, capacity);
+		if cfg!(heap_debug) {
+			::core::ptr::write_bytes(ptr as *mut u8, POISON_ALLOC, size);
+			set_redzone(ptr, size, capacity);
+		}
+		Some(ptr)
+		},
+	Err(e) => {
+		log_error!("Unable to allocate {} bytes: {:?}", size, e);
+		None
 		},
-	_ => panic!("TODO: Non-global heaps"),
 	}
 }
 
 /// Attempt to expand in-place
 unsafe fn expand(pointer: *mut (), newsize: usize) -> bool
 {
-	S_GLOBAL_HEAP.lock().expand_alloc(pointer, newsize)
+	if pointer == ZERO_ALLOC {
+		false
+	}
+	else if slab::contains(pointer) {
+		let capacity = slab::class_size( slab::class_of(pointer) );
+		if newsize + REDZONE_SIZE <= capacity {
+			set_redzone(pointer, newsize, capacity);
+			true
+		}
+		else {
+			false
+		}
+	}
+	else {
+		let mut lh = S_GLOBAL_HEAP.lock();
+		if lh.expand_alloc(pointer, newsize) {
+			set_redzone(pointer, newsize, lh.alloc_info(pointer).1);
+			true
+		}
+		else {
+			false
+		}
+	}
 }
 unsafe fn shrink(pointer: *mut (), newsize: usize)
 {
-	S_GLOBAL_HEAP.lock().shrink_alloc(pointer, newsize)
+	if pointer == ZERO_ALLOC || !slab::contains(pointer) {
+		let mut lh = S_GLOBAL_HEAP.lock();
+		lh.shrink_alloc(pointer, newsize);
+		if pointer != ZERO_ALLOC {
+			set_redzone(pointer, newsize, lh.alloc_info(pointer).1);
+		}
+	}
+	else {
+		set_redzone(pointer, newsize, slab::class_size( slab::class_of(pointer) ));
+	}
 }
 
+/// Release an allocation (`size` is zero if unknown, e.g. from C `free`)
 unsafe fn deallocate(pointer: *mut (), size: usize, align: usize)
 {
-	S_GLOBAL_HEAP.lock().deallocate(pointer as *mut (), size, align);
+	if pointer == ZERO_ALLOC {
+		assert!(size == 0, "ZERO_ALLOC but size({}) != 0", size);
+		return ;
+	}
+	if slab::contains(pointer) {
+		let capacity = slab::class_size( slab::class_of(pointer) );
+		assert!(size + REDZONE_SIZE <= capacity, "Freeing {:p} with size {} larger than its size class ({})", pointer, size, capacity);
+		check_redzone(pointer, size, capacity);
+		tracker::record_free(slab::get_tag(pointer), capacity);
+		if cfg!(heap_debug) {
+			::core::ptr::write_bytes(pointer as *mut u8, POISON_FREE, capacity);
+		}
+		slab::deallocate(pointer);
+	}
+	else {
+		let (tag, capacity) = {
+			let mut lh = S_GLOBAL_HEAP.lock();
+			let (_, capacity, tag) = lh.alloc_info(pointer);
+			check_redzone(pointer, size, capacity);
+			if cfg!(heap_debug) {
+				::core::ptr::write_bytes(pointer as *mut u8, POISON_FREE, capacity);
+			}
+			lh.deallocate(pointer, size, align);
+			(tag, capacity)
+			};
+		tracker::record_free(tag, capacity);
+	}
+}
+
+/// Fill the bytes following an allocation with the red-zone pattern
+unsafe fn set_redzone(ptr: *mut (), size: usize, capacity: usize)
+{
+	if cfg!(heap_debug) {
+		::core::ptr::write_bytes((ptr as *mut u8).offset(size as isize), REDZONE_BYTE, capacity - size);
+	}
+}
+/// Check that the red-zone following an allocation is intact (if `size` is zero, only the minimum red-zone is checked)
+unsafe fn check_redzone(ptr: *mut (), size: usize, capacity: usize)
+{
+	if cfg!(heap_debug) {
+		let start = if size == 0 { capacity - REDZONE_SIZE } else { size };
+		let zone = ::core::slice::from_raw_parts((ptr as *const u8).offset(start as isize), capacity - start);
+		if let Some(ofs) = zone.iter().position(|&b| b != REDZONE_BYTE) {
+			panic!("Heap overrun: {:p}+{} red-zone corrupted at +{} ({:#x})", ptr, size, start + ofs, zone[ofs]);
+		}
+	}
+}
+/// Check that a freed slab object wasn't written to (the first word is used by the free list)
+unsafe fn check_poison(ptr: *mut (), capacity: usize)
+{
+	if cfg!(heap_debug) {
+		let ofs = ::core::mem::size_of::<usize>();
+		let data = ::core::slice::from_raw_parts((ptr as *const u8).offset(ofs as isize), capacity - ofs);
+		if let Some(i) = data.iter().position(|&b| b != POISON_FREE) {
+			panic!("Heap use-after-free: {:p} modified at +{} while free ({:#x})", ptr, ofs + i, data[i]);
+		}
+	}
 }
 
 
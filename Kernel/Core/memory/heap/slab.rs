@@ -0,0 +1,393 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/memory/heap/slab.rs
+//! Size-class (slab) allocator for small objects
+//!
+//! Slabs are fixed-size and aligned to their size, so the header (holding the size class, free list, and
+//! per-object tags) is found by masking an object's address. Freed objects are pushed to a small per-CPU
+//! cache (only touched with interrupts held), and moved to/from the shared slabs in batches.
+use super::Error;
+use core::cell::UnsafeCell;
+use arch::threads::MAX_CPUS;
+
+/// Size (and alignment) of a single slab
+const SLAB_SIZE: usize = 0x4000;
+const NUM_CLASSES: usize = 7;
+/// Object sizes handled by the slab allocator (larger allocations go to the block heap)
+const CLASS_SIZES: [usize; NUM_CLASSES] = [16, 32, 64, 128, 256, 512, 1024];
+const MAGIC: u32 = 0x51AB0BEC;
+
+/// Number of objects held by each per-CPU cache
+const CACHE_SIZE: usize = 16;
+/// Number of objects moved between a per-CPU cache and the slabs at once
+const CACHE_BATCH: usize = CACHE_SIZE / 2;
+
+struct SlabHead
+{
+	magic: u32,
+	class: u16,
+	capacity: u16,
+	used: u16,
+	/// Offset of the first object
+	first_obj: u16,
+	free: *mut FreeObj,
+	/// Previous/next slabs in the class's partial list (or the next empty slab)
+	prev: *mut SlabHead,
+	next: *mut SlabHead,
+	// Followed by `capacity` tag bytes
+}
+struct FreeObj
+{
+	next: *mut FreeObj,
+}
+
+/// Shared slab state
+struct Depot
+{
+	/// Next unused slab address
+	next_slab: usize,
+	/// Unused slabs (available to any size class)
+	empty: *mut SlabHead,
+	n_empty: usize,
+	classes: [ClassDepot; NUM_CLASSES],
+}
+unsafe impl Send for Depot {}
+#[derive(Copy,Clone)]
+struct ClassDepot
+{
+	/// Slabs with at least one free object
+	partial: *mut SlabHead,
+	n_slabs: usize,
+	/// Objects not on a slab free list (either in use, or in a per-CPU cache)
+	n_taken: usize,
+}
+const CLASS_DEPOT_INIT: ClassDepot = ClassDepot { partial: 0 as *mut _, n_slabs: 0, n_taken: 0 };
+
+#[derive(Copy,Clone)]
+struct CpuCache
+{
+	count: usize,
+	items: [*mut (); CACHE_SIZE],
+}
+const CPU_CACHE_INIT: CpuCache = CpuCache { count: 0, items: [0 as *mut _; CACHE_SIZE] };
+
+struct CpuCaches(UnsafeCell<[[CpuCache; NUM_CLASSES]; MAX_CPUS]>);
+unsafe impl Sync for CpuCaches {}
+
+static S_DEPOT: ::sync::Mutex<Depot> = ::sync::Mutex::new(Depot {
+	next_slab: super::SLAB_START,
+	empty: 0 as *mut _,
+	n_empty: 0,
+	classes: [CLASS_DEPOT_INIT; NUM_CLASSES],
+	});
+static S_CPU_CACHES: CpuCaches = CpuCaches(UnsafeCell::new([[CPU_CACHE_INIT; NUM_CLASSES]; MAX_CPUS]));
+
+/// Size class for an allocation (`None` if too large for the slab allocator)
+pub fn class_for(size: usize, align: usize) -> Option<usize>
+{
+	// Objects are aligned to their size (the slab is aligned, and the first object is aligned to the class size)
+	let size = ::core::cmp::max(size, align);
+	CLASS_SIZES.iter().position(|&s| s >= size)
+}
+pub fn class_size(class: usize) -> usize
+{
+	CLASS_SIZES[class]
+}
+
+/// Returns true if the pointer was allocated by the slab allocator
+pub fn contains(ptr: *const ()) -> bool
+{
+	super::SLAB_START <= ptr as usize && (ptr as usize) < super::SLAB_END
+}
+/// Size class of an allocated object
+pub unsafe fn class_of(ptr: *const ()) -> usize
+{
+	let slab = slab_of(ptr);
+	slab.index_of(ptr);
+	slab.class as usize
+}
+/// Get the tag of an allocated object
+pub unsafe fn get_tag(ptr: *const ()) -> u8
+{
+	let slab = slab_of(ptr);
+	*slab.tags().offset( slab.index_of(ptr) as isize )
+}
+/// Set the tag of an allocated object
+pub unsafe fn set_tag(ptr: *const (), tag: u8)
+{
+	let slab = slab_of(ptr);
+	*slab.tags().offset( slab.index_of(ptr) as isize ) = tag;
+}
+
+/// Allocate an object of the specified class
+pub unsafe fn allocate(class: usize) -> Result<*mut (), Error>
+{
+	// Fast path: Take an object from this CPU's cache
+	if let Some(ptr) = with_cache(class, |c| c.pop()) {
+		return Ok(ptr);
+	}
+
+	// Slow path: Take a batch from the slabs, keep one and cache the rest
+	let mut batch = [0 as *mut (); CACHE_BATCH];
+	let count = try!( S_DEPOT.lock().take(class, &mut batch) );
+	let leftover = with_cache(class, |c| c.push_all(&batch[1 .. count]));
+	if leftover > 0 {
+		// - Another thread on this CPU refilled the cache while the depot was locked, return the excess
+		S_DEPOT.lock().put(&batch[count - leftover .. count]);
+	}
+	Ok(batch[0])
+}
+
+/// Release an object
+pub unsafe fn deallocate(ptr: *mut ())
+{
+	let class = class_of(ptr);
+	let mut batch = [0 as *mut (); CACHE_BATCH];
+	let flushed = with_cache(class, |c| {
+		let n = if c.count == CACHE_SIZE { c.drain(&mut batch) } else { 0 };
+		c.push(ptr);
+		n
+		});
+	if flushed > 0 {
+		S_DEPOT.lock().put(&batch[.. flushed]);
+	}
+}
+
+/// Log slab usage for each size class
+pub fn dump_stats()
+{
+	// Copy the statistics out before logging (the logger may allocate)
+	let (reserved, n_empty, classes) = {
+		let depot = S_DEPOT.lock();
+		(depot.next_slab - super::SLAB_START, depot.n_empty, depot.classes)
+		};
+	log_log!("Slabs: {} KiB reserved, {} unused slabs", reserved / 1024, n_empty);
+	for (i, c) in classes.iter().enumerate()
+	{
+		// NOTE: Unsynchronised read of other CPUs' caches, the value is only informational
+		// SAFE: Plain reads of integer counts
+		let cached = unsafe { (*S_CPU_CACHES.0.get()).iter() }.fold(0, |t, cpu| t + cpu[i].count);
+		log_log!("- {:4} bytes: {} slabs, {} in use, {} cached", CLASS_SIZES[i], c.n_slabs, c.n_taken.saturating_sub(cached), cached);
+	}
+}
+
+fn with_cache<R, F: FnOnce(&mut CpuCache)->R>(class: usize, f: F) -> R
+{
+	let _irq = ::sync::hold_interrupts();
+	// SAFE: Interrupts are held, so nothing else can access this CPU's cache
+	let cache = unsafe { &mut (*S_CPU_CACHES.0.get())[::arch::threads::cpu_num()][class] };
+	f(cache)
+}
+
+unsafe fn slab_of<'a>(ptr: *const ()) -> &'a mut SlabHead
+{
+	let slab = &mut *( (ptr as usize & !(SLAB_SIZE - 1)) as *mut SlabHead );
+	assert!(slab.magic == MAGIC, "Slab header for {:p} corrupted (magic {:#x} instead of {:#x})", ptr, slab.magic, MAGIC);
+	slab
+}
+
+/// Offset of the first object, and the number of objects, in a slab of the specified class
+fn layout(class: usize) -> (usize, usize)
+{
+	let size = CLASS_SIZES[class];
+	let header = ::core::mem::size_of::<SlabHead>();
+	// One tag byte per object follows the header
+	let estimate = (SLAB_SIZE - header) / (size + 1);
+	let first = ::lib::num::round_up(header + estimate, size);
+	(first, (SLAB_SIZE - first) / size)
+}
+
+impl SlabHead
+{
+	/// Index of the object at `ptr` (panics if `ptr` isn't the start of an object)
+	fn index_of(&self, ptr: *const ()) -> usize
+	{
+		let ofs = ptr as usize - self as *const _ as usize;
+		let size = CLASS_SIZES[self.class as usize];
+		assert!(ofs >= self.first_obj as usize && (ofs - self.first_obj as usize) % size == 0,
+			"Pointer {:p} isn't the start of a {} byte object", ptr, size);
+		(ofs - self.first_obj as usize) / size
+	}
+	fn tags(&self) -> *mut u8
+	{
+		// SAFE: Tags immediately follow the header, and are owned by the slab
+		unsafe { (self as *const SlabHead).offset(1) as *mut u8 }
+	}
+
+	/// Initialise a slab for the specified class, with all objects free
+	unsafe fn initialise(&mut self, class: usize)
+	{
+		let (first, capacity) = layout(class);
+		let size = CLASS_SIZES[class];
+		let base = self as *mut SlabHead as usize;
+		*self = SlabHead {
+			magic: MAGIC,
+			class: class as u16,
+			capacity: capacity as u16,
+			used: 0,
+			first_obj: first as u16,
+			free: 0 as *mut _,
+			prev: 0 as *mut _,
+			next: 0 as *mut _,
+			};
+		if cfg!(heap_debug) {
+			::core::ptr::write_bytes((base + first) as *mut u8, super::POISON_FREE, capacity * size);
+		}
+		// Build the free list in address order
+		for i in (0 .. capacity).rev() {
+			let obj = (base + first + i * size) as *mut FreeObj;
+			(*obj).next = self.free;
+			self.free = obj;
+		}
+	}
+}
+
+impl Depot
+{
+	/// Take up to `out.len()` objects from the slabs
+	unsafe fn take(&mut self, class: usize, out: &mut [*mut ()]) -> Result<usize, Error>
+	{
+		let mut count = 0;
+		while count < out.len()
+		{
+			if self.classes[class].partial.is_null() {
+				self.classes[class].partial = match self.new_slab(class)
+					{
+					Ok(slab) => slab,
+					// - Only report failure if nothing could be allocated
+					Err(e) => if count == 0 { return Err(e) } else { break },
+					};
+			}
+			let slab = &mut *self.classes[class].partial;
+			while count < out.len() && !slab.free.is_null()
+			{
+				let obj = slab.free;
+				slab.free = (*obj).next;
+				slab.used += 1;
+				out[count] = obj as *mut ();
+				count += 1;
+			}
+			if slab.free.is_null() {
+				self.unlink(class, slab);
+			}
+		}
+		self.classes[class].n_taken += count;
+		Ok(count)
+	}
+
+	/// Return objects to their slabs
+	unsafe fn put(&mut self, objs: &[*mut ()])
+	{
+		for &ptr in objs
+		{
+			let slab = slab_of(ptr);
+			let class = slab.class as usize;
+			slab.index_of(ptr);
+			assert!(slab.used > 0, "Freeing {:p} to an empty slab", ptr);
+
+			let was_full = slab.free.is_null();
+			let obj = ptr as *mut FreeObj;
+			(*obj).next = slab.free;
+			slab.free = obj;
+			slab.used -= 1;
+			self.classes[class].n_taken -= 1;
+
+			if was_full {
+				// Add to the head of the partial list
+				slab.prev = 0 as *mut _;
+				slab.next = self.classes[class].partial;
+				if !slab.next.is_null() {
+					(*slab.next).prev = slab;
+				}
+				self.classes[class].partial = slab;
+			}
+			else if slab.used == 0 && !(slab.prev.is_null() && slab.next.is_null()) {
+				// Release empty slabs (keeping the last partial slab, to avoid thrashing)
+				self.unlink(class, slab);
+				self.classes[class].n_slabs -= 1;
+				slab.magic = 0;
+				slab.next = self.empty;
+				self.empty = slab;
+				self.n_empty += 1;
+			}
+		}
+	}
+
+	/// Remove a slab from the partial list
+	unsafe fn unlink(&mut self, class: usize, slab: &mut SlabHead)
+	{
+		if slab.prev.is_null() {
+			self.classes[class].partial = slab.next;
+		}
+		else {
+			(*slab.prev).next = slab.next;
+		}
+		if !slab.next.is_null() {
+			(*slab.next).prev = slab.prev;
+		}
+		slab.prev = 0 as *mut _;
+		slab.next = 0 as *mut _;
+	}
+
+	/// Obtain a new slab (reusing an empty slab, or mapping a new one)
+	unsafe fn new_slab(&mut self, class: usize) -> Result<*mut SlabHead, Error>
+	{
+		let slab = if !self.empty.is_null() {
+				let slab = self.empty;
+				self.empty = (*slab).next;
+				self.n_empty -= 1;
+				slab
+			}
+			else {
+				if self.next_slab + SLAB_SIZE > super::SLAB_END {
+					return Err( Error::OutOfReservation );
+				}
+				let slab = self.next_slab as *mut SlabHead;
+				match ::memory::virt::allocate(slab as *mut (), SLAB_SIZE / ::PAGE_SIZE)
+				{
+				Ok(_) => {},
+				Err(::memory::virt::MapError::OutOfMemory) => return Err(Error::OutOfMemory),
+				Err(e @ _) => panic!("Unknown error from VMM: {:?}", e),
+				}
+				self.next_slab += SLAB_SIZE;
+				slab
+			};
+		log_trace!("new_slab(class={}) {:p}", class, slab);
+		(*slab).initialise(class);
+		self.classes[class].n_slabs += 1;
+		Ok(slab)
+	}
+}
+
+impl CpuCache
+{
+	fn pop(&mut self) -> Option<*mut ()> {
+		if self.count == 0 {
+			None
+		}
+		else {
+			self.count -= 1;
+			Some(self.items[self.count])
+		}
+	}
+	fn push(&mut self, ptr: *mut ()) {
+		assert!(self.count < CACHE_SIZE);
+		self.items[self.count] = ptr;
+		self.count += 1;
+	}
+	/// Push as many items as will fit, returning the number left over (at the end of `items`)
+	fn push_all(&mut self, items: &[*mut ()]) -> usize {
+		let n = ::core::cmp::min(items.len(), CACHE_SIZE - self.count);
+		self.items[self.count .. self.count + n].clone_from_slice(&items[.. n]);
+		self.count += n;
+		items.len() - n
+	}
+	/// Remove items to fill `out`
+	fn drain(&mut self, out: &mut [*mut ()]) -> usize {
+		let n = ::core::cmp::min(out.len(), self.count);
+		self.count -= n;
+		out[.. n].clone_from_slice(&self.items[self.count .. self.count + n]);
+		n
+	}
+}
@@ -0,0 +1,130 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/memory/heap/tracker.rs
+//! Per-module allocation tracking (for leak hunting)
+//!
+//! Each allocation records a one-byte tag identifying the module that made it (taken from the allocating
+//! thread, see `set_tag`). Counts are kept per-CPU (updated with interrupts held) and summed when dumped.
+use core::cell::UnsafeCell;
+use arch::threads::MAX_CPUS;
+
+/// Maximum number of distinct tags (tag zero is the kernel core/untagged)
+const MAX_TAGS: usize = 64;
+
+/// Tag names (index zero is unused, and reported as "kernel")
+static S_TAG_NAMES: ::sync::Spinlock<[&'static str; MAX_TAGS]> = ::sync::Spinlock::new([""; MAX_TAGS]);
+
+#[derive(Copy,Clone)]
+struct TagCounts
+{
+	allocs: usize,
+	frees: usize,
+	bytes_allocated: usize,
+	bytes_freed: usize,
+}
+const TAG_COUNTS_INIT: TagCounts = TagCounts { allocs: 0, frees: 0, bytes_allocated: 0, bytes_freed: 0 };
+
+struct PerCpuCounts(UnsafeCell<[[TagCounts; MAX_TAGS]; MAX_CPUS]>);
+unsafe impl Sync for PerCpuCounts {}
+static S_COUNTS: PerCpuCounts = PerCpuCounts(UnsafeCell::new([[TAG_COUNTS_INIT; MAX_TAGS]; MAX_CPUS]));
+
+/// Handle to a heap tag set on the current thread, restores the previous tag when dropped
+pub struct TagGuard(u8);
+impl ::core::ops::Drop for TagGuard
+{
+	fn drop(&mut self) {
+		::threads::set_heap_tag(self.0);
+	}
+}
+
+/// Attribute allocations made by the current thread (and threads it spawns) to `name`
+pub fn set_tag(name: &'static str) -> TagGuard
+{
+	TagGuard( ::threads::set_heap_tag( register(name) ) )
+}
+
+/// Obtain the tag for a name (allocating a new one if needed)
+fn register(name: &'static str) -> u8
+{
+	let mut names = S_TAG_NAMES.lock();
+	if let Some(i) = names[1..].iter().position(|&n| n == name) {
+		return (i + 1) as u8;
+	}
+	match names[1..].iter().position(|&n| n == "")
+	{
+	Some(i) => {
+		names[i + 1] = name;
+		(i + 1) as u8
+		},
+	None => {
+		log_warning!("Out of heap tags, allocations by '{}' will be attributed to the kernel", name);
+		0
+		},
+	}
+}
+
+fn tag_name(tag: u8) -> &'static str
+{
+	match tag
+	{
+	0 => "kernel",
+	_ => S_TAG_NAMES.lock()[tag as usize],
+	}
+}
+
+/// Tag for allocations made by the current thread
+pub fn current() -> u8
+{
+	::threads::get_heap_tag()
+}
+
+pub fn record_alloc(tag: u8, bytes: usize)
+{
+	with_counts(tag, |c| {
+		c.allocs += 1;
+		c.bytes_allocated += bytes;
+		});
+}
+pub fn record_free(tag: u8, bytes: usize)
+{
+	with_counts(tag, |c| {
+		c.frees += 1;
+		c.bytes_freed += bytes;
+		});
+}
+
+fn with_counts<F: FnOnce(&mut TagCounts)>(tag: u8, f: F)
+{
+	let _irq = ::sync::hold_interrupts();
+	// SAFE: Interrupts are held, so nothing else can access this CPU's counts
+	let counts = unsafe { &mut (*S_COUNTS.0.get())[::arch::threads::cpu_num()][tag as usize] };
+	f(counts)
+}
+
+/// Log the live allocations for each tag
+pub fn dump()
+{
+	log_log!("Allocations by module:");
+	for tag in 0 .. MAX_TAGS
+	{
+		// NOTE: Other CPUs may update their counts while this is running, but the values are only informational
+		// SAFE: Plain reads of integer counts
+		let total = unsafe { (*S_COUNTS.0.get()).iter() }
+			.map(|cpu| cpu[tag])
+			.fold(TAG_COUNTS_INIT, |a, c| TagCounts {
+				allocs: a.allocs + c.allocs,
+				frees: a.frees + c.frees,
+				bytes_allocated: a.bytes_allocated + c.bytes_allocated,
+				bytes_freed: a.bytes_freed + c.bytes_freed,
+				});
+		if total.allocs == 0 {
+			continue ;
+		}
+		log_log!("- {}: {} live ({} bytes), {} allocated in total",
+			tag_name(tag as u8),
+			total.allocs.wrapping_sub(total.frees), total.bytes_allocated.wrapping_sub(total.bytes_freed),
+			total.allocs
+			);
+	}
+}
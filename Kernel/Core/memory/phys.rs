@@ -23,6 +23,11 @@ static S_MAPALLOC : ::sync::Mutex<(usize,PAddr)> = mutex_init!( (0,0) );
 // TODO: Multiple stacks based on page colouring
 static S_FREE_STACK : ::sync::Mutex<PAddr> = mutex_init!( NOPAGE );
 // TODO: Reference counts (maybe require arch to expose that)
+/// Map entries that have had pages taken from their start by `allocate_range_bits` : (Entry Index, New Start)
+// NOTE: Fixed-size as this is used while allocating memory for the heap
+static S_MAP_STARTS: ::sync::Mutex<[(usize,PAddr); 8]> = mutex_init!( [(!0, 0); 8] );
+/// Shared all-zero frame, used to back demand-zero pages (mapped copy-on-write)
+static S_ZERO_FRAME: ::lib::LazyStatic<PAddr> = lazystatic_init!();
 
 /// A handle to a physical page (maintaining a reference to it, even when not mapped)
 pub struct FrameHandle(PAddr);
@@ -66,6 +71,10 @@ impl FrameHandle
 	pub unsafe fn from_addr_noref(addr: PAddr) -> FrameHandle {
 		FrameHandle(addr)
 	}
+	/// Returns true if this is the only reference to the frame
+	pub fn is_unique(&self) -> bool {
+		::arch::memory::phys::get_multiref_count(self.0 as u64 / ::PAGE_SIZE as u64) == 0
+	}
 	pub fn into_addr(self) -> PAddr {
 		let rv = self.0;
 		::core::mem::forget(self);
@@ -109,23 +118,58 @@ fn is_ram(phys: PAddr) -> bool
 	false
 }
 
-pub fn make_unique(page: PAddr, virt_addr: &[u8; ::PAGE_SIZE]) -> PAddr
+/// Allocate the shared zero frame (called once the virtual memory manager is up)
+#[doc(hidden)]
+pub fn init_zero_frame()
+{
+	let mut page = ::memory::virt::alloc_free().expect("Unable to allocate the zero frame");
+	for b in page.iter_mut() {
+		*b = 0;
+	}
+	let paddr = page.into_frame().into_addr();
+	log_debug!("Zero frame = {:#x}", paddr);
+	// SAFE: Called in a single-threaded context during init
+	unsafe {
+		S_ZERO_FRAME.prep(|| paddr);
+	}
+}
+/// Obtain the shared zero frame
+///
+/// This frame is never freed, each mapping of it holds a reference (and must be copy-on-write).
+pub fn zero_frame() -> PAddr
+{
+	*S_ZERO_FRAME
+}
+
+/// Obtain a unique copy of a frame (for copy-on-write)
+///
+/// If the frame is shared, a copy is made and the caller's reference to the original frame is released.
+/// On failure, the caller's reference to the original frame is retained.
+///
+/// NOTE: The copy isn't charged to the process's quota here, every COW page is charged when it's mapped (by
+/// `allocate_user`, `reserve`, or when cloned into a new process).
+pub fn make_unique(page: PAddr, virt_addr: &[u8; ::PAGE_SIZE]) -> Result<PAddr, ::memory::virt::MapError>
 {
 	if !is_ram(page) {
 		panic!("Calling 'make_unique' on non-RAM page");
 	}
-	else if ::arch::memory::phys::get_multiref_count(page as u64 / ::PAGE_SIZE as u64) == 0 {
-		page
+	else if page != zero_frame() && ::arch::memory::phys::get_multiref_count(page as u64 / ::PAGE_SIZE as u64) == 0 {
+		Ok( page )
 	}
 	else {
 		// 1. Allocate a new frame in temp region
-		let mut new_frame = ::memory::virt::alloc_free().expect("TODO: handle OOM in make_unique");
+		let mut new_frame = try!(::memory::virt::alloc_free());
 		// 2. Copy in content of old frame
 		new_frame.clone_from_slice( virt_addr );
-		new_frame.into_frame().into_addr()
+		// 3. Release the reference to the old frame (held by the mapping being replaced)
+		deref_frame(page);
+		Ok( new_frame.into_frame().into_addr() )
 	}
 }
 
+/// Allocate a contiguous range of frames below `1 << bits` (for hardware with DMA restrictions)
+///
+/// Returns NOPAGE if no suitable range is free
 pub fn allocate_range_bits(bits: u8, count: usize) -> PAddr
 {
 	// XXX: HACK! Falls back to the simple code if possible
@@ -133,12 +177,53 @@ pub fn allocate_range_bits(bits: u8, count: usize) -> PAddr
 	{
 		return allocate_range(count);
 	}
-	// 1. Locate the last block of a suitable bitness
-	// - Take care to correctly handle blocks that straddle bitness boundaries
-	// NOTE: Memory map constructor _can_ break blocks up at common bitness boundaries (16, 24, 32 bits) to make this more efficient
-	// 2. Obtain `count` pages from either the end (if possible) or the start of this block
-	// TODO: If the block is not large enough, return an error (NOPAGE)
-	panic!("TODO: allocate_range_bits(bits={}, count={})", bits, count);
+	let limit = 1 << bits;
+	let size = (count * ::PAGE_SIZE) as PAddr;
+
+	let mut h = S_MAPALLOC.lock();
+	let mut starts = S_MAP_STARTS.lock();
+	let map = get_memory_map();
+	// 1. Locate the first free block (at or after the allocation point) with enough space below the limit
+	// - Pages are taken from the start of the block, so the main allocator just skips them
+	for i in h.0 .. map.len()
+	{
+		if map[i].state != ::memory::memorymap::MemoryState::Free {
+			continue ;
+		}
+		let start = if i == h.0 { h.1 } else { map_entry_start(&starts[..], i) };
+		let end = ::core::cmp::min(map[i].end() as PAddr, limit);
+		if start + size > end {
+			continue ;
+		}
+		// 2. Obtain `count` pages from the start of this block
+		if i == h.0 {
+			h.1 = start + size;
+		}
+		else {
+			// - Update the existing entry, or use a free slot
+			match starts.iter().position(|e| e.0 == i).or_else(|| starts.iter().position(|e| e.0 == !0))
+			{
+			Some(slot) => starts[slot] = (i, start + size),
+			None => {
+				log_notice!("allocate_range_bits - No space to record allocation from block #{}", i);
+				continue ;
+				},
+			}
+		}
+		log_trace!("allocate_range_bits(bits={}, count={}) = {:#x}", bits, count, start);
+		return start;
+	}
+	log_error!("allocate_range_bits(bits={}, count={}) - No suitable free range", bits, count);
+	NOPAGE
+}
+/// Get the first unallocated address in a memory map entry (that is after the allocation point)
+fn map_entry_start(starts: &[(usize,PAddr)], idx: usize) -> PAddr
+{
+	match starts.iter().find(|e| e.0 == idx)
+	{
+	Some(e) => e.1,
+	None => get_memory_map()[idx].start as PAddr,
+	}
 }
 
 pub fn allocate_range(count: usize) -> PAddr
@@ -152,8 +237,11 @@ pub fn allocate_range(count: usize) -> PAddr
 		log_error!("Out of physical memory");
 		return NOPAGE;
 	}
-	// If there's less than one page left in the map entry, go to the next one
-	if addr + (1 * ::PAGE_SIZE) as PAddr > map[i].end() as PAddr
+	let shift = (count * ::PAGE_SIZE) as PAddr;
+	// If the allocation doesn't fit in the map entry, go to the next one with enough space
+	// - The next entry's start may have been advanced by `allocate_range_bits`, so is checked too
+	// TODO: Put the remaining pages of a skipped entry onto the free stack
+	while addr + shift > map[i].end() as PAddr
 	{
 		i += 1;
 		while i != map.len() && map[i].state != ::memory::memorymap::MemoryState::Free {
@@ -164,14 +252,9 @@ pub fn allocate_range(count: usize) -> PAddr
 			*h = (i, 0);
 			return NOPAGE;
 		}
-		addr = map[i].start as PAddr;
+		addr = map_entry_start(&S_MAP_STARTS.lock()[..], i);
 	}
 	let rv = addr;
-	let shift = (count * ::PAGE_SIZE) as PAddr;
-	if addr + shift > map[i].end() as PAddr {
-		todo!("Handle allocating from ahead in map ({:#x} + {:#x} > {:#x}, start={:#x})", addr, shift, map[i].end(), map[i].start);
-		// TODO: If the shift pushes this allocation over the edge of a map entry, stick the remaining entries onto the free stack and move to the next free block
-	}
 	addr += shift;
 	//log_trace!("allocate_range: rv={:#x}, i={}, addr={:#x}", rv, i, addr);
 	*h = (i, addr);
@@ -24,6 +24,7 @@ pub enum ProtectionMode
 	UserRW,
 	UserRX,
 	UserCOW,	// User Copy-on-write (becomes UserRW on write)
+	UserCOWX,	// User Copy-on-write executable (becomes UserRWX on write)
 	UserRWX,	// AVOID - Read-Write-Execute (exists for internal reasons)
 }
 
@@ -32,6 +33,8 @@ pub enum MapError
 {
 	OutOfMemory,
 	RangeInUse,
+	/// The process's page quota would be exceeded
+	QuotaExceeded,
 }
 impl_from! {
 	From<::memory::phys::Error>(_v) for MapError {
@@ -41,6 +44,7 @@ impl_from! {
 		match v {
 		MapError::OutOfMemory => "VMM: Out of memory",
 		MapError::RangeInUse => "VMM: Range in use",
+		MapError::QuotaExceeded => "VMM: Quota exceeded",
 		}
 	}
 }
@@ -82,7 +86,8 @@ pub fn init()
 {
 	// 1. Tell the architecture-specific VMM that it can clean up init state
 	::arch::memory::virt::post_init();
-	// 2. ???
+	// 2. Allocate the shared zero frame (used for demand-zero allocations)
+	::memory::phys::init_zero_frame();
 }
 
 struct Pages(*mut (), usize);
@@ -122,16 +127,71 @@ pub fn with_lock<F>(addr: usize, fcn: F)
 where
 	F: FnOnce()
 {
-	// TODO: Lock
-	log_notice!("TODO: with_lock(addr={:#x})", addr);
+	use arch::memory::addresses::is_global;
+	let _lh = if is_global(addr) { s_kernelspace_lock.lock() } else { s_userspace_lock.lock() };
 	fcn();
 }
 
+/// Handle running out of memory while servicing a page fault
+///
+/// Kills the process with the most allocated pages (possibly the current one) and returns so the access is
+/// retried. `is_user` indicates that the fault came from userland (so the thread can be terminated immediately).
+pub fn handle_fault_oom(addr: usize, err: MapError, is_user: bool)
+{
+	log_warning!("{} while handling fault at {:#x}", err, addr);
+	if ::threads::oom_kill(::threads::EXIT_STATUS_OOM).is_none() {
+		// Nothing else to kill, kill the faulting process
+		::threads::kill_current_process(::threads::EXIT_STATUS_OOM);
+	}
+	if is_user {
+		::threads::check_kill();
+	}
+	// Give the killed process a chance to exit (releasing its memory)
+	::threads::yield_time();
+}
+
+/// Number of times an allocation is retried (yielding between attempts) after the OOM policy kills a process
+const OOM_RETRY_COUNT: usize = 8;
+
+/// Run an allocation made on behalf of the current process, applying the OOM policy if it runs out of memory
+///
+/// Kills the process with the most allocated pages (possibly the current one) and retries the allocation while the
+/// victim exits. Returns `OutOfMemory` if there was nothing to kill, or memory still wasn't freed.
+///
+/// NOTE: Must not be called with the address space lock held (the victim needs it to release its memory)
+pub fn with_oom_retry<T, F>(mut fcn: F) -> Result<T, MapError>
+where
+	F: FnMut() -> Result<T, MapError>
+{
+	match fcn()
+	{
+	Err(MapError::OutOfMemory) => {},
+	rv @ _ => return rv,
+	}
+	log_warning!("Out of memory during allocation, applying OOM policy");
+	if ::threads::oom_kill(::threads::EXIT_STATUS_OOM).is_none() {
+		return Err(MapError::OutOfMemory);
+	}
+	for _ in 0 .. OOM_RETRY_COUNT
+	{
+		// Give the killed process a chance to exit (releasing its memory)
+		::threads::yield_time();
+		match fcn()
+		{
+		Err(MapError::OutOfMemory) => {},
+		rv @ _ => return rv,
+		}
+	}
+	Err(MapError::OutOfMemory)
+}
+
 /// Ensure that the provded pages are valid (i.e. backed by memory)
 pub fn allocate(addr: *mut (), page_count: usize) -> Result<(), MapError> {
 	allocate_int(addr, page_count, false)
 }
 /// Allocate memory for user access
+///
+/// Pages are initially mapped to the shared zero frame (copy-on-write), and only backed by a unique frame on first write.
 pub fn allocate_user(addr: *mut (), page_count: usize) -> Result<(), MapError> {
 	allocate_int(addr, page_count, true)
 }
@@ -151,7 +211,16 @@ fn allocate_int(addr: *mut (), page_count: usize, is_user: bool) -> Result<(), M
 			return Err(MapError::RangeInUse);
 		}
 	}
-	// 3. do `page_count` single arbitary allocations
+	// 3. User allocations are demand-zero (but charged to the process's quota up-front)
+	if is_user {
+		if ::threads::charge_pages(page_count).is_err() {
+			log_notice!("allocate_user({:p},{}) - Quota exceeded", addr, page_count);
+			return Err(MapError::QuotaExceeded);
+		}
+		map_zero_pages(addr, page_count);
+		return Ok( () );
+	}
+	// 4. do `page_count` single arbitary allocations
 	for pgptr in Pages(addr, page_count) {
 		if ! ::memory::phys::allocate( pgptr ) {
 			// Allocation error!
@@ -168,15 +237,66 @@ fn allocate_int(addr: *mut (), page_count: usize, is_user: bool) -> Result<(), M
 			return Err( MapError::OutOfMemory );
 		}
 	}
-	if is_user {
-		for pgptr in Pages(addr, page_count) {
-			// SAFE: This region has just been allocated, and is KernelRW, upgrading to allow user access
-			unsafe {
-				::arch::memory::virt::reprotect(pgptr, ProtectionMode::UserRW);
-			}
+
+	Ok( () )
+}
+
+/// Map the shared zero frame (copy-on-write) into a range of user pages
+///
+/// NOTE: The caller must hold the address space lock, and have checked that the range is free
+fn map_zero_pages(addr: *mut (), page_count: usize)
+{
+	let zero = ::memory::phys::zero_frame();
+	for pgptr in Pages(addr, page_count) {
+		::memory::phys::ref_frame(zero);
+		// SAFE: Range is unused (checked by caller), and the frame is read-only to the user
+		unsafe {
+			::arch::memory::virt::map(pgptr, zero, ProtectionMode::UserCOW);
 		}
 	}
+}
 
+/// Map a set of (shared) frames into user memory
+///
+/// Each page takes a new reference to its frame, and is charged to the process's quota like any other user page.
+pub fn map_user_frames(addr: *mut (), frames: &[::memory::phys::FrameHandle], prot: ProtectionMode) -> Result<(), MapError>
+{
+	use arch::memory::addresses::is_global;
+
+	match prot
+	{
+	ProtectionMode::UserRO | ProtectionMode::UserRW | ProtectionMode::UserRX | ProtectionMode::UserRWX => {},
+	_ => panic!("Invalid protection mode passed to map_user_frames - {:?}", prot),
+	}
+	if frames.len() == 0 {
+		return Ok( () );
+	}
+	if is_global(addr as usize) || is_global(addr as usize + frames.len() * ::PAGE_SIZE - 1) {
+		return Err(MapError::RangeInUse);
+	}
+
+	// 1. Lock
+	let _lh = s_userspace_lock.lock();
+	// 2. Ensure range is free
+	for pgptr in Pages(addr, frames.len())
+	{
+		if ::arch::memory::virt::is_reserved( pgptr ) {
+			log_notice!("map_user_frames({:p},{}) - {:p} already in use", addr, frames.len(), pgptr);
+			return Err(MapError::RangeInUse);
+		}
+	}
+	// 3. Charge and map
+	if ::threads::charge_pages(frames.len()).is_err() {
+		log_notice!("map_user_frames({:p},{}) - Quota exceeded", addr, frames.len());
+		return Err(MapError::QuotaExceeded);
+	}
+	for (pgptr, frame) in Pages(addr, frames.len()).zip(frames.iter())
+	{
+		// SAFE: Range is unused (checked above), and the mapping owns the new frame reference
+		unsafe {
+			::arch::memory::virt::map(pgptr, frame.clone().into_addr(), prot);
+		}
+	}
 	Ok( () )
 }
 
@@ -201,11 +321,22 @@ pub fn reserve(addr: *mut (), page_count: usize) -> Result<Reservation, ()>
 			return Err( () );
 		}
 	}
-	// 3. do `page_count` single arbitary allocations
-	for pgptr in Pages(addr, page_count)
-	{
-		// TODO: Instead map in COW zero pages
-		::memory::phys::allocate( pgptr );
+	// 3. Populate the region
+	if is_global(addr as usize) {
+		// - Kernel reservations are backed immediately
+		for pgptr in Pages(addr, page_count)
+		{
+			::memory::phys::allocate( pgptr );
+		}
+	}
+	else {
+		// - User reservations start as COW zero pages (replaced by `map_at`, or copied on first write)
+		//   Charged up-front like `allocate_user`, and released when unmapped (or by `cancel`)
+		if ::threads::charge_pages(page_count).is_err() {
+			log_notice!("reserve({:p},{}) - Quota exceeded", addr, page_count);
+			return Err( () );
+		}
+		map_zero_pages(addr, page_count);
 	}
 	
 	Ok( Reservation(addr, page_count) )
@@ -219,19 +350,46 @@ impl Reservation
 		// SAFE: Unique, and owned
 		unsafe { ::core::slice::from_raw_parts_mut( (self.0 as usize + idx * ::PAGE_SIZE) as *mut u8, ::PAGE_SIZE) }
 	}
-	//pub fn map_at(&mut self, idx: usize, frame: FrameHandle) -> Result<(),FrameHandle> {
-	//	assert!(idx < self.1);
-	//	let addr = (self.0 as usize + idx * ::PAGE_SIZE) as *mut ();
-	//	::arch::memory::virt::unmap(addr);
-	//	::arch::memory::virt::map(addr, frame.into_addr(), ProtectionMode::KernelRW);
-	//	Ok( () )
-	//}
+	/// Replace the page at `idx` with the provided frame (e.g. a shared page cache frame)
+	pub fn map_at(&mut self, idx: usize, frame: ::memory::phys::FrameHandle) {
+		assert!(idx < self.1);
+		let addr = (self.0 as usize + idx * ::PAGE_SIZE) as *mut ();
+		// SAFE: 'self' owns this region of memory, and the frame's reference is transferred to the mapping
+		unsafe {
+			if let Some(pa) = ::arch::memory::virt::unmap(addr) {
+				::memory::phys::deref_frame(pa);
+			}
+			::arch::memory::virt::map(addr, frame.into_addr(), ProtectionMode::KernelRW);
+		}
+	}
+	/// Release the reserved region (unmapping and dereferencing all pages)
+	pub fn cancel(self) {
+		// SAFE: 'self' owns this region of memory
+		unsafe {
+			for addr in Pages(self.0, self.1) {
+				if let Some(pa) = ::arch::memory::virt::unmap(addr) {
+					::memory::phys::deref_frame(pa);
+				}
+			}
+		}
+		if ! ::arch::memory::addresses::is_global(self.0 as usize) {
+			::threads::release_pages(self.1);
+		}
+	}
 	pub fn finalise(self, final_mode: ProtectionMode) -> Result<(),()> {
 		log_trace!("Reservation::finalise(final_mode={:?})", final_mode);
+		let zero = ::memory::phys::zero_frame();
 		for addr in Pages(self.0, self.1) {
+			// Untouched zero pages must stay copy-on-write if the final mode is writable
+			let mode = match final_mode
+				{
+				ProtectionMode::UserRW  if get_phys(addr) == zero => ProtectionMode::UserCOW,
+				ProtectionMode::UserRWX if get_phys(addr) == zero => ProtectionMode::UserCOWX,
+				m @ _ => m,
+				};
 			// SAFE: Just changing flags, and 'self' owns this region of memory.
 			unsafe {
-				::arch::memory::virt::reprotect(addr, final_mode);
+				::arch::memory::virt::reprotect(addr, mode);
 			}
 		}
 		Ok( () )
@@ -278,6 +436,7 @@ pub unsafe fn reprotect_user(addr: *mut (), prot: ProtectionMode) -> Result<(),(
 			if let Some(paddr) = ::arch::memory::virt::unmap(addr) {
 				::memory::phys::deref_frame(paddr);
 			}
+			::threads::release_pages(1);
 		}
 		else {
 			::arch::memory::virt::reprotect(addr, prot);
@@ -608,6 +767,7 @@ impl fmt::Display for MapError
 		{
 		MapError::RangeInUse => write!(f, "Requested range is in use"),
 		MapError::OutOfMemory => write!(f, "Out of memory"),
+		MapError::QuotaExceeded => write!(f, "Page quota exceeded"),
 		}
 	}
 }
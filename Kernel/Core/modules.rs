@@ -106,6 +106,8 @@ fn init_module(modstates: &mut [ModuleState], mods: &[ModuleInfo], i: usize)
 		}
 		// TODO: Do module initialisation in worker threads, and handle waiting for deps before calling init
 		log_debug!("#{}: {} Init", i, module.name);
+		// - Attribute allocations made by the module (and threads it spawns) to it
+		let _tag = ::memory::heap::set_tag(module.name);
 		(module.init)();
 		modstates[i] = ModuleState::Initialised;
 	}
@@ -3,6 +3,10 @@
 //
 // Core/sync/mutex.rs
 //! Thread blocking Mutex type
+//!
+//! Contended mutexes apply priority inheritance: the owner runs with (at least) the priority of the
+//! highest priority waiter until it releases the lock. When the lock is handed to a waiter, the new owner
+//! inherits from the waiters that remain.
 #[allow(unused_imports)]
 use prelude::*;
 use core::ops;
@@ -18,6 +22,12 @@ pub struct Mutex<T: Send>
 pub struct MutexInner
 {
 	held: bool,
+	/// Current owner (for priority inheritance, `None` during hand-over or before threading is up)
+	owner: Option<::threads::ThreadRef>,
+	/// Priority lent to the owner by the waiters (if it was raised)
+	lent: Option<::threads::Priority>,
+	/// Number of waiters in each priority class (used to pick the priority lent to the next owner)
+	waiting: [usize; ::threads::NUM_PRIORITIES],
 	queue: ::threads::WaitQueue,
 }
 
@@ -44,6 +54,9 @@ impl<T: Send> Mutex<T>
 		Mutex {
 			inner: ::sync::Spinlock::new(MutexInner {
 				held: false,
+				owner: None,
+				lent: None,
+				waiting: [0; ::threads::NUM_PRIORITIES],
 				queue: ::threads::WaitQueue::new(),
 				}),
 			val: ::core::cell::UnsafeCell::new(val),
@@ -59,14 +72,28 @@ impl<T: Send> Mutex<T>
 			let mut lh = self.inner.lock();
 			if lh.held != false
 			{
+				let prio = ::threads::get_priority();
+				lh.waiting[prio as usize] += 1;
+				// Lend this thread's priority to the owner, so a lower priority owner isn't starved while holding the lock
+				lh.lend_to_owner(prio);
 				// If mutex is locked, then wait for it to be unlocked
 				// - ThreadList::wait will release the passed spinlock
 				waitqueue_wait_ext!(lh, .queue);
 				// lh.queue.wait(lh);	// << Trips borrowck
+
+				// Lock was handed over by `unlock`, record the new owner
+				let mut lh = self.inner.lock();
+				lh.owner = ::threads::get_thread_ref();
+				lh.waiting[prio as usize] -= 1;
+				// - Inherit from the remaining waiters
+				if let Some(p) = lh.waiting.iter().rposition(|&c| c > 0) {
+					lh.lend_to_owner( ::threads::Priority::from_index(p) );
+				}
 			}
 			else
 			{
 				lh.held = true;
+				lh.owner = ::threads::get_thread_ref();
 			}
 		}
 		::core::sync::atomic::fence(::core::sync::atomic::Ordering::Acquire);
@@ -75,15 +102,37 @@ impl<T: Send> Mutex<T>
 	/// Release the mutex
 	fn unlock(&self) {
 		::core::sync::atomic::fence(::core::sync::atomic::Ordering::Release);
-		let mut lh = self.inner.lock();
-		if lh.queue.has_waiter()
-		{
-			lh.queue.wake_one();
-			// *held is still true, as the newly woken thread now owns the mutex
+		let (owner, lent) = {
+			let mut lh = self.inner.lock();
+			let owner = lh.owner.take();
+			let lent = lh.lent.take();
+			if lh.queue.has_waiter()
+			{
+				lh.queue.wake_one();
+				// *held is still true, as the newly woken thread now owns the mutex
+			}
+			else
+			{
+				lh.held = false;
+			}
+			(owner, lent)
+			};
+		// Return the priority lent through this lock (the owner keeps any lent through other locks)
+		if let (Some(owner), Some(prio)) = (owner, lent) {
+			owner.clear_inherited_priority(prio);
 		}
-		else
-		{
-			lh.held = false;
+	}
+}
+
+impl MutexInner
+{
+	/// Raise the owner's priority to (at least) `prio` for as long as it holds this lock
+	fn lend_to_owner(&mut self, prio: ::threads::Priority) {
+		if let Some(ref owner) = self.owner {
+			if owner.get_priority() < prio && self.lent.map(|p| p < prio).unwrap_or(true) {
+				::threads::inherit_priority(owner, self.lent, prio);
+				self.lent = Some(prio);
+			}
 		}
 	}
 }
@@ -0,0 +1,89 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/threads/control.rs
+//! Process control notifications (exit requests, child exits, ...)
+use prelude::*;
+use lib::ring_buffer::RingBuf;
+use super::thread::ProcessID;
+use super::sleep_object::{SleepObject,SleepObjectRef};
+
+/// Maximum number of notifications queued for a process (extra notifications are dropped)
+pub const CONTROL_QUEUE_LEN: usize = 32;
+
+/// Notification delivered to a process's control queue
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum ControlEvent
+{
+	/// Another process (e.g. the session) asked this process to exit cleanly
+	TerminateRequest,
+	/// A child process exited (PID, exit status)
+	ChildExited(ProcessID, u32),
+	/// A window owned by this process was closed by the window manager (window ID)
+	WindowClosed(u32),
+	/// Application-defined notification
+	User(u32),
+}
+
+/// Per-process queue of control notifications
+pub struct ControlQueue
+{
+	/// Pending exit request, kept separately so it can't be lost to a full queue
+	terminate_pending: bool,
+	events: RingBuf<ControlEvent>,
+	waiters: Vec<SleepObjectRef>,
+}
+
+impl ControlQueue
+{
+	pub fn new() -> ControlQueue {
+		ControlQueue {
+			terminate_pending: false,
+			events: RingBuf::new(CONTROL_QUEUE_LEN),
+			waiters: Vec::new(),
+		}
+	}
+
+	/// Queue a notification and wake waiters, returns false if it was dropped (queue full)
+	pub fn push(&mut self, ev: ControlEvent) -> bool {
+		let rv = match ev
+			{
+			ControlEvent::TerminateRequest => { self.terminate_pending = true; true },
+			_ => self.events.push_back(ev).is_ok(),
+			};
+		if rv {
+			for w in self.waiters.iter() {
+				w.signal();
+			}
+		}
+		rv
+	}
+
+	/// Take the next notification (exit requests are returned first)
+	pub fn pop(&mut self) -> Option<ControlEvent> {
+		if self.terminate_pending {
+			self.terminate_pending = false;
+			Some(ControlEvent::TerminateRequest)
+		}
+		else {
+			self.events.pop_front()
+		}
+	}
+
+	fn is_empty(&self) -> bool {
+		!self.terminate_pending && self.events.is_empty()
+	}
+
+	/// Register a sleep object to be signalled when a notification is available
+	pub fn bind_wait(&mut self, obj: &mut SleepObject) {
+		if !self.is_empty() {
+			obj.signal();
+		}
+		self.waiters.push( obj.get_ref() );
+	}
+	/// Remove a registration made by `bind_wait`, returns true if a notification is available
+	pub fn clear_wait(&mut self, obj: &mut SleepObject) -> bool {
+		self.waiters.retain(|v| !v.is_from(obj));
+		!self.is_empty()
+	}
+}
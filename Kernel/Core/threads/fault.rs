@@ -0,0 +1,115 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/threads/fault.rs
+//! User fault reports (delivered to the parent process)
+
+/// Number of general purpose registers saved in a fault report
+pub const FAULT_REG_COUNT: usize = 16;
+/// Maximum number of return addresses in a fault backtrace
+pub const FAULT_BACKTRACE_LEN: usize = 16;
+/// Maximum number of loaded images in a fault report
+pub const FAULT_MAX_IMAGES: usize = 16;
+/// Maximum length of an image name in a fault report
+pub const FAULT_IMAGE_NAME_LEN: usize = 32;
+
+/// Class of an unhandled user fault
+#[derive(Copy,Clone,PartialEq,Eq,Debug)]
+pub enum FaultKind
+{
+	/// Access to an unmapped address, or a protection violation (page fault / data or prefetch abort)
+	Page = 0,
+	/// General protection fault
+	Protection = 1,
+	/// Undefined/invalid instruction
+	InvalidOpcode = 2,
+	/// Any other exception
+	Other = 3,
+}
+
+/// An image loaded in a process (registered by the loader, as load addresses are only known to it)
+#[derive(Copy,Clone,Debug,Default)]
+pub struct FaultImage
+{
+	/// Start of the image's address range
+	pub start: usize,
+	/// End of the image's address range (exclusive)
+	pub end: usize,
+	/// Load bias (difference between run-time and file addresses)
+	pub bias: usize,
+	/// Library name, NUL padded (empty for the executable)
+	pub name: [u8; FAULT_IMAGE_NAME_LEN],
+}
+
+/// Details of the fault that terminated a process
+#[derive(Copy,Clone,Debug)]
+pub struct FaultInfo
+{
+	pub kind: FaultKind,
+	/// Architecture-specific code (amd64: vector and error code, ARM: fault status register)
+	pub code: u32,
+	/// Faulting data address (zero if not applicable)
+	pub address: usize,
+	/// Instruction pointer at the time of the fault
+	pub ip: usize,
+	/// User stack pointer at the time of the fault
+	pub sp: usize,
+	/// General purpose registers (amd64: RAX,RCX,RDX,RBX,RSP,RBP,RSI,RDI,R8-R15, ARM: R0-R15)
+	pub regs: [usize; FAULT_REG_COUNT],
+	/// Return addresses, innermost first (starting with `ip`)
+	pub backtrace: [usize; FAULT_BACKTRACE_LEN],
+	pub backtrace_len: usize,
+	/// Images loaded in the process (filled from the process's registered images when saved)
+	pub images: [FaultImage; FAULT_MAX_IMAGES],
+	pub image_count: usize,
+}
+
+impl FaultInfo
+{
+	pub fn new(kind: FaultKind, code: u32, address: usize, ip: usize, sp: usize, regs: [usize; FAULT_REG_COUNT]) -> FaultInfo {
+		let mut rv = FaultInfo {
+			kind: kind, code: code, address: address,
+			ip: ip, sp: sp,
+			regs: regs,
+			backtrace: [0; FAULT_BACKTRACE_LEN],
+			backtrace_len: 1,
+			images: [Default::default(); FAULT_MAX_IMAGES],
+			image_count: 0,
+			};
+		rv.backtrace[0] = ip;
+		rv
+	}
+
+	/// Walk the user stack using the frame pointer chain
+	///
+	/// Both amd64 (RBP) and ARM (R11) frames store the previous frame pointer at `[fp]` and the return address
+	/// at `[fp + word]`. The walk stops at an unreadable frame, or if the chain doesn't move up the stack.
+	pub fn walk_frame_pointers(&mut self, mut fp: usize) {
+		const WORD: usize = ::core::mem::size_of::<usize>();
+		while self.backtrace_len < FAULT_BACKTRACE_LEN && fp != 0
+		{
+			if ::arch::memory::addresses::is_global(fp) {
+				break ;
+			}
+			let (next_fp, ra) = match (::memory::user::read::<usize>(fp), ::memory::user::read::<usize>(fp + WORD))
+				{
+				(Ok(a), Ok(b)) => (a, b),
+				_ => break,
+				};
+			if ra == 0 {
+				break ;
+			}
+			self.backtrace[self.backtrace_len] = ra;
+			self.backtrace_len += 1;
+			if next_fp <= fp {
+				break ;
+			}
+			fp = next_fp;
+		}
+	}
+
+	/// Return addresses (innermost first)
+	pub fn backtrace(&self) -> &[usize] {
+		&self.backtrace[..self.backtrace_len]
+	}
+}
@@ -7,23 +7,32 @@ use prelude::*;
 
 mod thread;
 mod thread_list;
+mod run_queue;
 mod wait_queue;
 
 mod worker_thread;
 
 mod sleep_object;
 
+mod fault;
+mod control;
+
 pub use self::thread::{Thread,ThreadPtr};
 pub use self::thread::{ThreadHandle,ProcessHandle};
-pub use self::thread::new_idle_thread;
+pub use self::thread::{Priority,ThreadRef,NUM_PRIORITIES};
+pub use self::thread::{new_idle_thread,new_ap_idle_thread};
+pub use self::thread::{oom_kill,post_control};
 
 pub use self::worker_thread::WorkerThread;
 
 pub use self::thread_list::{ThreadList,THREADLIST_INIT};
 pub use self::sleep_object::{SleepObject,SleepObjectRef};
 pub use self::wait_queue::WaitQueue;
+pub use self::fault::{FaultInfo,FaultKind,FaultImage,FAULT_REG_COUNT,FAULT_BACKTRACE_LEN,FAULT_MAX_IMAGES,FAULT_IMAGE_NAME_LEN};
+pub use self::control::{ControlEvent,CONTROL_QUEUE_LEN};
 
 use lib::mem::aref::{Aref,ArefBorrow};
+use core::sync::atomic::{AtomicUsize,AtomicBool,Ordering};
 
 /// A bitset of wait events
 pub type EventMask = u32;
@@ -31,12 +40,28 @@ pub type EventMask = u32;
 // ----------------------------------------------
 // Statics
 //static s_all_threads:	::sync::Mutex<Map<uint,*const Thread>> = mutex_init!(Map{});
-#[allow(non_upper_case_globals)]
-static s_runnable_threads: ::sync::Spinlock<ThreadList> = ::sync::Spinlock::new(THREADLIST_INIT);
+/// Per-CPU scheduler state (indexed by CPU number)
+static S_CPUS: ::lib::LazyStatic<Vec<PerCpu>> = ::lib::LazyStatic::new();
+/// Bitmask of CPUs currently halted in the idle loop
+static S_IDLE_CPUS: ::core::sync::atomic::AtomicUsize = ::core::sync::atomic::ATOMIC_USIZE_INIT;
 static S_PID0: ::lib::LazyStatic<::lib::mem::Arc<thread::Process>> = ::lib::LazyStatic::new();
 // Spinlocked due to low contention, and because the current thread is pushed to it
 static S_TO_REAP_THREADS: ::sync::Spinlock<ThreadList> = ::sync::Spinlock::new(THREADLIST_INIT);
 
+/// Scheduler state for a single CPU
+struct PerCpu
+{
+	run_queue: ::sync::Spinlock<run_queue::RunQueue>,
+	/// Priority class of the running thread
+	cur_priority: AtomicUsize,
+	/// Tick at which the running thread's time slice ends (zero if not time-sliced, i.e. idle)
+	slice_end: AtomicUsize,
+	/// Tick at which the running thread was switched in
+	switch_time: AtomicUsize,
+	/// Set to request that the running thread be preempted (on return to userland)
+	preempt: AtomicBool,
+}
+
 // ----------------------------------------------
 // Code
 /// Initialise the threading subsystem
@@ -44,7 +69,14 @@ pub fn init()
 {
 	// SAFE: Runs before any form of multi-threading starts
 	unsafe {
-		S_PID0.prep( || thread::Process::new_pid0() )
+		S_PID0.prep( || thread::Process::new_pid0() );
+		S_CPUS.prep( || (0 .. ::arch::threads::MAX_CPUS).map(|_| PerCpu {
+			run_queue: ::sync::Spinlock::new(run_queue::RUNQUEUE_INIT),
+			cur_priority: AtomicUsize::new(Priority::Normal as usize),
+			slice_end: AtomicUsize::new(0),
+			switch_time: AtomicUsize::new(0),
+			preempt: AtomicBool::new(false),
+			}).collect() );
 	}
 	let mut tid0 = Thread::new_boxed(0, "ThreadZero", S_PID0.clone());
 	tid0.cpu_state = ::arch::threads::init_tid0_state();
@@ -57,6 +89,9 @@ fn reap_threads() -> bool
 	while let Some(thread) = S_TO_REAP_THREADS.lock().pop() {
 		log_log!("Reaping thread {:?}", thread);
 		assert!(&*thread as *const Thread != ::arch::threads::borrow_thread() as *const _, "Reaping thread from itself");
+		// Wait until the CPU that was running this thread has finished switching away from it
+		while thread.cpu_state.is_running() {
+		}
 		match thread.into_boxed()
 		{
 		Ok(thread) => drop(thread),
@@ -73,17 +108,22 @@ pub fn idle_thread()
 	{
 		if ! reap_threads()
 		{
+			let cpu_mask = 1 << ::arch::threads::cpu_num();
 			// SAFE: I know what I'm doing, and we trust idle() to re-enable them
 			unsafe { ::arch::sync::stop_interrupts(); }
+			// Mark as idle before checking the queues, so a thread pushed after the check triggers a wake
+			S_IDLE_CPUS.fetch_or(cpu_mask, Ordering::SeqCst);
 			if let Some(thread) = get_thread_to_run() {
+				S_IDLE_CPUS.fetch_and(!cpu_mask, Ordering::SeqCst);
 				// SAFE: We turned them off, we turn them back on
 				unsafe { ::arch::sync::start_interrupts(); }
 				log_debug!("Idle task switch to {:?}", thread);
-				::arch::threads::switch_to(thread);
+				switch_to(thread, false);
 			}
 			else {
 				// NOTE: Idle _must_ re-enable interrupts
 				::arch::threads::idle();
+				S_IDLE_CPUS.fetch_and(!cpu_mask, Ordering::SeqCst);
 			}
 		}
 		else
@@ -100,15 +140,93 @@ pub fn yield_time()
 	reap_threads();
 
 	// Add current thread to active queue, then reschedule
-	s_runnable_threads.lock().push( get_cur_thread() );
+	push_runnable( get_cur_thread() );
 	reschedule();
 }
 
 pub fn yield_to(thread: ThreadPtr)
 {
 	log_debug!("Yielding CPU to {:?}", thread);
-	s_runnable_threads.lock().push( get_cur_thread() );
-	::arch::threads::switch_to( thread );
+	push_runnable( get_cur_thread() );
+	switch_to(thread, false);
+}
+
+/// Set the current thread's priority class
+///
+/// NOTE: Limited by the process's priority class (user processes can't exceed `Normal`)
+pub fn set_priority(prio: Priority)
+{
+	with_cur_thread(|cur| cur.set_priority(prio));
+	update_cpu_priority();
+}
+/// Obtain the current thread's effective priority class
+pub fn get_priority() -> Priority
+{
+	with_cur_thread(|cur| cur.get_priority())
+}
+/// Lower the current process's priority class (see `thread::Process::lower_priority`)
+pub fn lower_process_priority(prio: Priority) -> Result<Priority,Priority>
+{
+	let rv = with_cur_thread(|cur| cur.get_process_info().lower_priority(prio));
+	update_cpu_priority();
+	rv
+}
+/// Refresh the running CPU's priority class after the current thread's effective priority changed
+fn update_cpu_priority()
+{
+	if S_CPUS.ls_is_valid() {
+		let _irq = ::sync::hold_interrupts();
+		let cpu = &S_CPUS[::arch::threads::cpu_num()];
+		cpu.cur_priority.store(with_cur_thread(|cur| cur.get_priority()) as usize, Ordering::Relaxed);
+	}
+}
+/// Obtain a shared reference to the current thread (e.g. to record a lock owner)
+///
+/// Returns `None` if threading hasn't been initialised yet
+pub fn get_thread_ref() -> Option<ThreadRef>
+{
+	let p = ::arch::threads::borrow_thread();
+	// SAFE: Checks for NULL, and the thread should be vaild while executing
+	unsafe {
+		if p.is_null() {
+			None
+		}
+		else {
+			Some( (*p).get_ref() )
+		}
+	}
+}
+/// Total time (in ticks) spent running the current thread
+pub fn get_cpu_time() -> ::time::TickCount
+{
+	// Include the time since the thread was switched in
+	let running = if S_CPUS.ls_is_valid() {
+			let _irq = ::sync::hold_interrupts();
+			::time::ticks() - S_CPUS[::arch::threads::cpu_num()].switch_time.load(Ordering::Relaxed) as ::time::TickCount
+		}
+		else {
+			0
+		};
+	with_cur_thread(|cur| cur.get_cpu_time()) + running
+}
+
+/// Raise a thread's priority to (at least) `prio` for as long as it holds a contended lock (priority inheritance)
+///
+/// `prev` is the priority previously lent through the same lock (which `prio` replaces)
+pub fn inherit_priority(thread: &ThreadRef, prev: Option<Priority>, prio: Priority)
+{
+	if thread.inherit_priority(prev, prio)
+	{
+		log_trace!("{:?} inherits priority {:?}", thread, prio);
+		// If the thread is waiting to run, move it to the queue for its new priority
+		let _irq = ::sync::hold_interrupts();
+		for cpu in S_CPUS[.. ::arch::threads::cpu_count()].iter()
+		{
+			if cpu.run_queue.lock().requeue(thread) {
+				break ;
+			}
+		}
+	}
 }
 
 pub fn terminate_thread() -> !
@@ -118,6 +236,11 @@ pub fn terminate_thread() -> !
 		panic!("TID 0 terminated");
 	}
 
+	// Wake joiners (if `exit_thread` wasn't used, the exit value is zero)
+	with_cur_thread(|cur| cur.mark_exit(0));
+	// Release the process's objects if this is the last thread (done while still able to block)
+	with_cur_thread(|cur| cur.get_process_info().thread_terminated());
+
 	// NOTE: Can this just obtain a handle to the current thread then drop it?
 	// - No... kinda needs to be properly reaped. (so that no outstanding pointers exist)
 	//
@@ -131,24 +254,135 @@ pub fn terminate_thread() -> !
 	unreachable!();
 }
 
+/// Terminate the current thread with the passed exit value (see `ThreadHandle::join`)
+pub fn exit_thread(value: u32) -> !
+{
+	with_cur_thread(|cur| cur.mark_exit(value));
+	terminate_thread();
+}
+
 pub fn exit_process(status: u32) -> ! {
-	// Requirements:
-	// - Save exit status somewhere
-	match with_cur_thread( |cur| cur.get_process_info().mark_exit(status) )
-	{
-	Ok(_) => {},
-	Err(_) => todo!("Two threads raced to exit"),
+	// - Save the exit status, and request that all other threads terminate
+	//  > Other threads terminate on their next syscall boundary (sleeping threads are woken by the kill)
+	if with_cur_thread( |cur| cur.get_process_info().request_kill(status) ) {
+		log_notice!("Terminating process with status={:#x}", status);
+	}
+	else {
+		// Another thread exited first (or the process was killed), just terminate this thread
+		log_notice!("Process already exited, terminating thread");
 	}
-	log_notice!("Terminating process with status={:#x}", status);
-
-	// - Request all other threads terminate
-	// TODO: How would this be done cleanly? Need to wake all and terminate on syscall boundary?
 	
 	// - Terminate this thread
-	//  > Process reaping is handled by the PCB dropping when refcount reaches zero
+	//  > Objects are released by the last thread to terminate, the address space once all are reaped
 	terminate_thread();
 }
 
+// Exit statuses set by the kernel (EXIT_STATUS_*), shared with userland via syscalls.inc.rs
+include!("../../../exitstatus.inc.rs");
+
+/// Terminate the current process after an unhandled user fault, saving the fault report for the parent
+pub fn fault_process(info: FaultInfo) -> ! {
+	log_warning!("User fault {:?} (code {:#x}) at {:#x} addr={:#x}", info.kind, info.code, info.ip, info.address);
+	for &addr in info.backtrace() {
+		log_warning!(" > {:#x}", addr);
+	}
+	// - Saved before the exit status is set, so it's visible to anything woken by the termination
+	with_cur_thread(|cur| cur.get_process_info().set_fault(info));
+	exit_process(EXIT_STATUS_FAULT);
+}
+
+/// Record the images loaded in the current process, included in its fault report (see `FaultInfo::images`)
+pub fn set_loaded_images(images: &[FaultImage]) {
+	with_cur_thread(|cur| cur.get_process_info().set_loaded_images(images))
+}
+
+/// Kill the current process (threads terminate on their next return to userland)
+pub fn kill_current_process(status: u32)
+{
+	if get_process_id() == 0 {
+		panic!("Attempting to kill PID0 (status={:#x})", status);
+	}
+	with_cur_thread(|cur| cur.get_process_info().request_kill(status));
+}
+
+/// Charge pages to the current process's quota
+pub fn charge_pages(count: usize) -> Result<(),()>
+{
+	with_cur_thread(|cur| cur.get_process_info().charge_pages(count))
+}
+/// Release pages charged with `charge_pages`
+pub fn release_pages(count: usize)
+{
+	with_cur_thread(|cur| cur.get_process_info().release_pages(count))
+}
+/// Returns true if the current thread's process has been killed
+#[is_safe(irq)]
+pub fn is_killed() -> bool
+{
+	let p = ::arch::threads::borrow_thread();
+	// SAFE: Checks for NULL, and the thread should be vaild while executing
+	unsafe { !p.is_null() && (*p).get_process_info().is_kill_requested() }
+}
+/// Wake the passed sleep object if the current process is killed (used by user-initiated waits)
+pub fn bind_wait_kill(obj: &mut SleepObject)
+{
+	with_cur_thread(|cur| cur.get_process_info().bind_wait_kill(obj))
+}
+/// Remove a registration made with `bind_wait_kill`
+pub fn clear_wait_kill(obj: &mut SleepObject)
+{
+	with_cur_thread(|cur| cur.get_process_info().clear_wait_kill(obj))
+}
+/// Register a sleeping thread's object with the current process (see `SleepObject::wait`)
+///
+/// Returns false (dropping the reference) for kernel threads, or if the process has already been killed.
+fn bind_sleep_kill(r: SleepObjectRef) -> bool
+{
+	let p = ::arch::threads::borrow_thread();
+	// SAFE: Checks for NULL, and the thread should be vaild while executing
+	let process = unsafe {
+		if p.is_null() {
+			return false;
+		}
+		(*p).get_process_info()
+		};
+	if process.get_pid() == 0 {
+		false
+	}
+	else {
+		process.add_kill_sleeper(r)
+	}
+}
+/// Remove a registration made by `bind_sleep_kill`
+fn clear_sleep_kill(obj: &SleepObject)
+{
+	with_cur_thread(|cur| cur.get_process_info().remove_kill_sleeper(obj))
+}
+/// Take the next control notification queued for the current process
+pub fn pop_control() -> Option<ControlEvent>
+{
+	with_cur_thread(|cur| cur.get_process_info().pop_control())
+}
+/// Wake the passed sleep object when a control notification is queued for the current process
+pub fn bind_wait_control(obj: &mut SleepObject)
+{
+	with_cur_thread(|cur| cur.get_process_info().bind_wait_control(obj))
+}
+/// Remove a registration made with `bind_wait_control`, returns true if a notification is pending
+pub fn clear_wait_control(obj: &mut SleepObject) -> bool
+{
+	with_cur_thread(|cur| cur.get_process_info().clear_wait_control(obj))
+}
+/// Terminate the current thread if its process has been killed
+///
+/// Called on kernel entry/exit points where the thread holds no kernel resources
+pub fn check_kill()
+{
+	if is_killed() {
+		terminate_thread();
+	}
+}
+
 pub fn get_thread_id() -> thread::ThreadID
 {
 	let p = ::arch::threads::borrow_thread();
@@ -172,6 +406,26 @@ pub fn get_process_id() -> thread::ProcessID {
 	p.get_process_info().get_pid()
 }
 
+/// Heap allocation tag of the current thread (zero if threading isn't initialised yet)
+pub fn get_heap_tag() -> u8
+{
+	let p = ::arch::threads::borrow_thread();
+	// SAFE: Checks for NULL, and the thread should be vaild while executing
+	unsafe {
+		if p == 0 as *const _ {
+			0
+		}
+		else {
+			(*p).get_heap_tag()
+		}
+	}
+}
+/// Set the current thread's heap allocation tag, returning the previous tag
+pub fn set_heap_tag(tag: u8) -> u8
+{
+	with_cur_thread(|cur| cur.set_heap_tag(tag))
+}
+
 fn with_cur_thread<T, F: FnOnce(&thread::Thread)->T>(fcn: F) -> T
 {
 	// SAFE: Checks for NULL, and the thread should be vaild while executing
@@ -234,13 +488,13 @@ pub fn reschedule()
 			if &*thread as *const _ == ::arch::threads::borrow_thread() as *const _
 			{
 				log_debug!("Task switch to self, idle");
-				::arch::threads::switch_to(thread);
+				switch_to(thread, false);
 				::arch::threads::idle();
 			}
 			else
 			{
 				log_debug!("Task switch to {:?}", thread);
-				::arch::threads::switch_to(thread);
+				switch_to(thread, false);
 				//log_debug!("Awoke");
 			}
 			return ;
@@ -253,7 +507,7 @@ pub fn reschedule()
 				log_trace!("reschedule() - No active threads, idling");
 				
 				// Switch to the idle thread
-				::arch::threads::switch_to( thread );
+				switch_to(thread, true);
 			}
 			else {
 				::core::mem::forget(thread);
@@ -276,19 +530,140 @@ fn rel_cur_thread(t: ThreadPtr)
 //	BorrowedThread( Some(get_cur_thread()) )
 //}
 
+/// Add a thread to the current CPU's run queue, waking an idle CPU to pick it up
+fn push_runnable(thread: ThreadPtr)
+{
+	let _irq_lock = ::arch::sync::hold_interrupts();
+	let cpu = ::arch::threads::cpu_num();
+	let prio = thread.get_priority();
+	S_CPUS[cpu].run_queue.lock().push(thread);
+
+	// If another CPU is idle, wake it (it will steal from this queue)
+	let idle = S_IDLE_CPUS.load(Ordering::SeqCst) & !(1 << cpu);
+	if idle != 0 {
+		::arch::threads::wake_cpu( idle.trailing_zeros() as usize );
+	}
+	else {
+		// Otherwise, preempt a CPU running a lower priority thread
+		for (i, c) in S_CPUS[.. ::arch::threads::cpu_count()].iter().enumerate()
+		{
+			if c.cur_priority.load(Ordering::Relaxed) < prio as usize {
+				c.preempt.store(true, Ordering::SeqCst);
+				::arch::threads::wake_cpu(i);
+				break ;
+			}
+		}
+	}
+}
+
+/// Switch to another thread, updating CPU time accounting and starting the new thread's time slice
+fn switch_to(thread: ThreadPtr, is_idle: bool)
+{
+	let now = ::time::ticks();
+	{
+		let _irq = ::sync::hold_interrupts();
+		let cpu = &S_CPUS[::arch::threads::cpu_num()];
+		// - Charge the outgoing thread for the time since it was switched in
+		let prev = cpu.switch_time.swap(now as usize, Ordering::Relaxed) as ::time::TickCount;
+		with_cur_thread(|cur| cur.add_cpu_time(now - prev));
+		// - Start the incoming thread's slice
+		cpu.preempt.store(false, Ordering::Relaxed);
+		if is_idle {
+			cpu.cur_priority.store(Priority::Idle as usize, Ordering::Relaxed);
+			cpu.slice_end.store(0, Ordering::Relaxed);
+		}
+		else {
+			let prio = thread.get_priority();
+			let end = now + prio.time_slice();
+			cpu.cur_priority.store(prio as usize, Ordering::Relaxed);
+			cpu.slice_end.store(end as usize, Ordering::Relaxed);
+			::arch::request_tick(end);
+		}
+	}
+	::arch::threads::switch_to(thread);
+}
+
+/// Check for expired time slices (called from the timer interrupt)
+///
+/// Returns the tick at which the earliest remaining slice ends
+#[is_safe(irq)]
+pub fn check_time_slices(now: ::time::TickCount) -> Option<::time::TickCount>
+{
+	if ! S_CPUS.ls_is_valid() {
+		return None;
+	}
+	let mut earliest = None;
+	for (i, cpu) in S_CPUS[.. ::arch::threads::cpu_count()].iter().enumerate()
+	{
+		let end = cpu.slice_end.load(Ordering::Relaxed) as ::time::TickCount;
+		if end == 0 {
+			continue ;
+		}
+		let end = if end > now {
+				end
+			}
+			else if run_queue::any_queued( Priority::from_index(cpu.cur_priority.load(Ordering::Relaxed)) ) {
+				// Another thread of at least the same priority is waiting, preempt
+				// - The next slice is started when the next thread is switched in
+				cpu.slice_end.store(0, Ordering::Relaxed);
+				cpu.preempt.store(true, Ordering::SeqCst);
+				::arch::threads::wake_cpu(i);
+				continue ;
+			}
+			else {
+				// Nothing to switch to, extend the current slice
+				let prio = Priority::from_index(cpu.cur_priority.load(Ordering::Relaxed));
+				let end = now + prio.time_slice();
+				cpu.slice_end.store(end as usize, Ordering::Relaxed);
+				end
+			};
+		earliest = Some(::core::cmp::min(earliest.unwrap_or(!0), end));
+	}
+	earliest
+}
+
+/// Returns true (and clears the request) if the current thread should be preempted
+///
+/// Called by the architecture code when returning to userland from an interrupt
+#[is_safe(irq)]
+pub fn take_preempt_request() -> bool
+{
+	if ! S_CPUS.ls_is_valid() {
+		return false;
+	}
+	S_CPUS[::arch::threads::cpu_num()].preempt.swap(false, Ordering::SeqCst)
+}
+
 fn get_thread_to_run() -> Option<ThreadPtr>
 {
 	let _irq_lock = ::arch::sync::hold_interrupts();
-	let mut handle = s_runnable_threads.lock();
-	if handle.empty()
+	let cpu = ::arch::threads::cpu_num();
+	let count = ::arch::threads::cpu_count();
+	// 1. Find the queue with the highest priority thread
+	// - Prefers this CPU's queue, then steals from the others (starting from the next one along, to spread the load)
+	let mut best: Option<(usize, Priority)> = None;
+	for i in 0 .. count
 	{
-		// WTF? At least an idle thread should be ready
-		None
+		let idx = (cpu + i) % count;
+		if let Some(p) = S_CPUS[idx].run_queue.lock().highest() {
+			if best.map(|(_,bp)| p > bp).unwrap_or(true) {
+				best = Some( (idx, p) );
+			}
+		}
 	}
-	else
+	// 2. Pop off that queue
+	// - The queue could have been emptied since it was checked, in which case the caller will try again later
+	match best
 	{
-		// 2. Pop off a new thread
-		handle.pop()
+	Some( (idx, _) ) => {
+		let rv = S_CPUS[idx].run_queue.lock().pop();
+		if idx != cpu {
+			log_trace!("CPU{} stole {:?} from CPU{}", cpu, rv, idx);
+		}
+		rv
+		},
+	// WTF? At least an idle thread should be ready
+	None => None,
 	}
 }
 
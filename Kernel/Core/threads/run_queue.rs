@@ -0,0 +1,73 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/threads/run_queue.rs
+//! Per-CPU queue of runnable threads
+use core::sync::atomic::{AtomicUsize,Ordering,ATOMIC_USIZE_INIT};
+use super::{ThreadList,THREADLIST_INIT,ThreadPtr};
+use super::thread::{Priority,NUM_PRIORITIES};
+
+/// Queue of runnable threads, one FIFO list per priority class
+pub struct RunQueue
+{
+	lists: [ThreadList; NUM_PRIORITIES],
+}
+
+pub const RUNQUEUE_INIT: RunQueue = RunQueue {
+	lists: [THREADLIST_INIT, THREADLIST_INIT, THREADLIST_INIT],
+	};
+
+/// Number of queued threads in each class (across all CPUs)
+static S_QUEUED_COUNTS: [AtomicUsize; NUM_PRIORITIES] = [ATOMIC_USIZE_INIT, ATOMIC_USIZE_INIT, ATOMIC_USIZE_INIT];
+
+/// Returns true if any CPU has a queued thread of (at least) the specified class
+pub fn any_queued(min: Priority) -> bool
+{
+	(min as usize .. NUM_PRIORITIES).any(|i| S_QUEUED_COUNTS[i].load(Ordering::Relaxed) > 0)
+}
+
+impl RunQueue
+{
+	/// Add a thread to the back of the list for its current priority
+	pub fn push(&mut self, thread: ThreadPtr)
+	{
+		let idx = thread.get_priority() as usize;
+		S_QUEUED_COUNTS[idx].fetch_add(1, Ordering::Relaxed);
+		self.lists[idx].push(thread);
+	}
+	/// Pop the first thread from the highest non-empty class
+	pub fn pop(&mut self) -> Option<ThreadPtr>
+	{
+		for idx in (0 .. NUM_PRIORITIES).rev()
+		{
+			if let Some(t) = self.lists[idx].pop() {
+				S_QUEUED_COUNTS[idx].fetch_sub(1, Ordering::Relaxed);
+				return Some(t);
+			}
+		}
+		None
+	}
+
+	/// Returns the highest class with a queued thread
+	pub fn highest(&self) -> Option<Priority>
+	{
+		(0 .. NUM_PRIORITIES).rev().find(|&i| !self.lists[i].empty()).map(Priority::from_index)
+	}
+
+	/// Move a thread to the list for its current priority (if it's queued in a lower class)
+	///
+	/// Returns true if the thread was found
+	pub fn requeue(&mut self, thread: &super::ThreadRef) -> bool
+	{
+		let new_idx = thread.get_priority() as usize;
+		for idx in 0 .. new_idx
+		{
+			if let Some(t) = self.lists[idx].remove_first(|t| thread.is_thread(t)) {
+				S_QUEUED_COUNTS[idx].fetch_sub(1, Ordering::Relaxed);
+				self.push(t);
+				return true;
+			}
+		}
+		false
+	}
+}
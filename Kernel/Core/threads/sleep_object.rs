@@ -5,7 +5,6 @@
 //! Sleep object
 use core::ops;
 use super::thread::{ThreadPtr, RunState};
-use super::s_runnable_threads;
 
 /// An object on which a thread can sleep, woken by various event sources
 ///
@@ -58,10 +57,21 @@ impl<'a> SleepObject<'a>
 	}
 	
 	/// Wait the current thread on this object
+	///
+	/// Threads of user processes are also woken if their process is killed (callers should expect spurious wakes)
 	pub fn wait(&self)
 	{
 		//log_trace!("SleepObject::wait {:p} '{}'", self, self.name);
 		
+		// - Register with the current process (woken by `Process::request_kill`), removed once awake
+		let kill_bound = super::bind_sleep_kill(self.get_ref_int());
+		self.wait_int();
+		if kill_bound {
+			super::clear_sleep_kill(self);
+		}
+	}
+	fn wait_int(&self)
+	{
 		let irql = ::sync::hold_interrupts();
 		let mut lh = self.inner.lock();
 		assert!( lh.thread.is_none(), "A thread is already sleeping on object {:p} '{}'", self, self.name );
@@ -100,7 +110,7 @@ impl<'a> SleepObject<'a>
 		if let Some(mut t) = lh.thread.take()
 		{
 			t.set_state( RunState::Runnable );
-			s_runnable_threads.lock().push(t);
+			super::push_runnable(t);
 		}
 		else
 		{
@@ -112,6 +122,10 @@ impl<'a> SleepObject<'a>
 	///
 	/// NOTE: After this is called, self must not move. This is enforced using a self-borrow
 	pub fn get_ref(&'a self) -> SleepObjectRef {
+		self.get_ref_int()
+	}
+	/// Obtain a reference without the self-borrow (the caller must ensure it's released before `self` moves)
+	fn get_ref_int(&self) -> SleepObjectRef {
 		self.inner.lock().reference_count += 1;
 		SleepObjectRef {
 			obj: self as *const _ as *const () as *const _,
@@ -13,6 +13,7 @@
  */
 use prelude::*;
 use lib::mem::Arc;
+use core::sync::atomic::{AtomicUsize,AtomicBool,Ordering};
 
 /// Thread identifier (unique)
 pub type ThreadID = u32;
@@ -35,13 +36,99 @@ pub enum RunState
 unsafe impl Send for RunState { }
 impl Default for RunState { fn default() -> RunState { RunState::Runnable } }
 
+/// Scheduling priority class
+///
+/// Higher classes always run before lower ones, threads within a class are round-robin scheduled.
+#[derive(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Debug)]
+pub enum Priority
+{
+	/// Only runs when no other threads are runnable
+	Idle = 0,
+	/// Default class for both kernel and user threads
+	Normal = 1,
+	/// Latency-sensitive kernel threads (IRQ workers, GUI rendering)
+	Realtime = 2,
+}
+/// Number of priority classes (size of a run queue)
+pub const NUM_PRIORITIES: usize = 3;
+
+impl Priority
+{
+	/// Convert from an index (inverse of `as usize`)
+	pub fn from_index(idx: usize) -> Priority {
+		match idx
+		{
+		0 => Priority::Idle,
+		1 => Priority::Normal,
+		_ => Priority::Realtime,
+		}
+	}
+	/// Length of a time slice for this class, in ticks
+	///
+	/// Based on the `TIMESLICE` boot option, realtime threads get half the base slice (they're expected
+	/// to block quickly), and idle threads get double.
+	pub fn time_slice(&self) -> ::time::TickCount {
+		let base = base_time_slice();
+		match *self
+		{
+		Priority::Realtime => ::core::cmp::max(base / 2, 1),
+		Priority::Normal => base,
+		Priority::Idle => base * 2,
+		}
+	}
+}
+
+/// Base time slice (ms), cached from the boot configuration on first use
+static S_BASE_TIME_SLICE: AtomicUsize = ::core::sync::atomic::ATOMIC_USIZE_INIT;
+fn base_time_slice() -> ::time::TickCount {
+	match S_BASE_TIME_SLICE.load(Ordering::Relaxed)
+	{
+	0 => {
+		let v = match ::config::get_string(::config::Value::TimeSlice).parse::<usize>()
+			{
+			Ok(0) | Err(_) => {
+				log_warning!("Invalid TIMESLICE value, using 10ms");
+				10
+				},
+			Ok(v) => v,
+			};
+		S_BASE_TIME_SLICE.store(v, Ordering::Relaxed);
+		v as ::time::TickCount
+		},
+	v => v as ::time::TickCount,
+	}
+}
+
 pub struct Process
 {
 	name: String,
 	pid: ProcessID,
-	address_space: ::memory::virt::AddressSpace,
+	/// PID of the process that created this one (zero for kernel-created processes)
+	parent: ProcessID,
+	/// Address space, released once all threads have been reaped
+	address_space: ::sync::Mutex<Option<::memory::virt::AddressSpace>>,
 	// TODO: use of a tuple here looks a little crufty
-	exit_status: ::sync::Mutex< (Option<u32>, Option<::threads::sleep_object::SleepObjectRef>) >,
+	exit_status: ::sync::Mutex< (Option<u32>, Vec<::threads::sleep_object::SleepObjectRef>) >,
+	/// Sleeping threads to wake when the process is killed (so they can terminate)
+	kill_waiters: ::sync::Mutex< Vec<::threads::sleep_object::SleepObjectRef> >,
+	/// Number of threads that haven't yet terminated
+	live_threads: AtomicUsize,
+	/// Number of threads that haven't yet been reaped
+	thread_count: AtomicUsize,
+	/// Maximum priority class for threads in this process (can only be lowered)
+	priority: AtomicUsize,
+	/// Maximum number of pages this process may allocate (zero for unlimited)
+	page_quota: AtomicUsize,
+	/// Number of pages currently charged to this process
+	page_count: AtomicUsize,
+	/// Set when the process has been killed, threads terminate on their next kernel entry
+	kill_requested: AtomicBool,
+	/// Report of the fault that terminated the process (if any)
+	fault_info: ::sync::Mutex<Option<::threads::FaultInfo>>,
+	/// Images registered by the loader (copied into the fault report)
+	loaded_images: ::sync::Mutex<Vec<::threads::FaultImage>>,
+	/// Pending control notifications (see `::threads::ControlEvent`)
+	control: ::sync::Mutex<super::control::ControlQueue>,
 	pub proc_local_data: ::sync::RwLock<Vec< ::lib::mem::aref::Aref<::core::any::Any+Sync+Send> >>,
 }
 /// Handle to a process, used for spawning and communicating
@@ -57,9 +144,25 @@ struct SharedBlock
 	name: String,
 	tid: ThreadID,
 	process: Arc<Process>,
+	/// Requested priority class
+	base_priority: AtomicUsize,
+	/// Priority inherited from threads blocked on a mutex held by this thread
+	inherited_priority: AtomicUsize,
+	/// Number of held mutexes lending each priority class (`inherited_priority` is the highest with a non-zero count)
+	lent_priorities: ::sync::Spinlock<[usize; NUM_PRIORITIES]>,
+	/// Total time spent running (ticks)
+	cpu_time: AtomicUsize,
+	/// Exit value (set when the thread terminates), and sleep objects waiting for it
+	exit_status: ::sync::Mutex< (Option<u32>, Vec<::threads::sleep_object::SleepObjectRef>) >,
 }
 
+/// Shared reference to a thread (used to track mutex owners for priority inheritance)
+#[derive(Clone)]
+pub struct ThreadRef(Arc<SharedBlock>);
+
 /// An owning thread handle
+///
+/// Dropping the handle detaches the thread (it continues to run)
 pub struct ThreadHandle
 {
 	block: Arc<SharedBlock>,
@@ -81,6 +184,8 @@ pub struct Thread
 	pub cpu_state: ::arch::threads::State,
 	/// Next thread in intrusive list
 	pub next: Option<ThreadPtr>,
+	/// Heap allocation tag (inherited from the creating thread)
+	heap_tag: AtomicUsize,
 }
 assert_trait!{Thread : Send}
 
@@ -120,42 +225,233 @@ fn allocate_pid() -> u32
 	(rv + 1) as u32
 }
 
+/// Pointer to a live process (entries are removed by `Process::drop`)
+struct ProcessPtr(*const Process);
+unsafe impl Send for ProcessPtr {}
+/// List of all user processes (used by the out-of-memory policy, and to deliver control notifications)
+static S_PROCESSES: ::sync::mutex::LazyMutex<Vec<ProcessPtr>> = ::sync::mutex::LazyMutex::new();
+
+/// Select the process charged with the most pages and kill it
+///
+/// Returns the PID of the killed process, or `None` if there was nothing suitable to kill
+pub fn oom_kill(status: u32) -> Option<ProcessID>
+{
+	let (pid, parent, exited) = {
+		let lh = S_PROCESSES.lock_init(|| Vec::new());
+		// SAFE: Entries are removed (under this lock) before the process is freed
+		let victim = lh.iter()
+			.map(|p| unsafe { &*p.0 })
+			.filter(|p| !p.is_kill_requested())
+			.max_by_key(|p| p.get_page_count());
+		match victim
+		{
+		Some(p) if p.get_page_count() > 0 => {
+			log_warning!("Out of memory, killing PID{} '{}' ({} pages)", p.pid, p.name, p.get_page_count());
+			(p.pid, p.parent, p.kill_inner(status))
+			},
+		_ => return None,
+		}
+		};
+	// - Parent notified outside the list lock (`post_control` takes it)
+	if exited && parent != 0 {
+		post_control(parent, ::threads::ControlEvent::ChildExited(pid, status));
+	}
+	Some(pid)
+}
+
+/// Queue a control notification for the process with the specified PID
+///
+/// Returns false if the process doesn't exist (or has exited), or its queue is full
+pub fn post_control(pid: ProcessID, ev: ::threads::ControlEvent) -> bool
+{
+	let lh = S_PROCESSES.lock_init(|| Vec::new());
+	// SAFE: Entries are removed (under this lock) before the process is freed
+	match lh.iter().map(|p| unsafe { &*p.0 }).find(|p| p.pid == pid)
+	{
+	Some(p) => p.post_control(ev),
+	None => false,
+	}
+}
+
+impl ::core::ops::Drop for Process
+{
+	fn drop(&mut self) {
+		let mut lh = S_PROCESSES.lock_init(|| Vec::new());
+		let ptr = self as *const Process;
+		lh.retain(|p| p.0 != ptr);
+	}
+}
+
 impl Process
 {
 	pub fn new_pid0() -> Arc<Process> {
 		Arc::new(Process {
 			name: String::from("PID0"),
 			pid: 0,
+			parent: 0,
 			exit_status: Default::default(),
-			address_space: ::memory::virt::AddressSpace::pid0(),
+			kill_waiters: Default::default(),
+			live_threads: AtomicUsize::new(0),
+			thread_count: AtomicUsize::new(0),
+			address_space: ::sync::Mutex::new( Some(::memory::virt::AddressSpace::pid0()) ),
+			// Kernel threads are allowed to use any priority
+			priority: AtomicUsize::new(Priority::Realtime as usize),
+			page_quota: AtomicUsize::new(0),
+			page_count: AtomicUsize::new(0),
+			kill_requested: AtomicBool::new(false),
+			fault_info: Default::default(),
+			loaded_images: Default::default(),
+			control: ::sync::Mutex::new( super::control::ControlQueue::new() ),
 			proc_local_data: ::sync::RwLock::new( Vec::new() ),
 		})
 	}
-	pub fn new<S: Into<String>+::core::fmt::Debug>(name: S, addr_space: ::memory::virt::AddressSpace) -> Arc<Process>
+	pub fn new<S: Into<String>+::core::fmt::Debug>(name: S, parent: ProcessID, addr_space: ::memory::virt::AddressSpace, page_quota: usize) -> Arc<Process>
 	{
-		Arc::new(Process {
+		let rv = Arc::new(Process {
 			pid: allocate_pid(),
+			parent: parent,
 			name: name.into(),
 			exit_status: Default::default(),
-			address_space: addr_space,
+			kill_waiters: Default::default(),
+			live_threads: AtomicUsize::new(0),
+			thread_count: AtomicUsize::new(0),
+			address_space: ::sync::Mutex::new( Some(addr_space) ),
+			priority: AtomicUsize::new(Priority::Normal as usize),
+			page_quota: AtomicUsize::new(page_quota),
+			page_count: AtomicUsize::new(0),
+			kill_requested: AtomicBool::new(false),
+			fault_info: Default::default(),
+			loaded_images: Default::default(),
+			control: ::sync::Mutex::new( super::control::ControlQueue::new() ),
 			proc_local_data: ::sync::RwLock::new( Vec::new() ),
-		})
+		});
+		S_PROCESSES.lock_init(|| Vec::new()).push( ProcessPtr(&*rv) );
+		rv
 	}
 	
 	fn empty_cpu_state(&self) -> ::arch::threads::State {
-		::arch::threads::State::new( &self.address_space )
+		let lh = self.address_space.lock();
+		::arch::threads::State::new( lh.as_ref().expect("Creating a thread in a torn-down process") )
 	}
 
 	pub fn get_pid(&self) -> ProcessID { self.pid }
 
+	/// Maximum priority class for this process's threads
+	pub fn get_priority(&self) -> Priority {
+		Priority::from_index( self.priority.load(Ordering::Relaxed) )
+	}
+	/// Lower the process's priority class, returns the previous class (or `Err` with the current class if `prio` is higher)
+	pub fn lower_priority(&self, prio: Priority) -> Result<Priority,Priority> {
+		let mut cur = self.priority.load(Ordering::Relaxed);
+		loop
+		{
+			if prio as usize > cur {
+				return Err( Priority::from_index(cur) );
+			}
+			let v = self.priority.compare_and_swap(cur, prio as usize, Ordering::Relaxed);
+			if v == cur {
+				return Ok( Priority::from_index(cur) );
+			}
+			cur = v;
+		}
+	}
+
+	/// Maximum number of pages this process can allocate (zero for unlimited)
+	pub fn get_page_quota(&self) -> usize {
+		self.page_quota.load(Ordering::Relaxed)
+	}
+	/// Number of pages currently charged to this process
+	pub fn get_page_count(&self) -> usize {
+		self.page_count.load(Ordering::Relaxed)
+	}
+	/// Charge `count` pages to this process, fails if that would exceed the quota
+	pub fn charge_pages(&self, count: usize) -> Result<(),()> {
+		let quota = self.page_quota.load(Ordering::Relaxed);
+		let mut cur = self.page_count.load(Ordering::Relaxed);
+		loop
+		{
+			if quota != 0 && cur + count > quota {
+				return Err( () );
+			}
+			let v = self.page_count.compare_and_swap(cur, cur + count, Ordering::Relaxed);
+			if v == cur {
+				return Ok( () );
+			}
+			cur = v;
+		}
+	}
+	/// Release pages previously charged with `charge_pages`
+	pub fn release_pages(&self, count: usize) {
+		let mut cur = self.page_count.load(Ordering::Relaxed);
+		loop
+		{
+			// NOTE: Saturating, as pages not allocated by this process (e.g. mapped files) can be unmapped
+			let v = self.page_count.compare_and_swap(cur, cur.saturating_sub(count), Ordering::Relaxed);
+			if v == cur {
+				return ;
+			}
+			cur = v;
+		}
+	}
+
+	/// Request that the process be killed (the exit status is set immediately)
+	///
+	/// Running threads terminate at their next kernel entry/exit, and every thread sleeping on a `SleepObject`
+	/// (IPC, VFS and user waits) is woken. Threads blocked on kernel locks continue once the lock is released
+	/// (such locks are only held for bounded periods). Returns false if the process had already exited or been killed.
+	pub fn request_kill(&self, status: u32) -> bool {
+		let rv = self.kill_inner(status);
+		if rv {
+			self.notify_parent(status);
+		}
+		rv
+	}
+	/// Kill without notifying the parent (for callers holding the process list lock)
+	fn kill_inner(&self, status: u32) -> bool {
+		if self.kill_requested.swap(true, Ordering::SeqCst) {
+			return false;
+		}
+		let rv = self.set_exit_status(status).is_ok();
+		for w in self.kill_waiters.lock().iter() {
+			w.signal();
+		}
+		rv
+	}
+	/// Returns true if the process has been killed
+	pub fn is_kill_requested(&self) -> bool {
+		self.kill_requested.load(Ordering::Relaxed)
+	}
+
+	/// Save a fault report (only the first fault is kept)
+	pub fn set_fault(&self, mut info: ::threads::FaultInfo) {
+		let mut lh = self.fault_info.lock();
+		if lh.is_none() {
+			let images = self.loaded_images.lock();
+			info.image_count = images.len();
+			info.images[..images.len()].clone_from_slice(&images);
+			*lh = Some(info);
+		}
+	}
+	/// Replace the list of loaded images (truncated to `FAULT_MAX_IMAGES`)
+	pub fn set_loaded_images(&self, images: &[::threads::FaultImage]) {
+		let mut lh = self.loaded_images.lock();
+		lh.clear();
+		lh.extend( images.iter().take(::threads::FAULT_MAX_IMAGES).cloned() );
+	}
+
+	/// Set the exit status (waking waiters) and notify the parent, fails if the process has already exited
 	pub fn mark_exit(&self, status: u32) -> Result<(),()> {
+		try!( self.set_exit_status(status) );
+		self.notify_parent(status);
+		Ok( () )
+	}
+	fn set_exit_status(&self, status: u32) -> Result<(),()> {
 		let mut lh = self.exit_status.lock();
 		if lh.0.is_some() {
 			Err( () )
 		}
 		else {
-			
-			if let Some(ref sleep_ref) = lh.1 {
+			for sleep_ref in lh.1.iter() {
 				sleep_ref.signal();
 			}
 
@@ -163,12 +459,129 @@ impl Process
 			Ok( () )
 		}
 	}
+
+	/// Post `ChildExited` to the parent process (if it was created by a user process)
+	fn notify_parent(&self, status: u32) {
+		if self.parent != 0 {
+			post_control(self.parent, ::threads::ControlEvent::ChildExited(self.pid, status));
+		}
+	}
+
+	/// Queue a control notification, returns false if it was dropped (process exited, or queue full)
+	pub fn post_control(&self, ev: ::threads::ControlEvent) -> bool {
+		if self.exit_status.lock().0.is_some() {
+			return false;
+		}
+		let rv = self.control.lock().push(ev);
+		if !rv {
+			log_debug!("{} - Control queue full, dropping {:?}", self, ev);
+		}
+		rv
+	}
+	/// Take the next pending control notification
+	pub fn pop_control(&self) -> Option<::threads::ControlEvent> {
+		self.control.lock().pop()
+	}
+	/// Register a sleep object to be signalled when a control notification is queued
+	pub fn bind_wait_control(&self, obj: &mut ::threads::SleepObject) {
+		self.control.lock().bind_wait(obj)
+	}
+	/// Remove a registration made by `bind_wait_control`, returns true if a notification is pending
+	pub fn clear_wait_control(&self, obj: &mut ::threads::SleepObject) -> bool {
+		self.control.lock().clear_wait(obj)
+	}
+
+	/// Register a sleep object to be signalled if this process is killed
+	pub fn bind_wait_kill(&self, obj: &mut ::threads::SleepObject) {
+		let mut lh = self.kill_waiters.lock();
+		if self.is_kill_requested() {
+			obj.signal();
+		}
+		else {
+			lh.push( obj.get_ref() );
+		}
+	}
+	/// Remove a registration made by `bind_wait_kill`
+	pub fn clear_wait_kill(&self, obj: &mut ::threads::SleepObject) {
+		self.kill_waiters.lock().retain(|v| !v.is_from(obj));
+	}
+	/// Register a thread sleeping in `SleepObject::wait`, returns false if the process was already killed
+	///
+	/// Unlike `bind_wait_kill`, the object isn't signalled if already killed (loops re-sleeping after a kill would spin)
+	pub fn add_kill_sleeper(&self, r: ::threads::SleepObjectRef) -> bool {
+		let mut lh = self.kill_waiters.lock();
+		if self.is_kill_requested() {
+			false
+		}
+		else {
+			lh.push(r);
+			true
+		}
+	}
+	/// Remove a registration made by `add_kill_sleeper` (leaving any made by `bind_wait_kill`)
+	pub fn remove_kill_sleeper(&self, obj: &::threads::SleepObject) {
+		let mut lh = self.kill_waiters.lock();
+		if let Some(i) = lh.iter().rposition(|v| v.is_from(obj)) {
+			lh.swap_remove(i);
+		}
+	}
+
+	/// Called by a thread of this process as it terminates
+	///
+	/// When the last thread terminates, the process's objects (process-local data) are released.
+	pub fn thread_terminated(&self) {
+		if self.live_threads.fetch_sub(1, Ordering::SeqCst) == 1 && self.pid != 0 {
+			// Last thread calling `exit_thread` is a clean exit
+			let _ = self.mark_exit(0);
+			log_log!("{} - Last thread terminated, releasing objects", self);
+			// NOTE: Items are dropped outside the lock, as object destructors may access process-local data
+			let items = ::core::mem::replace(&mut *self.proc_local_data.write(), Vec::new());
+			drop(items);
+		}
+	}
+	/// Called when a thread of this process is reaped, frees the address space after the last one
+	fn thread_reaped(&self) {
+		if self.thread_count.fetch_sub(1, Ordering::SeqCst) == 1 && self.pid != 0 {
+			log_log!("{} - All threads reaped, releasing address space", self);
+			let addr_space = self.address_space.lock().take();
+			drop(addr_space);
+		}
+	}
 }
 
 impl ProcessHandle
 {
-	pub fn new<S: Into<String>+::core::fmt::Debug>(name: S, clone_start: usize, clone_end: usize) -> ProcessHandle {
-		ProcessHandle( Process::new(name, ::memory::virt::AddressSpace::new(clone_start, clone_end).expect("ProcessHandle::new - OOM")) )
+	/// Create a new process, cloning the specified range of the current address space
+	///
+	/// The new process inherits the current process's page quota (see `set_page_quota`), and is charged for the
+	/// cloned pages (which it can copy on write). The current process is sent `ControlEvent::ChildExited` when the
+	/// new one exits.
+	pub fn new<S: Into<String>+::core::fmt::Debug>(name: S, clone_start: usize, clone_end: usize) -> Result<ProcessHandle, ::memory::virt::MapError> {
+		let (parent, quota) = super::with_cur_thread(|cur| {
+			let p = cur.get_process_info();
+			(p.get_pid(), p.get_page_quota())
+			});
+		let (addr_space, page_count) = try!(::memory::virt::AddressSpace::new(clone_start, clone_end));
+		let process = Process::new(name, parent, addr_space, quota);
+		if process.charge_pages(page_count).is_err() {
+			log_notice!("ProcessHandle::new - {} cloned pages exceeds quota of {}", page_count, quota);
+			return Err( ::memory::virt::MapError::QuotaExceeded );
+		}
+		Ok( ProcessHandle(process) )
+	}
+
+	/// Set the maximum number of pages the process can allocate (before it is started)
+	///
+	/// A process can't grant its children more than its own quota (zero is unlimited).
+	pub fn set_page_quota(&self, pages: usize) -> Result<(),()> {
+		let parent = super::with_cur_thread(|cur| cur.get_process_info().get_page_quota());
+		if parent != 0 && (pages == 0 || pages > parent) {
+			Err( () )
+		}
+		else {
+			self.0.page_quota.store(pages, Ordering::Relaxed);
+			Ok( () )
+		}
 	}
 	
 	pub fn start_root_thread(&mut self, ip: usize, sp: usize) {
@@ -239,24 +652,19 @@ impl ProcessHandle
 		if let Some(_status) = lh.0 {
 			obj.signal();
 		}
-		else if let Some(_) = lh.1 {
-			todo!("Multiple threads sleeping on this process");
-		}
 		else {
-			lh.1 = Some( obj.get_ref() );
+			lh.1.push( obj.get_ref() );
 		}
 	}
 	pub fn clear_wait_terminate(&self, obj: &mut ::threads::SleepObject) -> bool {
 		log_trace!("clear_wait_terminate({:p}, obj={:p})", self, obj);
 		let mut lh = self.0.exit_status.lock();
 
-		if let Some(ref v) = lh.1 {
-			assert!(v.is_from(obj), "clear_wait_terminate from different object");
-		}
-		else {
-			log_trace!("- Wasn't registered");
+		match lh.1.iter().position(|v| v.is_from(obj))
+		{
+		Some(idx) => { lh.1.remove(idx); },
+		None => log_trace!("- Wasn't registered"),
 		}
-		lh.1 = None;
 		
 		lh.0.is_some()
 	}
@@ -264,6 +672,24 @@ impl ProcessHandle
 	pub fn get_exit_status(&self) -> Option<u32> {
 		self.0.exit_status.lock().0
 	}
+
+	/// Report of the fault that terminated the process (`None` if it didn't fault)
+	pub fn get_fault(&self) -> Option<::threads::FaultInfo> {
+		*self.0.fault_info.lock()
+	}
+
+	/// Queue a control notification for the process (see `Process::post_control`)
+	pub fn post_control(&self, ev: ::threads::ControlEvent) -> bool {
+		self.0.post_control(ev)
+	}
+
+	/// Kill the process, all threads terminate at the next system call boundary
+	///
+	/// Returns false if the process had already exited
+	pub fn kill(&self, status: u32) -> bool {
+		log_notice!("Killing {:?} (status={:#x})", self, status);
+		self.0.request_kill(status)
+	}
 }
 impl ::core::ops::Drop for ProcessHandle {
 	fn drop(&mut self) {
@@ -274,8 +700,14 @@ impl ::core::ops::Drop for ProcessHandle {
 impl ThreadHandle
 {
 	pub fn new<F: FnOnce()+Send+'static, S: Into<String>>(name: S, fcn: F, process: Arc<Process>) -> ThreadHandle
+	{
+		ThreadHandle::new_with_priority(name, fcn, process, Priority::Normal)
+	}
+	/// Create and start a new thread with the specified priority class
+	pub fn new_with_priority<F: FnOnce()+Send+'static, S: Into<String>>(name: S, fcn: F, process: Arc<Process>, priority: Priority) -> ThreadHandle
 	{
 		let mut thread = Thread::new_boxed(allocate_tid(), name, process);
+		thread.block.base_priority.store(priority as usize, Ordering::Relaxed);
 		let handle = ThreadHandle {
 			block: thread.block.clone(),
 			};
@@ -286,6 +718,64 @@ impl ThreadHandle
 		
 		handle
 	}
+
+	/// Start a new userland thread in the current process
+	///
+	/// `tls_base` is the thread's userland TLS pointer (see `::arch::threads::set_user_tls_base`), and `arg` is
+	/// passed to the entrypoint as its first argument.
+	pub fn new_user(ip: usize, sp: usize, tls_base: usize, arg: usize) -> ThreadHandle
+	{
+		let process = super::with_cur_thread(|cur| cur.block.process.clone());
+		let tid = allocate_tid();
+		let name = format!("{}#{}", process.name, tid);
+		log_trace!("new_user({}, ip={:#x}, sp={:#x}, tls_base={:#x})", name, ip, sp, tls_base);
+		let mut thread = Thread::new_boxed(tid, name, process);
+		let handle = ThreadHandle {
+			block: thread.block.clone(),
+			};
+		::arch::threads::start_thread( &mut thread,
+			// SAFE: Well... trusting caller to give us sane addresses etc, but that's the user's problem
+			move || unsafe {
+				::arch::threads::set_user_tls_base(tls_base);
+				::arch::drop_to_user(ip, sp, arg)
+			}
+			);
+		super::yield_to(thread);
+		
+		handle
+	}
+
+	pub fn bind_wait_terminate(&self, obj: &mut ::threads::SleepObject) {
+		let mut lh = self.block.exit_status.lock();
+		if lh.0.is_some() {
+			obj.signal();
+		}
+		else {
+			lh.1.push( obj.get_ref() );
+		}
+	}
+	pub fn clear_wait_terminate(&self, obj: &mut ::threads::SleepObject) -> bool {
+		let mut lh = self.block.exit_status.lock();
+		lh.1.retain(|v| !v.is_from(obj));
+		lh.0.is_some()
+	}
+	/// Exit value of the thread (`None` if still running)
+	pub fn get_exit_status(&self) -> Option<u32> {
+		self.block.exit_status.lock().0
+	}
+	/// Block until the thread terminates, returning its exit value
+	pub fn join(self) -> u32 {
+		let mut obj = ::threads::SleepObject::new("ThreadHandle::join");
+		self.bind_wait_terminate(&mut obj);
+		// NOTE: Sleep objects can be woken spuriously (e.g. by a kill), so wait until the status is set
+		let mut rv = self.get_exit_status();
+		while rv.is_none() {
+			obj.wait();
+			rv = self.get_exit_status();
+		}
+		self.clear_wait_terminate(&mut obj);
+		rv.unwrap()
+	}
 }
 impl ::core::fmt::Debug for ThreadHandle
 {
@@ -297,7 +787,8 @@ impl ::core::fmt::Debug for ThreadHandle
 impl ::core::ops::Drop for ThreadHandle
 {
 	fn drop(&mut self) {
-		panic!("TODO: Wait for thread to terminate when handle is dropped");
+		// NOTE: The thread owns itself, so dropping the handle just detaches it
+		log_trace!("Dropping handle to thread {} (exit={:?})", self.block, self.block.exit_status.lock().0);
 	}
 }
 
@@ -372,11 +863,21 @@ impl Thread
 	/// Create a new thread
 	pub fn new_boxed<S: Into<String>>(tid: ThreadID, name: S, process: Arc<Process>) -> ThreadPtr
 	{
+		process.live_threads.fetch_add(1, Ordering::SeqCst);
+		process.thread_count.fetch_add(1, Ordering::SeqCst);
 		let rv = box Thread {
 			cpu_state: process.empty_cpu_state(),
-			block: Arc::new( SharedBlock { tid: tid, name: name.into(), process: process } ),
+			block: Arc::new( SharedBlock {
+				tid: tid, name: name.into(), process: process,
+				base_priority: AtomicUsize::new(Priority::Normal as usize),
+				inherited_priority: AtomicUsize::new(Priority::Idle as usize),
+				lent_priorities: ::sync::Spinlock::new([0; NUM_PRIORITIES]),
+				cpu_time: AtomicUsize::new(0),
+				exit_status: Default::default(),
+				} ),
 			run_state: RunState::Runnable,
 			next: None,
+			heap_tag: AtomicUsize::new(::threads::get_heap_tag() as usize),
 			};
 		
 		// TODO: Add to global list of threads (removed on destroy)
@@ -386,6 +887,26 @@ impl Thread
 	}
 	
 	pub fn get_tid(&self) -> ThreadID { self.block.tid }
+
+	/// Record the thread's exit value (waking joiners), ignored if already set
+	pub fn mark_exit(&self, value: u32) {
+		let mut lh = self.block.exit_status.lock();
+		if lh.0.is_none() {
+			lh.0 = Some(value);
+			for sleep_ref in lh.1.iter() {
+				sleep_ref.signal();
+			}
+		}
+	}
+	
+	/// Tag used to attribute this thread's heap allocations
+	pub fn get_heap_tag(&self) -> u8 {
+		self.heap_tag.load(Ordering::Relaxed) as u8
+	}
+	/// Set the heap allocation tag, returning the previous value
+	pub fn set_heap_tag(&self, tag: u8) -> u8 {
+		self.heap_tag.swap(tag as usize, Ordering::Relaxed) as u8
+	}
 	
 	/// Set the execution state of this thread
 	pub fn set_state(&mut self, state: RunState) {
@@ -404,6 +925,76 @@ impl Thread
 	pub fn get_process_info(&self) -> &Process {
 		&*self.block.process
 	}
+
+	/// Effective priority class (accounting for the process's limit and inherited priority)
+	pub fn get_priority(&self) -> Priority {
+		self.block.priority()
+	}
+	/// Set the thread's requested priority class
+	pub fn set_priority(&self, prio: Priority) {
+		self.block.base_priority.store(prio as usize, Ordering::Relaxed);
+	}
+	/// Obtain a shared reference to this thread
+	pub fn get_ref(&self) -> ThreadRef {
+		ThreadRef(self.block.clone())
+	}
+	/// Add to the thread's CPU time (called by the scheduler when switching away)
+	pub fn add_cpu_time(&self, ticks: ::time::TickCount) {
+		self.block.cpu_time.fetch_add(ticks as usize, Ordering::Relaxed);
+	}
+	/// Total time this thread has spent running (in ticks)
+	pub fn get_cpu_time(&self) -> ::time::TickCount {
+		self.block.cpu_time.load(Ordering::Relaxed) as ::time::TickCount
+	}
+}
+
+impl SharedBlock
+{
+	/// Set the inherited priority to the highest lent priority, returns true if it changed
+	fn update_inherited(&self, lent: &[usize; NUM_PRIORITIES]) -> bool {
+		let new = lent.iter().rposition(|&c| c > 0).unwrap_or(Priority::Idle as usize);
+		self.inherited_priority.swap(new, Ordering::Relaxed) != new
+	}
+	fn priority(&self) -> Priority {
+		let base = ::core::cmp::min( self.base_priority.load(Ordering::Relaxed), self.process.priority.load(Ordering::Relaxed) );
+		Priority::from_index( ::core::cmp::max(base, self.inherited_priority.load(Ordering::Relaxed)) )
+	}
+}
+
+impl ThreadRef
+{
+	/// Returns true if this references the passed thread
+	pub fn is_thread(&self, thread: &Thread) -> bool {
+		&*self.0 as *const SharedBlock == &*thread.block as *const SharedBlock
+	}
+	/// Effective priority class of the thread
+	pub fn get_priority(&self) -> Priority {
+		self.0.priority()
+	}
+	/// Lend `prio` to the thread through a held mutex (replacing `prev`, the priority that mutex previously lent)
+	///
+	/// Returns true if the thread's inherited priority changed
+	pub fn inherit_priority(&self, prev: Option<Priority>, prio: Priority) -> bool {
+		let mut lh = self.0.lent_priorities.lock();
+		if let Some(p) = prev {
+			lh[p as usize] -= 1;
+		}
+		lh[prio as usize] += 1;
+		self.0.update_inherited(&lh)
+	}
+	/// Return a priority lent by `inherit_priority` (when the lending mutex is released)
+	///
+	/// The inherited priority is recomputed from the mutexes the thread still holds
+	pub fn clear_inherited_priority(&self, prio: Priority) {
+		let mut lh = self.0.lent_priorities.lock();
+		lh[prio as usize] -= 1;
+		self.0.update_inherited(&lh);
+	}
+}
+impl_fmt! {
+	Debug(self, f) for ThreadRef {
+		write!(f, "ThreadRef({})", self.0)
+	}
 }
 
 pub fn new_idle_thread(cpu: usize) -> ThreadPtr {
@@ -411,6 +1002,10 @@ pub fn new_idle_thread(cpu: usize) -> ThreadPtr {
 	::arch::threads::start_thread(&mut thread, super::idle_thread);
 	thread
 }
+/// Create the idle thread for an application processor (the architecture code provides its stack and starts it)
+pub fn new_ap_idle_thread(cpu: usize) -> ThreadPtr {
+	Thread::new_boxed(allocate_tid(), format!("Idle#{}", cpu), super::S_PID0.clone())
+}
 
 impl ::core::fmt::Display for SharedBlock
 {
@@ -440,6 +1035,7 @@ impl ::core::ops::Drop for Thread
 	{
 		// TODO: Remove self from the global thread map
 		log_debug!("Destroying thread {:?} - {} handles to block, {} to process", self, Arc::strong_count(&self.block), Arc::strong_count(&self.block.process));
+		self.block.process.thread_reaped();
 	}
 }
 
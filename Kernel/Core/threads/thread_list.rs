@@ -39,6 +39,39 @@ impl ThreadList
 		None => None
 		}
 	}
+	/// Remove the first thread matching the predicate
+	pub fn remove_first<F: FnMut(&Thread)->bool>(&mut self, mut pred: F) -> Option<ThreadPtr>
+	{
+		let head_matches = match self.first
+			{
+			Some(ref t) => pred(t),
+			None => return None,
+			};
+		if head_matches {
+			return self.pop();
+		}
+		// SAFE: The list is uniquely borrowed, and `prev` always points to a node owned by the list
+		unsafe {
+			let mut prev: *mut Thread = &mut **self.first.as_mut().unwrap();
+			loop
+			{
+				let matches = match (*prev).next
+					{
+					Some(ref t) => pred(t),
+					None => return None,
+					};
+				if matches {
+					let mut t = (*prev).next.take().unwrap();
+					(*prev).next = t.next.take();
+					if (*prev).next.is_none() {
+						self.last = Some(NonZero::new(prev));
+					}
+					return Some(t);
+				}
+				prev = &mut **(*prev).next.as_mut().unwrap();
+			}
+		}
+	}
 	/// Push a thread to the back
 	pub fn push(&mut self, t: ThreadPtr)
 	{
@@ -8,7 +8,6 @@ use super::thread::RunState;
 use super::ThreadList;
 
 use super::{get_cur_thread,rel_cur_thread,reschedule};
-use super::s_runnable_threads;
 
 /// A list of waiting threads, can be woken one at a time, or all at once
 pub struct WaitQueue
@@ -78,7 +77,7 @@ impl WaitQueue
 		Some(mut t) => {
 			t.set_state( RunState::Runnable );
 						let _irq_lock = ::arch::sync::hold_interrupts();
-			s_runnable_threads.lock().push(t);
+			super::push_runnable(t);
 			},
 		None => {}
 		}
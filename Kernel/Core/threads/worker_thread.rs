@@ -18,6 +18,12 @@ impl WorkerThread
 		let handle = super::thread::ThreadHandle::new(name, fcn, super::S_PID0.clone());
 		WorkerThread(handle)
 	}
+	/// Construct a new worker thread with the specified priority class
+	pub fn with_priority<F: FnOnce()+Send+'static>(name: &str, priority: super::Priority, fcn: F) -> WorkerThread
+	{
+		let handle = super::thread::ThreadHandle::new_with_priority(name, fcn, super::S_PID0.clone(), priority);
+		WorkerThread(handle)
+	}
 }
 
 
@@ -3,16 +3,68 @@
 //
 // Core/time.rs
 //! Kernel timing and timers
+//!
+//! Timers are stored in a hashed timer wheel (one slot per tick, entries further away than a full
+//! revolution stay in their slot until their expiry passes), processed by `time_tick` from the
+//! architecture's timer interrupt. The same tick also drives scheduler time slices.
+use prelude::*;
+use core::ptr;
+use threads::{SleepObject,SleepObjectRef};
 
 /// Timer ticks (ms)
 pub type TickCount = u64;
 
+/// Number of slots in the timer wheel
+const WHEEL_SIZE: usize = 64;
+
 /// Obtain the number of timer ticks since an arbitary point (system startup)
 pub fn ticks() -> u64
 {
 	::arch::cur_timestamp()
 }
 
+/// Convert a tick count to milliseconds
+pub fn ticks_to_ms(ticks: TickCount) -> u64
+{
+	ticks / ::arch::TIMESTAMP_PER_MS
+}
+
+/// Wall-clock base: milliseconds since the UNIX epoch at a given tick count
+static S_REALTIME_BASE: ::sync::Spinlock<Option<(u64, TickCount)>> = ::sync::Spinlock::new(None);
+
+/// Set the current wall-clock time (milliseconds since 1970-01-01 00:00:00 UTC)
+pub fn set_realtime(unix_ms: u64)
+{
+	let now = ticks();
+	log_log!("set_realtime({}) @ {}", unix_ms, now);
+	let _irq = ::sync::hold_interrupts();
+	*S_REALTIME_BASE.lock() = Some( (unix_ms, now) );
+}
+/// Obtain the current wall-clock time (milliseconds since the UNIX epoch), `None` if not yet known
+pub fn realtime() -> Option<u64>
+{
+	let base = {
+		let _irq = ::sync::hold_interrupts();
+		*S_REALTIME_BASE.lock()
+		};
+	base.map(|(unix_ms, base_ticks)| unix_ms + (ticks() - base_ticks))
+}
+
+/// Convert a UTC calendar date and time into seconds since the UNIX epoch
+///
+/// `month` and `day` are 1-based. Dates before 1970 are not supported.
+pub fn unix_timestamp(year: u32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> u64
+{
+	// Days from civil (proleptic Gregorian, years starting in March)
+	let (y, m) = if month <= 2 { (year as u64 - 1, month as u64 + 9) } else { (year as u64, month as u64 - 3) };
+	let era = y / 400;
+	let yoe = y - era * 400;
+	let doy = (153 * m + 2) / 5 + day as u64 - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	let days = era * 146097 + doe - 719468;
+	((days * 24 + hour as u64) * 60 + minute as u64) * 60 + second as u64
+}
+
 
 /// Records the current time on construction, and prints the elapsed time with {:?} / {}
 pub struct ElapsedLogger(TickCount);
@@ -52,5 +104,245 @@ impl CacheTimer
 	}
 }
 
+/// A cancellable one-shot timer, signalling a sleep object when it expires
+///
+/// The timer is cancelled if dropped before expiry.
+pub struct Timer
+{
+	// NOTE: Boxed so the wheel can hold a pointer to it
+	node: Box<TimerNode>,
+}
+struct TimerNode
+{
+	/// Next node in the wheel slot (only valid while `armed`)
+	next: *mut TimerNode,
+	expiry: TickCount,
+	armed: bool,
+	target: Option<SleepObjectRef>,
+}
+
+struct TimerWheel
+{
+	slots: [*mut TimerNode; WHEEL_SIZE],
+	/// Last tick processed by `time_tick`
+	last_tick: TickCount,
+	/// Lower bound on the earliest armed expiry (may be stale-early after a cancel)
+	next_expiry: Option<TickCount>,
+}
+unsafe impl Send for TimerWheel {}
+
+static S_TIMER_WHEEL: ::sync::Spinlock<TimerWheel> = ::sync::Spinlock::new(TimerWheel {
+	slots: [0 as *mut _; WHEEL_SIZE],
+	last_tick: 0,
+	next_expiry: None,
+	});
+
+impl Timer
+{
+	/// Create a timer that signals `obj` once `ticks()` reaches `expiry`
+	pub fn new(expiry: TickCount, obj: &SleepObject) -> Timer
+	{
+		let mut node = Box::new(TimerNode {
+			next: ptr::null_mut(),
+			expiry: expiry,
+			armed: false,
+			target: Some(obj.get_ref()),
+			});
+		// NOTE: The expiry is checked against the wheel's last processed tick under the wheel lock, so a
+		// tick racing with this insertion can't pass over the timer's slot.
+		let inserted = {
+			let _irq = ::sync::hold_interrupts();
+			let mut lh = S_TIMER_WHEEL.lock();
+			if expiry <= ::core::cmp::max(lh.last_tick, ticks()) {
+				false
+			}
+			else {
+				lh.insert(&mut *node);
+				true
+			}
+			};
+		if inserted {
+			::arch::request_tick(expiry);
+		}
+		else {
+			// Already expired, fire immediately
+			node.target.take().unwrap().signal();
+		}
+		Timer { node: node }
+	}
+	/// Create a timer that signals `obj` after `duration` ticks
+	pub fn after(duration: TickCount, obj: &SleepObject) -> Timer
+	{
+		Timer::new(ticks() + duration, obj)
+	}
+
+	/// Returns the tick count at which this timer expires
+	pub fn expiry(&self) -> TickCount {
+		self.node.expiry
+	}
+	/// Returns true if the timer has fired
+	pub fn is_expired(&self) -> bool {
+		let _irq = ::sync::hold_interrupts();
+		let _lh = S_TIMER_WHEEL.lock();
+		self.node.target.is_none()
+	}
+
+	/// Cancel the timer, returns `true` if it was cancelled before firing
+	pub fn cancel(mut self) -> bool {
+		self.cancel_int()
+	}
+	fn cancel_int(&mut self) -> bool {
+		let target = {
+			let _irq = ::sync::hold_interrupts();
+			let mut lh = S_TIMER_WHEEL.lock();
+			if self.node.armed {
+				lh.remove(&mut *self.node);
+			}
+			self.node.target.take()
+			};
+		// NOTE: Reference dropped outside of the wheel lock
+		target.is_some()
+	}
+}
+impl ::core::ops::Drop for Timer
+{
+	fn drop(&mut self) {
+		self.cancel_int();
+	}
+}
+impl_fmt! {
+	Debug(self,f) for Timer {
+		write!(f, "Timer({})", self.node.expiry)
+	}
+}
+
+impl TimerWheel
+{
+	fn insert(&mut self, node: &mut TimerNode)
+	{
+		let slot = (node.expiry % WHEEL_SIZE as u64) as usize;
+		node.next = self.slots[slot];
+		node.armed = true;
+		self.next_expiry = Some(::core::cmp::min(self.next_expiry.unwrap_or(!0), node.expiry));
+		self.slots[slot] = node;
+	}
+	fn remove(&mut self, node: &mut TimerNode)
+	{
+		let slot = (node.expiry % WHEEL_SIZE as u64) as usize;
+		let mut pp: *mut *mut TimerNode = &mut self.slots[slot];
+		// SAFE: All nodes in the list are valid while armed (removed before being freed), and the wheel lock is held
+		unsafe {
+			while !(*pp).is_null() {
+				if *pp == node as *mut _ {
+					*pp = node.next;
+					break;
+				}
+				pp = &mut (**pp).next;
+			}
+		}
+		node.next = ptr::null_mut();
+		node.armed = false;
+	}
+
+	/// Fire all timers in a slot that have expired, returns the earliest remaining expiry
+	fn fire_slot(&mut self, slot: usize, now: TickCount) -> Option<TickCount>
+	{
+		let mut earliest = None;
+		let mut pp: *mut *mut TimerNode = &mut self.slots[slot];
+		// SAFE: (see `remove`)
+		unsafe {
+			while !(*pp).is_null() {
+				let node = &mut **pp;
+				if node.expiry <= now {
+					*pp = node.next;
+					node.next = ptr::null_mut();
+					node.armed = false;
+					if let Some(t) = node.target.take() {
+						t.signal();
+					}
+				}
+				else {
+					earliest = Some(::core::cmp::min(earliest.unwrap_or(!0), node.expiry));
+					pp = &mut node.next;
+				}
+			}
+		}
+		earliest
+	}
+}
+
+/// Process expired timers and time slices (called from the timer interrupt)
+///
+/// Returns the expiry of the earliest remaining timer, for the architecture code to schedule the next tick
+#[is_safe(irq)]	// Holds interrupts before locking
+pub fn time_tick() -> Option<TickCount>
+{
+	let now = ticks();
+	let earliest = fire_timers(now);
+	match ::threads::check_time_slices(now)
+	{
+	Some(e) => Some(::core::cmp::min(earliest.unwrap_or(!0), e)),
+	None => earliest,
+	}
+}
+fn fire_timers(now: TickCount) -> Option<TickCount>
+{
+	let _irq = ::sync::hold_interrupts();
+	let mut lh = S_TIMER_WHEEL.lock();
+	// `now` was read before the lock was taken, a racing call may have already processed further
+	if now <= lh.last_tick {
+		return lh.next_expiry;
+	}
+	// Nothing can have expired yet, so there's no need to walk the wheel
+	match lh.next_expiry
+	{
+	Some(e) if e > now => {
+		lh.last_tick = now;
+		return Some(e);
+		},
+	None => {
+		lh.last_tick = now;
+		return None;
+		},
+	_ => {},
+	}
+	// Slots that have passed since the last call need to be fired, remaining slots are only checked for the earliest expiry
+	let passed = ::core::cmp::min(now.saturating_sub(lh.last_tick), WHEEL_SIZE as u64) as usize;
+	let mut earliest = None;
+	for i in 0 .. WHEEL_SIZE
+	{
+		let slot = ((lh.last_tick + 1 + i as u64) % WHEEL_SIZE as u64) as usize;
+		let e = if i < passed {
+				lh.fire_slot(slot, now)
+			}
+			else {
+				lh.earliest_in_slot(slot)
+			};
+		if let Some(e) = e {
+			earliest = Some(::core::cmp::min(earliest.unwrap_or(!0), e));
+		}
+	}
+	lh.last_tick = now;
+	lh.next_expiry = earliest;
+	earliest
+}
+
+impl TimerWheel
+{
+	fn earliest_in_slot(&self, slot: usize) -> Option<TickCount>
+	{
+		let mut rv = None;
+		let mut p = self.slots[slot];
+		// SAFE: (see `remove`)
+		unsafe {
+			while !p.is_null() {
+				rv = Some(::core::cmp::min(rv.unwrap_or(!0), (*p).expiry));
+				p = (*p).next;
+			}
+		}
+		rv
+	}
+}
+
 // vim: ft=rust
 
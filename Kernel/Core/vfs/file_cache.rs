@@ -0,0 +1,250 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/vfs/file_cache.rs
+//! Per-file page cache
+//!
+//! Holds the frames backing memory-mapped regions of a file. `read` and `write` consult this cache so that all
+//! users of a file (handles and every mapping of it) see the same data.
+//!
+//! Pages that are clean and no longer mapped are evicted when a mapping or handle is released, and when the
+//! cache grows past `IDLE_PAGE_LIMIT` pages.
+//
+// TODO: Also evict when the system is low on memory (requires a hook into the PMM)
+#[allow(unused_imports)]
+use prelude::*;
+use PAGE_SIZE;
+use lib::VecMap;
+use memory::phys::FrameHandle;
+use memory::page_cache::S_PAGE_CACHE;
+use super::node::File;
+
+/// Number of cached pages a file can hold before idle pages are evicted on load
+const IDLE_PAGE_LIMIT: usize = 64;
+
+struct CachedFilePage
+{
+	frame: FrameHandle,
+	/// Number of active writeback (shared writable) mappings of this page
+	writeback_maps: usize,
+	/// The page may have been modified via a writeback mapping since the last flush
+	dirty: bool,
+}
+
+/// Cache of the pages of a single file (indexed by page number)
+#[derive(Default)]
+pub struct FilePageCache
+{
+	pages: ::sync::Mutex< VecMap<u64, CachedFilePage> >,
+}
+
+impl FilePageCache
+{
+	/// Read data from the file, using cached pages where present
+	pub fn read(&self, file: &File, ofs: u64, dst: &mut [u8]) -> super::Result<usize>
+	{
+		let size = file.size();
+		if ofs > size {
+			return Err( super::Error::InvalidParameter );
+		}
+		let len = ::core::cmp::min(dst.len() as u64, size - ofs) as usize;
+		let dst = &mut dst[..len];
+
+		let lh = self.pages.lock();
+		let mut done = 0;
+		while done < len
+		{
+			let pos = ofs + done as u64;
+			let (page, page_ofs) = (pos / PAGE_SIZE as u64, (pos % PAGE_SIZE as u64) as usize);
+			let chunk = ::core::cmp::min(PAGE_SIZE - page_ofs, len - done);
+			match lh.get(&page)
+			{
+			Some(p) => {
+				let mapping = try!(map_frame(&p.frame));
+				dst[done .. done + chunk].clone_from_slice( &mapping.data()[page_ofs .. page_ofs + chunk] );
+				},
+			None => {
+				// Not cached, read directly from the filesystem (the cache is only populated by mappings)
+				let n = try!( file.read(pos, &mut dst[done .. done + chunk]) );
+				if n < chunk {
+					return Ok(done + n);
+				}
+				},
+			}
+			done += chunk;
+		}
+		Ok(done)
+	}
+
+	/// Write data to the file (updating any cached pages to match)
+	///
+	/// Writes go straight through to the filesystem, the cache lock is held for the duration to keep the
+	/// cached copies consistent.
+	pub fn write(&self, file: &File, ofs: u64, src: &[u8]) -> super::Result<usize>
+	{
+		let mut lh = self.pages.lock();
+		Self::write_locked(&mut lh, file, ofs, src)
+	}
+
+	/// Append data to the end of the file
+	///
+	/// The cache lock is held across reading the size and writing, so concurrent appends do not interleave.
+	pub fn append(&self, file: &File, src: &[u8]) -> super::Result<usize>
+	{
+		let mut lh = self.pages.lock();
+		let ofs = file.size();
+		Self::write_locked(&mut lh, file, ofs, src)
+	}
+
+	fn write_locked(pages: &mut VecMap<u64, CachedFilePage>, file: &File, ofs: u64, src: &[u8]) -> super::Result<usize>
+	{
+		let count = try!( file.write(ofs, src) );
+
+		// The last page of the file may be cached (zero-filled past EOF), so extending writes also need patching
+		let mut done = 0;
+		while done < count
+		{
+			let pos = ofs + done as u64;
+			let (page, page_ofs) = (pos / PAGE_SIZE as u64, (pos % PAGE_SIZE as u64) as usize);
+			let chunk = ::core::cmp::min(PAGE_SIZE - page_ofs, count - done);
+			if let Some(p) = pages.get_mut(&page) {
+				let mut mapping = try!(map_frame(&p.frame));
+				mapping.data_mut()[page_ofs .. page_ofs + chunk].clone_from_slice( &src[done .. done + chunk] );
+			}
+			done += chunk;
+		}
+		Ok(count)
+	}
+
+	/// Set the size of the file, clearing cached data past the new end (so a later extension reads as zeroes)
+	pub fn truncate(&self, file: &File, size: u64) -> super::Result<()>
+	{
+		let mut lh = self.pages.lock();
+		let new_size = try!( file.truncate(size) );
+		for (&page, p) in lh.iter_mut()
+		{
+			let ofs = page * PAGE_SIZE as u64;
+			if ofs + PAGE_SIZE as u64 <= new_size {
+				continue ;
+			}
+			let start = if ofs >= new_size { 0 } else { (new_size - ofs) as usize };
+			let mut mapping = try!(map_frame(&p.frame));
+			for b in mapping.data_mut()[start ..].iter_mut() {
+				*b = 0;
+			}
+		}
+		Self::evict_idle_locked(&mut lh);
+		Ok( () )
+	}
+
+	/// Release all pages that are clean and not mapped anywhere
+	pub fn evict_idle(&self)
+	{
+		Self::evict_idle_locked(&mut self.pages.lock());
+	}
+	fn evict_idle_locked(pages: &mut VecMap<u64, CachedFilePage>)
+	{
+		// - A unique frame handle means no mappings hold the frame
+		pages.retain(|_, p| p.dirty || p.writeback_maps > 0 || !p.frame.is_unique());
+	}
+
+	/// Obtain a handle to the frame backing the specified page (loading it if not already cached)
+	///
+	/// If `writeback` is set, the page is considered dirty until `unmap_page` is called and the page flushed.
+	pub fn map_page(&self, file: &File, page: u64, writeback: bool) -> super::Result<FrameHandle>
+	{
+		use lib::vec_map::Entry;
+		let mut lh = self.pages.lock();
+		if lh.len() >= IDLE_PAGE_LIMIT && lh.get(&page).is_none() {
+			Self::evict_idle_locked(&mut lh);
+		}
+		let ent = match lh.entry(page)
+			{
+			Entry::Occupied(e) => e.into_mut(),
+			Entry::Vacant(e) => e.insert(CachedFilePage {
+				frame: try!(load_page(file, page)),
+				writeback_maps: 0,
+				dirty: false,
+				}),
+			};
+		if writeback {
+			ent.writeback_maps += 1;
+			ent.dirty = true;
+		}
+		Ok( ent.frame.clone() )
+	}
+
+	/// Release a mapping reference to a page (the frame handle itself is released by unmapping)
+	pub fn unmap_page(&self, page: u64, writeback: bool)
+	{
+		if writeback {
+			let mut lh = self.pages.lock();
+			match lh.get_mut(&page)
+			{
+			Some(p) => {
+				assert!(p.writeback_maps > 0, "FilePageCache::unmap_page - Page {} had no writeback mappings", page);
+				p.writeback_maps -= 1;
+				},
+			None => log_error!("FilePageCache::unmap_page - Page {} not in cache", page),
+			}
+		}
+	}
+
+	/// Write all potentially-dirty pages back to the filesystem
+	pub fn flush(&self, file: &File) -> super::Result<()>
+	{
+		let mut lh = self.pages.lock();
+		let size = file.size();
+		for (&page, p) in lh.iter_mut()
+		{
+			if !p.dirty {
+				continue ;
+			}
+			let ofs = page * PAGE_SIZE as u64;
+			// Data past EOF is discarded (mappings cannot extend the file)
+			if ofs < size {
+				let len = ::core::cmp::min(PAGE_SIZE as u64, size - ofs) as usize;
+				let mapping = try!(map_frame(&p.frame));
+				log_trace!("FilePageCache::flush - Page {} ({} bytes)", page, len);
+				try!( file.write(ofs, &mapping.data()[..len]) );
+			}
+			// Pages still mapped writable could be modified again at any time
+			p.dirty = p.writeback_maps > 0;
+		}
+		Ok( () )
+	}
+}
+
+/// Map a cached frame into kernel space for access
+fn map_frame(frame: &FrameHandle) -> super::Result<::memory::page_cache::CachedPage>
+{
+	S_PAGE_CACHE.map(frame).map_err(|_| super::Error::OutOfMemory)
+}
+
+/// Create a new frame containing the specified page of the file (zero-filled past EOF)
+fn load_page(file: &File, page: u64) -> super::Result<FrameHandle>
+{
+	let ofs = page * PAGE_SIZE as u64;
+	let size = file.size();
+	let mut new_page = try!( ::memory::virt::with_oom_retry(::memory::virt::alloc_free).map_err(|_| super::Error::OutOfMemory) );
+
+	let valid = if ofs >= size { 0 } else { ::core::cmp::min(PAGE_SIZE as u64, size - ofs) as usize };
+	let mut done = 0;
+	let mut res = Ok( () );
+	while done < valid
+	{
+		match file.read(ofs + done as u64, &mut new_page[done .. valid])
+		{
+		Ok(0) => break,
+		Ok(n) => done += n,
+		Err(e) => { res = Err(e); break },
+		}
+	}
+	for b in new_page[done..].iter_mut() {
+		*b = 0;
+	}
+
+	// NOTE: `FreePage` must be converted into a frame before returning (it panics on drop)
+	let frame = new_page.into_frame();
+	res.map(|_| frame)
+}
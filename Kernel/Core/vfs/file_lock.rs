@@ -0,0 +1,208 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/vfs/file_lock.rs
+//! File open-mode exclusivity and advisory byte-range locks
+#[allow(unused_imports)]
+use prelude::*;
+use core::sync::atomic::{AtomicUsize,Ordering};
+use threads::{SleepObject,SleepObjectRef};
+
+/// Class of an open file handle, used to enforce the exclusivity rules of `FileOpenMode`
+#[derive(Copy,Clone,Debug,PartialEq)]
+pub enum OpenClass
+{
+	/// Read-only access (`SharedRO` and `Execute`), blocked by `ExclWriter` and `Unique`
+	Reader,
+	/// Exclusive writer (`ExclRW`), blocks all other handles except appenders
+	ExclWriter,
+	/// Sole opener (`UniqueRW`), blocks all other handles
+	Unique,
+	/// Append-only writer, blocked only by `Unique`
+	Appender,
+	/// Unsynchronised read-write, can't be opened while any other type of handle is open (but doesn't block others)
+	Unsynch,
+}
+
+#[derive(Default,Debug)]
+struct OpenCounts
+{
+	readers: usize,
+	excl_writers: usize,
+	unique: usize,
+	appenders: usize,
+	unsynch: usize,
+}
+
+#[derive(Debug)]
+struct RangeLock
+{
+	owner: usize,
+	ofs: u64,
+	len: u64,
+	exclusive: bool,
+}
+
+#[derive(Default)]
+struct RangeLocks
+{
+	locks: Vec<RangeLock>,
+	/// Sleep objects waiting for a lock to be released (all are woken on release)
+	waiters: Vec<SleepObjectRef>,
+}
+
+/// Lock state for a single file
+#[derive(Default)]
+pub struct FileLocks
+{
+	opens: ::sync::Mutex<OpenCounts>,
+	ranges: ::sync::Mutex<RangeLocks>,
+}
+
+static S_NEXT_OWNER: AtomicUsize = AtomicUsize::new(1);
+
+/// Allocate a new owner ID for byte-range locks (one per open handle)
+pub fn new_owner() -> usize
+{
+	S_NEXT_OWNER.fetch_add(1, Ordering::Relaxed)
+}
+
+impl OpenCounts
+{
+	fn total(&self) -> usize {
+		self.readers + self.excl_writers + self.unique + self.appenders + self.unsynch
+	}
+	fn count_mut(&mut self, class: OpenClass) -> &mut usize {
+		match class
+		{
+		OpenClass::Reader => &mut self.readers,
+		OpenClass::ExclWriter => &mut self.excl_writers,
+		OpenClass::Unique => &mut self.unique,
+		OpenClass::Appender => &mut self.appenders,
+		OpenClass::Unsynch => &mut self.unsynch,
+		}
+	}
+	/// Returns true if a new handle of the given class would conflict with existing handles
+	fn conflicts(&self, class: OpenClass) -> bool {
+		match class
+		{
+		OpenClass::Reader => self.unique > 0 || self.excl_writers > 0,
+		OpenClass::ExclWriter => self.total() != self.appenders,
+		OpenClass::Unique => self.total() > 0,
+		OpenClass::Appender => self.unique > 0,
+		OpenClass::Unsynch => self.total() != self.unsynch,
+		}
+	}
+}
+
+impl RangeLock
+{
+	fn overlaps(&self, ofs: u64, len: u64) -> bool {
+		let end = ofs.saturating_add(len);
+		let self_end = self.ofs.saturating_add(self.len);
+		ofs < self_end && self.ofs < end
+	}
+}
+
+impl FileLocks
+{
+	/// Register a new open handle of the specified class
+	///
+	/// If `is_clone` is set, the new handle shares the exclusivity of an existing handle (and cannot fail)
+	pub fn open(&self, class: OpenClass, is_clone: bool) -> super::Result<()>
+	{
+		let mut lh = self.opens.lock();
+		if !is_clone && lh.conflicts(class) {
+			log_debug!("FileLocks::open({:?}) - Conflict with {:?}", class, *lh);
+			return Err( super::Error::Locked );
+		}
+		*lh.count_mut(class) += 1;
+		Ok( () )
+	}
+	/// Release a handle registered with `open`
+	pub fn close(&self, class: OpenClass)
+	{
+		let mut lh = self.opens.lock();
+		let c = lh.count_mut(class);
+		assert!(*c > 0, "FileLocks::close({:?}) - No handles of this class open", class);
+		*c -= 1;
+	}
+
+	/// Acquire an advisory lock on a range of the file (fails with `Locked` if a conflicting lock is held)
+	///
+	/// Shared locks conflict only with exclusive locks held by other owners.
+	pub fn lock_range(&self, owner: usize, ofs: u64, len: u64, exclusive: bool) -> super::Result<()>
+	{
+		if len == 0 {
+			return Err( super::Error::InvalidParameter );
+		}
+		let mut lh = self.ranges.lock();
+		if lh.locks.iter().any(|l| l.owner != owner && l.overlaps(ofs, len) && (exclusive || l.exclusive)) {
+			return Err( super::Error::Locked );
+		}
+		lh.locks.push(RangeLock { owner: owner, ofs: ofs, len: len, exclusive: exclusive });
+		Ok( () )
+	}
+	/// Release `owner`'s locks on the specified range
+	///
+	/// Locks that only partially overlap the range are trimmed (or split in two, if the range is in the middle).
+	pub fn unlock_range(&self, owner: usize, ofs: u64, len: u64) -> super::Result<()>
+	{
+		let mut lh = self.ranges.lock();
+		let end = ofs.saturating_add(len);
+		let mut released = false;
+		let mut remainders = Vec::new();
+		lh.locks.retain(|l| {
+			if l.owner != owner || !l.overlaps(ofs, len) {
+				return true;
+			}
+			released = true;
+			let l_end = l.ofs.saturating_add(l.len);
+			if l.ofs < ofs {
+				remainders.push(RangeLock { owner: owner, ofs: l.ofs, len: ofs - l.ofs, exclusive: l.exclusive });
+			}
+			if end < l_end {
+				remainders.push(RangeLock { owner: owner, ofs: end, len: l_end - end, exclusive: l.exclusive });
+			}
+			false
+			});
+		if !released {
+			return Err( super::Error::InvalidParameter );
+		}
+		lh.locks.extend(remainders);
+		Self::wake_waiters(&mut lh);
+		Ok( () )
+	}
+	/// Release every lock held by `owner` (called when the handle is closed)
+	pub fn release_owner(&self, owner: usize)
+	{
+		let mut lh = self.ranges.lock();
+		let before = lh.locks.len();
+		lh.locks.retain(|l| l.owner != owner);
+		if lh.locks.len() != before {
+			Self::wake_waiters(&mut lh);
+		}
+	}
+
+	fn wake_waiters(lh: &mut RangeLocks)
+	{
+		for w in lh.waiters.drain(..) {
+			w.signal();
+		}
+	}
+
+	/// Register a sleep object to be woken when any range lock is released
+	pub fn bind_wait_release(&self, obj: &mut SleepObject)
+	{
+		self.ranges.lock().waiters.push( obj.get_ref() );
+	}
+	/// Deregister a sleep object, returns true if a release happened while it was registered
+	pub fn clear_wait_release(&self, obj: &mut SleepObject) -> bool
+	{
+		let mut lh = self.ranges.lock();
+		let before = lh.waiters.len();
+		lh.waiters.retain(|w| !w.is_from(obj));
+		// If the reference was already removed, the waiters were woken by a release
+		lh.waiters.len() == before
+	}
+}
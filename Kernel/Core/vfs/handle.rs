@@ -5,7 +5,7 @@
 //! Opened file interface
 #[allow(unused_imports)]
 use prelude::*;
-use super::node::{CacheHandle,NodeType};
+use super::node::{CacheHandle,NodeType,OpenFlags};
 use lib::byte_str::{ByteStr,ByteString};
 use super::Path;
 
@@ -14,11 +14,13 @@ use super::Path;
 pub struct Any {
 	node: CacheHandle,
 }
-#[derive(Debug,Clone)]
+#[derive(Debug)]
 /// Normal file
 pub struct File {
 	node: CacheHandle,
 	mode: FileOpenMode,
+	/// Owner ID for advisory range locks held by this handle
+	lock_owner: usize,
 }
 #[derive(Debug,Clone)]
 /// Directory (for enumeration)
@@ -51,9 +53,9 @@ pub enum FileOpenMode
 	///
 	/// No changes to the file will be visible to the user (as the file is locked)
 	ExclRW,
-	/// Unique read-write, does Copy-on-write to create a new file
+	/// Unique read-write, denies any other opens while held
 	///
-	/// No changes to the file will be visible to the user (as it has its own copy)
+	/// No other handle can observe or change the file while this is held
 	UniqueRW,
 	/// Append only (allows multiple readers/writers)
 	///
@@ -65,6 +67,21 @@ pub enum FileOpenMode
 	Unsynch,
 }
 
+impl FileOpenMode
+{
+	fn lock_class(&self) -> super::file_lock::OpenClass {
+		use super::file_lock::OpenClass;
+		match *self
+		{
+		FileOpenMode::SharedRO | FileOpenMode::Execute => OpenClass::Reader,
+		FileOpenMode::ExclRW => OpenClass::ExclWriter,
+		FileOpenMode::UniqueRW => OpenClass::Unique,
+		FileOpenMode::Append => OpenClass::Appender,
+		FileOpenMode::Unsynch => OpenClass::Unsynch,
+		}
+	}
+}
+
 #[derive(Debug)]
 pub enum MemoryMapMode
 {
@@ -90,6 +107,10 @@ impl Any
 	pub fn get_class(&self) -> super::node::NodeClass {
 		self.node.get_class()
 	}
+	/// Owning (user, group) of the node
+	pub fn get_owner(&self) -> (u32, u32) {
+		self.node.get_owner()
+	}
 	
 	/// Upgrade the handle to a directory handle
 	pub fn to_dir(self) -> super::Result<Dir> {
@@ -125,6 +146,10 @@ pub struct MemoryMapHandle<'a>
 	handle: &'a File,
 	base: *mut (),
 	len: usize,
+	/// First page of the file covered by the mapping
+	page: u64,
+	/// Mapping writes back to the file
+	writeback: bool,
 }
 
 impl File
@@ -139,17 +164,16 @@ impl File
 		if !node.is_file() {
 			return Err(super::Error::TypeMismatch);
 		}
+		// TODO: Check permissions (must be readable/writable/executable in current context)
+		// - Mount options
 		match mode
 		{
-		// TODO: Mark file as shared
-		// TODO: Check permissions (must be readable in current context)
 		FileOpenMode::SharedRO => {},
-		// TODO: Mark file as shared
-		// TODO: Check permissions (must be executable in current context)
-		FileOpenMode::Execute => {},
-		_ => todo!("Acquire lock depending on mode({:?})", mode),
+		FileOpenMode::Execute => if node.is_no_exec() { return Err(super::Error::PermissionDenied) },
+		_ => if node.is_read_only() { return Err(super::Error::ReadOnlyFilesystem) },
 		}
-		Ok(File { node: node, mode: mode })
+		try!( node.file_open(mode.lock_class(), false) );
+		Ok(File { node: node, mode: mode, lock_owner: super::file_lock::new_owner() })
 	}
 	
 	pub fn size(&self) -> u64 {
@@ -162,11 +186,56 @@ impl File
 	/// slice).
 	pub fn read(&self, ofs: u64, dst: &mut [u8]) -> super::Result<usize> {
 		assert!(self.node.is_file());
+		match self.mode
+		{
+		FileOpenMode::Append => return Err(super::Error::PermissionDenied),
+		_ => {},
+		}
 		self.node.read(ofs, dst)
 	}
+	/// Write data to the file at the specified offset
+	///
+	/// Returns the number of bytes written, the file can only be extended by writing at the current end.
+	/// For `Append` handles the offset is ignored, and the data is atomically written to the end of the file.
 	pub fn write(&self, ofs: u64, src: &[u8]) -> super::Result<usize> {
 		assert!(self.node.is_file());
-		todo!("Handle::write({:#x}, {:p}+{}", ofs, src.as_ptr(), src.len());
+		match self.mode
+		{
+		FileOpenMode::SharedRO | FileOpenMode::Execute => Err(super::Error::PermissionDenied),
+		FileOpenMode::Append => self.node.append(src),
+		_ => self.node.write(ofs, src),
+		}
+	}
+	/// Set the size of the file (truncating, or extending with zeroes)
+	pub fn set_size(&self, size: u64) -> super::Result<()> {
+		match self.mode
+		{
+		FileOpenMode::SharedRO | FileOpenMode::Execute | FileOpenMode::Append => Err(super::Error::PermissionDenied),
+		_ => self.node.truncate(size),
+		}
+	}
+	/// Write any pages modified through writeback mappings to the backing filesystem
+	pub fn flush(&self) -> super::Result<()> {
+		self.node.flush()
+	}
+
+	/// Acquire an advisory lock on a byte range of the file
+	///
+	/// Fails with `Locked` if another handle holds a conflicting lock, use `bind_wait_lock` to wait for a release.
+	pub fn lock_range(&self, ofs: u64, len: u64, exclusive: bool) -> super::Result<()> {
+		self.node.lock_range(self.lock_owner, ofs, len, exclusive)
+	}
+	/// Release this handle's advisory locks within a byte range
+	pub fn unlock_range(&self, ofs: u64, len: u64) -> super::Result<()> {
+		self.node.unlock_range(self.lock_owner, ofs, len)
+	}
+	/// Register a sleep object to be signalled when any advisory lock on this file is released
+	pub fn bind_wait_lock(&self, obj: &mut ::threads::SleepObject) {
+		self.node.bind_wait_lock(obj)
+	}
+	/// Deregister a sleep object, returning true if a lock was released
+	pub fn clear_wait_lock(&self, obj: &mut ::threads::SleepObject) -> bool {
+		self.node.clear_wait_lock(obj)
 	}
 
 	
@@ -182,8 +251,9 @@ impl File
 			{
 			FileOpenMode::Execute => {},
 			FileOpenMode::SharedRO => {},
-			//FileOpenMode::ExclRW => /* NOTE: Needs extra checks to ensure that aliasing does not occur */
-			//FileOpenMode::UniqueRW => /* NOTE: Needs extra checks to ensure that aliasing does not occur */
+			// NOTE: Mappings share the page cache, so writes through this handle are visible in the mapping
+			FileOpenMode::ExclRW => {},
+			FileOpenMode::UniqueRW => {},
 			_ => return Err(super::Error::PermissionDenied),
 			},
 		// Executable - Execute mode only
@@ -200,28 +270,41 @@ impl File
 			//FileOpenMode::SharedRO => {},
 			_ => return Err(super::Error::PermissionDenied),
 			},
-		// Writeback - Requires exclusive access to the file (or a copy)
+		// Writeback - Shared writable mapping, requires a writable handle
+		// - All writeback mappings of a file share the same page cache frames
 		MemoryMapMode::WriteBack => match self.mode
 			{
-			//FileOpenMode::ExclRW => /* NOTE: Needs extra checks to ensure that aliasing does not occur */
-			//FileOpenMode::UniqueRW => /* NOTE: Needs extra checks to ensure that aliasing does not occur */
+			FileOpenMode::ExclRW => {},
+			FileOpenMode::UniqueRW => {},
+			FileOpenMode::Unsynch => {},
 			_ => return Err(super::Error::PermissionDenied),
 			},
 		}
 		
-		// TODO: Handle unaligned mappings somehow
-		// - Depends on several qirks:
-		//  > Unaligned address could write to an existing page (converting it to a private) - But how would that interact with existing mappings?
-		//  > Unaligned sizes would usually cause a new anon mapping, but if its unaligned becuase of EOF, it should just be COW as usual
-		assert!(address % ::PAGE_SIZE == 0, "TODO: Unaligned memory_map (address={})", address);
-		assert!(size % ::PAGE_SIZE == 0, "TODO: Unaligned memory_map (size={})", size);
+		// NOTE: Unaligned sizes are rounded up to a page, the page cache zero-fills the tail of the last page
+		// TODO: Handle unaligned addresses somehow
+		// - An unaligned address could write to an existing page (converting it to a private) - But how would that interact with existing mappings?
+		if address % ::PAGE_SIZE != 0 {
+			log_notice!("memory_map - Unaligned address {:#x}", address);
+			return Err( super::Error::InvalidParameter );
+		}
 		if address % ::PAGE_SIZE != (ofs % ::PAGE_SIZE as u64) as usize {
 			return Err( super::Error::Unknown("memory_map alignment mismatch") );
 		}
-		// - Limit checking (ofs + size must be within size of the file)
-		// TODO: Limit checking
+		if size == 0 {
+			return Err( super::Error::InvalidParameter );
+		}
+		let page_count = ::lib::num::div_up(size, ::PAGE_SIZE);
+		let first_page = ofs / ::PAGE_SIZE as u64;
+		// - Limit checking (all pages must contain some of the file, partial last page is allowed)
+		let file_pages = ::lib::num::div_up(self.node.get_valid_size(), ::PAGE_SIZE as u64);
+		if first_page + page_count as u64 > file_pages {
+			log_notice!("memory_map - Range {:#x}+{:#x} past EOF ({} pages)", ofs, size, file_pages);
+			return Err( super::Error::InvalidParameter );
+		}
+		let writeback = match mode { MemoryMapMode::WriteBack => true, _ => false };
+
 		// - Reserve the region to be mapped (reserve sticks a zero page in)
-		let page_count = size / ::PAGE_SIZE;
 		let mut resv = match ::memory::virt::reserve(address as *mut (), page_count)
 			{
 			Ok(v) => v,
@@ -231,23 +314,20 @@ impl File
 				},
 			};
 		// - Obtain handles to each cached page, and map into the reservation
+		// > All mappings share the frames in the file's page cache (COW mappings will copy on first write)
 		for i in 0 .. page_count {
-			let page = ofs / ::PAGE_SIZE as u64 + i as u64;
-			// 1. Search the node for this particular page
-			//let lh = self.page_cache.read();
-			//  - If found, map over region
-			// 2. Drop lock, read data from file, and try again
-			//drop(lh)
-			try!( self.node.read(page * ::PAGE_SIZE as u64, resv.get_mut_page(i)) );
-			// 3. Acquire write on lock, and attempt to insert a handle to this page
-			//let lh = self.page_cache.write();
-			//match lh.try_insert(pag, self.get_page_handle(i))
-			//{
-			//Ok(_) => {},	// Inserted correctly
-			//Err(h) => {	// Another handle made a page for this first
-			//	resv.map_at(i, h);	// - Map over our original attempt
-			//	},
-			//}
+			match self.node.map_page(first_page + i as u64, writeback)
+			{
+			Ok(frame) => resv.map_at(i, frame),
+			Err(e) => {
+				log_notice!("memory_map - Failed to obtain page {}: {:?}", first_page + i as u64, e);
+				resv.cancel();
+				for j in 0 .. i {
+					self.node.unmap_page(first_page + j as u64, writeback);
+				}
+				return Err(e);
+				},
+			}
 		}
 		resv.finalise( match mode
 			{
@@ -262,9 +342,23 @@ impl File
 			handle: self,
 			base: address as *mut (),
 			len: page_count * ::PAGE_SIZE,
+			page: first_page,
+			writeback: writeback,
 			})
 	}
 }
+impl Clone for File
+{
+	fn clone(&self) -> File {
+		// The clone shares this handle's open-mode exclusivity, but has its own range locks
+		self.node.file_open(self.mode.lock_class(), true).expect("File::clone - file_open failed");
+		File {
+			node: self.node.clone(),
+			mode: self.mode.clone(),
+			lock_owner: super::file_lock::new_owner(),
+			}
+	}
+}
 impl ::core::ops::Drop for File
 {
 	fn drop(&mut self) {
@@ -272,9 +366,17 @@ impl ::core::ops::Drop for File
 		{
 		FileOpenMode::SharedRO => {},
 		FileOpenMode::Execute => {},
-		_ => todo!("File::drop() - mode={:?}", self.mode),
+		FileOpenMode::Append => {},
+		FileOpenMode::ExclRW | FileOpenMode::UniqueRW | FileOpenMode::Unsynch => {
+			// Flush out any changes made via (possibly still active) writeback mappings
+			if let Err(e) = self.node.flush() {
+				log_warning!("File::drop() - Error flushing {:?}: {:?}", self.node, e);
+			}
+			},
 		}
-		// TODO: For files, we need to release the lock
+		self.node.release_range_locks(self.lock_owner);
+		self.node.trim_cache();
+		self.node.file_close(self.mode.lock_class());
 	}
 }
 
@@ -282,7 +384,21 @@ impl<'a> Drop for MemoryMapHandle<'a>
 {
 	fn drop(&mut self)
 	{
-		todo!("MemoryMapHandle::drop {{ handle={:?}, base={:p}+{}}}", self.handle, self.base, self.len);
+		log_trace!("MemoryMapHandle::drop {{ handle={:?}, base={:p}+{} }}", self.handle, self.base, self.len);
+		if self.writeback {
+			if let Err(e) = self.handle.node.flush() {
+				log_warning!("MemoryMapHandle::drop - Error flushing {:?}: {:?}", self.handle.node, e);
+			}
+		}
+		let page_count = self.len / ::PAGE_SIZE;
+		// SAFE: This handle owns the mapped region
+		unsafe {
+			::memory::virt::unmap(self.base, page_count);
+		}
+		for i in 0 .. page_count {
+			self.handle.node.unmap_page(self.page + i as u64, self.writeback);
+		}
+		self.handle.node.trim_cache();
 	}
 }
 
@@ -312,20 +428,41 @@ impl Dir
 		assert!(node.is_dir());
 		Ok( Dir { node: node } )
 	}
+	/// Create a new (empty) file
+	pub fn create_file(&self, name: &ByteStr) -> super::Result<Any> {
+		let node = try!(self.node.create(name, NodeType::File));
+		Ok( Any { node: node } )
+	}
 	/// Create a new symbolic link
 	pub fn symlink(&self, name: &str, target: &Path) -> super::Result<()> {
 		try!(self.node.create(name.as_ref(), NodeType::Symlink(target)));
 		Ok( () )
 	}
 
+	/// Remove an entry from this directory
+	pub fn unlink(&self, name: &ByteStr) -> super::Result<()> {
+		self.node.unlink(name)
+	}
+	/// Rename an entry in this directory
+	pub fn rename(&self, old_name: &ByteStr, new_name: &ByteStr) -> super::Result<()> {
+		self.node.rename(old_name, new_name)
+	}
+	/// Create a watch that reports changes to this directory's entries
+	pub fn watch(&self) -> super::Result<super::watch::Watch> {
+		super::watch::Watch::new(self.node.clone())
+	}
+
 	/// Open a child of this node
 	pub fn open_child(&self, name: &ByteStr) -> super::Result<Any> {
 		let node = try!(self.node.open_child(name));
 		Ok(Any { node: node })
 	}
 
-	pub fn open_child_path(&self, path: &Path) -> super::Result<Any> {
-		let node = try!(CacheHandle::from_path_at_node(self.node.clone(), path));
+	/// Open a path relative to this directory
+	///
+	/// `flags` control if a final symbolic link is followed, and if the path may leave this directory.
+	pub fn open_child_path(&self, path: &Path, flags: OpenFlags) -> super::Result<Any> {
+		let node = try!(CacheHandle::from_path_at_node_ex(self.node.clone(), path, flags));
 		Ok(Any{ node: node })
 	}
 
@@ -380,7 +517,8 @@ impl<'a> ::core::iter::Iterator for DirIter<'a> {
 impl Symlink
 {
 	pub fn open(path: &Path) -> super::Result<Symlink> {
-		try!(Any::open(path)).to_symlink()
+		let node = try!(CacheHandle::from_path_ex(path, OpenFlags { no_follow: true, ..Default::default() }));
+		Any { node: node }.to_symlink()
 	}
 	pub fn get_target(&self) -> super::Result<ByteString> {
 		self.node.get_target()
@@ -73,8 +73,11 @@ pub use self::path::{Path,PathBuf};
 pub mod node;
 pub mod mount;
 pub mod handle;
+pub mod watch;
 mod path;
 mod ramfs;
+mod file_cache;
+mod file_lock;
 
 fn init()
 {
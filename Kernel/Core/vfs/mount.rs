@@ -26,6 +26,27 @@ struct MountedVolume
 {
 	mountpoint_node: CacheHandle,
 	fs: Box<Filesystem>,
+	options: MountOptions,
+}
+
+/// Options applied to a mount (parsed from strings such as `ro` or `codepage=437`)
+///
+/// Access restrictions (`read_only` and `no_exec`) are enforced by the VFS, the remainder are up to the driver.
+#[derive(Debug,Default,Clone)]
+pub struct MountOptions
+{
+	/// Deny all modifications to the filesystem (`ro`)
+	pub read_only: bool,
+	/// Deny opening files for execution (`noexec`)
+	pub no_exec: bool,
+	/// Inform the volume of freed blocks, if supported (`discard`)
+	pub discard: bool,
+	/// Owning user for filesystems without ownership information (`uid=N`), reported for every node
+	pub uid: Option<u32>,
+	/// Owning group for filesystems without ownership information (`gid=N`), reported for every node
+	pub gid: Option<u32>,
+	/// Codepage used for legacy (non-unicode) names (`codepage=N`)
+	pub codepage: Option<u16>,
 }
 
 
@@ -55,7 +76,7 @@ pub trait Driver:
 	/// Mount the provided volume as this filesystem
 	///
 	/// NOTE: `handle` isn't actually usable until after this function returns
-	fn mount(&self, vol: VolumeHandle, handle: SelfHandle) -> super::Result<Box<Filesystem>>;
+	fn mount(&self, vol: VolumeHandle, handle: SelfHandle, options: &MountOptions) -> super::Result<Box<Filesystem>>;
 }
 
 pub struct DriverRegistration(&'static str);
@@ -64,8 +85,10 @@ pub struct DriverRegistration(&'static str);
 static S_DRIVERS: LazyStatic<RwLock< VecMap<&'static str, &'static Driver> >> = lazystatic_init!();
 /// Mounted volumes
 static S_VOLUMES: LazyStatic<RwLock< SparseVec<MountedVolume> >> = lazystatic_init!();
-/// Root mount
-static S_ROOT_VOLUME: RwLock<Option<Box<Filesystem>>> = RwLock::new(None);
+/// Root mount (and its options)
+static S_ROOT_VOLUME: RwLock<Option<(Box<Filesystem>, MountOptions)>> = RwLock::new(None);
+/// Serialises unmount operations
+static S_UNMOUNT_LOCK: ::sync::Mutex<()> = ::sync::Mutex::new(());
 
 pub fn init()
 {
@@ -77,9 +100,9 @@ pub fn init()
 }
 
 /// Mount a volume at the provided location
-// TODO: Parse options
-pub fn mount(location: &Path, vol: VolumeHandle, fs: &str, _options: &[&str]) -> Result<(),MountError>
+pub fn mount(location: &Path, vol: VolumeHandle, fs: &str, options: &[&str]) -> Result<(),MountError>
 {
+	let options = try!(MountOptions::parse(options));
 	let drivers = S_DRIVERS.read();
 	// 1. (maybe) detect filesystem
 	let driver = if fs == "" {
@@ -105,7 +128,7 @@ pub fn mount(location: &Path, vol: VolumeHandle, fs: &str, _options: &[&str]) ->
 	
 	if location == Path::new("/")
 	{
-		let fs: Box<_> = match driver.mount(vol, SelfHandle(0))
+		let fs: Box<_> = match driver.mount(vol, SelfHandle(0), &options)
 			{
 			Ok(v) => v,
 			Err(_) => return Err(MountError::CallFailed),
@@ -115,7 +138,7 @@ pub fn mount(location: &Path, vol: VolumeHandle, fs: &str, _options: &[&str]) ->
 			log_warning!("TODO: Support remounting /");
 			return Err(MountError::MountpointUsed);
 		}
-		*lh = Some(fs);
+		*lh = Some( (fs, options) );
 	}
 	else
 	{
@@ -134,13 +157,17 @@ pub fn mount(location: &Path, vol: VolumeHandle, fs: &str, _options: &[&str]) ->
 		
 		// 3. Reserve the mountpoint ID (using a placeholder instance)
 		// NOTE: Nothing should know of this index until after mount is completed
-		let vidx = S_VOLUMES.write().insert(MountedVolume { mountpoint_node: nh, fs: Box::new(NullFs) });
+		let vidx = S_VOLUMES.write().insert(MountedVolume { mountpoint_node: nh, fs: Box::new(NullFs), options: options.clone() });
 
 		// 4. Mount and register volume
-		let fs = match driver.mount(vol, SelfHandle(vidx))
+		let fs = match driver.mount(vol, SelfHandle(vidx), &options)
 			{
 			Ok(v) => v,
-			Err(_) => return Err(MountError::CallFailed),
+			Err(e) => {
+				log_notice!("Mounting {:?} failed: {:?}", location, e);
+				S_VOLUMES.write().remove(vidx);
+				return Err(MountError::CallFailed);
+				},
 			};
 
 		// 5. Store and bind to mountpoint
@@ -156,6 +183,56 @@ pub fn mount(location: &Path, vol: VolumeHandle, fs: &str, _options: &[&str]) ->
 
 	Ok( () )
 }
+
+/// Unmount the volume mounted at the provided location
+///
+/// Fails with `MountError::Busy` if any nodes on the volume are still open
+pub fn unmount(location: &Path) -> Result<(),MountError>
+{
+	// 1. Locate the mount (resolving the path yields the root of the mounted volume)
+	let mount_id = {
+		let nh = match CacheHandle::from_path(location)
+			{
+			Ok(nh) => nh,
+			Err(_) => return Err(MountError::InvalidMountpoint),
+			};
+		let (mount_id, inode) = nh.get_ids();
+		// - The root filesystem cannot be unmounted
+		if mount_id == 0 || inode != Handle::from_id(mount_id).root_inode() {
+			return Err(MountError::InvalidMountpoint);
+		}
+		mount_id
+		};
+
+	// 2. Unbind from the mountpoint (so no new lookups reach the volume)
+	// - Serialised, so a concurrent unmount of the same volume can't see it half-removed
+	let _lh = S_UNMOUNT_LOCK.lock();
+	let mountpoint_node = match S_VOLUMES.read().get(mount_id - 1)
+		{
+		Some(v) => v.mountpoint_node.clone(),
+		None => return Err(MountError::InvalidMountpoint),
+		};
+	mountpoint_node.unmount();
+
+	// 3. Purge cached nodes (failing if any are still referenced) and remove the volume
+	// NOTE: The volume is removed with the node cache locked, so a lookup that raced the unbind above
+	//       either holds a node (and the purge fails) or can no longer find the volume.
+	let removed = match super::node::purge_mount(mount_id, || S_VOLUMES.write().remove(mount_id - 1))
+		{
+		Some(v) => v,
+		None => {
+			log_notice!("Unmount of {:?} failed, volume in use", location);
+			mountpoint_node.mount(mount_id);
+			return Err(MountError::Busy);
+			},
+		};
+
+	// 4. Release the filesystem instance (outside of the locks)
+	drop(removed);
+	log_log!("Unmounted {:?}", location);
+	Ok( () )
+}
+
 #[derive(Debug)]
 pub enum MountError
 {
@@ -164,6 +241,10 @@ pub enum MountError
 	InvalidMountpoint,
 	MountpointUsed,
 	CallFailed,
+	/// An option was not recognised, or had an invalid value
+	BadOption,
+	/// The volume is in use
+	Busy,
 }
 impl_fmt! {
 	Display(self,f) for MountError {
@@ -174,11 +255,47 @@ impl_fmt! {
 			&MountError::InvalidMountpoint => "The specified mountpoint was invalid",
 			&MountError::MountpointUsed => "The specified mountpoint was already used",
 			&MountError::CallFailed => "Driver's mount call failed",
+			&MountError::BadOption => "Invalid mount option",
+			&MountError::Busy => "The volume is in use",
 			})
 	}
 }
 
 
+impl MountOptions
+{
+	/// Parse a list of option strings
+	pub fn parse(options: &[&str]) -> Result<MountOptions,MountError>
+	{
+		let mut rv = MountOptions::default();
+		for &opt in options
+		{
+			let (name, value) = match opt.find('=')
+				{
+				Some(pos) => (&opt[..pos], Some(&opt[pos+1..])),
+				None => (opt, None),
+				};
+			match (name, value)
+			{
+			("ro", None) => rv.read_only = true,
+			("rw", None) => rv.read_only = false,
+			("noexec", None) => rv.no_exec = true,
+			("exec", None) => rv.no_exec = false,
+			("discard", None) => rv.discard = true,
+			("nodiscard", None) => rv.discard = false,
+			("uid", Some(v)) => rv.uid = Some( try!(v.parse().map_err(|_| MountError::BadOption)) ),
+			("gid", Some(v)) => rv.gid = Some( try!(v.parse().map_err(|_| MountError::BadOption)) ),
+			("codepage", Some(v)) => rv.codepage = Some( try!(v.parse().map_err(|_| MountError::BadOption)) ),
+			_ => {
+				log_notice!("Unknown/malformed mount option '{}'", opt);
+				return Err(MountError::BadOption);
+				},
+			}
+		}
+		Ok(rv)
+	}
+}
+
 impl DriverRegistration
 {
 	pub fn new(name: &'static str, fs: &'static Driver) -> Option<DriverRegistration> {
@@ -206,24 +323,45 @@ impl Handle
 			Handle(id)
 		}
 	}
+	/// Obtain a handle to a volume that may have been unmounted
+	pub fn try_from_id(id: usize) -> Option<Handle> {
+		if id == 0 || S_VOLUMES.read().get(id-1).is_some() {
+			Some( Handle(id) )
+		}
+		else {
+			None
+		}
+	}
 	
 	pub fn id(&self) -> usize {
 		self.0
 	}
 	pub fn root_inode(&self) -> InodeId {
-		self.with_fs(|fs| fs.root_inode())
+		self.with_fs(|fs, _| fs.root_inode())
 	}
 	
 	pub fn get_node(&self, id: InodeId) -> Option<Node> {
-		self.with_fs(|fs| fs.get_node_by_inode(id))
+		self.with_fs(|fs, _| fs.get_node_by_inode(id))
+	}
+	/// Returns true if the volume was mounted read-only
+	pub fn is_read_only(&self) -> bool {
+		self.with_fs(|_, o| o.read_only)
+	}
+	/// Returns true if files on this volume cannot be executed
+	pub fn is_no_exec(&self) -> bool {
+		self.with_fs(|_, o| o.no_exec)
 	}
 
-	fn with_fs<R, F: FnOnce(&Filesystem)->R>(&self, f: F) -> R {
+	fn with_fs<R, F: FnOnce(&Filesystem, &MountOptions)->R>(&self, f: F) -> R {
 		if self.0 == 0 {
-			f(&**S_ROOT_VOLUME.read().as_ref().unwrap())
+			let lh = S_ROOT_VOLUME.read();
+			let &(ref fs, ref options) = lh.as_ref().unwrap();
+			f(&**fs, options)
 		}
 		else {
-			f(&*S_VOLUMES.read().get(self.0 - 1).unwrap().fs)
+			let lh = S_VOLUMES.read();
+			let v = lh.get(self.0 - 1).unwrap();
+			f(&*v.fs, &v.options)
 		}
 	}
 }
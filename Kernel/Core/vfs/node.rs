@@ -33,6 +33,10 @@ pub trait NodeBase: Send {
 	fn get_id(&self) -> InodeId;
 	/// Return an &Any associated with this node (not nessesarily same as `self`, up to the driver)
 	fn get_any(&self) -> &Any;
+	/// Return the owning (user, group) of this node
+	fn get_owner(&self) -> (u32, u32) {
+		(0, 0)
+	}
 }
 /// Trait for "File" nodes
 pub trait File: NodeBase {
@@ -73,6 +77,11 @@ pub trait Dir: NodeBase {
 	fn link(&self, name: &ByteStr, inode: &NodeBase) -> Result<()>;
 	/// Remove the specified name
 	fn unlink(&self, name: &ByteStr) -> Result<()>;
+	/// Rename an entry within this directory
+	fn rename(&self, old_name: &ByteStr, new_name: &ByteStr) -> Result<()> {
+		let _ = (old_name, new_name);
+		Err( super::Error::Unknown("Rename not supported by filesystem") )
+	}
 }
 /// Trait for symbolic link nodes.
 pub trait Symlink: NodeBase {
@@ -101,10 +110,11 @@ pub enum Node
 enum CacheNodeInt
 {
 	File {
-		fsnode: Box<File>
-		
-		// File memory map data
-		//mapped_pages: HashMap<u64,FrameHandle>,
+		fsnode: Box<File>,
+		/// Pages shared between `read`/`write` and memory mappings
+		pages: super::file_cache::FilePageCache,
+		/// Open-mode exclusivity and advisory range locks
+		locks: super::file_lock::FileLocks,
 		},
 	Dir {
 		mountpoint: AtomicUsize,	// 0 is invalid (that's root), so means "no mount"
@@ -122,7 +132,7 @@ impl_from!{
 	From<Node>(v) for CacheNodeInt {
 		match v
 		{
-		Node::File(f) => CacheNodeInt::File { fsnode: f },
+		Node::File(f) => CacheNodeInt::File { fsnode: f, pages: Default::default(), locks: Default::default() },
 		Node::Dir(f) => CacheNodeInt::Dir { fsnode: f, mountpoint: AtomicUsize::new(0) },
 		Node::Symlink(f) => CacheNodeInt::Symlink { target: f.read(), fsnode: f },
 		Node::Special(f) => CacheNodeInt::Special { fsnode: f },
@@ -130,6 +140,31 @@ impl_from!{
 	}
 }
 
+/// Flags controlling how a path is resolved
+#[derive(Debug,Default,Copy,Clone)]
+pub struct OpenFlags
+{
+	/// Don't follow a symbolic link as the final component (open the link itself)
+	pub no_follow: bool,
+	/// Fail if resolution would leave the starting node (via `..` or an absolute symbolic link)
+	pub beneath: bool,
+}
+
+/// Maximum number of symbolic links followed while resolving a single path
+const MAX_SYMLINK_HOPS: usize = 16;
+
+/// State for a single path resolution
+struct Resolver
+{
+	flags: OpenFlags,
+	/// Number of symbolic links followed so far
+	hops: usize,
+	/// Directories traversed to reach the current node (used for `..` and relative symbolic links)
+	parents: Vec<CacheHandle>,
+	/// Root of the caller's view, absolute symbolic links are resolved from here
+	root: CacheHandle,
+}
+
 struct CachedNode
 {
 	refcount: AtomicUsize,
@@ -173,6 +208,36 @@ impl Clone for CacheHandle
 	}
 }
 
+impl ::core::ops::Drop for CacheHandle
+{
+	fn drop(&mut self) {
+		// SAFE: self.ptr is always valid, and operation is atomic
+		// NOTE: Unused nodes are kept in the cache, they're only removed by `purge_mount`
+		unsafe {
+			(*self.ptr).refcount.fetch_sub(1, atomic::Ordering::Relaxed);
+		}
+	}
+}
+
+/// Remove all cached nodes for the specified mount, then call `release` (with the cache still locked)
+///
+/// Returns `None` (and removes nothing) if any node on the mount is still referenced. As `release` runs
+/// with the node cache locked, no new node can be opened on the mount between the purge and the release.
+pub fn purge_mount<R, F: FnOnce()->R>(mountpt: usize, release: F) -> Option<R>
+{
+	let mut lh = S_NODE_CACHE.lock();
+	if lh.iter().any(|(k,v)| k.0 == mountpt && v.refcount.load(atomic::Ordering::Relaxed) > 0) {
+		return None;
+	}
+	let keys: Vec<_> = lh.iter().map(|(k,_)| *k).filter(|k| k.0 == mountpt).collect();
+	// - Nodes are dropped after the lock is released (dropping can call into the filesystem)
+	let nodes: Vec<_> = keys.iter().filter_map(|k| lh.remove(k)).collect();
+	let rv = release();
+	drop(lh);
+	drop(nodes);
+	Some(rv)
+}
+
 impl CacheHandle
 {
 	/// Obtain a node handle using a mountpoint ID and inode number
@@ -188,7 +253,8 @@ impl CacheHandle
 				e.into_mut()
 				},
 			Entry::Vacant(e) =>
-				match super::mount::Handle::from_id(mountpoint).get_node(inode)
+				// NOTE: `try_from_id` fails if the volume was unmounted (which is done with the node cache locked)
+				match super::mount::Handle::try_from_id(mountpoint).and_then(|h| h.get_node(inode))
 				{
 				Some(node) => e.insert(Box::new(CachedNode { node: node.into(), refcount: AtomicUsize::new(1) })),
 				None => return Err( super::Error::NotFound ),
@@ -211,7 +277,12 @@ impl CacheHandle
 			let new_mountpoint = new_mountpoint.load(atomic::Ordering::Relaxed);
 			if new_mountpoint != 0 {
 				// Then recurse (hopefully only once) with the new mountpoint
-				let new_inode = super::mount::Handle::from_id(new_mountpoint).root_inode();
+				// - The volume can be unmounted between reading the mountpoint and this lookup
+				let new_inode = match super::mount::Handle::try_from_id(new_mountpoint)
+					{
+					Some(h) => h.root_inode(),
+					None => return Err( super::Error::NotFound ),
+					};
 				log_trace!("CacheHandle::from_ids({},{}) => Mount {}, {}",
 					mountpoint, inode,  new_mountpoint, new_inode);
 				return CacheHandle::from_ids(new_mountpoint, new_inode);
@@ -223,72 +294,44 @@ impl CacheHandle
 	}
 	
 	
-	pub fn from_path_at_node(mut node_h: CacheHandle, path: &Path) -> super::Result<CacheHandle>
+	/// Obtain a node handle using a path relative to another node (following symbolic links)
+	pub fn from_path_at_node(node_h: CacheHandle, path: &Path) -> super::Result<CacheHandle>
+	{
+		CacheHandle::from_path_at_node_ex(node_h, path, Default::default())
+	}
+	/// Obtain a node handle using a path relative to another node, with resolution flags
+	///
+	/// Absolute paths (and absolute symbolic links) are resolved relative to `node_h`, as it's treated as the root
+	/// of the caller's view.
+	pub fn from_path_at_node_ex(node_h: CacheHandle, path: &Path, flags: OpenFlags) -> super::Result<CacheHandle>
 	{
-		log_function!("CacheHandle::from_path_at_node(node_h={:?}, {:?})", node_h, path);
+		log_function!("CacheHandle::from_path_at_node_ex(node_h={:?}, {:?}, {:?})", node_h, path, flags);
 		let path = if path.is_absolute() {
 				try!(path.split_off_first().ok_or(super::Error::MalformedPath)).1
 			}
 			else {
 				path
 			};
-		// Iterate path components
-		for seg in path
-		{
-			log_trace!("seg = {:?}", seg);
-			// Loop resolving symbolic links
-			loop
-			{
-				// TODO: Should symlinks be handled in this function? Or should the passed path be without symlinks?
-				node_h = if let CacheNodeInt::Symlink { ref target, .. } = *node_h.as_ref() {
-					//log_debug!("- seg={:?} : SYMLINK {:?}", seg, name);
-					let linkpath = Path::new(&target);
-					if linkpath.is_absolute() {
-						try!(CacheHandle::from_path(linkpath))
-					}
-					else {
-						//TODO: To make this work (or any path-relative symlink), the current position in
-						//      `path` needs to be known.
-						// It can be hacked up though...
-						let parent_segs_len = seg.as_ref().as_ptr() as usize - AsRef::<[u8]>::as_ref(path).as_ptr() as usize;
-						let parent = ByteStr::new( &AsRef::<[u8]>::as_ref(path)[..parent_segs_len] );
-
-						//let segs = [ &path[..pos], &link ];
-						//let p = PathChain::new(&segs);
-						//try!( CacheHandle::from_path(p) )
-						// Recurse with a special chained path type
-						// (that iterates but can't be sliced).
-						todo!("Relative symbolic links {:?} relative to {:?}", target, parent)
-					}
-				}
-				else {
-					break;
-				};
-			}
+		let mut resolver = Resolver {
+			flags: flags,
+			hops: 0,
+			parents: Vec::new(),
+			root: node_h.clone(),
+			};
+		let rv = try!( resolver.walk(node_h, path, !flags.no_follow) );
+		log_trace!("CacheHandle::from_path_at_node_ex() {:?}", rv);
+		Ok( rv )
+	}
 
-			// Look up this component in the current node
-			node_h = match *node_h.as_ref()
-				{
-				CacheNodeInt::Dir { fsnode: ref dir, .. } => {
-					//log_debug!("- seg={:?} : DIR", seg);
-					let next_id = match dir.lookup(seg)
-						{
-						Ok(v) => v,
-						Err(_) => return Err(super::Error::NotFound),
-						};
-					try!(CacheHandle::from_ids( node_h.mountpt, next_id ))
-					},
-				_ => return Err(super::Error::NonDirComponent),
-				};
-		}
-		log_trace!("CacheHandle::from_path_at_node() {:?}", node_h);
-		Ok( node_h )
-	}
-
-	/// Obtain a node handle using a path
+	/// Obtain a node handle using a path (following symbolic links)
 	pub fn from_path(path: &Path) -> super::Result<CacheHandle>
 	{
-		log_function!("CacheHandle::from_path({:?})", path);
+		CacheHandle::from_path_ex(path, Default::default())
+	}
+	/// Obtain a node handle using a path, with resolution flags
+	pub fn from_path_ex(path: &Path, flags: OpenFlags) -> super::Result<CacheHandle>
+	{
+		log_function!("CacheHandle::from_path_ex({:?}, {:?})", path, flags);
 		// TODO: Support path caching?
 		
 		// - Remove the leading / from the absolute path
@@ -302,7 +345,7 @@ impl CacheHandle
 		let mph = super::mount::Handle::from_id(0);
 		let node_h = try!(CacheHandle::from_ids( mph.id(), mph.root_inode() ));
 
-		CacheHandle::from_path_at_node(node_h, path)
+		CacheHandle::from_path_at_node_ex(node_h, path, flags)
 	}
 	
 	pub fn get_class(&self) -> NodeClass {
@@ -317,6 +360,16 @@ impl CacheHandle
 	pub fn is_dir(&self) -> bool {
 		self.get_class() == NodeClass::Dir
 	}
+	/// Owning (user, group) of this node, as reported by the filesystem
+	pub fn get_owner(&self) -> (u32, u32) {
+		match self.as_ref()
+		{
+		&CacheNodeInt::Dir { ref fsnode, .. } => fsnode.get_owner(),
+		&CacheNodeInt::File { ref fsnode, .. } => fsnode.get_owner(),
+		&CacheNodeInt::Symlink { ref fsnode, .. } => fsnode.get_owner(),
+		&CacheNodeInt::Special { ref fsnode, .. } => fsnode.get_owner(),
+		}
+	}
 	pub fn is_file(&self) -> bool {
 		self.get_class() == NodeClass::File
 	}
@@ -324,6 +377,11 @@ impl CacheHandle
 		self.get_class() == NodeClass::Symlink
 	}
 
+	/// Obtain the (mountpoint, inode) pair identifying this node
+	pub fn get_ids(&self) -> (usize, InodeId) {
+		(self.mountpt, self.inode)
+	}
+
 	pub fn get_any(&self) -> &Any {
 		match self.as_ref()
 		{
@@ -338,15 +396,46 @@ impl CacheHandle
 impl CacheHandle
 {
 	pub fn create(&self, name: &ByteStr, ty: NodeType) -> super::Result<CacheHandle> {
+		try!(self.check_writable());
 		match self.as_ref()
 		{
 		&CacheNodeInt::Dir { ref fsnode, .. } => {
 			let inode = try!(fsnode.create(name, ty));
+			super::watch::notify(self.mountpt, self.inode, super::watch::ChangeKind::Created, inode, name);
 			Ok( try!(CacheHandle::from_ids(self.mountpt, inode)) )
 			},
 		_ => Err( super::Error::Unknown("Calling create on non-directory") ),
 		}
 	}
+	/// Remove a name from this directory
+	pub fn unlink(&self, name: &ByteStr) -> super::Result<()> {
+		try!(self.check_writable());
+		match self.as_ref()
+		{
+		&CacheNodeInt::Dir { ref fsnode, .. } => {
+			let inode = try!(fsnode.lookup(name));
+			try!(fsnode.unlink(name));
+			super::watch::notify(self.mountpt, self.inode, super::watch::ChangeKind::Removed, inode, name);
+			Ok( () )
+			},
+		_ => Err( super::Error::Unknown("Calling unlink on non-directory") ),
+		}
+	}
+	/// Rename an entry within this directory
+	pub fn rename(&self, old_name: &ByteStr, new_name: &ByteStr) -> super::Result<()> {
+		try!(self.check_writable());
+		match self.as_ref()
+		{
+		&CacheNodeInt::Dir { ref fsnode, .. } => {
+			let inode = try!(fsnode.lookup(old_name));
+			try!(fsnode.rename(old_name, new_name));
+			super::watch::notify(self.mountpt, self.inode, super::watch::ChangeKind::RenamedFrom, inode, old_name);
+			super::watch::notify(self.mountpt, self.inode, super::watch::ChangeKind::RenamedTo, inode, new_name);
+			Ok( () )
+			},
+		_ => Err( super::Error::Unknown("Calling rename on non-directory") ),
+		}
+	}
 	pub fn read_dir(&self, ofs: usize, items: &mut ReadDirCallback) -> super::Result<usize> {
 		match self.as_ref()
 		{
@@ -387,6 +476,14 @@ impl CacheHandle
 		_ => false,
 		}
 	}
+	/// Clear the mount binding on this node, returning the previously mounted ID
+	pub fn unmount(&self) -> usize {
+		match self.as_ref()
+		{
+		&CacheNodeInt::Dir { ref mountpoint, .. } => mountpoint.swap(0, atomic::Ordering::Relaxed),
+		_ => 0,
+		}
+	}
 }
 /// Normal file methods
 impl CacheHandle
@@ -402,13 +499,222 @@ impl CacheHandle
 	pub fn read(&self, ofs: u64, dst: &mut [u8]) -> super::Result<usize> {
 		match self.as_ref()
 		{
-		&CacheNodeInt::File { ref fsnode, .. } => Ok( try!(fsnode.read(ofs, dst)) ),
+		&CacheNodeInt::File { ref fsnode, ref pages, .. } => pages.read(&**fsnode, ofs, dst),
 		_ => Err( super::Error::Unknown("Calling read on non-file") ),
 		}
 	}
+	pub fn write(&self, ofs: u64, src: &[u8]) -> super::Result<usize> {
+		try!(self.check_writable());
+		match self.as_ref()
+		{
+		&CacheNodeInt::File { ref fsnode, ref pages, .. } => {
+			let old_size = fsnode.size();
+			let rv = try!(pages.write(&**fsnode, ofs, src));
+			if fsnode.size() != old_size {
+				super::watch::notify_size(self.mountpt, self.inode);
+			}
+			Ok(rv)
+			},
+		_ => Err( super::Error::Unknown("Calling write on non-file") ),
+		}
+	}
+
+	/// Set the size of the file (truncating, or extending with zeroes)
+	pub fn truncate(&self, size: u64) -> super::Result<()> {
+		try!(self.check_writable());
+		match self.as_ref()
+		{
+		&CacheNodeInt::File { ref fsnode, ref pages, .. } => {
+			let old_size = fsnode.size();
+			try!(pages.truncate(&**fsnode, size));
+			if fsnode.size() != old_size {
+				super::watch::notify_size(self.mountpt, self.inode);
+			}
+			Ok( () )
+			},
+		_ => Err( super::Error::Unknown("Calling truncate on non-file") ),
+		}
+	}
+
+	/// Obtain the (shared) frame backing a page of the file, for use in a memory map
+	pub fn map_page(&self, page: u64, writeback: bool) -> super::Result<::memory::phys::FrameHandle> {
+		match self.as_ref()
+		{
+		&CacheNodeInt::File { ref fsnode, ref pages, .. } => pages.map_page(&**fsnode, page, writeback),
+		_ => Err( super::Error::Unknown("Calling map_page on non-file") ),
+		}
+	}
+	/// Release a page obtained with `map_page`
+	pub fn unmap_page(&self, page: u64, writeback: bool) {
+		match self.as_ref()
+		{
+		&CacheNodeInt::File { ref pages, .. } => pages.unmap_page(page, writeback),
+		_ => {},
+		}
+	}
+	/// Write modified mapped pages back to the filesystem
+	pub fn flush(&self) -> super::Result<()> {
+		match self.as_ref()
+		{
+		&CacheNodeInt::File { ref fsnode, ref pages, .. } => pages.flush(&**fsnode),
+		_ => Ok( () ),
+		}
+	}
+	/// Release cached pages that are clean and no longer mapped
+	pub fn trim_cache(&self) {
+		match self.as_ref()
+		{
+		&CacheNodeInt::File { ref pages, .. } => pages.evict_idle(),
+		_ => {},
+		}
+	}
+	/// Atomically append data to the end of the file
+	pub fn append(&self, src: &[u8]) -> super::Result<usize> {
+		try!(self.check_writable());
+		match self.as_ref()
+		{
+		&CacheNodeInt::File { ref fsnode, ref pages, .. } => {
+			let rv = try!(pages.append(&**fsnode, src));
+			if rv > 0 {
+				super::watch::notify_size(self.mountpt, self.inode);
+			}
+			Ok(rv)
+			},
+		_ => Err( super::Error::Unknown("Calling append on non-file") ),
+		}
+	}
+
+	fn file_locks(&self) -> Option<&super::file_lock::FileLocks> {
+		match self.as_ref()
+		{
+		&CacheNodeInt::File { ref locks, .. } => Some(locks),
+		_ => None,
+		}
+	}
+	/// Register an open handle (enforcing open-mode exclusivity)
+	pub fn file_open(&self, class: super::file_lock::OpenClass, is_clone: bool) -> super::Result<()> {
+		match self.file_locks()
+		{
+		Some(l) => l.open(class, is_clone),
+		None => Err( super::Error::TypeMismatch ),
+		}
+	}
+	/// Release an open handle registered with `file_open`
+	pub fn file_close(&self, class: super::file_lock::OpenClass) {
+		if let Some(l) = self.file_locks() {
+			l.close(class)
+		}
+	}
+	/// Acquire an advisory lock on a byte range
+	pub fn lock_range(&self, owner: usize, ofs: u64, len: u64, exclusive: bool) -> super::Result<()> {
+		match self.file_locks()
+		{
+		Some(l) => l.lock_range(owner, ofs, len, exclusive),
+		None => Err( super::Error::TypeMismatch ),
+		}
+	}
+	/// Release advisory locks held by `owner` within a byte range
+	pub fn unlock_range(&self, owner: usize, ofs: u64, len: u64) -> super::Result<()> {
+		match self.file_locks()
+		{
+		Some(l) => l.unlock_range(owner, ofs, len),
+		None => Err( super::Error::TypeMismatch ),
+		}
+	}
+	/// Release all advisory locks held by `owner`
+	pub fn release_range_locks(&self, owner: usize) {
+		if let Some(l) = self.file_locks() {
+			l.release_owner(owner)
+		}
+	}
+	/// Wake the sleep object when an advisory lock is released
+	pub fn bind_wait_lock(&self, obj: &mut ::threads::SleepObject) {
+		if let Some(l) = self.file_locks() {
+			l.bind_wait_release(obj)
+		}
+	}
+	pub fn clear_wait_lock(&self, obj: &mut ::threads::SleepObject) -> bool {
+		match self.file_locks()
+		{
+		Some(l) => l.clear_wait_release(obj),
+		None => false,
+		}
+	}
 }
 
 
+impl Resolver
+{
+	/// Walk `path` starting at `node`, `follow_last` controls if a symbolic link as the final component is followed
+	fn walk(&mut self, mut node: CacheHandle, path: &Path, follow_last: bool) -> super::Result<CacheHandle>
+	{
+		let mut it = path.iter().peekable();
+		while let Some(seg) = it.next()
+		{
+			let is_last = it.peek().is_none();
+			log_trace!("seg = {:?}", seg);
+			if seg == "" || seg == "." {
+				continue ;
+			}
+			if seg == ".." {
+				node = match self.parents.pop()
+					{
+					Some(p) => p,
+					None if self.flags.beneath => return Err(super::Error::PermissionDenied),
+					// Already at the top of this resolution, defer to the filesystem
+					None => {
+						if !node.is_dir() {
+							return Err(super::Error::NonDirComponent);
+						}
+						try!(node.open_child(seg))
+						},
+					};
+				continue ;
+			}
+
+			// Look up this component in the current node
+			if !node.is_dir() {
+				return Err(super::Error::NonDirComponent);
+			}
+			let child = try!(node.open_child(seg));
+			if child.is_symlink() && (follow_last || !is_last) {
+				node = try!(self.follow(node, &child));
+			}
+			else {
+				self.parents.push(node);
+				node = child;
+			}
+		}
+		Ok(node)
+	}
+
+	/// Resolve the target of a symbolic link located in `dir`
+	fn follow(&mut self, dir: CacheHandle, link: &CacheHandle) -> super::Result<CacheHandle>
+	{
+		self.hops += 1;
+		if self.hops > MAX_SYMLINK_HOPS {
+			log_notice!("Symbolic link limit reached at {:?}", link);
+			return Err(super::Error::RecursionDepthExceeded);
+		}
+		let target = try!(link.get_target());
+		let target = Path::new(&target);
+		log_trace!("- Following link to {:?}", target);
+		if target.is_absolute() {
+			if self.flags.beneath {
+				return Err(super::Error::PermissionDenied);
+			}
+			let root = self.root.clone();
+			self.parents.clear();
+			let target = try!(target.split_off_first().ok_or(super::Error::MalformedPath)).1;
+			self.walk(root, target, true)
+		}
+		else {
+			// Relative links are resolved from the directory containing the link
+			self.walk(dir, target, true)
+		}
+	}
+}
+
 /// Symbolic link methods
 impl CacheHandle
 {
@@ -421,6 +727,27 @@ impl CacheHandle
 	}
 }
 
+/// Mount options
+impl CacheHandle
+{
+	/// Returns true if the node is on a volume mounted read-only
+	pub fn is_read_only(&self) -> bool {
+		super::mount::Handle::from_id(self.mountpt).is_read_only()
+	}
+	/// Returns true if the node is on a volume mounted with `noexec`
+	pub fn is_no_exec(&self) -> bool {
+		super::mount::Handle::from_id(self.mountpt).is_no_exec()
+	}
+	fn check_writable(&self) -> super::Result<()> {
+		if self.is_read_only() {
+			Err( super::Error::ReadOnlyFilesystem )
+		}
+		else {
+			Ok( () )
+		}
+	}
+}
+
 impl CacheHandle
 {
 	fn as_ref(&self) -> &CacheNodeInt {
@@ -62,7 +62,7 @@ impl mount::Driver for Driver
 		// RAMFS should never bind to an arbitary volume
 		Ok(0)
 	}
-	fn mount(&self, vol: VolumeHandle, _: mount::SelfHandle) -> super::Result<Box<mount::Filesystem>> {
+	fn mount(&self, vol: VolumeHandle, _: mount::SelfHandle, _options: &mount::MountOptions) -> super::Result<Box<mount::Filesystem>> {
 		let rv = Box::new(RamFS {
 			// SAFE: ArefInner must not change addresses, but because you can't move out of a boxed trait, we're good
 			inner: unsafe { ArefInner::new( RamFSInner {
@@ -178,6 +178,17 @@ impl node::Dir for FileRef {
 	fn unlink(&self, name: &ByteStr) -> vfs::Result<()> {
 		todo!("<FileRef as Dir>::unlink({:?})", name)
 	}
+	fn rename(&self, old_name: &ByteStr, new_name: &ByteStr) -> vfs::Result<()> {
+		let mut lh = self.dir().ents.write();
+		if lh.get(new_name).is_some() {
+			return Err(vfs::Error::AlreadyExists);
+		}
+		match lh.remove(&ByteString::from(old_name))
+		{
+		Some(inode) => { lh.insert(From::from(new_name), inode); Ok( () ) },
+		None => Err(vfs::Error::NotFound),
+		}
+	}
 }
 impl node::Symlink for FileRef {
 	fn read(&self) -> ByteString {
@@ -0,0 +1,219 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Core/vfs/watch.rs
+//! Directory change notifications
+//!
+//! Each watch is registered in a global list against the directory's (mountpoint, inode) pair, and keeps a
+//! snapshot of the directory's entries so that size changes to child files can be attributed to a name.
+#[allow(unused_imports)]
+use prelude::*;
+use lib::VecMap;
+use lib::mem::Arc;
+use lib::ring_buffer::RingBuf;
+use lib::byte_str::{ByteStr,ByteString};
+use threads::{SleepObject,SleepObjectRef};
+use super::node::{CacheHandle,InodeId};
+
+/// Maximum number of un-read change records held by a watch (further changes set the overflow flag)
+const MAX_QUEUED: usize = 32;
+
+/// Type of change reported by a watch
+#[derive(Debug,Copy,Clone,PartialEq)]
+pub enum ChangeKind
+{
+	/// A new entry was created
+	Created,
+	/// An entry was removed
+	Removed,
+	/// An entry was renamed away from this name (followed by a `RenamedTo` with the new name)
+	RenamedFrom,
+	/// An entry was renamed to this name
+	RenamedTo,
+	/// The size of a file in the directory changed
+	SizeChanged,
+	/// Changes were lost because the queue was full, the directory should be re-enumerated
+	Overflow,
+}
+
+/// A single change record
+#[derive(Debug)]
+pub struct ChangeRecord
+{
+	pub kind: ChangeKind,
+	pub inode: InodeId,
+	pub name: ByteString,
+}
+
+/// Handle to a directory watch, changes are queued until read with `pop`
+pub struct Watch
+{
+	// NOTE: The directory handle keeps the node (and hence the inode number) alive
+	dir: CacheHandle,
+	state: Arc<WatchState>,
+}
+
+struct WatchState
+{
+	mountpt: usize,
+	dir_inode: InodeId,
+	inner: ::sync::Mutex<WatchInner>,
+}
+struct WatchInner
+{
+	/// Last known names for inodes in the directory
+	entries: VecMap<InodeId, ByteString>,
+	queue: RingBuf<ChangeRecord>,
+	overflowed: bool,
+	waiters: Vec<SleepObjectRef>,
+}
+
+static S_WATCHES: ::sync::Mutex<Vec<Arc<WatchState>>> = ::sync::Mutex::new(Vec::new_const());
+
+impl Watch
+{
+	/// Create a new watch on the provided directory node
+	pub fn new(dir: CacheHandle) -> super::Result<Watch>
+	{
+		// Register before taking the snapshot, so changes made while enumerating are queued
+		let (mountpt, inode) = dir.get_ids();
+		let state = Arc::new(WatchState {
+			mountpt: mountpt,
+			dir_inode: inode,
+			inner: ::sync::Mutex::new(WatchInner {
+				entries: VecMap::new(),
+				queue: RingBuf::new(MAX_QUEUED),
+				overflowed: false,
+				waiters: Vec::new(),
+				}),
+			});
+		S_WATCHES.lock().push( state.clone() );
+		// - Dropping the handle on error deregisters the watch
+		let rv = Watch { dir: dir, state: state };
+
+		let mut entries: Vec<(InodeId, ByteString)> = Vec::new();
+		let mut ofs = 0;
+		loop
+		{
+			let mut count = 0;
+			ofs = try!(rv.dir.read_dir(ofs, &mut |inode, name| {
+				entries.push( (inode, name.collect()) );
+				count += 1;
+				true
+				}));
+			if count == 0 {
+				break;
+			}
+		}
+		// Names recorded by changes queued during enumeration are newer than the snapshot
+		{
+			use lib::vec_map::Entry;
+			let mut lh = rv.state.inner.lock();
+			for (inode, name) in entries
+			{
+				if let Entry::Vacant(e) = lh.entries.entry(inode) {
+					e.insert(name);
+				}
+			}
+		}
+		Ok(rv)
+	}
+
+	/// Take the next change record from the queue
+	///
+	/// If the record's name is longer than `max_name`, it is left queued and `Err(name_len)` is returned
+	pub fn pop(&self, max_name: usize) -> Result<Option<ChangeRecord>, usize>
+	{
+		let mut lh = self.state.inner.lock();
+		if let Some(len) = lh.queue.front().map(|r| r.name.len()) {
+			if len > max_name {
+				Err(len)
+			}
+			else {
+				Ok(lh.queue.pop_front())
+			}
+		}
+		else if lh.overflowed {
+			lh.overflowed = false;
+			Ok(Some(ChangeRecord { kind: ChangeKind::Overflow, inode: 0, name: ByteString::new() }))
+		}
+		else {
+			Ok(None)
+		}
+	}
+	/// Returns true if there are un-read change records
+	pub fn has_changes(&self) -> bool {
+		let lh = self.state.inner.lock();
+		!lh.queue.is_empty() || lh.overflowed
+	}
+
+	/// Register a sleep object to be signalled when a change is queued
+	pub fn bind_wait(&self, obj: &mut SleepObject)
+	{
+		let mut lh = self.state.inner.lock();
+		if !lh.queue.is_empty() || lh.overflowed {
+			obj.signal();
+		}
+		lh.waiters.push( obj.get_ref() );
+	}
+	/// Deregister a sleep object, returns true if changes are pending
+	pub fn clear_wait(&self, obj: &mut SleepObject) -> bool
+	{
+		let mut lh = self.state.inner.lock();
+		lh.waiters.retain(|w| !w.is_from(obj));
+		!lh.queue.is_empty() || lh.overflowed
+	}
+}
+impl ::core::ops::Drop for Watch
+{
+	fn drop(&mut self)
+	{
+		let ptr: *const WatchState = &*self.state;
+		S_WATCHES.lock().retain(|w| &**w as *const _ != ptr);
+	}
+}
+
+impl WatchInner
+{
+	fn push(&mut self, kind: ChangeKind, inode: InodeId, name: ByteString)
+	{
+		match kind
+		{
+		ChangeKind::Created | ChangeKind::RenamedTo => { self.entries.insert(inode, name.clone()); },
+		ChangeKind::Removed | ChangeKind::RenamedFrom => { self.entries.remove(&inode); },
+		_ => {},
+		}
+		if !self.overflowed {
+			if let Err(_) = self.queue.push_back(ChangeRecord { kind: kind, inode: inode, name: name }) {
+				self.overflowed = true;
+			}
+		}
+		for w in self.waiters.iter() {
+			w.signal();
+		}
+	}
+}
+
+/// Report a change to an entry in the specified directory
+pub fn notify(mountpt: usize, dir_inode: InodeId, kind: ChangeKind, inode: InodeId, name: &ByteStr)
+{
+	for w in S_WATCHES.lock().iter().filter(|w| w.mountpt == mountpt && w.dir_inode == dir_inode)
+	{
+		w.inner.lock().push(kind, inode, ByteString::from(name));
+	}
+}
+
+/// Report a change in the size of a file (to every watched directory that contains it)
+pub fn notify_size(mountpt: usize, inode: InodeId)
+{
+	for w in S_WATCHES.lock().iter().filter(|w| w.mountpt == mountpt)
+	{
+		let mut lh = w.inner.lock();
+		let name = match lh.entries.get(&inode)
+			{
+			Some(n) => n.clone(),
+			None => continue,
+			};
+		lh.push(ChangeKind::SizeChanged, inode, name);
+	}
+}
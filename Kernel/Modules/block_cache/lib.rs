@@ -114,8 +114,8 @@ impl CacheHandle
 			let mut lh = S_BLOCK_CACHE.lock_init(|| Default::default());
 			let handle = match lh.map.entry( (self.vh.idx(), cache_block) )
 				{
-				Entry::Occupied(v) => v.into_mut().borrow(),
-				Entry::Vacant(v) => v.insert( Box::new( try!(CachedBlock::new(&self.vh, cache_block)) ) ).borrow(),
+				Entry::Occupied(v) => try!(v.into_mut().borrow()),
+				Entry::Vacant(v) => try!(v.insert( Box::new( try!(CachedBlock::new(&self.vh, cache_block)) ) ).borrow()),
 				};
 			// SAFE: 1. The internal data is boxed, 2. The box won't be dropped while a borrow exists.
 			unsafe { ::core::mem::transmute::<MetaBlockHandle, MetaBlockHandle>(handle) }
@@ -185,11 +185,11 @@ impl CacheHandle
 	}
 }
 
-fn map_cached_frame(frame: &::kernel::memory::phys::FrameHandle) -> ::kernel::memory::page_cache::CachedPage
+fn map_cached_frame(frame: &::kernel::memory::phys::FrameHandle) -> Result<::kernel::memory::page_cache::CachedPage, IoError>
 {
 	// TODO: If this returns that there's no free mappings, go and steal one from within the cache
 	// - Or just do a GC pass, then try again.
-	::kernel::memory::page_cache::S_PAGE_CACHE.map(frame).expect("TODO: OOM in CachedBlock::borrow")
+	::kernel::memory::page_cache::S_PAGE_CACHE.map(frame).map_err(|_| IoError::Unknown("OOM"))
 }
 
 // --------------------------------------------------------------------
@@ -224,20 +224,20 @@ impl CachedBlock
 		Ok( () )
 	}
 	
-	fn borrow(&self) -> MetaBlockHandle {
+	fn borrow(&self) -> Result<MetaBlockHandle, IoError> {
 
 		if self.mapping.read().is_none()
 		{
 			let mut lh = self.mapping.write();
 			if lh.is_none() {
-				*lh = Some( map_cached_frame(&self.block_paddr) );
+				*lh = Some( try!(map_cached_frame(&self.block_paddr)) );
 			}
 		}
 
 		self.reference_count.fetch_add(1, Ordering::Acquire);
 		self.last_access.bump();
 
-		MetaBlockHandle(self)
+		Ok( MetaBlockHandle(self) )
 	}
 }
 
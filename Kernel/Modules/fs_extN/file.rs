@@ -110,21 +110,21 @@ impl vfs::node::File for File
 	}
 
 	fn truncate(&self, newsize: u64) -> vfs::node::Result<u64> {
-		if newsize == 0
+		if newsize == self.inode.i_size()
 		{
-			todo!("truncate - 0");
+			Ok( newsize )
 		}
-		else if newsize == self.inode.i_size()
+		else if newsize == 0
 		{
-			Ok( newsize )
+			Err( vfs::Error::Unknown("TODO: truncate - 0") )
 		}
 		else if newsize < self.inode.i_size()
 		{
-			todo!("truncate - shrink");
+			Err( vfs::Error::Unknown("TODO: truncate - shrink") )
 		}
 		else
 		{
-			todo!("truncate - grow");
+			Err( vfs::Error::Unknown("TODO: truncate - grow") )
 		}
 	}
 	fn clear(&self, ofs: u64, size: u64) -> vfs::node::Result<()> {
@@ -79,7 +79,7 @@ impl vfs::mount::Driver for Driver
 			Ok(0)
 		}
 	}
-	fn mount(&self, vol: VolumeHandle, mounthandle: vfs::mount::SelfHandle) -> vfs::Result<Box<vfs::mount::Filesystem>> {
+	fn mount(&self, vol: VolumeHandle, mounthandle: vfs::mount::SelfHandle, _options: &vfs::mount::MountOptions) -> vfs::Result<Box<vfs::mount::Filesystem>> {
 		Ok( try!(instance::Instance::new_boxed(vol, mounthandle)) )
 	}
 }
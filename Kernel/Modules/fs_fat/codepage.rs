@@ -0,0 +1,92 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Modules/fs_fat/codepage.rs
+//! OEM codepage handling for short (8.3) names
+
+/// Codepage used to decode bytes >= 0x80 in short names
+#[derive(Copy,Clone,Debug)]
+pub enum Codepage
+{
+	/// IBM PC (US)
+	Cp437,
+}
+
+impl Codepage
+{
+	/// Look up a codepage by number (as passed in the `codepage=` mount option)
+	pub fn from_number(num: u16) -> Option<Codepage> {
+		match num
+		{
+		437 => Some(Codepage::Cp437),
+		_ => None,
+		}
+	}
+
+	/// Convert a single byte into a unicode codepoint
+	pub fn decode(&self, b: u8) -> char {
+		if b < 0x80 {
+			b as char
+		}
+		else {
+			match *self
+			{
+			Codepage::Cp437 => CP437_HIGH[b as usize - 0x80],
+			}
+		}
+	}
+}
+
+static CP437_HIGH: [char; 128] = [
+	'Ç','ü','é','â','ä','à','å','ç','ê','ë','è','ï','î','ì','Ä','Å',
+	'É','æ','Æ','ô','ö','ò','û','ù','ÿ','Ö','Ü','¢','£','¥','₧','ƒ',
+	'á','í','ó','ú','ñ','Ñ','ª','º','¿','⌐','¬','½','¼','¡','«','»',
+	'░','▒','▓','│','┤','╡','╢','╖','╕','╣','║','╗','╝','╜','╛','┐',
+	'└','┴','┬','├','─','┼','╞','╟','╚','╔','╩','╦','╠','═','╬','╧',
+	'╨','╤','╥','╙','╘','╒','╓','╫','╪','┘','┌','█','▄','▌','▐','▀',
+	'α','ß','Γ','π','Σ','σ','µ','τ','Φ','Θ','Ω','δ','∞','φ','ε','∩',
+	'≡','±','≥','≤','⌠','⌡','÷','≈','°','∙','·','√','ⁿ','²','■','\u{A0}',
+	];
+
+/// A short name converted to UTF-8
+pub struct ShortName
+{
+	// 8.3 names are at most 12 characters, each at most 3 bytes of UTF-8
+	buf: [u8; 12*3],
+	len: usize,
+}
+impl ShortName
+{
+	/// Convert a NUL-padded short name using the provided codepage
+	pub fn new(name: &[u8], cp: Codepage) -> ShortName {
+		let mut rv = ShortName { buf: [0; 12*3], len: 0 };
+		for &b in name.iter().take_while(|&&b| b != 0)
+		{
+			rv.push( cp.decode(b) );
+		}
+		rv
+	}
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.buf[..self.len]
+	}
+
+	fn push(&mut self, c: char) {
+		let c = c as u32;
+		let (len, first) = if c < 0x80 {
+				(1, c as u8)
+			}
+			else if c < 0x800 {
+				(2, 0xC0 | (c >> 6) as u8)
+			}
+			else {
+				(3, 0xE0 | (c >> 12) as u8)
+			};
+		self.buf[self.len] = first;
+		for i in 1 .. len
+		{
+			let shift = 6 * (len - 1 - i);
+			self.buf[self.len + i] = 0x80 | ((c >> shift) & 0x3F) as u8;
+		}
+		self.len += len;
+	}
+}
@@ -11,6 +11,7 @@ use super::on_disk;
 use super::file::FileNode;
 use super::ClusterList;
 use super::FilesystemInner;
+use super::codepage::{Codepage,ShortName};
 use utf16::Str16;
 
 pub struct DirNode
@@ -44,6 +45,9 @@ impl node::NodeBase for DirNode {
 	fn get_any(&self) -> &::core::any::Any {
 		self
 	}
+	fn get_owner(&self) -> (u32, u32) {
+		self.fs.owner
+	}
 }
 
 impl DirNode {
@@ -241,6 +245,10 @@ impl DirEntShort {
 	fn name(&self) -> &ByteStr {
 		ByteStr::new( (&self.name).split(|&e|e==0).next().unwrap() )
 	}
+	/// Returns the name converted using the filesystem's codepage (if set)
+	fn decoded_name(&self, cp: Option<Codepage>) -> Option<ShortName> {
+		cp.map(|cp| ShortName::new(&self.name, cp))
+	}
 	fn inode(&self, parent_dir: u32) -> node::InodeId {
 		super::InodeRef::new(self.cluster, parent_dir).to_id()
 	}
@@ -307,7 +315,12 @@ impl node::Dir for DirNode {
 				match ent {
 				DirEnt::End => return Err(vfs::Error::NotFound),
 				DirEnt::Short(e) => {
-					if e.name() == name || lfn.name() == name {
+					let short_match = match e.decoded_name(self.fs.codepage)
+						{
+						Some(n) => n.as_bytes() == name.as_bytes(),
+						None => e.name() == name,
+						};
+					if short_match || lfn.name() == name {
 						return Ok( e.inode(self.start_cluster) );
 					}
 					lfn.clear();
@@ -345,6 +358,9 @@ impl node::Dir for DirNode {
 					let cont = if lfn.is_valid() {
 							callback(inode, &mut lfn.name().wtf8())
 						}
+						else if let Some(n) = e.decoded_name(self.fs.codepage) {
+							callback(inode, &mut n.as_bytes().iter().cloned())
+						}
 						else {
 							callback(inode, &mut e.name().as_bytes().iter().cloned())
 						};
@@ -364,13 +380,16 @@ impl node::Dir for DirNode {
 		Ok( cur_ofs )
 	}
 	fn create(&self, name: &ByteStr, nodetype: node::NodeType) -> node::Result<node::InodeId> {
-		todo!("DirNode::create('{:?}', {:?})", name, nodetype);
+		log_notice!("TODO: DirNode::create('{:?}', {:?})", name, nodetype);
+		Err( vfs::Error::Unknown("TODO: FAT create") )
 	}
-	fn link(&self, name: &ByteStr, node: &node::NodeBase) -> node::Result<()> {
-		todo!("DirNode::link('{:?}', {:#x})", name, node.get_id());
+	fn link(&self, name: &ByteStr, _node: &node::NodeBase) -> node::Result<()> {
+		log_notice!("TODO: DirNode::link('{:?}')", name);
+		Err( vfs::Error::Unknown("TODO: FAT link") )
 	}
 	fn unlink(&self, name: &ByteStr) -> node::Result<()> {
-		todo!("DirNode::unlink('{:?}')", name);
+		log_notice!("TODO: DirNode::unlink('{:?}')", name);
+		Err( vfs::Error::Unknown("TODO: FAT unlink") )
 	}
 }
 
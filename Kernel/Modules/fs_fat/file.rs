@@ -35,16 +35,24 @@ impl node::NodeBase for FileNode {
 	fn get_any(&self) -> &::core::any::Any {
 		self
 	}
+	fn get_owner(&self) -> (u32, u32) {
+		self.fs.owner
+	}
 }
 impl node::File for FileNode {
 	fn size(&self) -> u64 {
 		self.size as u64
 	}
 	fn truncate(&self, newsize: u64) -> node::Result<u64> {
-		todo!("FileNode::truncate({:#x})", newsize);
+		if newsize == self.size as u64 {
+			return Ok(newsize);
+		}
+		log_notice!("TODO: FileNode::truncate({:#x})", newsize);
+		Err( vfs::Error::Unknown("TODO: FAT truncate") )
 	}
 	fn clear(&self, ofs: u64, size: u64) -> node::Result<()> {
-		todo!("FileNode::clear({:#x}+{:#x}", ofs, size);
+		log_notice!("TODO: FileNode::clear({:#x}+{:#x})", ofs, size);
+		Err( vfs::Error::Unknown("TODO: FAT clear") )
 	}
 	fn read(&self, ofs: u64, buf: &mut [u8]) -> node::Result<usize> {
 		// Sanity check and bound parameters
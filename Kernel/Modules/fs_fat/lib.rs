@@ -36,6 +36,8 @@ mod on_disk;
 mod dir;
 /// File IO
 mod file;
+/// OEM codepages
+mod codepage;
 
 #[derive(Copy,Clone,Debug)]
 enum Size
@@ -78,6 +80,11 @@ pub struct FilesystemInner
 	// XXX: Should really use the above line for this, but BlockCache exists
 	/// A cache of metadata clusters (i.e. directories)
 	metadata_block_cache: ::blockcache::BlockCache,
+
+	/// Codepage for short names (`None` passes bytes through unchanged)
+	codepage: Option<codepage::Codepage>,
+	/// Owning (user, group) reported for all nodes (from the `uid`/`gid` mount options)
+	owner: (u32, u32),
 }
 
 /// Inodes IDs destrucure into two 28-bit cluster IDs, and a 16-bit dir offset
@@ -125,7 +132,23 @@ impl mount::Driver for Driver
 			Ok(1)
 		}
 	}
-	fn mount(&self, vol: VolumeHandle, _mounthandle: mount::SelfHandle) -> vfs::Result<Box<mount::Filesystem>> {
+	fn mount(&self, vol: VolumeHandle, _mounthandle: mount::SelfHandle, options: &mount::MountOptions) -> vfs::Result<Box<mount::Filesystem>> {
+		let codepage = match options.codepage
+			{
+			None => None,
+			Some(num) => match codepage::Codepage::from_number(num)
+				{
+				Some(cp) => Some(cp),
+				None => {
+					log_notice!("Unsupported codepage {}", num);
+					return Err(vfs::Error::InvalidParameter);
+					},
+				},
+			};
+		// FAT has no ownership information, so every node is owned by the mount's uid/gid
+		let owner = (options.uid.unwrap_or(0), options.gid.unwrap_or(0));
+		// NOTE: `discard` is ignored, as this driver does not yet free clusters
+
 		let vol = ::block_cache::CacheHandle::new(vol);
 
 		// Read the bootsector
@@ -196,6 +219,8 @@ impl mount::Driver for Driver
 				root_sector_count: root_dir_sectors as u32,
 				
 				metadata_block_cache: ::blockcache::BlockCache::new(),
+				codepage: codepage,
+				owner: owner,
 
 				vh: vol,
 				}) },
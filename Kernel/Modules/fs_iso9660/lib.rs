@@ -63,7 +63,7 @@ impl mount::Driver for Driver
 			Ok(0)
 		}
 	}
-	fn mount(&self, vol: VolumeHandle, _mounthandle: mount::SelfHandle) -> vfs::Result<Box<mount::Filesystem>> {
+	fn mount(&self, vol: VolumeHandle, _mounthandle: mount::SelfHandle, _options: &mount::MountOptions) -> vfs::Result<Box<mount::Filesystem>> {
 		// For this to work properly, the block size must evenly divide 2048
 		if 2048 % vol.block_size() != 0 {
 			return Err( vfs::Error::Unknown("Can't mount ISO9660 with sector size not a factor of 2048"/*, vol.block_size()*/) );
@@ -124,6 +124,11 @@ impl InputChannel
 		(false, KeyCode::F10) => if self.try_change_session(10) { return ; },
 		(false, KeyCode::F11) => if self.try_change_session(11) { return ; },
 		(false, KeyCode::F12) => if self.try_change_session(12) { return ; },
+		// Ctrl-Alt-PrintScreen dumps heap statistics to the kernel log
+		(false, KeyCode::PrintScreen) => if self.ctrl_held.get() && self.alt_held.get() {
+			::kernel::memory::heap::dump_stats();
+			return ;
+			},
 		_ => {},
 		}
 
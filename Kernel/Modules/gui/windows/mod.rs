@@ -26,6 +26,11 @@ mod winbuf;
 
 use self::window::Window;
 
+/// Identifier for a window that's unique across groups
+fn window_id(grp: GrpId, win: WinId) -> u32 {
+	(grp as u32) << 16 | win as u32
+}
+
 /// Window groups combine windows into "sessions", that can be switched with magic key combinations
 struct WindowGroup
 {
@@ -45,6 +50,8 @@ struct WindowGroup
 	windows: SparseVec< (Pos, Aref<Window>) >,
 	/// Render order (indexes into `windows`, and visibilities)
 	render_order: Vec< (WinId, Vec<Rect>) >,
+	/// Modal dialogs, as (dialog, owner) pairs. Input to the owner is redirected to the dialog while it exists
+	modal_dialogs: Vec< (WinId, WinId) >,
 }
 
 
@@ -89,7 +96,8 @@ pub fn init()
 	// Create render thread
 	// SAFE: Called in single-threaded context
 	unsafe { S_EVENT_QUEUE.prep(|| ::kernel::lib::ring_buffer::AtomicRingBuf::new(32)); }
-	S_RENDER_THREAD.init( || ::kernel::threads::WorkerThread::new("GUI Compositor", render_thread) );
+	// - Realtime, so a busy userland process can't starve rendering
+	S_RENDER_THREAD.init( || ::kernel::threads::WorkerThread::with_priority("GUI Compositor", ::kernel::threads::Priority::Realtime, render_thread) );
 }
 
 
@@ -223,6 +231,7 @@ impl WindowGroup
 			focussed_window: 0,
 			windows: SparseVec::new(),
 			render_order: Vec::new(),
+			modal_dialogs: Vec::new(),
 			}
 	}
 	/// Increment the reference count
@@ -241,6 +250,7 @@ impl WindowGroup
 			// Delete all windows
 			self.focussed_window = 0;
 			self.render_order.truncate(0);
+			self.modal_dialogs.truncate(0);
 			// - Can't drop all the windows yet, their handles include ArefBorrow-s
 			//self.windows = Default::default();
 			true
@@ -250,6 +260,25 @@ impl WindowGroup
 		}
 	}
 
+	/// Notify the owners of all windows in this group that they have been closed (group destroyed)
+	fn post_closed(&self, grp: GrpId)
+	{
+		for idx in 0 .. self.windows.len()
+		{
+			self.post_window_closed(grp, idx as WinId);
+		}
+	}
+	/// Notify the owner of a window that it has been closed
+	fn post_window_closed(&self, grp: GrpId, idx: WinId)
+	{
+		if let Some(&(_, ref win)) = self.windows.get(idx as usize) {
+			if win.owner() != 0 {
+				let id = window_id(grp, idx);
+				::kernel::threads::post_control(win.owner(), ::kernel::threads::ControlEvent::WindowClosed(id));
+			}
+		}
+	}
+
 	/// Re-draw this window group
 	fn redraw(&mut self, full: bool)
 	{
@@ -279,7 +308,7 @@ impl WindowGroup
 		}
 	}
 	
-	fn get_win_at_pos(&self, x: u32, y: u32) -> Option<&(Pos, Aref<Window>)> {
+	fn get_win_at_pos(&self, x: u32, y: u32) -> Option<(WinId, &(Pos, Aref<Window>))> {
 		let mut rv = None;
 		// Iterate render order finding the highest (latest) window which contains this point
 		for &(winidx, _) in &self.render_order
@@ -290,7 +319,7 @@ impl WindowGroup
 
 			if pos.x <= x && pos.y <= y {
 				if x < pos.x + dims.w && y < pos.y + dims.h {
-					rv = Some(ptr);
+					rv = Some( (winidx, ptr) );
 				}
 			}
 		}
@@ -298,11 +327,59 @@ impl WindowGroup
 		rv
 	}
 
+	/// Get the window that mouse input at a position goes to (none if it's blocked by a modal dialog)
+	fn get_input_win_at_pos(&self, x: u32, y: u32) -> Option<&(Pos, Aref<Window>)> {
+		match self.get_win_at_pos(x, y)
+		{
+		Some( (idx, _) ) if self.get_modal_dialog(idx).is_some() => None,
+		Some( (_, ptr) ) => Some(ptr),
+		None => None,
+		}
+	}
+	/// Get the modal dialog blocking input to a window (the innermost, if the dialog has its own dialog)
+	fn get_modal_dialog(&self, winidx: WinId) -> Option<WinId> {
+		let mut rv = None;
+		let mut cur = winidx;
+		// - Bounded by the number of dialogs, in case of a loop
+		for _ in 0 .. self.modal_dialogs.len()
+		{
+			match self.modal_dialogs.iter().find(|&&(_, owner)| owner == cur)
+			{
+			Some(&(dialog, _)) => {
+				rv = Some(dialog);
+				cur = dialog;
+				},
+			None => break,
+			}
+		}
+		rv
+	}
+	/// Make a window a modal dialog of another window in this group
+	fn set_modal_owner(&mut self, idx: WinId, owner: WinId) -> bool {
+		if idx == owner || self.windows.get(owner as usize).is_none() {
+			return false;
+		}
+		self.modal_dialogs.retain(|&(dialog, _)| dialog != idx);
+		self.modal_dialogs.push( (idx, owner) );
+		// Move focus off the (now blocked) owner
+		if self.focussed_window == owner && self.get_render_idx(idx).is_some() {
+			self.focussed_window = idx;
+		}
+		true
+	}
+
 	fn handle_input(&mut self, ev: super::input::Event) {
 		use super::input::Event;
 		match ev
 		{
 		Event::KeyDown(..) | Event::KeyUp(..) | Event::KeyFire(..) | Event::Text(..) => {
+			// - Keyboard input to a window with a modal dialog goes to the dialog (if it's visible)
+			if let Some(dialog) = self.get_modal_dialog(self.focussed_window) {
+				if self.get_render_idx(dialog).is_none() {
+					return ;
+				}
+				self.focussed_window = dialog;
+			}
 			// - Apply shortcuts defined by the current session (TODO)
 			// - Pass events to the current window
 			if let Some(_) = self.get_render_idx( self.focussed_window )
@@ -318,7 +395,7 @@ impl WindowGroup
 			}
 			},
 		Event::MouseMove(x,y, dx,dy) =>
-			if let Some(newwin) = self.get_win_at_pos(x,y)
+			if let Some(newwin) = self.get_input_win_at_pos(x,y)
 			{
 				//if self.mouse_last_win != &newwin {
 				//}
@@ -331,21 +408,21 @@ impl WindowGroup
 				//}
 			},
 		Event::MouseDown(x,y, btn) =>
-			if let Some(newwin) = self.get_win_at_pos(x,y)
+			if let Some(newwin) = self.get_input_win_at_pos(x,y)
 			{
 				//self.mouse_down_win = &newwin;
 				let Pos { x: bx, y: by } = newwin.0;
 				newwin.1.handle_input( Event::MouseDown(x - bx, y - by, btn) );
 			},
 		Event::MouseClick(x,y, btn, count) =>
-			if let Some(newwin) = self.get_win_at_pos(x,y)
+			if let Some(newwin) = self.get_input_win_at_pos(x,y)
 			{
 				//self.mouse_down_win = &newwin;
 				let Pos { x: bx, y: by } = newwin.0;
 				newwin.1.handle_input( Event::MouseClick(x - bx, y - by, btn, count) );
 			},
 		Event::MouseUp(x,y, btn) =>
-			if let Some(newwin) = self.get_win_at_pos(x,y)
+			if let Some(newwin) = self.get_input_win_at_pos(x,y)
 			{
 				//if self.mouse_down_win != &newwin {
 				//}
@@ -438,7 +515,8 @@ impl WindowGroup
 		self.recalc_vis_int(vis_idx);
 
 		// TODO: Have a better method than just switching focus on show
-		self.focussed_window = idx;
+		// - Windows blocked by a modal dialog can't take focus
+		self.focussed_window = self.get_modal_dialog(idx).unwrap_or(idx);
 	}
 	fn hide_window(&mut self, idx: WinId) {
 		if let Some(pos) = self.get_render_idx(idx)
@@ -492,10 +570,13 @@ impl WindowGroup
 	}
 
 
-	/// Drops (functionally destroys) a window
-	fn drop_window(&mut self, idx: WinId) {
+	/// Drops (functionally destroys) a window, notifying its owner
+	fn drop_window(&mut self, grp: GrpId, idx: WinId) {
+		self.post_window_closed(grp, idx);
 		self.hide_window(idx);
 		self.windows.remove(idx as usize);
+		// Closing a dialog unblocks its owner
+		self.modal_dialogs.retain(|&(dialog, owner)| dialog != idx && owner != idx);
 	}
 }
 
@@ -553,6 +634,7 @@ impl ::core::ops::Drop for WindowGroupHandle
 	{
 		if self.with_wg(|wg| wg.deref()) == true {
 			log_notice!("Window group {} destroyed", self.0);
+			self.with_wg(|wg| wg.post_closed(self.0));
 			S_WINDOW_GROUPS.lock().remove(self.0 as usize);
 			switch_active(0);
 		}
@@ -600,6 +682,22 @@ impl WindowHandle
 		self.grp.lock().move_window(self.win_id, pos);
 	}
 
+	/// Make this window a modal dialog of another window (by system-wide ID) in the same group
+	///
+	/// While this window exists, input to the owner is redirected to it. Returns `false` if the owner isn't
+	/// a window in this group.
+	pub fn set_modal_owner(&mut self, owner_id: u32) -> bool {
+		if (owner_id >> 16) as GrpId != self.grp_id {
+			return false;
+		}
+		self.grp.lock().set_modal_owner(self.win_id, owner_id as WinId)
+	}
+
+	/// System-wide identifier for this window (as reported in `ControlEvent::WindowClosed`)
+	pub fn get_id(&self) -> u32 {
+		window_id(self.grp_id, self.win_id)
+	}
+
 	/// Return the dimensions of the currently usable portion of the window
 	pub fn get_dims(&self) -> Dims {
 		self.get_win().dims()
@@ -655,7 +753,7 @@ impl ::core::ops::Drop for WindowHandle
 		log_debug!("WindowHandle::drop - {}/{}", self.grp_id, self.win_id);
 		// WindowHandle uniquely owns the window, so can just drop it
 		self.win = None;
-		self.grp.lock().drop_window( self.win_id );
+		self.grp.lock().drop_window( self.grp_id, self.win_id );
 	}
 }
 
@@ -22,6 +22,8 @@ pub struct Window
 	/// Window name (set once, never changes after)
 	// TODO: Box<str>
 	name: String,
+	/// PID of the process that created the window (zero for kernel windows)
+	owner: u32,
 	
 	/// Actual window data
 	/// 
@@ -64,6 +66,7 @@ impl Window
 		use core::default::Default;
 		Window {
 			name: name,
+			owner: ::kernel::threads::get_process_id(),
 			buf: Default::default(),
 			dirty_rects: Default::default(),
 			is_dirty: atomic::ATOMIC_BOOL_INIT,
@@ -78,6 +81,10 @@ impl Window
 	pub fn name(&self) -> &str {
 		&self.name
 	}
+	/// PID of the process that created this window
+	pub fn owner(&self) -> u32 {
+		self.owner
+	}
 	pub fn dims(&self) -> Dims {
 		self.buf.read().dims()
 	}
@@ -29,6 +29,8 @@ fn init()
 }
 
 const RX_BUFFER_LENGTH: usize = 0x2000+16;
+/// Time allowed for the card to complete a reset (ms)
+const RESET_TIMEOUT: u64 = 100;
 const RX_BUFFER_LIMIT : usize = 0x3000;
 
 struct BusDev( nic::Registration<Card>, ::kernel::irqs::ObjectHandle );
@@ -106,8 +108,11 @@ impl BusDev
 				card.write_8(Regs::CONFIG1, 0x00);
 				// - Reset and wait for reset bit to clear
 				card.write_8(Regs::CMD, 0x10);
+				let timeout = ::kernel::time::ticks() + RESET_TIMEOUT;
 				while card.read_8(Regs::CMD) & 0x10 != 0 {
-					// TODO: Timeout
+					if ::kernel::time::ticks() > timeout {
+						return Err("Timeout waiting for reset");
+					}
 				}
 
 				// - Mask all interrupts on
@@ -0,0 +1,65 @@
+// "Tifflin" Kernel - PL031 RTC Driver
+// - By John Hodge (thePowersGang)
+//
+// Modules/rtc_pl031/lib.rs
+//! ARM PrimeCell Real Time Clock (PL031)
+//!
+//! Only used to seed the kernel's wall-clock time at startup
+#![no_std]
+#![feature(linkage)]	// for module_define!
+use kernel::prelude::*;
+use kernel::device_manager;
+
+#[macro_use]
+extern crate kernel;
+
+module_define!{rtc_PL031, [DeviceManager], init}
+
+fn init()
+{
+	static FDT_DRIVER: FdtDriver = FdtDriver;
+	device_manager::register_driver(&FDT_DRIVER);
+}
+
+/// Data register (current time, seconds since the UNIX epoch)
+const REG_RTCDR: usize = 0x000;
+
+struct FdtDriver;
+impl device_manager::Driver for FdtDriver
+{
+	fn name(&self) -> &str {
+		"rtc-pl031"
+	}
+	fn bus_type(&self) -> &str {
+		"fdt"
+	}
+	fn handles(&self, bus_dev: &device_manager::BusDevice) -> u32
+	{
+		// NOTE: `compatible` is a NUL-separated list (e.g. "arm,pl031\0arm,primecell\0")
+		if bus_dev.get_attr("compatible").unwrap_str().split('\0').any(|v| v == "arm,pl031") {
+			1
+		}
+		else {
+			0
+		}
+	}
+	fn bind(&self, bus_dev: &mut device_manager::BusDevice) -> Box<device_manager::DriverInstance+'static>
+	{
+		let io = bus_dev.bind_io(0);
+		// SAFE: No-sideeffect IO read
+		let seconds = unsafe { io.read_32(REG_RTCDR) };
+		log_log!("PL031 RTC: {} seconds since epoch", seconds);
+		::kernel::time::set_realtime(seconds as u64 * 1000);
+
+		Box::new( Instance { _io: io } )
+	}
+}
+
+struct Instance
+{
+	_io: device_manager::IOBinding,
+}
+impl device_manager::DriverInstance for Instance
+{
+}
+
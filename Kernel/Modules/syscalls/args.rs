@@ -32,6 +32,9 @@ unsafe impl Pod for u32 {}
 unsafe impl Pod for ::values::WaitItem {}
 unsafe impl Pod for ::values::GuiEvent {}	// Kinda lies, but meh
 unsafe impl Pod for ::values::RpcMessage {}
+unsafe impl Pod for ::values::FaultInfo {}
+unsafe impl Pod for ::values::FaultImage {}
+unsafe impl Pod for ::values::ControlMessage {}
 
 impl<T: Pod> SyscallArg for Freeze<T>
 {
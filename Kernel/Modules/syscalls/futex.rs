@@ -0,0 +1,108 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Modules/syscalls/futex.rs
+//! Userland address-keyed wait queues (futexes)
+//!
+//! Waiters are keyed on the process and the virtual address of a word, userland sync primitives sleep
+//! here when contended (after checking that the word still holds the value they observed).
+use kernel::prelude::*;
+use kernel::sync::mutex::LazyMutex;
+use kernel::threads::{SleepObject,SleepObjectRef};
+use kernel::memory::freeze::FreezeMut;
+use core::sync::atomic::{AtomicUsize,Ordering};
+
+use Error;
+use values;
+
+struct Waiter
+{
+	pid: u32,
+	addr: usize,
+	obj: SleepObjectRef,
+}
+
+/// All sleeping threads (in wait order)
+static S_WAITERS: LazyMutex<Vec<Waiter>> = lazymutex_init!();
+
+/// Obtain a reference to a user word, checking that it's a valid and aligned user address
+///
+/// The returned freeze must be held for as long as the word is accessed (ensuring it isn't unmapped)
+fn get_word(addr: usize) -> Result<FreezeMut<AtomicUsize>, Error>
+{
+	let size = ::core::mem::size_of::<usize>();
+	if addr % size != 0 || addr == 0 || addr + size > ::kernel::arch::memory::addresses::USER_END {
+		return Err( Error::BadValue );
+	}
+	// SAFE: Pointer is to (mapped) user memory, which is only ever accessed atomically here
+	unsafe {
+		match ::kernel::memory::buf_to_slice_mut(addr as *mut AtomicUsize, 1)
+		{
+		Some(v) => Ok( try!(FreezeMut::new(&mut v[0])) ),
+		None => Err( Error::InvalidBuffer(addr as *const (), size) ),
+		}
+	}
+}
+
+/// Sleep until woken by `wake`, if the word at `addr` still equals `value`
+///
+/// `wake_time_mono` is an absolute timeout (`!0` to wait forever)
+pub fn wait(addr: usize, value: usize, wake_time_mono: u64) -> Result<Result<u32,u32>, Error>
+{
+	let word = try!(get_word(addr));
+	let pid = ::kernel::threads::get_process_id();
+	let mut waiter = SleepObject::new("futex");
+	{
+		// NOTE: The value check is done with the lock held, so a `wake` can't be missed
+		let mut lh = S_WAITERS.lock_init(|| Vec::new());
+		if word.load(Ordering::SeqCst) != value {
+			return Ok( Err(values::FUTEX_ERR_CHANGED) );
+		}
+		lh.push(Waiter { pid: pid, addr: addr, obj: waiter.get_ref() });
+	}
+
+	let timer = if wake_time_mono != !0 {
+			Some( ::kernel::time::Timer::new(wake_time_mono, &waiter) )
+		}
+		else {
+			None
+		};
+	::kernel::threads::bind_wait_kill(&mut waiter);
+	waiter.wait();
+	::kernel::threads::clear_wait_kill(&mut waiter);
+	drop(timer);
+
+	// If the entry is still present, this thread wasn't woken by `wake` (i.e. the timer fired)
+	let mut lh = S_WAITERS.lock();
+	match lh.iter().position(|w| w.obj.is_from(&waiter))
+	{
+	Some(idx) => {
+		lh.remove(idx);
+		Ok( Err(values::FUTEX_ERR_TIMEDOUT) )
+		},
+	None => Ok( Ok(0) ),
+	}
+}
+
+/// Wake up to `count` threads waiting on `addr`, returns the number woken
+pub fn wake(addr: usize, count: usize) -> Result<u32, Error>
+{
+	// NOTE: The word isn't read, but is frozen so the wake can't race with it being unmapped
+	let _word = try!(get_word(addr));
+	let pid = ::kernel::threads::get_process_id();
+	let mut lh = S_WAITERS.lock_init(|| Vec::new());
+	let mut woken = 0;
+	let mut i = 0;
+	while i < lh.len() && woken < count
+	{
+		if lh[i].pid == pid && lh[i].addr == addr {
+			let w = lh.remove(i);
+			w.obj.signal();
+			woken += 1;
+		}
+		else {
+			i += 1;
+		}
+	}
+	Ok(woken as u32)
+}
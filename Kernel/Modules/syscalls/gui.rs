@@ -200,6 +200,15 @@ impl objects::Object for Window
 			let rv = (d.w as u64) << 32 | (d.h as u64);
 			Ok( rv )
 			},
+		values::GUI_WIN_GETID => {
+			log_debug!("GUI_WIN_GETID()");
+			Ok( self.0.lock().get_id() as u64 )
+			},
+		values::GUI_WIN_SETOWNER => {
+			let owner: u32 = try!(args.get());
+			log_debug!("GUI_WIN_SETOWNER({:#x})", owner);
+			Ok( if self.0.lock().set_modal_owner(owner) { 0 } else { 1 } )
+			},
 		values::GUI_WIN_GETPOS => {
 			log_debug!("GUI_WIN_GETPOS()");
 			let p = self.0.lock().get_pos();
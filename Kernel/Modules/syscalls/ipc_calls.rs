@@ -6,10 +6,12 @@
 use args::Args;
 use kernel::memory::freeze::{Freeze,FreezeMut};
 use core::sync::atomic::{AtomicU8,Ordering};
+use kernel::lib::mem::Arc;
 use values::RpcMessage;
+use objects::ObjectAlloc;
 
 struct SyncChannel {
-	ptr: *const SyncChannelBack,
+	back: Arc<SyncChannelBack>,
 	side_idx: u8,
 }
 
@@ -31,18 +33,34 @@ impl ::objects::Object for SyncChannel
 		::values::IPC_RPC_SEND => {
 			let data: Freeze<::values::RpcMessage> = try!(args.get());
 			let obj: u32 = try!(args.get());
-			todo!("IPC_RPC_SEND({:p}, {})", &*data, obj);
+			Ok( ::from_result(self.send(&data, obj).map(|_| 0u32)) )
 			},
 		::values::IPC_RPC_RECV => {
-			let _data: FreezeMut<::values::RpcMessage> = try!(args.get());
+			let mut data: FreezeMut<::values::RpcMessage> = try!(args.get());
 
-			if let Some(msg) = self.take_message()
+			if let Some( (msg, obj) ) = self.take_message()
 			{
-				todo!("IPC_RPC_RECV - Message present");
+				*data = msg;
+				match obj
+				{
+				None => Ok( 0 ),
+				Some(obj) => match ::objects::insert_raw(obj)
+					{
+					Ok(h) => Ok( h as u64 ),
+					Err(e) => {
+						log_notice!("IPC_RPC_RECV - Dropped attached object: {:?}", e);
+						Ok( 0 )
+						},
+					},
+				}
+			}
+			else if self.is_peer_closed()
+			{
+				Ok( ::from_result::<u32,u32>(Err(::values::IPC_RPC_ERR_CLOSED)) )
 			}
 			else
 			{
-				Ok( 0x1000 )
+				Ok( ::from_result::<u32,u32>(Err(::values::IPC_RPC_ERR_EMPTY)) )
 			}
 			},
 		_ => ::objects::object_has_no_such_method_ref("ipc_calls::SyncChannel", call),
@@ -57,15 +75,31 @@ impl ::objects::Object for SyncChannel
 		let mut ret = 0;
 		if flags & ::values::EV_IPC_RPC_RECV != 0 {
 			self.wait_upon(obj);
+			if self.has_message() || self.is_peer_closed() {
+				obj.signal();
+			}
 			ret |= ::values::EV_IPC_RPC_RECV;
 		}
+		if flags & ::values::EV_IPC_RPC_SEND != 0 {
+			self.get_other_side().space_queue.wait_upon(obj);
+			if self.can_send() {
+				obj.signal();
+			}
+			ret |= ::values::EV_IPC_RPC_SEND;
+		}
 		ret
 	}
 	fn clear_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
 		let mut ret = 0;
 		if flags & ::values::EV_IPC_RPC_RECV != 0 {
 			self.clear_wait(obj);
-			if self.has_message() {
+			if self.has_message() || self.is_peer_closed() {
+				ret += 1;
+			}
+		}
+		if flags & ::values::EV_IPC_RPC_SEND != 0 {
+			self.get_other_side().space_queue.clear_wait(obj);
+			if self.can_send() {
 				ret += 1;
 			}
 		}
@@ -94,31 +128,76 @@ pub fn new_pair() -> Result< (u32,u32), () >
 #[derive(Default)]
 struct SyncChannelBack
 {
-	dying_refs: AtomicU8,
+	/// Bitmask of closed sides
 	dead_refs: AtomicU8,
 	sides: [ SyncChannelSide; 2 ],
 }
 #[derive(Default)]
 struct SyncChannelSide
 {
-	message: ::kernel::sync::Spinlock<Option<RpcMessage>>,
+	/// Pending message, and the object attached to it
+	message: ::kernel::sync::Spinlock<Option<(RpcMessage, Option<ObjectAlloc>)>>,
 	queue: ::kernel::async::queue::Source,
+	/// Senders waiting for `message` to be taken
+	space_queue: ::kernel::async::queue::Source,
 }
 
 impl SyncChannel
 {
 	fn new_pair() -> (SyncChannel, SyncChannel) {
-		// SAFE: Allocation is safe?
-		let ptr = unsafe { ::kernel::memory::heap::alloc( SyncChannelBack::default() ) };
+		let back = Arc::new( SyncChannelBack::default() );
 
-		(SyncChannel { ptr: ptr, side_idx: 0 }, SyncChannel { ptr: ptr, side_idx: 1 })
+		(SyncChannel { back: back.clone(), side_idx: 0 }, SyncChannel { back: back, side_idx: 1 })
 	}
 
 	fn get_side(&self) -> &SyncChannelSide {
-		// SAFE: Destructor ensures that pointer is valid until both are dead
-		unsafe {
-			&(*self.ptr).sides[self.side_idx as usize]
+		&self.back.sides[self.side_idx as usize]
+	}
+	fn get_other_side(&self) -> &SyncChannelSide {
+		&self.back.sides[1 - self.side_idx as usize]
+	}
+	/// Returns true if the other end of the channel has been dropped
+	fn is_peer_closed(&self) -> bool {
+		self.back.dead_refs.load(Ordering::SeqCst) & (1 << (1 - self.side_idx)) != 0
+	}
+
+	/// Pass a message (and optionally an object, moved out of the caller's object list) to the other side
+	pub fn send(&self, msg: &RpcMessage, handle: u32) -> Result<(), u32> {
+		let side = self.get_other_side();
+		if self.is_peer_closed() {
+			return Err(::values::IPC_RPC_ERR_CLOSED);
+		}
+		if side.message.lock().is_some() {
+			return Err(::values::IPC_RPC_ERR_FULL);
+		}
+		// NOTE: Taken before re-locking, as object list accesses can block
+		let obj = if handle == 0 {
+				None
+			}
+			else {
+				match ::objects::take_raw(handle)
+				{
+				Ok(o) => Some(o),
+				Err(e) => {
+					log_debug!("IPC_RPC_SEND - Unable to take object {}: {:?}", handle, e);
+					return Err(::values::IPC_RPC_ERR_OBJECT);
+					},
+				}
+			};
+		{
+			let mut lh = side.message.lock();
+			if lh.is_some() {
+				// Another thread sent in the meantime, return the object
+				drop(lh);
+				if let Some(o) = obj {
+					::objects::restore_raw(handle, o);
+				}
+				return Err(::values::IPC_RPC_ERR_FULL);
+			}
+			*lh = Some( (*msg, obj) );
 		}
+		side.queue.wake_one();
+		Ok( () )
 	}
 
 	pub fn wait_upon(&self, waiter: &mut ::kernel::threads::SleepObject) {
@@ -131,26 +210,27 @@ impl SyncChannel
 	pub fn has_message(&self) -> bool {
 		self.get_side().message.lock().is_some()
 	}
-	pub fn take_message(&self) -> Option<RpcMessage> {
-		self.get_side().message.lock().take()
+	/// Returns true if a send wouldn't fail with IPC_RPC_ERR_FULL
+	fn can_send(&self) -> bool {
+		self.get_other_side().message.lock().is_none() || self.is_peer_closed()
+	}
+	pub fn take_message(&self) -> Option<(RpcMessage, Option<ObjectAlloc>)> {
+		let rv = self.get_side().message.lock().take();
+		if rv.is_some() {
+			// Wake the other side's senders
+			self.get_side().space_queue.wake_all();
+		}
+		rv
 	}
 }
 
 impl ::core::ops::Drop for SyncChannel {
 	fn drop(&mut self) {
-		// SAFE: Pointer is valid
-		let should_free = unsafe {
-			if (*self.ptr).dying_refs.fetch_or(1 << self.side_idx, Ordering::SeqCst) != 0 {
-				// Other side is in shutdown or dead.
-			}
-			else {
-			}
-
-			(*self.ptr).dead_refs.fetch_or(1 << self.side_idx, Ordering::SeqCst) != 0
-			};
-		if should_free {
-			todo!("Deallocate SyncChannel");
-		}
+		self.back.dead_refs.fetch_or(1 << self.side_idx, Ordering::SeqCst);
+		// Discard any undelivered message (dropping the attached object outside the lock)
+		let _discarded = self.take_message();
+		// Wake the other side (which will see IPC_RPC_ERR_CLOSED)
+		self.get_other_side().queue.wake_all();
+		self.get_side().space_queue.wake_all();
 	}
 }
-
@@ -0,0 +1,300 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Modules/syscalls/ipc_channel.rs
+//! Message channels (variable-length messages with attached objects)
+//!
+//! Each direction has a bounded queue of messages, senders get IPC_CHAN_ERR_FULL when it fills (and can wait on
+//! EV_IPC_CHAN_SEND for space). Attached objects are removed from the sender's object list when the message is
+//! queued, and added to the receiver's list when it's received.
+use kernel::prelude::*;
+use kernel::lib::mem::Arc;
+use kernel::sync::Mutex;
+use kernel::threads::{SleepObject,SleepObjectRef};
+use kernel::memory::freeze::{Freeze,FreezeMut};
+use args::Args;
+use objects::ObjectAlloc;
+use values;
+
+/// Handle to one end of a channel
+struct Channel
+{
+	inner: Arc<ChannelInner>,
+	side: usize,
+}
+struct ChannelInner
+{
+	/// Queue of messages *to* each side
+	queues: [Mutex<MessageQueue>; 2],
+}
+#[derive(Default)]
+struct MessageQueue
+{
+	messages: ::kernel::lib::Queue<Message>,
+	count: usize,
+	/// The receiving side has been closed (messages are dropped)
+	rx_closed: bool,
+	/// The sending side has been closed (the receiver gets IPC_CHAN_ERR_CLOSED once the queue is empty)
+	tx_closed: bool,
+	/// Threads waiting for a message
+	rx_waiters: Vec<SleepObjectRef>,
+	/// Threads waiting for space
+	tx_waiters: Vec<SleepObjectRef>,
+}
+struct Message
+{
+	data: Vec<u8>,
+	objects: Vec<ObjectAlloc>,
+}
+
+pub fn new_pair() -> Result< (u32,u32), () >
+{
+	let inner = Arc::new(ChannelInner {
+		queues: [Default::default(), Default::default()],
+		});
+
+	let a = ::objects::new_object( Channel { inner: inner.clone(), side: 0 } );
+	if a == !0 {
+		return Err( () );
+	}
+	let b = ::objects::new_object( Channel { inner: inner, side: 1 } );
+	if b == !0 {
+		::objects::drop_object(a);
+		return Err( () );
+	}
+	Ok( (a,b) )
+}
+
+impl Channel
+{
+	/// Queue for messages received by this end
+	fn rx_queue(&self) -> &Mutex<MessageQueue> {
+		&self.inner.queues[self.side]
+	}
+	/// Queue for messages sent by this end
+	fn tx_queue(&self) -> &Mutex<MessageQueue> {
+		&self.inner.queues[1 - self.side]
+	}
+
+	/// Returns true if `obj` is an endpoint of this channel
+	fn is_same_channel(&self, obj: &ObjectAlloc) -> bool {
+		match obj.as_any().downcast_ref::<Channel>()
+		{
+		Some(c) => &*c.inner as *const ChannelInner == &*self.inner as *const ChannelInner,
+		None => false,
+		}
+	}
+
+	fn send(&self, data: &[u8], handles: &[u32]) -> Result<(), u32>
+	{
+		if data.len() > values::IPC_CHAN_MAX_DATA || handles.len() > values::IPC_CHAN_MAX_OBJECTS {
+			return Err(values::IPC_CHAN_ERR_TOOLARGE);
+		}
+		for (i, &h) in handles.iter().enumerate() {
+			// Check that all handles are valid (and not duplicated) before any are moved
+			if ::objects::get_class(h).is_err() || handles[..i].contains(&h) {
+				return Err(values::IPC_CHAN_ERR_OBJECT);
+			}
+		}
+
+		let mut q = self.tx_queue().lock();
+		if q.rx_closed {
+			return Err(values::IPC_CHAN_ERR_CLOSED);
+		}
+		if q.count == values::IPC_CHAN_QUEUE_DEPTH {
+			return Err(values::IPC_CHAN_ERR_FULL);
+		}
+
+		let mut objects = Vec::with_capacity(handles.len());
+		for &h in handles
+		{
+			match ::objects::take_raw(h)
+			{
+			Ok(o) => objects.push(o),
+			Err(e) => {
+				// Another thread is using the object, return the objects already taken
+				log_debug!("IPC_CHAN_SEND - Unable to take object {}: {:?}", h, e);
+				for (o, &h) in objects.into_iter().zip(handles.iter()) {
+					::objects::restore_raw(h, o);
+				}
+				return Err(values::IPC_CHAN_ERR_OBJECT);
+				},
+			}
+		}
+		// An endpoint of this channel can't be sent over it (the queued message would keep the channel alive forever)
+		if objects.iter().any(|o| self.is_same_channel(o)) {
+			log_debug!("IPC_CHAN_SEND - Attempting to send an endpoint over its own channel");
+			for (o, &h) in objects.into_iter().zip(handles.iter()) {
+				::objects::restore_raw(h, o);
+			}
+			return Err(values::IPC_CHAN_ERR_OBJECT);
+		}
+
+		let mut buf = Vec::with_capacity(data.len());
+		buf.push_all(data);
+		q.messages.push(Message { data: buf, objects: objects });
+		q.count += 1;
+		for w in q.rx_waiters.iter() {
+			w.signal();
+		}
+		Ok( () )
+	}
+
+	fn recv(&self, data: &mut [u8], handles: &mut [u32]) -> Result<u32, u32>
+	{
+		let msg = {
+			let mut q = self.rx_queue().lock();
+			let (len, count) = try!(q.peek());
+			if len > data.len() || count > handles.len() {
+				return Err(values::IPC_CHAN_ERR_TOOLARGE);
+			}
+			if count > ::objects::free_slots() {
+				return Err(values::IPC_CHAN_ERR_OBJECT);
+			}
+			let msg = q.messages.pop().unwrap();
+			q.count -= 1;
+			for w in q.tx_waiters.iter() {
+				w.signal();
+			}
+			msg
+			};
+
+		data[.. msg.data.len()].clone_from_slice(&msg.data);
+		let count = msg.objects.len();
+		for (slot, obj) in handles.iter_mut().zip(msg.objects.into_iter()) {
+			*slot = match ::objects::insert_raw(obj)
+				{
+				Ok(h) => h,
+				Err(e) => {
+					// NOTE: Only if another thread used the slots after the check above
+					log_notice!("IPC_CHAN_RECV - Dropped attached object: {:?}", e);
+					!0
+					},
+				};
+		}
+		Ok( (msg.data.len() | count << 16) as u32 )
+	}
+
+	fn peek(&self) -> Result<u32, u32> {
+		let (len, count) = try!(self.rx_queue().lock().peek());
+		Ok( (len | count << 16) as u32 )
+	}
+}
+
+impl MessageQueue
+{
+	/// Size and object count of the next message
+	fn peek(&self) -> Result<(usize, usize), u32> {
+		match self.messages.iter().next()
+		{
+		Some(m) => Ok( (m.data.len(), m.objects.len()) ),
+		None if self.tx_closed => Err(values::IPC_CHAN_ERR_CLOSED),
+		None => Err(values::IPC_CHAN_ERR_EMPTY),
+		}
+	}
+	fn rx_ready(&self) -> bool {
+		!self.messages.is_empty() || self.tx_closed
+	}
+	fn tx_ready(&self) -> bool {
+		self.count < values::IPC_CHAN_QUEUE_DEPTH || self.rx_closed
+	}
+}
+
+impl ::objects::Object for Channel
+{
+	const CLASS: u16 = values::CLASS_IPC_CHANNEL;
+	fn class(&self) -> u16 { Self::CLASS }
+	fn as_any(&self) -> &Any { self }
+	fn try_clone(&self) -> Option<u32> {
+		None
+	}
+	fn handle_syscall_ref(&self, call: u16, args: &mut Args) -> Result<u64,::Error>
+	{
+		match call
+		{
+		values::IPC_CHAN_SEND => {
+			let data: Freeze<[u8]> = try!(args.get());
+			let handles: Freeze<[u32]> = try!(args.get());
+			Ok( ::from_result(self.send(&data, &handles).map(|_| 0u32)) )
+			},
+		values::IPC_CHAN_RECV => {
+			let mut data: FreezeMut<[u8]> = try!(args.get());
+			let mut handles: FreezeMut<[u32]> = try!(args.get());
+			Ok( ::from_result(self.recv(&mut data, &mut handles)) )
+			},
+		values::IPC_CHAN_PEEK => {
+			Ok( ::from_result(self.peek()) )
+			},
+		_ => ::objects::object_has_no_such_method_ref("ipc_channel::Channel", call),
+		}
+	}
+	fn bind_wait(&self, flags: u32, obj: &mut SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_IPC_CHAN_RECV != 0 {
+			let mut q = self.rx_queue().lock();
+			if q.rx_ready() {
+				obj.signal();
+			}
+			else {
+				q.rx_waiters.push(obj.get_ref());
+			}
+			ret += 1;
+		}
+		if flags & values::EV_IPC_CHAN_SEND != 0 {
+			let mut q = self.tx_queue().lock();
+			if q.tx_ready() {
+				obj.signal();
+			}
+			else {
+				q.tx_waiters.push(obj.get_ref());
+			}
+			ret += 1;
+		}
+		ret
+	}
+	fn clear_wait(&self, flags: u32, obj: &mut SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_IPC_CHAN_RECV != 0 {
+			let mut q = self.rx_queue().lock();
+			if let Some(i) = q.rx_waiters.iter().position(|w| w.is_from(obj)) {
+				q.rx_waiters.remove(i);
+			}
+			if q.rx_ready() {
+				ret |= values::EV_IPC_CHAN_RECV;
+			}
+		}
+		if flags & values::EV_IPC_CHAN_SEND != 0 {
+			let mut q = self.tx_queue().lock();
+			if let Some(i) = q.tx_waiters.iter().position(|w| w.is_from(obj)) {
+				q.tx_waiters.remove(i);
+			}
+			if q.tx_ready() {
+				ret |= values::EV_IPC_CHAN_SEND;
+			}
+		}
+		ret
+	}
+}
+
+impl ::core::ops::Drop for Channel
+{
+	fn drop(&mut self)
+	{
+		// Discard undelivered messages (dropping attached objects outside the lock)
+		let _discarded = {
+			let mut q = self.rx_queue().lock();
+			q.rx_closed = true;
+			q.count = 0;
+			for w in q.tx_waiters.iter() {
+				w.signal();
+			}
+			::core::mem::replace(&mut q.messages, Default::default())
+			};
+		// Wake the other end's receivers (which will see IPC_CHAN_ERR_CLOSED)
+		let mut q = self.tx_queue().lock();
+		q.tx_closed = true;
+		for w in q.rx_waiters.iter() {
+			w.signal();
+		}
+	}
+}
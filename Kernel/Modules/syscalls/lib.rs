@@ -22,10 +22,13 @@ mod objects;
 mod args;
 
 mod threads;
+mod futex;
 #[path="gui.rs"]
 mod gui_calls;
 mod vfs;
 mod ipc_calls;
+mod ipc_channel;
+mod shared_mem;
 
 pub type ObjectHandle = u32;
 
@@ -79,14 +82,30 @@ pub unsafe extern "C" fn syscalls_handler(id: u32, first_arg: *const usize, coun
 }
 
 fn invoke(call_id: u32, args: &[usize]) -> u64 {
-	match invoke_int(call_id, &mut Args::new(args))
+	// Threads of killed processes terminate on their next system call
+	::kernel::threads::check_kill();
+	let rv = match invoke_int(call_id, &mut Args::new(args))
+		{
+		Ok(v) => v,
+		Err(e) => {
+			log_log!("Syscall formatting error in call {:#x} - {:?} {}", call_id, e, e);
+			::kernel::threads::exit_process(values::EXIT_STATUS_BADSYSCALL);
+			// !0
+			},
+		};
+	// - And if the process was killed during the call (e.g. while sleeping)
+	::kernel::threads::check_kill();
+	rv
+}
+
+/// Convert a memory allocation error into a MEM_ERR_* code
+fn map_error_code(e: ::kernel::memory::virt::MapError) -> u32 {
+	use kernel::memory::virt::MapError;
+	match e
 	{
-	Ok(v) => v,
-	Err(e) => {
-		log_log!("Syscall formatting error in call {:#x} - {:?} {}", call_id, e, e);
-		::kernel::threads::exit_process(0x8000_0000);
-		// !0
-		},
+	MapError::RangeInUse => values::MEM_ERR_INUSE,
+	MapError::OutOfMemory => values::MEM_ERR_OUTOFMEMORY,
+	MapError::QuotaExceeded => values::MEM_ERR_QUOTA,
 	}
 }
 
@@ -151,7 +170,8 @@ fn invoke_int(call_id: u32, args: &mut Args) -> Result<u64,Error>
 			},
 		// - 0/2: Terminate current thread
 		CORE_EXITTHREAD => {
-			threads::terminate(); 0
+			let value: u32 = try!(args.get());
+			threads::exit_thread(value); 0
 			},
 		// - 0/3: Start process
 		CORE_STARTPROCESS => {
@@ -162,13 +182,15 @@ fn invoke_int(call_id: u32, args: &mut Args) -> Result<u64,Error>
 				log_log!("CORE_STARTPROCESS - {:#x}--{:#x} invalid", start, end);
 				return Err( Error::BadValue );
 			}
-			threads::newprocess(&name, start, end) as u64
+			from_result(threads::newprocess(&name, start, end))
 			},
 		// - 0/4: Start thread
 		CORE_STARTTHREAD => {
 			let ip: usize = try!(args.get());
 			let sp: usize = try!(args.get());
-			threads::newthread(sp, ip) as u64
+			let tls_base: usize = try!(args.get());
+			let arg: usize = try!(args.get());
+			threads::newthread(sp, ip, tls_base, arg) as u64
 			},
 		// - 0/5: Wait for event
 		CORE_WAIT => {
@@ -176,6 +198,40 @@ fn invoke_int(call_id: u32, args: &mut Args) -> Result<u64,Error>
 			let timeout: u64 = try!(args.get());
 			try!(threads::wait(&mut events, timeout)) as u64
 			},
+		// - 0/8: Monotonic time
+		CORE_GETTIME => {
+			::kernel::time::ticks()
+			},
+		// - 0/9: Wall-clock time
+		CORE_GETREALTIME => {
+			::kernel::time::realtime().unwrap_or(!0)
+			},
+		// - 0/10: Lower process priority
+		CORE_SETPRIORITY => {
+			let class: u32 = try!(args.get());
+			from_result(try!(threads::set_priority(class)))
+			},
+		// - 0/11: Thread CPU time
+		CORE_GETCPUTIME => {
+			::kernel::time::ticks_to_ms( ::kernel::threads::get_cpu_time() )
+			},
+		// - 0/12: Wait on a userland address
+		CORE_FUTEXWAIT => {
+			let addr: usize = try!(args.get());
+			let value: usize = try!(args.get());
+			let timeout: u64 = try!(args.get());
+			from_result(try!(futex::wait(addr, value, timeout)))
+			},
+		// - 0/13: Wake threads waiting on a userland address
+		CORE_FUTEXWAKE => {
+			let addr: usize = try!(args.get());
+			let count: usize = try!(args.get());
+			try!(futex::wake(addr, count)) as u64
+			},
+		CORE_SETTLSBASE => {
+			let base: usize = try!(args.get());
+			::kernel::arch::threads::set_user_tls_base(base); 0
+			},
 		// === 1: Window Manager / GUI
 		// - 1/0: New group (requires permission, has other restrictions)
 		GUI_NEWGROUP => {
@@ -207,11 +263,8 @@ fn invoke_int(call_id: u32, args: &mut Args) -> Result<u64,Error>
 			let addr: usize = try!(args.get());
 			let count: usize = try!(args.get());
 			log_debug!("MEM_ALLOCATE({:#x},{})", addr, count);
-			match ::kernel::memory::virt::allocate_user(addr as *mut (), count)
-			{
-			Ok(_) => 0,
-			Err(e) => todo!("MEM_ALLOCATE - error {:?}", e),
-			}
+			let rv = ::kernel::memory::virt::with_oom_retry(|| ::kernel::memory::virt::allocate_user(addr as *mut (), count));
+			from_result( rv.map(|_| 0u32).map_err(map_error_code) )
 			},
 		MEM_REPROTECT => {
 			let addr: usize = try!(args.get());
@@ -241,6 +294,14 @@ fn invoke_int(call_id: u32, args: &mut Args) -> Result<u64,Error>
 			Err( () ) => error_code(0) as u64,
 			}
 			},
+		MEM_NEWSHARED => {
+			let count: usize = try!(args.get());
+			log_debug!("MEM_NEWSHARED({})", count);
+			if count == 0 || count > values::MEM_SHARED_MAX_PAGES {
+				return Err( Error::BadValue );
+			}
+			from_result( shared_mem::new(count) )
+			},
 		// === 4: IPC
 		IPC_NEWPAIR => {
 			match ipc_calls::new_pair()
@@ -249,6 +310,17 @@ fn invoke_int(call_id: u32, args: &mut Args) -> Result<u64,Error>
 			Err( () ) => !0
 			}
 			},
+		IPC_NEWCHANNEL => {
+			match ipc_channel::new_pair()
+			{
+			Ok( (oh_a, oh_b) ) => oh_a as u64 | (oh_b as u64) << 32,
+			Err( () ) => !0
+			}
+			},
+		// === 4: VFS management
+		VFS_GETMOUNTCAP => {
+			from_result(vfs::get_mount_cap())
+			},
 		// === *: Default
 		_ => {
 			log_error!("Unknown syscall {:05x}", call_id);
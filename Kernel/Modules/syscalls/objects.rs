@@ -358,6 +358,40 @@ pub fn take_object<T: Object+'static>(handle: u32) -> Result<T,super::Error> {
 	}
 }
 
+/// Remove an object from this process's list (e.g. to pass it to another process over IPC)
+pub fn take_raw(handle: u32) -> Result<ObjectAlloc,super::Error> {
+	if handle == 0 {
+		// The "this process" object can't be moved
+		Err( super::Error::NoSuchObject(handle) )
+	}
+	else {
+		get_process_local::<ProcessObjects>().take_object(handle)
+	}
+}
+/// Add an object (removed with `take_raw`) to this process's list
+pub fn insert_raw(obj: ObjectAlloc) -> Result<u32,super::Error> {
+	get_process_local::<ProcessObjects>().find_and_fill_slot(|| UserObject { data: obj })
+}
+/// Return an object removed with `take_raw` to its original slot (or another slot if that has been reused)
+pub fn restore_raw(handle: u32, obj: ObjectAlloc) {
+	let objs = get_process_local::<ProcessObjects>();
+	let obj = {
+		let mut lh = objs.get(handle).expect("restore_raw - Bad handle").write();
+		if lh.is_none() {
+			*lh = Some(UserObject { data: obj });
+			return ;
+		}
+		obj
+		};
+	if let Err(e) = objs.find_and_fill_slot(|| UserObject { data: obj }) {
+		log_notice!("restore_raw({}) - Unable to restore object: {:?}", handle, e);
+	}
+}
+/// Number of unused slots in this process's object list
+pub fn free_slots() -> usize {
+	get_process_local::<ProcessObjects>().iter().filter(|e| e.read().is_none()).count()
+}
+
 #[inline(never)]
 pub fn drop_object(handle: u32)
 {
@@ -0,0 +1,101 @@
+// "Tifflin" Kernel
+// - By John Hodge (thePowersGang)
+//
+// Modules/syscalls/shared_mem.rs
+//! Anonymous shared memory objects
+//!
+//! The object owns a reference to each of its frames, and every mapping takes another (so the memory stays valid
+//! while mapped, even after all handles are closed). Mappings are charged to the mapping process's quota.
+use kernel::prelude::*;
+use kernel::lib::mem::Arc;
+use kernel::memory::phys::FrameHandle;
+use kernel::memory::virt::ProtectionMode;
+use args::Args;
+use values;
+
+struct SharedMemory
+{
+	frames: Arc<Vec<FrameHandle>>,
+	/// Only read-only (or executable) mappings are allowed
+	readonly: bool,
+}
+
+/// Create a new (zeroed) shared memory object, returning the object handle
+pub fn new(page_count: usize) -> Result<u32, u32>
+{
+	assert!(page_count > 0 && page_count <= values::MEM_SHARED_MAX_PAGES);
+	let mut frames = Vec::with_capacity(page_count);
+	for _ in 0 .. page_count
+	{
+		// NOTE: If this fails, the frames already allocated are released when `frames` drops
+		let mut page = try!( ::kernel::memory::virt::with_oom_retry(::kernel::memory::virt::alloc_free).map_err(::map_error_code) );
+		for b in page.iter_mut() {
+			*b = 0;
+		}
+		frames.push( page.into_frame() );
+	}
+
+	let rv = ::objects::new_object( SharedMemory { frames: Arc::new(frames), readonly: false } );
+	if rv == !0 {
+		return Err(values::MEM_ERR_OUTOFMEMORY);
+	}
+	Ok(rv)
+}
+
+impl SharedMemory
+{
+	fn map(&self, addr: usize, mode: ProtectionMode) -> Result<u32, u32>
+	{
+		if self.readonly && (mode == ProtectionMode::UserRW || mode == ProtectionMode::UserRWX) {
+			return Err(values::MEM_ERR_READONLY);
+		}
+		try!( ::kernel::memory::virt::map_user_frames(addr as *mut (), &self.frames, mode).map_err(::map_error_code) );
+		Ok(0)
+	}
+}
+
+impl ::objects::Object for SharedMemory
+{
+	const CLASS: u16 = values::CLASS_MEM_SHARED;
+	fn class(&self) -> u16 { Self::CLASS }
+	fn as_any(&self) -> &Any { self }
+	fn try_clone(&self) -> Option<u32> {
+		Some( ::objects::new_object( SharedMemory { frames: self.frames.clone(), readonly: self.readonly } ) )
+	}
+	fn handle_syscall_ref(&self, call: u16, args: &mut Args) -> Result<u64,::Error>
+	{
+		match call
+		{
+		values::MEM_SHARED_GETSIZE => {
+			Ok( self.frames.len() as u64 )
+			},
+		values::MEM_SHARED_MAP => {
+			let addr: usize = try!(args.get());
+			let mode: u8 = try!(args.get());
+			log_debug!("MEM_SHARED_MAP({:#x},{})", addr, mode);
+			if addr % ::kernel::PAGE_SIZE != 0 {
+				return Err( ::Error::BadValue );
+			}
+			let mode = match mode
+				{
+				0 => ProtectionMode::UserRO,
+				1 => ProtectionMode::UserRW,
+				2 => ProtectionMode::UserRX,
+				3 => ProtectionMode::UserRWX,
+				_ => return Err( ::Error::BadValue ),
+				};
+			Ok( ::from_result(self.map(addr, mode)) )
+			},
+		values::MEM_SHARED_CLONERO => {
+			Ok( ::objects::new_object( SharedMemory { frames: self.frames.clone(), readonly: true } ) as u64 )
+			},
+		_ => ::objects::object_has_no_such_method_ref("shared_mem::SharedMemory", call),
+		}
+	}
+	fn bind_wait(&self, _flags: u32, _obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		0
+	}
+	fn clear_wait(&self, _flags: u32, _obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		0
+	}
+}
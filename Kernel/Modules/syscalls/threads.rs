@@ -10,6 +10,8 @@ use ObjectHandle;
 use Error;
 use values;
 use args::Args;
+use kernel::memory::freeze::{Freeze,FreezeMut};
+use kernel::threads::{FaultKind,FaultImage,ControlEvent};
 //use kernel::threads::get_process_local;
 
 /// Current process type (provides an object handle for IPC)
@@ -31,17 +33,59 @@ impl ::objects::Object for CurProcess
 			let class: u16 = try!(args.get());
 			Ok( ::objects::get_unclaimed(class, &tag) )
 			},
+		// Dequeue a control notification (returns 0 if there are none)
+		values::CORE_THISPROCESS_RECVCONTROL => {
+			let mut dst: FreezeMut<values::ControlMessage> = try!(args.get());
+			match ::kernel::threads::pop_control()
+			{
+			Some(ev) => {
+				*dst = match ev
+					{
+					ControlEvent::TerminateRequest => values::ControlMessage { kind: values::CONTROL_KIND_TERMINATE, source: 0, value: 0 },
+					ControlEvent::ChildExited(pid, status) => values::ControlMessage { kind: values::CONTROL_KIND_CHILDEXITED, source: pid, value: status },
+					ControlEvent::WindowClosed(win) => values::ControlMessage { kind: values::CONTROL_KIND_WINDOWCLOSED, source: win, value: 0 },
+					ControlEvent::User(v) => values::ControlMessage { kind: values::CONTROL_KIND_USER, source: 0, value: v },
+					};
+				Ok(1)
+				},
+			None => Ok(0),
+			}
+			},
+		// Record the loaded images (included in fault reports, extra entries are ignored)
+		values::CORE_THISPROCESS_SETIMAGES => {
+			let images: Freeze<[values::FaultImage]> = try!(args.get());
+			let mut buf = [FaultImage::default(); values::FAULT_MAX_IMAGES];
+			let count = ::core::cmp::min(images.len(), buf.len());
+			for (d, s) in buf.iter_mut().zip(images.iter())
+			{
+				*d = FaultImage { start: s.start, end: s.end, bias: s.bias, name: s.name };
+			}
+			::kernel::threads::set_loaded_images(&buf[..count]);
+			Ok(0)
+			},
 		_ => ::objects::object_has_no_such_method_ref("threads::CurProcess", call),
 		}
 	}
 	//fn handle_syscall_val(self, call: u16, _args: &mut Args) -> Result<u64,Error> {
 	//	::objects::object_has_no_such_method_val("threads::CurProcess", call)
 	//}
-	fn bind_wait(&self, _flags: u32, _obj: &mut ::kernel::threads::SleepObject) -> u32 {
-		0
+	fn bind_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		let mut ret = 0;
+		// Wait for a control notification
+		if flags & values::EV_THISPROCESS_CONTROL != 0 {
+			::kernel::threads::bind_wait_control(obj);
+			ret |= values::EV_THISPROCESS_CONTROL;
+		}
+		ret
 	}
-	fn clear_wait(&self, _flags: u32, _obj: &mut ::kernel::threads::SleepObject) -> u32 {
-		0
+	fn clear_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_THISPROCESS_CONTROL != 0 {
+			if ::kernel::threads::clear_wait_control(obj) {
+				ret |= values::EV_THISPROCESS_CONTROL;
+			}
+		}
+		ret
 	}
 }
 
@@ -50,19 +94,48 @@ pub fn exit(status: u32) {
 	::kernel::threads::exit_process(status);
 }
 #[inline(never)]
-pub fn terminate() {
-	todo!("terminate()");
+pub fn exit_thread(value: u32) {
+	::kernel::threads::exit_thread(value);
 }
 #[inline(never)]
-pub fn newthread(sp: usize, ip: usize) -> ObjectHandle {
-	todo!("newthread(sp={:#x},ip={:#x})", sp, ip);
+pub fn newthread(sp: usize, ip: usize, tls_base: usize, arg: usize) -> ObjectHandle {
+	// NOTE: Don't need to validate these values, as they're used only in user-space
+	let handle = ::kernel::threads::ThreadHandle::new_user(ip, sp, tls_base, arg);
+	::objects::new_object( Thread(handle) )
 }
 #[inline(never)]
-pub fn newprocess(name: &str,  clone_start: usize, clone_end: usize) -> ObjectHandle {
+pub fn newprocess(name: &str,  clone_start: usize, clone_end: usize) -> Result<ObjectHandle,u32> {
 	// 1. Create a new process image (virtual address space)
-	let process = ::kernel::threads::ProcessHandle::new(name, clone_start, clone_end);
+	let process = match ::kernel::memory::virt::with_oom_retry(|| ::kernel::threads::ProcessHandle::new(name, clone_start, clone_end))
+		{
+		Ok(v) => v,
+		Err(e) => {
+			log_notice!("newprocess({}) - Error creating address space: {:?}", name, e);
+			return Err( ::map_error_code(e) );
+			},
+		};
 	
-	::objects::new_object( ProtoProcess(process) )
+	Ok( ::objects::new_object( ProtoProcess(process) ) )
+}
+
+/// Lower the current process's priority class
+///
+/// Returns the previous class, or `Err` with the current class if `class` is higher
+#[inline(never)]
+pub fn set_priority(class: u32) -> Result<Result<u32,u32>,Error>
+{
+	use kernel::threads::Priority;
+	let prio = match class
+		{
+		values::PRIORITY_IDLE => Priority::Idle,
+		values::PRIORITY_NORMAL => Priority::Normal,
+		_ => return Err( Error::BadValue ),
+		};
+	Ok( match ::kernel::threads::lower_process_priority(prio)
+		{
+		Ok(prev) => Ok(prev as u32),
+		Err(cur) => Err(cur as u32),
+		} )
 }
 
 // ret: number of events triggered
@@ -74,11 +147,13 @@ pub fn wait(events: &mut [values::WaitItem], wake_time_mono: u64) -> Result<u32,
 	for ev in events.iter() {
 		num_bound += try!(::objects::wait_on_object(ev.object, ev.flags, &mut waiter));
 	}
+	// Wake if the process is killed while sleeping (the thread terminates on return)
+	::kernel::threads::bind_wait_kill(&mut waiter);
 
 	if num_bound == 0 && wake_time_mono == !0 {
 		// Attempting to sleep on no events with an infinite timeout! Would sleep forever
+		// - Only a kill will wake it (handled by the unbounded wait below)
 		log_error!("TODO: What to do when a thread tries to sleep forever");
-		waiter.wait();
 	}
 
 	// A wake time of 0 means to not sleep at all, just check the status of the events
@@ -86,14 +161,16 @@ pub fn wait(events: &mut [values::WaitItem], wake_time_mono: u64) -> Result<u32,
 	if wake_time_mono != 0 {
 		// !0 indicates an unbounded wait (no need to set a wakeup time)
 		if wake_time_mono != !0 {
-			todo!("Set a wakeup timer at {}", wake_time_mono);
-			//waiter.wait_until(wake_time_mono);
+			let timer = ::kernel::time::Timer::new(wake_time_mono, &waiter);
+			waiter.wait();
+			timer.cancel();
 		}
 		else {
 			waiter.wait();
 		}
 	}
 
+	::kernel::threads::clear_wait_kill(&mut waiter);
 	Ok( events.iter_mut().fold(0, |total,ev| total + ::objects::clear_wait(ev.object, ev.flags, &mut waiter).unwrap()) )
 }
 
@@ -115,7 +192,16 @@ impl ::objects::Object for ProtoProcess
 			let tag: ::values::FixedStr8 = try!(args.get());
 			let handle: u32 = try!(args.get());
 			::objects::give_object(&self.0, &tag, handle).map(|_| 0)
+			},
+		// Limit the number of pages the child can allocate
+		values::CORE_PROTOPROCESS_SETQUOTA => {
+			let pages: usize = try!(args.get());
+			match self.0.set_page_quota(pages)
+			{
+			Ok( () ) => Ok(0),
+			Err( () ) => Ok( ::error_code(0) as u64 ),
 			}
+			},
 		_ => ::objects::object_has_no_such_method_ref("threads::ProtoProcess", call),
 		}
 	}
@@ -154,12 +240,69 @@ impl ::objects::Object for Process
 	fn try_clone(&self) -> Option<u32> {
 		None
 	}
-	fn handle_syscall_ref(&self, call: u16, _args: &mut Args) -> Result<u64,Error>
+	fn handle_syscall_ref(&self, call: u16, args: &mut Args) -> Result<u64,Error>
 	{
 		match call
 		{
 		// Request termination of child process
-		values::CORE_PROCESS_KILL => todo!("CORE_PROCESS_KILL"),
+		values::CORE_PROCESS_KILL => {
+			self.0.kill(values::EXIT_STATUS_KILLED);
+			Ok(0)
+			},
+		// Read the exit status (!0 if the process is still running)
+		values::CORE_PROCESS_GETEXITSTATUS => Ok( match self.0.get_exit_status()
+			{
+			Some(status) => status as u64,
+			None => !0,
+			} ),
+		// Read the fault report (returns 0 if the process didn't fault)
+		values::CORE_PROCESS_GETFAULT => {
+			let mut dst: FreezeMut<values::FaultInfo> = try!(args.get());
+			match self.0.get_fault()
+			{
+			Some(f) => {
+				*dst = values::FaultInfo {
+					kind: match f.kind
+						{
+						FaultKind::Page => values::FAULT_KIND_PAGE,
+						FaultKind::Protection => values::FAULT_KIND_PROTECTION,
+						FaultKind::InvalidOpcode => values::FAULT_KIND_INVALIDOPCODE,
+						FaultKind::Other => values::FAULT_KIND_OTHER,
+						},
+					code: f.code,
+					address: f.address,
+					ip: f.ip,
+					sp: f.sp,
+					regs: f.regs,
+					backtrace_len: f.backtrace_len,
+					backtrace: f.backtrace,
+					image_count: f.image_count,
+					images: {
+						let mut v = [values::FaultImage::default(); values::FAULT_MAX_IMAGES];
+						for (d, s) in v.iter_mut().zip(f.images.iter())
+						{
+							*d = values::FaultImage { start: s.start, end: s.end, bias: s.bias, name: s.name };
+						}
+						v
+						},
+					};
+				Ok(1)
+				},
+			None => Ok(0),
+			}
+			},
+		// Queue a control notification (only exit requests and user notifications can be sent by userland)
+		values::CORE_PROCESS_NOTIFY => {
+			let kind: u32 = try!(args.get());
+			let value: u32 = try!(args.get());
+			let ev = match kind
+				{
+				values::CONTROL_KIND_TERMINATE => ControlEvent::TerminateRequest,
+				values::CONTROL_KIND_USER => ControlEvent::User(value),
+				_ => return Err( Error::BadValue ),
+				};
+			Ok( if self.0.post_control(ev) { 0 } else { 1 } )
+			},
 		_ => ::objects::object_has_no_such_method_ref("threads::Process", call),
 		}
 	}
@@ -186,3 +329,44 @@ impl ::objects::Object for Process
 		ret
 	}
 }
+
+pub struct Thread(::kernel::threads::ThreadHandle);
+impl ::objects::Object for Thread
+{
+	const CLASS: u16 = values::CLASS_CORE_THREAD;
+	fn class(&self) -> u16 { Self::CLASS }
+	fn as_any(&self) -> &Any { self }
+	fn try_clone(&self) -> Option<u32> {
+		None
+	}
+	fn handle_syscall_ref(&self, call: u16, _args: &mut Args) -> Result<u64,Error>
+	{
+		match call
+		{
+		// Read the exit value (!0 if the thread is still running)
+		values::CORE_THREAD_GETEXITVALUE => Ok( match self.0.get_exit_status()
+			{
+			Some(value) => value as u64,
+			None => !0,
+			} ),
+		_ => ::objects::object_has_no_such_method_ref("threads::Thread", call),
+		}
+	}
+	fn bind_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_THREAD_TERMINATED != 0 {
+			self.0.bind_wait_terminate(obj);
+			ret += 1;
+		}
+		ret
+	}
+	fn clear_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_THREAD_TERMINATED != 0 {
+			if self.0.clear_wait_terminate(obj) {
+				ret |= values::EV_THREAD_TERMINATED;
+			}
+		}
+		ret
+	}
+}
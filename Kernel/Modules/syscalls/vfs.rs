@@ -37,8 +37,23 @@ impl_from! {
 		Error::PermissionDenied => VFSError::PermissionDenied,
 		Error::Locked => VFSError::FileLocked,
 		Error::MalformedPath => VFSError::MalformedPath,
-		Error::Unknown(reason) => todo!("VFS Error Unknown - '{}'", reason),
-		_ => todo!("VFS Error - {:?}", v),
+		Error::InvalidParameter => VFSError::InvalidParameter,
+		Error::ReadOnlyFilesystem => VFSError::ReadOnlyFilesystem,
+		Error::NonDirComponent => VFSError::TypeError,
+		Error::RecursionDepthExceeded => VFSError::SymlinkLoop,
+		Error::AlreadyExists => VFSError::AlreadyExists,
+		Error::BlockIoError(e) => {
+			log_notice!("VFS IO error - {:?}", e);
+			VFSError::IoError
+			},
+		Error::InconsistentFilesystem => VFSError::InconsistentFilesystem,
+		Error::OutOfSpace => VFSError::OutOfSpace,
+		Error::OutOfMemory => VFSError::OutOfMemory,
+		Error::TransientError => VFSError::TransientError,
+		Error::Unknown(reason) => {
+			log_notice!("VFS unknown error - '{}'", reason);
+			VFSError::Unknown
+			},
 		}
 	}}
 	From<node::NodeClass>(v) for ::values::VFSNodeType {
@@ -68,6 +83,11 @@ impl_from! {
 	}}
 }
 
+/// Maximum number of bytes transferred by a single READAT/WRITEAT (larger requests are short)
+///
+/// Counts are returned as a 31-bit value, see `::from_result`
+const MAX_TRANSFER: usize = 1 << 30;
+
 /// Convert a VFS result into an encoded syscall result
 fn to_result<T>(r: Result<T, ::kernel::vfs::Error>) -> Result<T, u32> {
 	r.map_err( |e| Into::into( <::values::VFSError as From<_>>::from(e) ) )
@@ -92,6 +112,102 @@ pub fn init_handles(loader_handle: ::kernel::vfs::handle::File, init_handle: ::k
 	::objects::push_as_unclaimed("RwRoot", ::objects::new_object( Dir::new( handle::Dir::open(Path::new("/")).unwrap() ) ) );
 }
 
+#[inline(never)]
+pub fn get_mount_cap() -> Result<u32,u32> {
+	// Only init can mount/unmount volumes
+	// TODO: Use a capability system instead of hardcoding to only PID0
+	if ::kernel::threads::get_process_id() == 0 {
+		Ok( objects::new_object(MountCap) )
+	}
+	else {
+		log_notice!("VFS_GETMOUNTCAP - Denied for PID {}", ::kernel::threads::get_process_id());
+		Err( 0 )
+	}
+}
+
+impl_from! {
+	From<::kernel::vfs::mount::MountError>(v) for ::values::VFSMountError {{
+		use kernel::vfs::mount::MountError;
+		use values::VFSMountError;
+		match v
+		{
+		MountError::UnknownFilesystem => VFSMountError::UnknownFilesystem,
+		MountError::NoHandler => VFSMountError::UnknownFilesystem,
+		MountError::InvalidMountpoint => VFSMountError::InvalidMountpoint,
+		MountError::MountpointUsed => VFSMountError::MountpointUsed,
+		MountError::CallFailed => VFSMountError::CallFailed,
+		MountError::BadOption => VFSMountError::BadOption,
+		MountError::Busy => VFSMountError::Busy,
+		}
+	}}
+}
+
+/// Capability allowing the owner to mount and unmount volumes
+struct MountCap;
+impl objects::Object for MountCap
+{
+	const CLASS: u16 = values::CLASS_VFS_MOUNTCAP;
+	fn class(&self) -> u16 { Self::CLASS }
+	fn as_any(&self) -> &Any { self }
+	fn try_clone(&self) -> Option<u32> {
+		None
+	}
+	fn handle_syscall_ref(&self, call: u16, args: &mut Args) -> Result<u64,Error> {
+		use kernel::vfs::mount;
+		use kernel::metadevs::storage;
+		Ok(match call
+		{
+		values::VFS_MOUNTCAP_ENUMVOLS => {
+			let idx: usize = try!(args.get());
+			let mut name: FreezeMut<[u8]> = try!(args.get());
+			log_debug!("VFS_MOUNTCAP_ENUMVOLS({}, {:p}+{})", idx, name.as_ptr(), name.len());
+			match storage::enum_lvs().into_iter().nth(idx)
+			{
+			Some( (_, v) ) => {
+				// NOTE: Names are truncated to fit the provided buffer
+				let len = ::core::cmp::min(name.len(), v.len());
+				name[.. len].clone_from_slice( &v.as_bytes()[.. len] );
+				v.len() as u64
+				},
+			None => !0,
+			}
+			},
+		values::VFS_MOUNTCAP_MOUNT => {
+			let volume: Freeze<str> = try!(args.get());
+			let mountpt: Freeze<[u8]> = try!(args.get());
+			let options: Freeze<str> = try!(args.get());
+			log_debug!("VFS_MOUNTCAP_MOUNT({:?}, {:?}, {:?})", &*volume, Path::new(&mountpt), &*options);
+			// - `fs=NAME` is handled here, the remaining options are passed to the driver
+			let mut fs = "";
+			let options: Vec<&str> = options.split(',')
+				.filter(|v| !v.is_empty())
+				.filter(|&&v| if v.starts_with("fs=") { fs = &v[3..]; false } else { true })
+				.collect();
+			let rv = match storage::VolumeHandle::open_named(&volume)
+				{
+				Ok(vh) => mount::mount(Path::new(&mountpt), vh, fs, &options)
+					.map_err(|e| Into::<u32>::into(::values::VFSMountError::from(e))),
+				Err(e) => {
+					log_log!("VFS_MOUNTCAP_MOUNT - Unable to open '{}': {}", &*volume, e);
+					Err( Into::<u32>::into(::values::VFSMountError::NoSuchVolume) )
+					},
+				};
+			super::from_result(rv.map(|_| 0u32))
+			},
+		values::VFS_MOUNTCAP_UNMOUNT => {
+			let mountpt: Freeze<[u8]> = try!(args.get());
+			log_debug!("VFS_MOUNTCAP_UNMOUNT({:?})", Path::new(&mountpt));
+			let rv = mount::unmount(Path::new(&mountpt))
+				.map_err(|e| Into::<u32>::into(::values::VFSMountError::from(e)));
+			super::from_result(rv.map(|_| 0u32))
+			},
+		_ => return ::objects::object_has_no_such_method_ref("vfs::MountCap", call),
+		})
+	}
+	fn bind_wait(&self, _flags: u32, _obj: &mut ::kernel::threads::SleepObject) -> u32 { 0 }
+	fn clear_wait(&self, _flags: u32, _obj: &mut ::kernel::threads::SleepObject) -> u32 { 0 }
+}
+
 
 // --------------------------------------------------------------------
 //
@@ -114,6 +230,10 @@ impl objects::Object for Node
 			let v32: u32 = ::values::VFSNodeType::from( self.0.get_class() ).into();
 			Ok( v32 as u64 )
 			},
+		values::VFS_NODE_GETOWNER => {
+			let (uid, gid) = self.0.get_owner();
+			Ok( (uid as u64) << 32 | gid as u64 )
+			},
 		_ => ::objects::object_has_no_such_method_ref("vfs::Node", call),
 		}
 	}
@@ -178,21 +298,15 @@ impl objects::Object for File
 			let ofs: u64 = try!(args.get());
 			let mut dest: FreezeMut<[u8]> = try!(args.get());
 			log_debug!("File::readat({}, {:p}+{} bytes)", ofs, dest.as_ptr(), dest.len());
-			match self.0.read(ofs, &mut dest)
-			{
-			Ok(count) => Ok(count as u64),
-			Err(e) => todo!("File::handle_syscall READAT Error {:?}", e),
-			}
+			let len = ::core::cmp::min(dest.len(), MAX_TRANSFER);
+			Ok( super::from_result( to_result(self.0.read(ofs, &mut dest[..len])).map(|count| count as u32) ) )
 			},
 		values::VFS_FILE_WRITEAT => {
 			let ofs: u64 = try!(args.get());
 			let src: Freeze<[u8]> = try!(args.get());
 			log_debug!("File::writeat({}, {:p}+{} bytes)", ofs, src.as_ptr(), src.len());
-			match self.0.write(ofs, &src)
-			{
-			Ok(count) => Ok(count as u64),
-			Err(e) => todo!("File::handle_syscall WRITEAT Error {:?}", e),
-			}
+			let len = ::core::cmp::min(src.len(), MAX_TRANSFER);
+			Ok( super::from_result( to_result(self.0.write(ofs, &src[..len])).map(|count| count as u32) ) )
 			},
 		values::VFS_FILE_MEMMAP => {
 			let ofs: u64 = try!(args.get());
@@ -211,24 +325,56 @@ impl objects::Object for File
 				};
 			log_debug!("VFS_FILE_MEMMAP({:#x}, {:#x}+{}, {:?})", ofs, addr, size, mode);
 			
-			match self.0.memory_map(addr, ofs, size, mode)
-			{
-			Ok(h) => {
-				// TODO: I would like the map handle to be avaliable, but I'd like the user to be able to "forget" it
-				// (so it becomes an indelible part of the address space).
-				// - That would likely need a new system call similar to Drop
-				// - XXX: The handle here has borrow of the file handle, so can't be stored as-is
-				::core::mem::forget(h);
-				Ok(0)
-				},
-			Err(e) => todo!("File::handle_syscall MEMMAP Error {:?}", e),
-			}
+			let res = to_result(self.0.memory_map(addr, ofs, size, mode))
+				.map(|h| {
+					// TODO: I would like the map handle to be avaliable, but I'd like the user to be able to "forget" it
+					// (so it becomes an indelible part of the address space).
+					// - That would likely need a new system call similar to Drop
+					// - XXX: The handle here has borrow of the file handle, so can't be stored as-is
+					// NOTE: Writeback mappings are flushed when the file handle is dropped
+					::core::mem::forget(h);
+					0u32
+					});
+			Ok( super::from_result(res) )
+			},
+		values::VFS_FILE_LOCKRANGE => {
+			let ofs: u64 = try!(args.get());
+			let len: u64 = try!(args.get());
+			let exclusive: bool = try!(args.get());
+			log_debug!("VFS_FILE_LOCKRANGE({:#x}+{:#x}, excl={})", ofs, len, exclusive);
+			Ok( super::from_result( to_result(self.0.lock_range(ofs, len, exclusive)).map(|_| 0u32) ) )
+			},
+		values::VFS_FILE_UNLOCKRANGE => {
+			let ofs: u64 = try!(args.get());
+			let len: u64 = try!(args.get());
+			log_debug!("VFS_FILE_UNLOCKRANGE({:#x}+{:#x})", ofs, len);
+			Ok( super::from_result( to_result(self.0.unlock_range(ofs, len)).map(|_| 0u32) ) )
+			},
+		values::VFS_FILE_SETSIZE => {
+			let size: u64 = try!(args.get());
+			log_debug!("VFS_FILE_SETSIZE({:#x})", size);
+			Ok( super::from_result( to_result(self.0.set_size(size)).map(|_| 0u32) ) )
 			},
 		_ => ::objects::object_has_no_such_method_ref("vfs::File", call),
 		}
 	}
-	fn bind_wait(&self, _flags: u32, _obj: &mut ::kernel::threads::SleepObject) -> u32 { 0 }
-	fn clear_wait(&self, _flags: u32, _obj: &mut ::kernel::threads::SleepObject) -> u32 { 0 }
+	fn bind_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_VFS_FILE_LOCKRELEASE != 0 {
+			self.0.bind_wait_lock(obj);
+			ret |= values::EV_VFS_FILE_LOCKRELEASE;
+		}
+		ret
+	}
+	fn clear_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_VFS_FILE_LOCKRELEASE != 0 {
+			if self.0.clear_wait_lock(obj) {
+				ret |= values::EV_VFS_FILE_LOCKRELEASE;
+			}
+		}
+		ret
+	}
 }
 
 
@@ -271,17 +417,50 @@ impl objects::Object for Dir
 			},
 		values::VFS_DIR_OPENPATH => {
 			let path: Freeze<[u8]> = try!(args.get());
+			let flags: u32 = try!(args.get());
 
 			let path = Path::new(&path);
-			log_debug!("VFS_DIR_OPENPATH({:?})", path);
+			log_debug!("VFS_DIR_OPENPATH({:?}, {:#x})", path, flags);
+			let flags = ::kernel::vfs::node::OpenFlags {
+				no_follow: flags & ::values::VFS_OPENPATH_NOFOLLOW != 0,
+				beneath: flags & ::values::VFS_OPENPATH_BENEATH != 0,
+				};
 			super::from_result(
-				to_result( self.handle.open_child_path(path) )
+				to_result( self.handle.open_child_path(path, flags) )
 					.map( |h| objects::new_object(Node(h)) )
 				)
 			},
+		values::VFS_DIR_CREATEFILE => {
+			let name: Freeze<[u8]> = try!(args.get());
+
+			let name = ::kernel::lib::byte_str::ByteStr::new(&*name);
+			log_debug!("VFS_DIR_CREATEFILE({:?})", name);
+
+			super::from_result(
+				to_result( self.handle.create_file(name) )
+					.map( |h| objects::new_object(Node(h)) )
+				)
+			},
+		values::VFS_DIR_RENAME => {
+			let old_name: Freeze<[u8]> = try!(args.get());
+			let new_name: Freeze<[u8]> = try!(args.get());
+
+			let old_name = ::kernel::lib::byte_str::ByteStr::new(&*old_name);
+			let new_name = ::kernel::lib::byte_str::ByteStr::new(&*new_name);
+			log_debug!("VFS_DIR_RENAME({:?}, {:?})", old_name, new_name);
+
+			super::from_result( to_result( self.handle.rename(old_name, new_name) ).map(|_| 0u32) )
+			},
 		values::VFS_DIR_ENUMERATE => {
 			objects::new_object( DirIter::new( self.handle.clone() ) ) as u64
 			},
+		values::VFS_DIR_WATCH => {
+			log_debug!("VFS_DIR_WATCH()");
+			super::from_result(
+				to_result( self.handle.watch() )
+					.map( |w| objects::new_object(Watch(w)) )
+				)
+			},
 		_ => return ::objects::object_has_no_such_method_ref("vfs::Dir", call),
 		})
 	}
@@ -344,6 +523,66 @@ impl objects::Object for DirIter
 
 type DirEnt = (::kernel::vfs::node::InodeId, ::kernel::lib::byte_str::ByteString);
 
+struct Watch(::kernel::vfs::watch::Watch);
+impl objects::Object for Watch
+{
+	const CLASS: u16 = values::CLASS_VFS_WATCH;
+	fn class(&self) -> u16 { Self::CLASS }
+	fn as_any(&self) -> &Any { self }
+	fn try_clone(&self) -> Option<u32> {
+		None
+	}
+	fn handle_syscall_ref(&self, call: u16, args: &mut Args) -> Result<u64,Error> {
+		Ok(match call
+		{
+		values::VFS_WATCH_READ => {
+			use kernel::vfs::watch::ChangeKind;
+			let mut name: FreezeMut<[u8]> = try!(args.get());
+			log_debug!("VFS_WATCH_READ({:p}+{})", name.as_ptr(), name.len());
+
+			match self.0.pop(name.len())
+			{
+			// Name doesn't fit, report the required length (with a kind of zero)
+			Err(len) => (len as u64) << 8,
+			Ok(None) => 0,
+			Ok(Some(rec)) => {
+				let kind = match rec.kind
+					{
+					ChangeKind::Created     => ::values::VFSChangeKind::Created,
+					ChangeKind::Removed     => ::values::VFSChangeKind::Removed,
+					ChangeKind::RenamedFrom => ::values::VFSChangeKind::RenamedFrom,
+					ChangeKind::RenamedTo   => ::values::VFSChangeKind::RenamedTo,
+					ChangeKind::SizeChanged => ::values::VFSChangeKind::SizeChanged,
+					ChangeKind::Overflow    => ::values::VFSChangeKind::Overflow,
+					};
+				let len = rec.name.len();
+				name[.. len].clone_from_slice( &rec.name.as_bytes()[.. len] );
+				(Into::<u8>::into(kind) as u64) | (len as u64) << 8
+				},
+			}
+			},
+		_ => return ::objects::object_has_no_such_method_ref("vfs::Watch", call),
+		})
+	}
+	fn bind_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_VFS_WATCH_CHANGE != 0 {
+			self.0.bind_wait(obj);
+			ret |= values::EV_VFS_WATCH_CHANGE;
+		}
+		ret
+	}
+	fn clear_wait(&self, flags: u32, obj: &mut ::kernel::threads::SleepObject) -> u32 {
+		let mut ret = 0;
+		if flags & values::EV_VFS_WATCH_CHANGE != 0 {
+			if self.0.clear_wait(obj) {
+				ret |= values::EV_VFS_WATCH_CHANGE;
+			}
+		}
+		ret
+	}
+}
+
 struct DirInner {
 	lower_ofs: usize,
 	
@@ -77,7 +77,14 @@ impl Interface for Mmio
 		}
 		else {
 			let size = if size == 0 || size > max_size { max_size } else { size };
-			let queue = Queue::new(idx, size);
+			let queue = match Queue::new(idx, size)
+				{
+				Ok(v) => v,
+				Err(e) => {
+					log_error!("Unable to allocate queue #{} (size {}): {:?}", idx, size, e);
+					return None;
+					},
+				};
 
 			// SAFE: Unique access, so no race possible
 			unsafe {
@@ -85,21 +85,21 @@ impl Queue
 		Self::get_first_size(count) + ((second + 0xFFF) & !0xFFF)
 	}
 
-	pub fn new(idx: usize, count: usize) -> Queue
+	pub fn new(idx: usize, count: usize) -> Result<Queue, ::kernel::memory::virt::MapError>
 	{
 		let n_pages = Self::get_alloc_size(count) / ::kernel::PAGE_SIZE;
 		assert!(n_pages > 0);
-		Queue {
+		Ok(Queue {
 			idx: idx,
 			size: count,
-			buffer: ::kernel::memory::virt::alloc_dma(32+12, n_pages, "VirtIO").expect("TODO: Handle alloc failure VirtIO queue"),
+			buffer: try!( ::kernel::memory::virt::alloc_dma(32+12, n_pages, "VirtIO") ),
 			descriptors_lock: Default::default(),
 			avail_ring_lock: Default::default(),
 
 			last_seen_used: AtomicUsize::new(0),
 			interrupt_flag: ::kernel::sync::Semaphore::new(0, count as isize),
 			avail_ring_res: (0..count).map(|_| AtomicUsize::new(0)).collect(),
-			}
+			})
 	}
 
 	pub fn check_interrupt(&self) {
@@ -79,7 +79,7 @@ fn sysinit()
 	Err(e) => {
 		panic!("Unable to open /system volume {}: {}", sysdisk, e);
 		},
-	Ok(vh) => match mount::mount("/system".as_ref(), vh, "", &[])
+	Ok(vh) => match mount::mount("/system".as_ref(), vh, "", &["ro"])
 		{
 		Ok(_) => {},
 		Err(e) => {
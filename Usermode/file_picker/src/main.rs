@@ -0,0 +1,294 @@
+// Tifflin OS - File picker
+// - By John Hodge (thePowersGang)
+//
+// file_picker/src/main.rs
+//! File open/save dialog, started by the handle server for `PickFile` requests
+//!
+//! Arguments: Picker mode (a digit, see `protocol::PickFileMode`), the description shown to the user, then the
+//! ID of the window the dialog is modal to (0 for none).
+//! The chosen file is opened with the requested mode and sent over the "Result" channel, closing the dialog
+//! without choosing a file cancels the request.
+
+extern crate wtk;
+#[macro_use(kernel_log)]
+extern crate syscalls;
+extern crate async;
+extern crate handle_server;
+
+use std::cell::RefCell;
+use std::ffi::{OsStr,OsString};
+use handle_server::protocol::{self, PickFileMode};
+use syscalls::vfs::{Dir,File,FileOpenMode};
+
+fn main()
+{
+	::wtk::initialise();
+	// Exit when the session ends
+	let _ = ::std::rt::control::start_handler(::std::rt::control::default_handler);
+
+	let root: Dir = ::syscalls::threads::S_THIS_PROCESS.receive_object("RwRoot").expect("No FS root handle passed");
+	let result: ::syscalls::ipc::RpcChannel = ::syscalls::threads::S_THIS_PROCESS.receive_object("Result").expect("No result channel passed");
+
+	let mut args = ::std::env::args_os();
+	let mode = args.next()
+		.and_then(|a| a.as_bytes().get(0).and_then(|&b| PickFileMode::try_from(b.wrapping_sub(b'0'))))
+		.expect("Invalid picker mode");
+	let description = args.next()
+		.map(|a| String::from_utf8_lossy(a.as_bytes()).into_owned())
+		.unwrap_or(String::new());
+	let owner = args.next()
+		.and_then(|a| ::std::str::from_utf8(a.as_bytes()).ok().and_then(|s| s.parse::<u32>().ok()))
+		.unwrap_or(0);
+
+	let dialog = Dialog::new(&root, mode, result);
+	dialog.populate();
+
+	let mut filename = ::wtk::TextInput::new();
+	filename.set_shadow(match mode
+		{
+		PickFileMode::Create => "New file name",
+		_ => "File name",
+		});
+	filename.bind_submit(|input, _win| dialog.pick_name(&input.get_content()));
+
+	let cancel_button = ::wtk::Button::new(
+		::wtk::Label::new("Cancel", ::wtk::Colour::theme_text()),
+		|_btn, _win| dialog.cancel()
+		);
+
+	let bottom = ::wtk::StaticBox::new_horiz((
+		::wtk::BoxEle::expand( &filename ),
+		::wtk::BoxEle::fixed( 1, () ),
+		::wtk::BoxEle::fixed( 64, &cancel_button ),
+		));
+	let description_label = ::wtk::Label::new(&description, ::wtk::Colour::theme_text());
+	let vbox = ::wtk::StaticBox::new_vert((
+		::wtk::BoxEle::fixed( 16, &description_label ),
+		::wtk::BoxEle::expand( &dialog ),
+		::wtk::BoxEle::fixed( 16, &bottom ),
+		));
+
+	let mut window = ::wtk::Window::new_def("File picker", &vbox).unwrap();
+	window.set_title(match mode
+		{
+		PickFileMode::Create => "Save File",
+		_ => "Open File",
+		});
+	window.set_dims(320, 240);
+	window.taborder_add( 1, &dialog );
+	window.taborder_add( 2, &filename );
+	window.focus( &dialog );
+	if owner != 0 {
+		if let Err(_) = window.set_modal_owner(owner) {
+			kernel_log!("Unable to make the picker modal to window {:#x}", owner);
+		}
+	}
+	window.show();
+
+	::async::idle_loop(&mut [
+		&mut window,
+		]);
+}
+
+/// Directory listing and result handling
+struct Dialog<'a>
+{
+	root: &'a Dir,
+	mode: PickFileMode,
+	result: ::syscalls::ipc::RpcChannel,
+
+	/// Path of the displayed directory (relative to `root`)
+	cur_path: RefCell<Vec<OsString>>,
+	list: ::wtk::ListView<[&'static str; 2], Entry>,
+}
+
+impl<'a> Dialog<'a>
+{
+	fn new(root: &'a Dir, mode: PickFileMode, result: ::syscalls::ipc::RpcChannel) -> Dialog<'a> {
+		Dialog {
+			root: root,
+			mode: mode,
+			result: result,
+			cur_path: Default::default(),
+			list: ::wtk::ListView::new(["T", "Filename"]),
+		}
+	}
+
+	/// Path to an item in the current directory
+	fn path_to(&self, name: Option<&OsStr>) -> Vec<u8> {
+		let mut rv = Vec::new();
+		for seg in self.cur_path.borrow().iter().map(|x| &**x).chain(name) {
+			rv.push(b'/');
+			rv.extend_from_slice(seg.as_bytes());
+		}
+		rv
+	}
+	fn cur_dir(&self) -> Result<Dir, ::syscalls::vfs::Error> {
+		if self.cur_path.borrow().len() == 0 {
+			Ok( self.root.clone() )
+		}
+		else {
+			self.root.open_child_path(&self.path_to(None)).and_then(|n| n.into_dir())
+		}
+	}
+
+	/// Fill the list with the contents of the current directory
+	fn populate(&self) {
+		let dir = match self.cur_dir()
+			{
+			Ok(v) => v,
+			Err(e) => {
+				kernel_log!("Unable to open directory - {:?}", e);
+				return ;
+				},
+			};
+		self.list.clear();
+		if self.cur_path.borrow().len() > 0 {
+			self.list.append_item( Entry::new(b"..", true) );
+		}
+		let mut iter = match dir.enumerate()
+			{
+			Ok(v) => v,
+			Err(_) => return,
+			};
+		let mut namebuf = [0; 512];
+		while let Ok(Some(name)) = iter.read_ent(&mut namebuf)
+		{
+			if name == b"." || name == b".." {
+				continue ;
+			}
+			let is_dir = match dir.open_child(name)
+				{
+				Ok(n) => n.class() == ::syscalls::vfs::NodeType::Dir,
+				Err(_) => false,
+				};
+			self.list.append_item( Entry::new(name, is_dir) );
+		}
+	}
+
+	/// Pick a file by name in the current directory (creating it in `Create` mode)
+	fn pick_name(&self, name: &str) {
+		if name == "" || name.contains('/') {
+			return ;
+		}
+		let node = match self.cur_dir()
+			{
+			Ok(dir) => match self.mode
+				{
+				PickFileMode::Create => dir.create_file(name).or_else(|_| dir.open_child(name)),
+				_ => dir.open_child(name),
+				},
+			Err(e) => Err(e),
+			};
+		match node
+		{
+		Ok(n) => self.pick(&self.path_to(Some(OsStr::new(name.as_bytes()))), n),
+		Err(e) => kernel_log!("Unable to open '{}' - {:?}", name, e),
+		}
+	}
+
+	/// Open the selected file with the requested mode and return it to the handle server (exits the process)
+	fn pick(&self, path: &[u8], node: ::syscalls::vfs::Node) {
+		let file = match self.mode
+			{
+			PickFileMode::ReadOnly => node.into_file(FileOpenMode::ReadOnly),
+			PickFileMode::ReadWrite => node.into_file(FileOpenMode::ExclRW),
+			// Saving replaces the existing contents (new and empty files are left alone, not all filesystems can truncate)
+			PickFileMode::Create => node.into_file(FileOpenMode::ExclRW).and_then(|f| if f.get_size() == 0 { Ok(f) } else { f.set_size(0).map(|_| f) }),
+			PickFileMode::OptionalWrite => match self.root.open_child_path(path)
+				{
+				// Fall back to read-only if the file can't be written
+				Ok(n) => n.into_file(FileOpenMode::ExclRW).or_else(|_| node.into_file(FileOpenMode::ReadOnly)),
+				Err(e) => Err(e),
+				},
+			};
+		match file
+		{
+		Ok(file) => self.finish(file, path),
+		Err(e) => kernel_log!("Unable to open {:?} - {:?}", OsStr::new(path), e),
+		}
+	}
+
+	fn finish(&self, file: File, path: &[u8]) -> ! {
+		if let Err(e) = self.result.send_obj( protocol::RspOpenedFile::new(path).into(), file ) {
+			kernel_log!("Unable to send picked file - {:?}", e);
+		}
+		::syscalls::threads::exit(0);
+	}
+	fn cancel(&self) -> ! {
+		let _ = self.result.send( protocol::RspError::new(protocol::ERR_CANCELLED, "Cancelled").into() );
+		::syscalls::threads::exit(0);
+	}
+}
+
+impl<'a> ::wtk::Element for Dialog<'a>
+{
+	fn render(&self, surface: ::wtk::surface::SurfaceView, force: bool) {
+		self.list.render(surface, force);
+	}
+	fn resize(&self, _w: u32, _h: u32) {
+	}
+	fn handle_event(&self, ev: ::wtk::InputEvent, _win: &mut ::wtk::WindowTrait) -> bool {
+		self.list.handle_event(
+			ev,
+			|ent| {
+				if ent.is_dir {
+					let name = ent.name.clone();
+					Some(move || {
+						{
+							let mut ps = self.cur_path.borrow_mut();
+							if name.as_bytes() == b".." {
+								ps.pop();
+							}
+							else {
+								ps.push(name);
+							}
+						}
+						self.populate()
+						})
+				}
+				else {
+					let path = self.path_to(Some(&*ent.name));
+					match self.root.open_child_path(&path)
+					{
+					Ok(n) => self.pick(&path, n),
+					Err(e) => kernel_log!("Unable to open {:?} - {:?}", ent.name, e),
+					}
+					None
+				}
+			})
+	}
+	fn with_element_at_pos(&self, pos: ::wtk::geom::PxPos, _dims: ::wtk::geom::PxDims, f: ::wtk::WithEleAtPosCb) -> bool {
+		f(self, pos)
+	}
+}
+
+struct Entry
+{
+	is_dir: bool,
+	name: OsString,
+	display_name: String,
+}
+impl Entry
+{
+	fn new(name: &[u8], is_dir: bool) -> Entry {
+		Entry {
+			is_dir: is_dir,
+			name: OsString::from(name),
+			display_name: String::from_utf8_lossy(name).into_owned(),
+		}
+	}
+}
+impl ::wtk::listview::Row for Entry {
+	fn count(&self) -> usize {
+		2
+	}
+	fn value(&self, col: usize) -> &str {
+		match col
+		{
+		0 => if self.is_dir { "d" } else { "f" },
+		1 => &self.display_name,
+		_ => "",
+		}
+	}
+}
@@ -3,7 +3,7 @@
 //
 ///
 use std::ffi::{OsString,OsStr};
-use listview::ListView;
+use wtk::ListView;
 use std::cell::RefCell;
 use std::fs::Path;
 use wtk::WindowTrait;
@@ -15,6 +15,8 @@ pub struct FileList<'a>
 	on_chdir: Box<Fn(&mut WindowTrait, &Path) + 'a>,
 
 	cur_paths: RefCell<Vec<OsString>>,
+	/// Currently displayed directory, and a watch on it
+	watch: RefCell<Option<(::syscalls::vfs::Dir, ::syscalls::vfs::DirWatch)>>,
 	
 	list: ListView<[&'static str; 2], FileEnt>,
 }
@@ -28,12 +30,22 @@ impl<'a> FileList<'a>
 			on_open: Box::new(|_,_,_|()),
 			on_chdir: Box::new(|_,_|()),
 			cur_paths: Default::default(),
+			watch: Default::default(),
 			list: ListView::new(["T", "Filename"]),
 		}
 	}
 	
 
 	pub fn populate(&self, dir: &::syscalls::vfs::Dir) {
+		// Create the watch before enumerating, so no changes are missed
+		*self.watch.borrow_mut() = match dir.watch()
+			{
+			Ok(w) => Some( (dir.clone(), w) ),
+			Err(e) => {
+				kernel_log!("Unable to watch directory - {:?}", e);
+				None
+				},
+			};
 		let mut iter = match dir.enumerate()
 			{
 			Ok(v) => v,
@@ -47,6 +59,59 @@ impl<'a> FileList<'a>
 		}
 	}
 
+	/// Wait item for changes to the displayed directory
+	pub fn wait_item(&self) -> Option<::syscalls::WaitItem> {
+		self.watch.borrow().as_ref().map(|&(_, ref w)| w.wait_change())
+	}
+
+	/// Apply changes reported by the directory watch, returns true if the list changed
+	pub fn refresh(&self) -> bool {
+		use syscalls::vfs::ChangeKind;
+		let rescan_dir = {
+			let mut wh = self.watch.borrow_mut();
+			let &mut (ref dir, ref mut watch) = match *wh
+				{
+				Some(ref mut v) => v,
+				None => return false,
+				};
+			let mut namebuf = vec![0; 256];
+			let mut changed = false;
+			let mut rescan = false;
+			loop
+			{
+				let (kind, name_len) = match watch.read_change(&mut namebuf)
+					{
+					Ok(Some(v)) => v,
+					Ok(None) => break,
+					// Name didn't fit, grow the buffer and try again
+					Err(len) => { namebuf.resize(len, 0); continue },
+					};
+				let name = &namebuf[..name_len];
+				match kind
+				{
+				ChangeKind::Created | ChangeKind::RenamedTo => {
+					self.list.append_item( FileEnt::new(dir, name) );
+					changed = true;
+					},
+				ChangeKind::Removed | ChangeKind::RenamedFrom => {
+					self.list.remove_items(|ent| { let n: &OsStr = &ent.name; n.as_bytes() == name });
+					changed = true;
+					},
+				// Sizes are not displayed
+				ChangeKind::SizeChanged => {},
+				ChangeKind::Overflow => rescan = true,
+				}
+			}
+			if !rescan {
+				return changed;
+			}
+			dir.clone()
+			};
+		// Changes were lost, fall back to a full enumeration
+		self.populate(&rescan_dir);
+		true
+	}
+
 	/// Bind to "Opening" a file (double-click or select+enter)
 	pub fn on_open<F: 'a>(&mut self, f: F)
 	where
@@ -158,7 +223,7 @@ impl FileEnt
 		}
 	}
 }
-impl ::listview::Row for FileEnt {
+impl ::wtk::listview::Row for FileEnt {
 	fn count(&self) -> usize {
 		2
 	}
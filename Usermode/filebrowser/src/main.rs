@@ -8,19 +8,15 @@ extern crate vec_ring;
 #[macro_use(kernel_log)]
 extern crate syscalls;
 extern crate loader;
+extern crate async;
 
-mod listview;
 mod filelist;
 
-mod iterx {
-	pub fn zip<A: IntoIterator, B: IntoIterator>(a: A, b: B) -> ::std::iter::Zip<A::IntoIter, B::IntoIter> {
-		Iterator::zip(a.into_iter(), b.into_iter())
-	}
-}
-
 fn main()
 {
 	::wtk::initialise();
+	// Exit when the session ends
+	let _ = ::std::rt::control::start_handler(::std::rt::control::default_handler);
 
 	let root_handle: ::syscalls::vfs::Dir = ::syscalls::threads::S_THIS_PROCESS.receive_object("ro:/").expect("No FS root handle passed");
 	//let root_handle = ::syscalls::vfs::Dir::open("/").unwrap();
@@ -37,7 +33,9 @@ fn main()
 	window.focus(&fl);
 	window.show();
 
-	window.idle_loop();
+	::async::idle_loop(&mut [
+		&mut Browser { window: window, list: &fl },
+		]);
 }
 
 fn get_app_exe(name: &[u8]) -> Result<::syscalls::vfs::File, ()> {
@@ -63,3 +61,31 @@ fn view_file(p: &::std::fs::Path, nh: ::syscalls::vfs::Node) {
 	Err(_e) => {},
 	}
 }
+
+/// Wait controller combining the window with the file list's directory watch
+struct Browser<'a, 'b: 'a>
+{
+	window: ::wtk::Window<'a, ::wtk::decorator::Standard>,
+	list: &'a ::filelist::FileList<'b>,
+}
+impl<'a, 'b> ::async::WaitController for Browser<'a, 'b>
+{
+	fn get_count(&self) -> usize {
+		::async::WaitController::get_count(&self.window) + if self.list.wait_item().is_some() { 1 } else { 0 }
+	}
+	fn populate(&self, cb: &mut FnMut(::syscalls::WaitItem)) {
+		::async::WaitController::populate(&self.window, cb);
+		if let Some(wi) = self.list.wait_item() {
+			cb(wi);
+		}
+	}
+	fn handle(&mut self, events: &[::syscalls::WaitItem]) {
+		let n = ::async::WaitController::get_count(&self.window);
+		::async::WaitController::handle(&mut self.window, &events[..n]);
+		// NOTE: If the directory changed while handling window events, this slot refers to the old watch.
+		// - That's harmless, as `refresh` only reads records that are already queued.
+		if events.len() > n && events[n].flags != 0 && self.list.refresh() {
+			self.window.redraw();
+		}
+	}
+}
@@ -31,6 +31,8 @@ enum ViewerMode {
 fn main()
 {
 	::wtk::initialise();
+	// Exit when the session ends
+	let _ = ::std::rt::control::start_handler(::std::rt::control::default_handler);
 	let mut file: ::syscalls::vfs::File = match ::syscalls::threads::S_THIS_PROCESS.receive_object("file")
 		{
 		Ok(v) => v,
@@ -0,0 +1,155 @@
+// Tifflin OS - handle_server
+// - By John Hodge (thePowersGang)
+//
+// handle_server/src/apps.rs
+//! Registered applications and the executable search path
+use handle_server::manifest::Manifest;
+use syscalls::vfs::{Dir,File,FileOpenMode};
+
+/// Directory containing application manifests (`<id>.app`)
+const MANIFEST_DIR: &'static str = "/system/Tifflin/apps";
+/// List of directories searched for executables (one per line)
+const SEARCH_PATH_FILE: &'static str = "/system/Tifflin/config/search_paths";
+/// Search path used if the search path file can't be read
+const DEFAULT_SEARCH_PATH: &'static [u8] = b"/system/Tifflin/bin";
+/// Upper limit on the size of configuration files
+const MAX_FILE_SIZE: u64 = 4096;
+
+pub struct Application
+{
+	/// Identifier (manifest file name without the extension)
+	pub id: Vec<u8>,
+	pub manifest_path: Vec<u8>,
+	pub binary: Vec<u8>,
+}
+
+pub struct Registry
+{
+	search_paths: Vec<Vec<u8>>,
+	apps: Vec<Application>,
+}
+
+impl Registry
+{
+	/// Load the search path and application manifests
+	pub fn load(root: &Dir) -> Registry
+	{
+		let search_paths = match read_file(root, SEARCH_PATH_FILE.as_bytes())
+			{
+			Ok(data) => data.split(|&b| b == b'\n')
+				.map(|l| if l.last() == Some(&b'\r') { &l[..l.len()-1] } else { l })
+				.filter(|l| l.len() > 0 && l[0] != b'#')
+				.map(|l| l.to_vec())
+				.collect(),
+			Err(e) => {
+				kernel_log!("NOTICE: Unable to read search paths, using default - {:?}", e);
+				vec![ DEFAULT_SEARCH_PATH.to_vec() ]
+				},
+			};
+
+		Registry {
+			search_paths: search_paths,
+			apps: load_manifests(root),
+		}
+	}
+
+	pub fn get(&self, index: usize) -> Option<&Application> {
+		self.apps.get(index)
+	}
+
+	/// Locate an executable, either a registered application ID or a name in the search path
+	pub fn find_executable(&self, root: &Dir, name: &[u8]) -> Option<(Vec<u8>, File)>
+	{
+		if let Some(app) = self.apps.iter().find(|a| a.id == name)
+		{
+			return match open_exec(root, &app.binary)
+				{
+				Ok(fh) => Some( (app.binary.clone(), fh) ),
+				Err(e) => {
+					kernel_log!("NOTICE: Unable to open binary for '{:?}' - {:?}", ::std::ffi::OsStr::new(name), e);
+					None
+					},
+				};
+		}
+
+		// Only bare names are searched for (paths would allow escaping the search path)
+		if name.len() == 0 || name.contains(&b'/') {
+			return None;
+		}
+		for dir in self.search_paths.iter()
+		{
+			let mut path = dir.clone();
+			path.push(b'/');
+			path.extend_from_slice(name);
+			if let Ok(fh) = open_exec(root, &path) {
+				return Some( (path, fh) );
+			}
+		}
+		None
+	}
+}
+
+fn load_manifests(root: &Dir) -> Vec<Application>
+{
+	let mut rv = Vec::new();
+	let dir = match root.open_child_path(MANIFEST_DIR).and_then(|n| n.into_dir())
+		{
+		Ok(v) => v,
+		Err(e) => {
+			kernel_log!("NOTICE: Unable to open application directory - {:?}", e);
+			return rv;
+			},
+		};
+	let mut iter = match dir.enumerate()
+		{
+		Ok(v) => v,
+		Err(_) => return rv,
+		};
+	let mut namebuf = [0; 256];
+	while let Ok(Some(name)) = iter.read_ent(&mut namebuf)
+	{
+		if !name.ends_with(b".app") || name.len() == 4 {
+			continue ;
+		}
+		let mut path = MANIFEST_DIR.as_bytes().to_vec();
+		path.push(b'/');
+		path.extend_from_slice(name);
+
+		let data = match read_file(root, &path)
+			{
+			Ok(v) => v,
+			Err(e) => {
+				kernel_log!("NOTICE: Unable to read manifest {:?} - {:?}", ::std::ffi::OsStr::new(name), e);
+				continue ;
+				},
+			};
+		let binary = match Manifest::parse(&data)
+			{
+			Ok(m) => m.binary().to_vec(),
+			Err(e) => {
+				kernel_log!("NOTICE: Invalid manifest {:?} - {:?}", ::std::ffi::OsStr::new(name), e);
+				continue ;
+				},
+			};
+		rv.push(Application {
+			id: name[.. name.len() - 4].to_vec(),
+			manifest_path: path,
+			binary: binary,
+			});
+	}
+	rv
+}
+
+fn open_exec(root: &Dir, path: &[u8]) -> Result<File, ::syscalls::vfs::Error> {
+	root.open_child_path(path).and_then(|x| x.into_file(FileOpenMode::Execute))
+}
+
+fn read_file(root: &Dir, path: &[u8]) -> Result<Vec<u8>, ::syscalls::vfs::Error>
+{
+	let file = try!( root.open_child_path(path).and_then(|x| x.into_file(FileOpenMode::ReadOnly)) );
+	let size = ::std::cmp::min(file.get_size(), MAX_FILE_SIZE) as usize;
+	let mut data = vec![0; size];
+	let len = try!( file.read_at(0, &mut data) );
+	data.truncate(len);
+	Ok(data)
+}
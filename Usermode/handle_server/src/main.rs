@@ -12,72 +12,307 @@ extern crate handle_server;
 
 use handle_server::protocol;
 
+mod apps;
+mod picker;
+
 struct Connection
 {
 	name: String,
 	channel: ::syscalls::ipc::RpcChannel,
+	/// File picker dialog open for this connection
+	picker: Option<picker::Picker>,
+}
+/// A registered named service
+struct Service
+{
+	name: String,
+	/// Channel used to pass new connections to the service
+	channel: ::syscalls::ipc::RpcChannel,
+	/// Connections waiting for the service to receive the previous one (oldest first)
+	pending: Vec<PendingConnection>,
+}
+/// A connection to a service that hasn't been passed to the service yet
+struct PendingConnection
+{
+	client_name: String,
+	/// Service's end of the connection
+	channel: ::syscalls::ipc::RpcChannel,
+}
+
+/// Maximum number of connections queued for a busy service
+const MAX_PENDING_CONNECTIONS: usize = 8;
+
+impl Service
+{
+	/// Handle a wakeup on the service channel, and pass on queued connections
+	///
+	/// Returns false if the service has closed
+	fn poll(&mut self) -> bool {
+		// Services are only sent messages, so a wakeup is usually the service going away (or receiving a connection)
+		match self.channel.try_receive()
+		{
+		Ok(_) => kernel_log!("NOTICE: Unexpected message from service '{}'", self.name),
+		Err(::syscalls::ipc::RxError::NoMessage) => {},
+		Err(::syscalls::ipc::RxError::ConnectionClosed) => return false,
+		}
+		self.flush_pending()
+	}
+	/// Pass queued connections to the service (in order) until its channel is full
+	///
+	/// Returns false if the service has closed
+	fn flush_pending(&mut self) -> bool {
+		while self.pending.len() > 0
+		{
+			let p = self.pending.remove(0);
+			match self.channel.try_send_obj( protocol::RspIncomingConnection::new(p.client_name.as_bytes()).into(), p.channel )
+			{
+			Ok( () ) => {},
+			Err( (::syscalls::ipc::SendError::Full, channel) ) => {
+				self.pending.insert(0, PendingConnection { client_name: p.client_name, channel: channel });
+				break ;
+				},
+			Err( (::syscalls::ipc::SendError::ConnectionClosed, _) ) => return false,
+			// - Dropping the channel closes the client's end
+			Err( (e, _) ) => kernel_log!("NOTICE: Failed to pass connection from '{}' to service '{}' - {:?}", p.client_name, self.name, e),
+			}
+		}
+		true
+	}
+}
+
+impl Connection
+{
+	/// Send a response (the client waits for each response, so this should never find the channel full)
+	fn reply(&self, msg: ::syscalls::ipc::RpcMessage) {
+		if let Err(e) = self.channel.send(msg) {
+			kernel_log!("NOTICE: Failed to reply to '{}' - {:?}", self.name, e);
+		}
+	}
+	fn reply_obj<T: ::syscalls::Object>(&self, msg: ::syscalls::ipc::RpcMessage, obj: T) {
+		if let Err(e) = self.channel.send_obj(msg, obj) {
+			kernel_log!("NOTICE: Failed to reply to '{}' - {:?}", self.name, e);
+		}
+	}
 }
 
 fn main()
 {
 	// handle_server gets the read-write root handle for the session user
 	let filesystem_root: ::syscalls::vfs::Dir = ::syscalls::threads::S_THIS_PROCESS.receive_object("RwRoot").expect("Failed to receive FS root");
+	// - and the session's window group (for dialogs)
+	let have_gui = match ::syscalls::threads::S_THIS_PROCESS.receive_object("guigrp")
+		{
+		Ok(grp) => { ::syscalls::gui::set_group(grp); true },
+		Err(e) => {
+			kernel_log!("NOTICE: No window group passed, file picking unavailable - {}", e);
+			false
+			},
+		};
+
+	let registry = apps::Registry::load(&filesystem_root);
 
 	// Active handle set - pre-populated with connection to leader
 	let mut handles = vec![
 		Connection {
 			name: String::from("Leader"),
 			channel: ::syscalls::threads::S_THIS_PROCESS.receive_object("HsChan").expect("Failed to receive leader channel"),
+			picker: None,
 		}
 		];
-
-	let mut waits: Vec<_> = handles.iter().map(|x| x.channel.wait_rx()).collect();
+	let mut services: Vec<Service> = Vec::new();
 
 	loop
 	{
+		let mut waits: Vec<_> = handles.iter().map(|x| x.channel.wait_rx())
+			.chain( services.iter().map(|x| x.channel.wait_rx()) )
+			.chain( services.iter().filter(|x| x.pending.len() > 0).map(|x| x.channel.wait_tx()) )
+			.chain( handles.iter().filter_map(|x| x.picker.as_ref()).map(|p| p.wait_result()) )
+			.chain( Some(::syscalls::threads::S_THIS_PROCESS.wait_control()) )
+			.collect();
 		::syscalls::threads::wait(&mut waits, !0);
-		for conn in handles.iter()
+
+		// The session leader asks the handle server to exit when the session ends
+		while let Some(n) = ::syscalls::threads::S_THIS_PROCESS.recv_control()
+		{
+			match n
+			{
+			::syscalls::threads::Notification::TerminateRequest => {
+				kernel_log!("Exit requested, shutting down");
+				return ;
+				},
+			_ => {},
+			}
+		}
+
+		let mut i = 0;
+		while i < services.len()
 		{
-			let (buffer, obj) = match conn.channel.try_receive()
+			if services[i].poll() {
+				i += 1;
+			}
+			else {
+				kernel_log!("Service '{}' closed", services[i].name);
+				services.remove(i);
+			}
+		}
+
+		// Forward results from file picker dialogs
+		for conn in handles.iter_mut()
+		{
+			let result = match conn.picker
 				{
-				Ok(v) => v,
-				Err(::syscalls::ipc::RxError::NoMessage) => continue,
-				Err(::syscalls::ipc::RxError::ConnectionClosed) => panic!("TODO: Handle connection loss"),
+				Some(ref p) => p.poll(),
+				None => None,
 				};
-			match protocol::Request::try_from(buffer)
+			if let Some( (msg, file) ) = result
 			{
-			// Request to open an executable
-			Ok(protocol::Request::OpenExecutable(req)) => {
-				// TODO: Search a set of paths and registered applications.
-				let path = match req.name()
-					{
-					b"fileviewer" => b"/system/bin/fileviewer",
-					_ => {
-						conn.channel.send( protocol::RspError::new(0, "Unknown name").into() );
-						continue
-						},
-					};
-				match filesystem_root.open_child_path(path).and_then(|x| x.into_file(::syscalls::vfs::FileOpenMode::Execute))
+				conn.picker = None;
+				match file
 				{
-				Ok(fh) => {
-					conn.channel.send_obj( protocol::RspOpenedFile::new(path).into(), fh );
-					},
-				Err(_) => {
-					conn.channel.send( protocol::RspError::new(0, "Could not open executable file").into() );
+				Some(file) => conn.reply_obj(msg, file),
+				None => conn.reply(msg),
+				}
+			}
+		}
+
+		let mut i = 0;
+		while i < handles.len()
+		{
+			let (buffer, obj) = match handles[i].channel.try_receive()
+				{
+				Ok(v) => v,
+				Err(::syscalls::ipc::RxError::NoMessage) => { i += 1; continue },
+				Err(::syscalls::ipc::RxError::ConnectionClosed) => {
+					kernel_log!("Connection '{}' closed", handles[i].name);
+					handles.remove(i);
 					continue
 					},
-				}
+				};
+			handle_request(&mut handles[i], &mut services, &registry, &filesystem_root, have_gui, buffer, obj);
+			i += 1;
+		}
+	}
+}
+
+fn handle_request(conn: &mut Connection, services: &mut Vec<Service>, registry: &apps::Registry, filesystem_root: &::syscalls::vfs::Dir, have_gui: bool, buffer: ::syscalls::ipc::RpcMessage, obj: Option<::syscalls::AnyObject>)
+{
+	match protocol::Request::try_from(buffer)
+	{
+	// Request to open an executable
+	Ok(protocol::Request::OpenExecutable(req)) => {
+		match registry.find_executable(filesystem_root, req.name())
+		{
+		Some( (path, fh) ) => {
+			conn.reply_obj( protocol::RspOpenedFile::new(&path).into(), fh );
+			},
+		None => {
+			conn.reply( protocol::RspError::new(protocol::ERR_NOT_FOUND, "Unknown name").into() );
+			},
+		}
+		},
+	// Enumerate registered applications (one per request, the manifest is returned read-only)
+	Ok(protocol::Request::ListApplications(req)) => {
+		let app = match registry.get(req.index())
+			{
+			Some(v) => v,
+			None => {
+				conn.reply( protocol::RspError::new(protocol::ERR_NOT_FOUND, "End of list").into() );
+				return
 				},
-			// Request the user pick a file to open
-			Ok(protocol::Request::PickFile(req)) => {
-				// TODO: Spawn a "open file" dialog linked to the calling process
-				unimplemented!();
+			};
+		match filesystem_root.open_child_path(&app.manifest_path).and_then(|x| x.into_file(::syscalls::vfs::FileOpenMode::ReadOnly))
+		{
+		Ok(fh) => {
+			conn.reply_obj( protocol::RspApplication::new(&app.id).into(), fh );
+			},
+		Err(_) => {
+			conn.reply( protocol::RspError::new(protocol::ERR_GENERIC, "Could not open manifest").into() );
+			},
+		}
+		},
+	// Request the user pick a file to open
+	// - The dialog is made modal to the client's window (if it passed one)
+	Ok(protocol::Request::PickFile(req)) => {
+		if !have_gui {
+			conn.reply( protocol::RspError::new(protocol::ERR_GENERIC, "No GUI available").into() );
+			return
+		}
+		if conn.picker.is_some() {
+			conn.reply( protocol::RspError::new(protocol::ERR_BUSY, "Picker already open").into() );
+			return
+		}
+		match picker::Picker::spawn(filesystem_root, req.mode(), req.owner(), req.description_raw())
+		{
+		Ok(p) => conn.picker = Some(p),
+		Err(msg) => conn.reply( protocol::RspError::new(protocol::ERR_GENERIC, msg).into() ),
+		}
+		},
+	// Register a named service (the channel to pass connections over is attached)
+	Ok(protocol::Request::RegisterService(req)) => {
+		let name = match ::std::str::from_utf8(req.name())
+			{
+			Ok(v) if v != "" => v,
+			_ => {
+				conn.reply( protocol::RspError::new(protocol::ERR_BAD_VALUE, "Invalid service name").into() );
+				return
 				},
-			Err(e) => {
-				kernel_log!("NOTICE: Unknown request from '{}' - {}", conn.name, buffer[0]);
-				conn.channel.send( protocol::RspError::new(0, "Unknown request").into() );
+			};
+		let channel = match obj.map(|o| o.downcast::<::syscalls::ipc::RpcChannel>())
+			{
+			Some(Ok(c)) => c,
+			_ => {
+				conn.reply( protocol::RspError::new(protocol::ERR_BAD_VALUE, "No channel attached").into() );
+				return
 				},
-			}
+			};
+		if services.iter().any(|s| s.name == name) {
+			conn.reply( protocol::RspError::new(protocol::ERR_EXISTS, "Service already registered").into() );
+			return
+		}
+		kernel_log!("Service '{}' registered by '{}'", name, conn.name);
+		services.push(Service {
+			name: String::from(name),
+			channel: channel,
+			pending: Vec::new(),
+			});
+		conn.reply( protocol::RspServiceRegistered::new().into() );
+		},
+	// Connect to a named service (creates a new channel, and passes one end to each side)
+	// - If the service hasn't received the previous connection yet, this one is queued (the client can use its end immediately)
+	Ok(protocol::Request::ConnectService(req)) => {
+		let idx = match services.iter().position(|s| s.name.as_bytes() == req.name())
+			{
+			Some(v) => v,
+			None => {
+				conn.reply( protocol::RspError::new(protocol::ERR_NOT_FOUND, "No such service").into() );
+				return
+				},
+			};
+		if services[idx].pending.len() >= MAX_PENDING_CONNECTIONS {
+			conn.reply( protocol::RspError::new(protocol::ERR_BUSY, "Service busy").into() );
+			return
+		}
+		let (svc_end, client_end) = match ::syscalls::ipc::RpcChannel::new_pair()
+			{
+			Ok(v) => v,
+			Err(_) => {
+				conn.reply( protocol::RspError::new(protocol::ERR_GENERIC, "Unable to create channel").into() );
+				return
+				},
+			};
+		services[idx].pending.push(PendingConnection { client_name: conn.name.clone(), channel: svc_end });
+		if services[idx].flush_pending() {
+			conn.reply_obj( protocol::RspNewChannel::new().into(), client_end );
+		}
+		else {
+			kernel_log!("Service '{}' closed", services[idx].name);
+			services.remove(idx);
+			conn.reply( protocol::RspError::new(protocol::ERR_NOT_FOUND, "No such service").into() );
 		}
+		},
+	Err(e) => {
+		kernel_log!("NOTICE: Unknown request from '{}' - {}", conn.name, buffer[0]);
+		conn.reply( protocol::RspError::new(protocol::ERR_GENERIC, "Unknown request").into() );
+		},
 	}
 }
@@ -0,0 +1,71 @@
+// Tifflin OS - handle_server
+// - By John Hodge (thePowersGang)
+//
+// handle_server/src/picker.rs
+//! File picker dialog processes
+use handle_server::protocol;
+
+const PICKER_PATH: &'static str = "/sysroot/bin/file_picker";
+
+/// A running file picker dialog
+pub struct Picker
+{
+	/// Channel the dialog sends its result over
+	channel: ::syscalls::ipc::RpcChannel,
+	_process: ::syscalls::threads::Process,
+}
+
+impl Picker
+{
+	/// Start a picker dialog browsing `root` (modal to the window `owner`, if non-zero)
+	pub fn spawn(root: &::syscalls::vfs::Dir, mode: protocol::PickFileMode, owner: u32, description: &[u8]) -> Result<Picker, &'static str>
+	{
+		let (local, remote) = match ::syscalls::ipc::RpcChannel::new_pair()
+			{
+			Ok(v) => v,
+			Err(_) => return Err("Unable to create channel"),
+			};
+		let fh = match ::syscalls::vfs::ROOT.open_child_path(PICKER_PATH).and_then(|x| x.into_file(::syscalls::vfs::FileOpenMode::Execute))
+			{
+			Ok(v) => v,
+			Err(e) => {
+				kernel_log!("Unable to open file picker - {:?}", e);
+				return Err("Unable to open file picker");
+				},
+			};
+		let mode_arg = [b'0' + mode as u8];
+		let owner_arg = format!("{}", owner);
+		let pp = match ::loader::new_process(fh, PICKER_PATH.as_bytes(), &[&mode_arg[..], description, owner_arg.as_bytes()])
+			{
+			Ok(v) => v,
+			Err(e) => {
+				kernel_log!("Unable to start file picker - {:?}", e);
+				return Err("Unable to start file picker");
+				},
+			};
+		pp.send_obj( "guigrp", ::syscalls::gui::clone_group_handle() );
+		pp.send_obj( "RwRoot", root.clone() );
+		pp.send_obj( "Result", remote );
+		Ok(Picker {
+			channel: local,
+			_process: pp.start(),
+			})
+	}
+
+	pub fn wait_result(&self) -> ::syscalls::threads::WaitItem {
+		self.channel.wait_rx()
+	}
+
+	/// Check for the dialog's result. The message is the response for the client (the opened file is attached)
+	///
+	/// If the dialog exits without sending a result (e.g. it's closed), the request was cancelled.
+	pub fn poll(&self) -> Option<(::syscalls::ipc::RpcMessage, Option<::syscalls::vfs::File>)>
+	{
+		match self.channel.try_receive()
+		{
+		Ok( (msg, obj) ) => Some( (msg, obj.and_then(|o| o.downcast().ok())) ),
+		Err(::syscalls::ipc::RxError::NoMessage) => None,
+		Err(::syscalls::ipc::RxError::ConnectionClosed) => Some( (protocol::RspError::new(protocol::ERR_CANCELLED, "Cancelled").into(), None) ),
+		}
+	}
+}
@@ -18,30 +18,32 @@ fn main()
 	//let daemons = Vec::new();
 	//let shells = Vec::new();
 
-	let session_root = {
-		let pp = loader::new_process(open_exec("/sysroot/bin/login"), b"/sysroot/bin/login", &[]).expect("Could not start login");
-
-		pp.send_obj("guigrp", {
-			let wingrp = syscalls::gui::Group::new("Session 1").unwrap();
-			wingrp.force_active().expect("Cannot force session 1 to be active");
-			wingrp
-			});
-		pp.send_obj("RwRoot", rw_root.clone() );
-		pp.start()
-		};
-
+	// Supervise the session root, restarting it whenever it terminates
 	loop {
-		let mut waits = [session_root.wait_terminate()];
-		::syscalls::threads::wait(&mut waits, !0);
-		drop(session_root);	// drop before panicking (leads to better reaping)
+		let session_root = start_session(&rw_root);
+		let status = session_root.wait();
+		if let Some(fault) = session_root.get_fault() {
+			::loader::log_fault(Some(&open_exec("/sysroot/bin/login")), &fault);
+		}
+		drop(session_root);	// drop before restarting (leads to better reaping)
 		
-		// Empty wait set for ???
-		::syscalls::threads::wait(&mut [], !0);
-
-		panic!("TODO: Handle login terminating");
+		kernel_log!("Session root (login) terminated with status {:#x}, restarting", status);
 	}
 }
 
+fn start_session(rw_root: &::syscalls::vfs::Dir) -> ::syscalls::threads::Process
+{
+	let pp = loader::new_process(open_exec("/sysroot/bin/login"), b"/sysroot/bin/login", &[]).expect("Could not start login");
+
+	pp.send_obj("guigrp", {
+		let wingrp = syscalls::gui::Group::new("Session 1").unwrap();
+		wingrp.force_active().expect("Cannot force session 1 to be active");
+		wingrp
+		});
+	pp.send_obj("RwRoot", rw_root.clone() );
+	pp.start()
+}
+
 fn get_handle<T: ::syscalls::Object>(desc: &str, tag: &str) -> T
 {
 	match ::syscalls::threads::S_THIS_PROCESS.receive_object(tag)
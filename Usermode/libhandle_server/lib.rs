@@ -9,12 +9,14 @@ extern crate syscalls;
 
 #[doc(hidden)]
 pub mod protocol;
+pub mod manifest;
 
 pub struct Connection
 {
 	channel: ::syscalls::ipc::RpcChannel,
 }
 
+#[derive(Debug)]
 pub enum OpenError
 {
 	Cancelled,
@@ -22,6 +24,106 @@ pub enum OpenError
 	PermissionDenied,
 }
 
+/// Error from service registration and connection
+#[derive(Debug)]
+pub enum ServiceError
+{
+	/// No service is registered with that name
+	NotFound,
+	/// Another service is already registered with that name
+	AlreadyRegistered,
+	/// The service has too many connections waiting to be accepted
+	Busy,
+	/// The name wasn't valid
+	BadName,
+}
+impl ServiceError
+{
+	fn from_code(code: u8) -> ServiceError {
+		match code
+		{
+		protocol::ERR_NOT_FOUND => ServiceError::NotFound,
+		protocol::ERR_EXISTS => ServiceError::AlreadyRegistered,
+		protocol::ERR_BUSY => ServiceError::Busy,
+		_ => ServiceError::BadName,
+		}
+	}
+}
+
+/// A registered service, receives connections made using `Connection::connect_service`
+pub struct Service
+{
+	channel: ::syscalls::ipc::RpcChannel,
+}
+impl Service
+{
+	/// Wait item that fires when a connection is waiting
+	pub fn wait_connection(&self) -> ::syscalls::threads::WaitItem {
+		self.channel.wait_rx()
+	}
+	/// Accept a waiting connection (returns `None` if there are none)
+	pub fn try_accept(&self) -> Option<::syscalls::ipc::RpcChannel> {
+		match self.channel.try_receive()
+		{
+		Ok( (msg, Some(obj)) ) => match protocol::Response::try_from(msg)
+			{
+			Ok(protocol::Response::IncomingConnection(_)) => Some( obj.downcast_panic() ),
+			_ => panic!("Unexpected message on service channel"),
+			},
+		Ok( (_, None) ) => panic!("Unexpected message on service channel"),
+		Err(::syscalls::ipc::RxError::NoMessage) => None,
+		Err(::syscalls::ipc::RxError::ConnectionClosed) => panic!("Handle server closed service channel"),
+		}
+	}
+}
+
+/// An application registered with the handle server (see `Connection::applications`)
+pub struct Application
+{
+	info: protocol::RspApplication,
+	manifest: ::syscalls::vfs::File,
+}
+impl Application
+{
+	/// Application identifier (can be passed to `Connection::open_executable`)
+	pub fn id(&self) -> &str {
+		::core::str::from_utf8(self.info.id()).expect("Invalid UTF-8 from handle server")
+	}
+	/// Read-only handle to the application's manifest (parse with `manifest::Manifest`)
+	pub fn manifest_file(&self) -> &::syscalls::vfs::File {
+		&self.manifest
+	}
+}
+
+/// Iterator over registered applications (makes a request for each item)
+pub struct Applications<'a>
+{
+	conn: &'a Connection,
+	index: u16,
+}
+impl<'a> Iterator for Applications<'a>
+{
+	type Item = Application;
+	fn next(&mut self) -> Option<Application> {
+		self.conn.channel.send( protocol::ReqListApplications::new(self.index).into() ).expect("Failed to send request to handle server");
+		let (rsp, obj) = self.conn.wait_response();
+		match protocol::Response::try_from(rsp)
+		{
+		Ok(protocol::Response::Application(info)) => {
+			self.index += 1;
+			Some(Application {
+				info: info,
+				manifest: obj.expect("No handle returned with Application response").downcast_panic(),
+				})
+			},
+		// End of the list
+		Ok(protocol::Response::Error(_)) => None,
+		Ok(_) => panic!("Unexpected response from handle server"),
+		Err(_) => panic!("Error receiving response from handle server"),
+		}
+	}
+}
+
 impl Connection
 {
 	/// Create a new connection by receiving the handle from the parent process
@@ -34,17 +136,29 @@ impl Connection
 			channel: channel,
 		}
 	}
-}
 
+	/// Wait for the response to a request
+	fn wait_response(&self) -> (::syscalls::ipc::RpcMessage, Option<::syscalls::AnyObject>) {
+		loop
+		{
+			::syscalls::threads::wait(&mut [ self.channel.wait_rx() ], !0);
+			match self.channel.try_receive()
+			{
+			Ok(v) => return v,
+			Err(::syscalls::ipc::RxError::NoMessage) => {},
+			Err(::syscalls::ipc::RxError::ConnectionClosed) => panic!("Handle server connection closed"),
+			}
+		}
+	}
+}
 
 /// Blocking requests
 impl Connection
 {
-	/// Open a named executable
+	/// Open a named executable (either a registered application's ID, or a name found in the search path)
 	pub fn open_executable(&self, name: &str) -> Result< ::syscalls::vfs::File, OpenError > {
-		self.channel.send( protocol::ReqOpenExecutable::new(name).into() );
-		::syscalls::threads::wait(&mut [ self.channel.wait_rx() ], !0);
-		let (rsp, obj) = self.channel.try_receive().unwrap();
+		self.channel.send( protocol::ReqOpenExecutable::new(name).into() ).expect("Failed to send request to handle server");
+		let (rsp, obj) = self.wait_response();
 		match protocol::Response::try_from(rsp)
 		{
 		Ok(protocol::Response::OpenedFile(v)) => {
@@ -58,21 +172,79 @@ impl Connection
 		}
 	}
 
+	/// List the applications registered with the handle server
+	pub fn applications(&self) -> Applications {
+		Applications {
+			conn: self,
+			index: 0,
+		}
+	}
+
+	/// Register a named service (other processes can then connect to it using `connect_service`)
+	pub fn register_service(&self, name: &str) -> Result< Service, ServiceError > {
+		let (local, remote) = ::syscalls::ipc::RpcChannel::new_pair().expect("Failed to create service channel");
+		self.channel.send_obj( protocol::ReqRegisterService::new(name).into(), remote ).expect("Failed to send request to handle server");
+		let (rsp, _) = self.wait_response();
+		match protocol::Response::try_from(rsp)
+		{
+		Ok(protocol::Response::ServiceRegistered(_)) => Ok( Service { channel: local } ),
+		Ok(protocol::Response::Error(e)) => Err( ServiceError::from_code(e.error_id()) ),
+		Ok(_) => panic!("Unexpected response from handle server"),
+		Err(_) => panic!("Error receiving response from handle server"),
+		}
+	}
+	/// Open a new connection to a named service
+	pub fn connect_service(&self, name: &str) -> Result< ::syscalls::ipc::RpcChannel, ServiceError > {
+		self.channel.send( protocol::ReqConnectService::new(name).into() ).expect("Failed to send request to handle server");
+		let (rsp, obj) = self.wait_response();
+		match protocol::Response::try_from(rsp)
+		{
+		Ok(protocol::Response::NewChannel(_)) => {
+			Ok( obj.expect("No handle returned with NewChannel response").downcast_panic() )
+			},
+		Ok(protocol::Response::Error(e)) => Err( ServiceError::from_code(e.error_id()) ),
+		Ok(_) => panic!("Unexpected response from handle server"),
+		Err(_) => panic!("Error receiving response from handle server"),
+		}
+	}
+
 	/// Ask the user to select a file for reading
-	pub fn select_file_ro(&self, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
-		unimplemented!()
+	///
+	/// `owner` is the ID of the window the dialog is modal to (see `Window::get_id`), if any.
+	pub fn select_file_ro(&self, owner: Option<u32>, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
+		self.select_file(protocol::PickFileMode::ReadOnly, owner, reason)
 	}
 	/// Ask the user to a select a file to edit (read+write)
-	pub fn select_file_rw(&self, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
-		unimplemented!()
+	pub fn select_file_rw(&self, owner: Option<u32>, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
+		self.select_file(protocol::PickFileMode::ReadWrite, owner, reason)
 	}
 	/// As the user to select a file to optionally edit (can return a ReadOnly handle)
-	pub fn select_file_maybe_write(&self, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
-		unimplemented!()
+	pub fn select_file_maybe_write(&self, owner: Option<u32>, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
+		self.select_file(protocol::PickFileMode::OptionalWrite, owner, reason)
+	}
+	/// Ask the user to select an output filename (for creation/over-write, an existing file is truncated)
+	pub fn select_file_new(&self, owner: Option<u32>, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
+		self.select_file(protocol::PickFileMode::Create, owner, reason)
 	}
-	/// Ask the user to select an output filename (for creation/over-write)
-	pub fn select_file_new(&self, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
-		unimplemented!()
+
+	/// Show the file picker dialog, and wait for the user to choose a file (or cancel)
+	fn select_file(&self, mode: protocol::PickFileMode, owner: Option<u32>, reason: &str) -> Result< ::syscalls::vfs::File, OpenError > {
+		self.channel.send( protocol::ReqPickFile::new(mode, owner.unwrap_or(0), reason).into() ).expect("Failed to send request to handle server");
+		let (rsp, obj) = self.wait_response();
+		match protocol::Response::try_from(rsp)
+		{
+		Ok(protocol::Response::OpenedFile(_)) => {
+			Ok( obj.expect("No handle returned with OpenFile response").downcast_panic() )
+			},
+		Ok(protocol::Response::Error(e)) => Err(match e.error_id()
+			{
+			protocol::ERR_CANCELLED => OpenError::Cancelled,
+			protocol::ERR_PERMISSION => OpenError::PermissionDenied,
+			_ => OpenError::NotFound,
+			}),
+		Ok(_) => panic!("Unexpected response from handle server"),
+		Err(_) => panic!("Error receiving response from handle server"),
+		}
 	}
 }
 
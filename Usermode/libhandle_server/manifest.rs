@@ -0,0 +1,127 @@
+// Tifflin OS - handle_server Library
+// - By John Hodge (thePowersGang)
+//
+// libhandle_server/manifest.rs
+//! Application manifests
+//!
+//! Manifests live in `/system/Tifflin/apps`, one per application named `<id>.app`. Each line is a `key=value`
+//! pair (blank lines and lines starting with `#` are ignored). Keys:
+//! - `name`: Name shown to the user (required)
+//! - `binary`: Path to the executable (required)
+//! - `args`: Space-separated arguments to pass to the executable
+//! - `env`: Space-separated `KEY=VALUE` environment entries
+//! - `handles`: Space-separated names of the objects the application expects to be sent (e.g. `ro:/`, `file`, `cwd`)
+//! - `icon`: Path to an icon image
+//!
+//! Unknown keys are ignored.
+
+/// A parsed manifest (borrowing from the manifest file's contents)
+pub struct Manifest<'a>
+{
+	name: &'a str,
+	binary: &'a [u8],
+	args: &'a [u8],
+	env: &'a [u8],
+	handles: &'a [u8],
+	icon: Option<&'a [u8]>,
+}
+
+#[derive(Debug)]
+pub enum ManifestError
+{
+	/// The given line (1-based) wasn't a valid `key=value` pair
+	Malformed(usize),
+	/// A required key was missing
+	Missing(&'static str),
+}
+
+impl<'a> Manifest<'a>
+{
+	pub fn parse(data: &'a [u8]) -> Result<Manifest<'a>, ManifestError>
+	{
+		let mut name = None;
+		let mut binary = None;
+		let mut args: &[u8] = &[];
+		let mut env: &[u8] = &[];
+		let mut handles: &[u8] = &[];
+		let mut icon = None;
+		for (i, line) in data.split(|&b| b == b'\n').enumerate()
+		{
+			let line = trim(line);
+			if line.len() == 0 || line[0] == b'#' {
+				continue ;
+			}
+			let (key, value) = match line.iter().position(|&b| b == b'=')
+				{
+				Some(pos) => (trim(&line[..pos]), trim(&line[pos+1..])),
+				None => return Err(ManifestError::Malformed(i+1)),
+				};
+			match key
+			{
+			b"name" => name = Some( try!(::core::str::from_utf8(value).map_err(|_| ManifestError::Malformed(i+1))) ),
+			b"binary" => binary = Some(value),
+			b"args" => args = value,
+			b"env" => env = value,
+			b"handles" => handles = value,
+			b"icon" => icon = Some(value),
+			_ => {},
+			}
+		}
+
+		Ok(Manifest {
+			name: try!(name.ok_or(ManifestError::Missing("name"))),
+			binary: try!(binary.ok_or(ManifestError::Missing("binary"))),
+			args: args,
+			env: env,
+			handles: handles,
+			icon: icon,
+			})
+	}
+
+	pub fn name(&self) -> &'a str {
+		self.name
+	}
+	pub fn binary(&self) -> &'a [u8] {
+		self.binary
+	}
+	pub fn args(&self) -> Words<'a> {
+		Words(self.args)
+	}
+	/// Environment entries (`KEY=VALUE`) for the new process
+	pub fn env(&self) -> Words<'a> {
+		Words(self.env)
+	}
+	/// Names of the objects to send to the new process
+	pub fn handles(&self) -> Words<'a> {
+		Words(self.handles)
+	}
+	pub fn icon(&self) -> Option<&'a [u8]> {
+		self.icon
+	}
+}
+
+/// Iterator over space-separated words in a manifest value
+pub struct Words<'a>(&'a [u8]);
+impl<'a> Iterator for Words<'a>
+{
+	type Item = &'a [u8];
+	fn next(&mut self) -> Option<&'a [u8]> {
+		let s = trim(self.0);
+		if s.len() == 0 {
+			self.0 = s;
+			return None;
+		}
+		let end = s.iter().position(|&b| is_space(b)).unwrap_or(s.len());
+		self.0 = &s[end..];
+		Some(&s[..end])
+	}
+}
+
+fn is_space(b: u8) -> bool {
+	b == b' ' || b == b'\t' || b == b'\r'
+}
+fn trim(s: &[u8]) -> &[u8] {
+	let start = s.iter().position(|&b| !is_space(b)).unwrap_or(s.len());
+	let end = s.iter().rposition(|&b| !is_space(b)).map(|x| x+1).unwrap_or(start);
+	&s[start..end]
+}
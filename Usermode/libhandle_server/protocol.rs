@@ -58,10 +58,28 @@ pub enum UnmarshalError
 	BadValue,
 }
 
+/// RspError code: Unspecified failure
+pub const ERR_GENERIC: u8 = 0;
+/// RspError code: The named item (executable, service) doesn't exist
+pub const ERR_NOT_FOUND: u8 = 1;
+/// RspError code: A service with that name is already registered
+pub const ERR_EXISTS: u8 = 2;
+/// RspError code: The service hasn't accepted the previous connection yet
+pub const ERR_BUSY: u8 = 3;
+/// RspError code: A value in the request was invalid
+pub const ERR_BAD_VALUE: u8 = 4;
+/// RspError code: The user cancelled the request
+pub const ERR_CANCELLED: u8 = 5;
+/// RspError code: The selected item couldn't be opened with the requested mode
+pub const ERR_PERMISSION: u8 = 6;
+
 pub enum Request
 {
 	OpenExecutable(ReqOpenExecutable),
 	PickFile(ReqPickFile),
+	RegisterService(ReqRegisterService),
+	ConnectService(ReqConnectService),
+	ListApplications(ReqListApplications),
 }
 impl Request
 {
@@ -78,6 +96,21 @@ impl Request
 			Some(rv) => Ok(Request::PickFile(rv)),
 			None => Err(UnmarshalError::BadValue),
 			},
+		Some(RequestId::RegisterService) => match ReqRegisterService::try_from(v)
+			{
+			Some(rv) => Ok(Request::RegisterService(rv)),
+			None => Err(UnmarshalError::BadValue),
+			},
+		Some(RequestId::ConnectService) => match ReqConnectService::try_from(v)
+			{
+			Some(rv) => Ok(Request::ConnectService(rv)),
+			None => Err(UnmarshalError::BadValue),
+			},
+		Some(RequestId::ListApplications) => match ReqListApplications::try_from(v)
+			{
+			Some(rv) => Ok(Request::ListApplications(rv)),
+			None => Err(UnmarshalError::BadValue),
+			},
 		None => Err(UnmarshalError::UnknownRequest),
 		}
 	}
@@ -88,11 +121,14 @@ enum RequestId
 {
 	OpenExecutable,
 	PickFile,
+	RegisterService,
+	ConnectService,
+	ListApplications,
 }
 impl RequestId
 {
 	pub fn try_from(v: u8) -> Option<RequestId> {
-		if v <= RequestId::PickFile as u8 {
+		if v <= RequestId::ListApplications as u8 {
 			// SAFE: Range checked
 			Some(unsafe { ::core::mem::transmute(v) })
 		}
@@ -126,10 +162,12 @@ def_proto_type! {
 	RequestId::PickFile => ReqPickFile
 	struct {
 		mode: PickFileMode,
-		description: [u8; 30],
+		owner: u32,
+		description: [u8; 26],
 	}
-	new(mode: PickFileMode, desc: &str) {
+	new(mode: PickFileMode, owner: u32, desc: &str) {
 		mode: mode,
+		owner: owner,
 		description: zero_pad_bytes_into(desc.as_bytes()),
 	}
 	try_from(v) {
@@ -138,18 +176,87 @@ def_proto_type! {
 			Some(v) => v,
 			None => return None,
 			},
-		description: zero_pad_bytes_into(&v[2..]),
+		owner: v[2] as u32 | (v[3] as u32) << 8 | (v[4] as u32) << 16 | (v[5] as u32) << 24,
+		description: zero_pad_bytes_into(&v[6..]),
 	}
 }
+def_message_transmute! { ReqPickFile }
 impl ReqPickFile
 {
 	pub fn mode(&self) -> PickFileMode {
 		self.mode
 	}
+	/// ID of the client window the dialog is modal to (0 for none)
+	pub fn owner(&self) -> u32 {
+		self.owner
+	}
 	pub fn description_raw(&self) -> &[u8] {
 		get_zero_terminated_slice(&self.description)
 	}
 }
+def_proto_type! {
+	RequestId::RegisterService => ReqRegisterService
+	struct {
+		name_buf: [u8; 31],
+	}
+	new(name: &str) {
+		name_buf: zero_pad_bytes_into(name.as_bytes()),
+	}
+	try_from(v) {
+		name_buf: zero_pad_bytes_into(&v[1..]),
+	}
+}
+def_message_transmute! { ReqRegisterService }
+impl ReqRegisterService
+{
+	pub fn name(&self) -> &[u8] {
+		get_zero_terminated_slice(&self.name_buf)
+	}
+}
+
+def_proto_type! {
+	RequestId::ConnectService => ReqConnectService
+	struct {
+		name_buf: [u8; 31],
+	}
+	new(name: &str) {
+		name_buf: zero_pad_bytes_into(name.as_bytes()),
+	}
+	try_from(v) {
+		name_buf: zero_pad_bytes_into(&v[1..]),
+	}
+}
+def_message_transmute! { ReqConnectService }
+impl ReqConnectService
+{
+	pub fn name(&self) -> &[u8] {
+		get_zero_terminated_slice(&self.name_buf)
+	}
+}
+
+def_proto_type! {
+	RequestId::ListApplications => ReqListApplications
+	struct {
+		index: u16,
+		_unused: [u8; 29],
+	}
+	new(index: u16) {
+		index: index,
+		_unused: [0; 29],
+	}
+	try_from(v) {
+		index: v[1] as u16 | (v[2] as u16) << 8,
+		_unused: [0; 29],
+	}
+}
+def_message_transmute! { ReqListApplications }
+impl ReqListApplications
+{
+	pub fn index(&self) -> usize {
+		self.index as usize
+	}
+}
+
 #[repr(u8)]
 #[derive(Copy,Clone)]
 pub enum PickFileMode
@@ -179,6 +286,9 @@ pub enum Response
 	Error(RspError),
 	OpenedFile(RspOpenedFile),
 	NewChannel(RspNewChannel),
+	ServiceRegistered(RspServiceRegistered),
+	IncomingConnection(RspIncomingConnection),
+	Application(RspApplication),
 }
 impl Response
 {
@@ -196,6 +306,9 @@ impl Response
 			Error => RspError,
 			OpenedFile => RspOpenedFile,
 			NewChannel => RspNewChannel,
+			ServiceRegistered => RspServiceRegistered,
+			IncomingConnection => RspIncomingConnection,
+			Application => RspApplication,
 			)
 	}
 }
@@ -206,11 +319,16 @@ enum ResponseId
 	Error,
 	OpenedFile,
 	NewChannel,
+	ServiceRegistered,
+	/// Sent to a service (with the new channel attached) when a client connects
+	IncomingConnection,
+	/// Response to ListApplications (with the manifest file attached)
+	Application,
 }
 impl ResponseId
 {
 	pub fn try_from(v: u8) -> Option<Self> {
-		if v <= ResponseId::OpenedFile as u8 {
+		if v <= ResponseId::Application as u8 {
 			// SAFE: Range checked
 			Some(unsafe { ::core::mem::transmute(v) })
 		}
@@ -280,6 +398,60 @@ def_proto_type! {
 }
 def_message_transmute! { RspNewChannel }
 
+def_proto_type! {
+	ResponseId::ServiceRegistered => RspServiceRegistered
+	struct {
+		_unused: [u8; 31],
+	}
+	new() {
+		_unused: [0; 31],
+	}
+	try_from(_v) {
+		_unused: [0; 31],
+	}
+}
+def_message_transmute! { RspServiceRegistered }
+
+def_proto_type! {
+	ResponseId::IncomingConnection => RspIncomingConnection
+	struct {
+		client_name: [u8; 31],
+	}
+	new(client: &[u8]) {
+		client_name: zero_pad_bytes_into(client),
+	}
+	try_from(v) {
+		client_name: zero_pad_bytes_into(&v[1..]),
+	}
+}
+def_message_transmute! { RspIncomingConnection }
+impl RspIncomingConnection
+{
+	pub fn client_name(&self) -> &[u8] {
+		get_zero_terminated_slice(&self.client_name)
+	}
+}
+
+def_proto_type! {
+	ResponseId::Application => RspApplication
+	struct {
+		id_buf: [u8; 31],
+	}
+	new(id: &[u8]) {
+		id_buf: zero_pad_bytes_into(id),
+	}
+	try_from(v) {
+		id_buf: zero_pad_bytes_into(&v[1..]),
+	}
+}
+def_message_transmute! { RspApplication }
+impl RspApplication
+{
+	pub fn id(&self) -> &[u8] {
+		get_zero_terminated_slice(&self.id_buf)
+	}
+}
+
 fn zero_pad_bytes_into<T: Default+AsMut<[u8]>>(src: &[u8]) -> T {
 	let mut rv = T::default();
 	for (&b, d) in Iterator::zip( src.iter(), rv.as_mut().iter_mut() ) {
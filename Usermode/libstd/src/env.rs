@@ -2,22 +2,71 @@
 //
 //
 use ::collections::Vec;
-use ::ffi::OsString;
+use ::ffi::{OsStr,OsString};
+use ::string::String;
 
 static mut S_ARGUMENTS: &'static [OsString] = &[];
+static mut S_ENVIRONMENT: &'static [(OsString, OsString)] = &[];
+static mut S_INHERITED_HANDLES: &'static [OsString] = &[];
+static mut S_CWD: Option<&'static ::syscalls::vfs::Dir> = None;
+
+/// Tag of the working directory handle (matches `loader::CWD_TAG`)
+const CWD_TAG: &'static str = "cwd";
+/// Tag of the inherited handles manifest channel (matches `loader::HANDLES_TAG`)
+const HANDLES_TAG: &'static str = "hndlst";
+
+/// Leak a vector into a slice that lives for the rest of the program
+fn into_static<T>(v: Vec<T>) -> &'static [T] {
+	// SAFE: The backing allocation is never freed
+	let rv = unsafe { &*(&v[..] as *const [_]) };
+	::core::mem::forget(v);
+	rv
+}
 
 #[no_mangle]
 #[linkage="external"]
 #[allow(private_no_mangle_fns)]
 #[allow(dead_code)]
-extern "C" fn register_arguments(args: &[&::ffi::OsStr]) {
-	kernel_log!("register_arguments(args={:?})", args);
+extern "C" fn register_arguments(args: &[&OsStr], env: &[&OsStr]) {
 	let args: Vec<_> = args.iter().map(|&a| OsString::from(a)).collect();
+	// - Environment entries are `KEY=VALUE`
+	let env: Vec<_> = env.iter().map(|&e| {
+		let e = e.as_bytes();
+		match e.iter().position(|&b| b == b'=')
+		{
+		Some(p) => (OsString::from(&e[..p]), OsString::from(&e[p+1..])),
+		None => (OsString::from(e), OsString::new()),
+		}
+		}).collect();
+	let handles = receive_handle_manifest();
+	// - Leaked, as statics can't have destructors
+	let cwd: Option<&'static ::syscalls::vfs::Dir> = ::syscalls::threads::S_THIS_PROCESS.receive_object(CWD_TAG).ok()
+		// SAFE: Box is never freed
+		.map(|d| unsafe { &*::heap::boxed::Box::into_raw(::heap::boxed::Box::new(d)) });
 	// SAFE: Runs in a single-threaded context
 	unsafe {
-		//S_ARGUMENTS = args.into_static();
-		S_ARGUMENTS = &*(&args[..] as *const [_]);
-		::core::mem::forget(args);
+		S_ARGUMENTS = into_static(args);
+		S_ENVIRONMENT = into_static(env);
+		S_INHERITED_HANDLES = into_static(handles);
+		S_CWD = cwd;
+	}
+}
+
+/// Receive the list of objects sent by the parent (empty if no manifest was sent)
+fn receive_handle_manifest() -> Vec<OsString> {
+	let ch: ::syscalls::ipc::Channel = match ::syscalls::threads::S_THIS_PROCESS.receive_object(HANDLES_TAG)
+		{
+		Ok(v) => v,
+		Err(_) => return Vec::new(),
+		};
+	let mut buf = [0u8; 128];
+	match ch.try_recv(&mut buf, &mut [])
+	{
+	Ok(info) => buf[..info.len].split(|&b| b == 0).filter(|t| t.len() > 0).map(OsString::from).collect(),
+	Err(e) => {
+		kernel_log!("receive_handle_manifest - Error {:?}", e);
+		Vec::new()
+		},
 	}
 }
 
@@ -40,3 +89,75 @@ impl Iterator for ArgsOs {
 		}
 	}
 }
+
+#[derive(Debug)]
+pub enum VarError
+{
+	/// The variable isn't in the environment
+	NotPresent,
+	/// The variable's value isn't valid UTF-8
+	NotUnicode(OsString),
+}
+
+/// Get an environment variable (passed by the parent at spawn)
+pub fn var_os<K: ?Sized + AsRef<OsStr>>(key: &K) -> Option<OsString> {
+	let key = key.as_ref();
+	// SAFE: The S_ENVIRONMENT array is only ever altered at startup
+	unsafe {
+		S_ENVIRONMENT.iter().find(|e| e.0.as_os_str() == key).map(|e| e.1.clone())
+	}
+}
+/// Get an environment variable as a string
+pub fn var<K: ?Sized + AsRef<OsStr>>(key: &K) -> Result<String, VarError> {
+	match var_os(key)
+	{
+	Some(v) => {
+		let s = v.to_str().map(String::from);
+		match s
+		{
+		Some(s) => Ok(s),
+		None => Err(VarError::NotUnicode(v)),
+		}
+		},
+	None => Err(VarError::NotPresent),
+	}
+}
+
+pub struct VarsOs(usize);
+/// Iterate all environment variables (as key/value pairs)
+pub fn vars_os() -> VarsOs {
+	VarsOs(0)
+}
+impl Iterator for VarsOs {
+	type Item = (OsString, OsString);
+	fn next(&mut self) -> Option<(OsString, OsString)> {
+		// SAFE: The S_ENVIRONMENT array is only ever altered at startup
+		unsafe {
+			if self.0 == S_ENVIRONMENT.len() {
+				None
+			}
+			else {
+				self.0 += 1;
+				Some( S_ENVIRONMENT[self.0 - 1].clone() )
+			}
+		}
+	}
+}
+
+/// Tags of the objects passed by the parent process (from the inherited handles manifest)
+///
+/// The objects themselves are obtained using `syscalls::threads::S_THIS_PROCESS.receive_object`
+pub fn inherited_handles() -> ::core::slice::Iter<'static, OsString> {
+	// SAFE: The S_INHERITED_HANDLES array is only ever altered at startup
+	unsafe {
+		S_INHERITED_HANDLES.iter()
+	}
+}
+
+/// Handle to the current working directory (if the parent provided one), used for relative paths
+pub fn current_dir_handle() -> Option<&'static ::syscalls::vfs::Dir> {
+	// SAFE: S_CWD is only ever altered at startup
+	unsafe {
+		S_CWD
+	}
+}
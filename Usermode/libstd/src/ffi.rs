@@ -40,6 +40,11 @@ impl AsRef<OsStr> for [u8] {
 		OsStr::new(self)
 	}
 }
+impl AsRef<OsStr> for str {
+	fn as_ref(&self) -> &OsStr {
+		OsStr::new(self)
+	}
+}
 
 impl_fmt!{
 	Debug(self,f) for OsStr {{
@@ -41,8 +41,12 @@ impl Node
 			}
 		}
 		else {
-			// Open relative to CWD
-			unimplemented!();
+			// Open relative to the working directory handle (passed by the parent, see `env::current_dir_handle`)
+			match ::env::current_dir_handle()
+			{
+			Some(cwd) => Ok(Node( try!(cwd.open_child_path(path)) )),
+			None => Err( ::syscalls::vfs::Error::FileNotFound.into() ),
+			}
 		}
 	}
 	
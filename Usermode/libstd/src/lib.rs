@@ -13,6 +13,8 @@
 #![feature(raw)]
 #![feature(collections,slice_concat_ext)]
 #![feature(macro_reexport)]
+#![feature(thread_local)]	// Used by thread_local!
+#![feature(allow_internal_unstable)]
 #![no_std]
 
 #[macro_use]
@@ -115,6 +117,11 @@ pub mod fs;
 
 pub mod error;
 
+pub mod time;
+
+#[macro_use]
+pub mod thread;
+
 pub use collections::{vec, string, borrow};
 
 pub mod os;
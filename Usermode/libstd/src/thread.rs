@@ -0,0 +1,283 @@
+//
+//
+//
+//! Native threads and thread-local storage
+use core::cell::UnsafeCell;
+use heap::boxed::Box;
+use collections::Vec;
+
+/// Size of the stack allocated for spawned threads
+const STACK_SIZE: usize = 0x20000;
+
+/// Result of joining a thread (`Err` if the thread terminated without returning a value)
+pub type Result<T> = ::core::result::Result<T, ()>;
+
+/// Handle to a spawned thread, used to wait for it to finish
+///
+/// Dropping the handle detaches the thread.
+pub struct JoinHandle<T>
+{
+	inner: Option<JoinInner<T>>,
+}
+struct JoinInner<T>
+{
+	thread: ::syscalls::threads::Thread,
+	result: Box<UnsafeCell<Option<T>>>,
+	stack: Vec<u8>,
+	tls: Vec<u8>,
+}
+/// Resources of a detached thread, freed once the thread has terminated
+struct Detached
+{
+	thread: ::syscalls::threads::Thread,
+	stack: Vec<u8>,
+	tls: Vec<u8>,
+	result: *mut u8,
+	drop_result: unsafe fn(*mut u8),
+}
+// NOTE: The result is only ever created by `spawn`, which requires `T: Send`
+unsafe impl Send for Detached {}
+impl ::core::ops::Drop for Detached
+{
+	fn drop(&mut self) {
+		// SAFE: Pointer and function were created together from the result box, and the thread has terminated
+		unsafe { (self.drop_result)(self.result); }
+	}
+}
+unsafe fn drop_result<T>(ptr: *mut u8) {
+	drop( Box::from_raw(ptr as *mut UnsafeCell<Option<T>>) );
+}
+
+/// Detached threads that were still running when their handle was dropped
+static S_DETACHED: ::sync::Mutex<Option<Vec<Detached>>> = ::sync::Mutex::new(None);
+
+/// Free the resources of any detached threads that have since terminated
+fn reap_detached()
+{
+	let mut lh = S_DETACHED.lock();
+	if let Some(ref mut list) = *lh {
+		list.retain(|d| d.thread.get_exit_value().is_none());
+	}
+}
+
+/// Data passed to a new thread (via the entrypoint's argument)
+struct ThreadStart<F, T>
+{
+	fcn: F,
+	result: *mut Option<T>,
+}
+
+/// Spawn a new thread running the passed closure
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+	F: FnOnce() -> T + Send + 'static,
+	T: Send + 'static
+{
+	reap_detached();
+
+	let result: Box<UnsafeCell<Option<T>>> = Box::new( UnsafeCell::new(None) );
+	let start = Box::new(ThreadStart {
+		fcn: f,
+		result: result.get(),
+		});
+
+	let template = ::rt::TlsTemplate::current();
+	let mut tls: Vec<u8> = Vec::with_capacity( template.block_size() );
+	let mut stack: Vec<u8> = Vec::with_capacity(STACK_SIZE);
+	// SAFE: Buffers are valid (and owned by the JoinHandle until the thread terminates)
+	let thread = unsafe {
+		let tp = template.init_block(tls.as_mut_ptr(), tls.capacity());
+		let sp = ::rt::initial_sp(stack.as_mut_ptr() as usize + stack.capacity());
+		let arg = Box::into_raw(start) as usize;
+		match ::syscalls::threads::start_thread(thread_root::<F,T> as usize, sp, tp, arg)
+		{
+		Ok(v) => v,
+		Err(e) => {
+			drop(Box::from_raw(arg as *mut ThreadStart<F,T>));
+			panic!("Failed to spawn thread - {:#x}", e);
+			},
+		}
+		};
+
+	JoinHandle {
+		inner: Some(JoinInner {
+			thread: thread,
+			result: result,
+			stack: stack,
+			tls: tls,
+			}),
+	}
+}
+
+/// Root of spawned threads
+extern "C" fn thread_root<F, T>(arg: usize) -> !
+where
+	F: FnOnce() -> T + Send + 'static,
+	T: Send + 'static
+{
+	// SAFE: `arg` is the leaked box created by `spawn`
+	let start = unsafe { Box::from_raw(arg as *mut ThreadStart<F,T>) };
+	let ThreadStart { fcn, result } = *start;
+	let rv = fcn();
+	// SAFE: The JoinHandle doesn't read the result until this thread terminates
+	unsafe { *result = Some(rv); }
+	run_tls_destructors();
+	::syscalls::threads::exit_thread(0);
+}
+
+impl<T> JoinHandle<T>
+{
+	/// Wait for the thread to finish, returning the value returned by its closure
+	pub fn join(mut self) -> Result<T> {
+		let inner = self.inner.take().unwrap();
+		inner.thread.join();
+		// - The thread has terminated, so the stack and TLS block can be freed (when `inner` drops)
+		// SAFE: Thread has terminated, no other references to the result
+		match unsafe { (*inner.result.get()).take() }
+		{
+		Some(v) => Ok(v),
+		None => Err( () ),
+		}
+	}
+}
+impl<T> ::core::ops::Drop for JoinHandle<T>
+{
+	fn drop(&mut self) {
+		if let Some(inner) = self.inner.take() {
+			// Detached - the thread still uses its stack, TLS, and result slot, so they're kept until it terminates
+			// - A thread can't free its own stack, so they're freed by a later spawn (or handle drop)
+			reap_detached();
+			if inner.thread.get_exit_value().is_none() {
+				let JoinInner { thread, result, stack, tls } = inner;
+				let d = Detached {
+					thread: thread,
+					stack: stack,
+					tls: tls,
+					result: Box::into_raw(result) as *mut u8,
+					drop_result: drop_result::<T>,
+					};
+				let mut lh = S_DETACHED.lock();
+				if lh.is_none() {
+					*lh = Some(Vec::new());
+				}
+				lh.as_mut().unwrap().push(d);
+			}
+		}
+	}
+}
+
+/// Declare a new thread-local storage key of type `std::thread::LocalKey`
+#[macro_export]
+#[allow_internal_unstable]
+macro_rules! thread_local {
+	() => {};
+	($(#[$attr:meta])* static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => (
+		thread_local!($(#[$attr])* static $name: $t = $init);
+		thread_local!($($rest)*);
+		);
+	($(#[$attr:meta])* static $name:ident: $t:ty = $init:expr) => (
+		$(#[$attr])* static $name: $crate::thread::LocalKey<$t> = {
+			fn __init() -> $t { $init }
+			fn __getit() -> &'static $crate::thread::__LocalKeyInner<$t> {
+				#[thread_local]
+				static __KEY: $crate::thread::__LocalKeyInner<$t> = $crate::thread::__LocalKeyInner::new();
+				&__KEY
+			}
+			$crate::thread::LocalKey::new(__getit, __init)
+			};
+		);
+	($(#[$attr:meta])* pub static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => (
+		thread_local!($(#[$attr])* pub static $name: $t = $init);
+		thread_local!($($rest)*);
+		);
+	($(#[$attr:meta])* pub static $name:ident: $t:ty = $init:expr) => (
+		$(#[$attr])* pub static $name: $crate::thread::LocalKey<$t> = {
+			fn __init() -> $t { $init }
+			fn __getit() -> &'static $crate::thread::__LocalKeyInner<$t> {
+				#[thread_local]
+				static __KEY: $crate::thread::__LocalKeyInner<$t> = $crate::thread::__LocalKeyInner::new();
+				&__KEY
+			}
+			$crate::thread::LocalKey::new(__getit, __init)
+			};
+		);
+}
+
+/// A thread-local storage key (created with `thread_local!`)
+///
+/// Values are lazily initialised on first use in each thread, and destroyed when a spawned thread exits.
+/// NOTE: Values on the main thread are never destroyed.
+pub struct LocalKey<T: 'static>
+{
+	inner: fn() -> &'static __LocalKeyInner<T>,
+	init: fn() -> T,
+}
+#[doc(hidden)]
+pub struct __LocalKeyInner<T>
+{
+	value: UnsafeCell<Option<T>>,
+}
+// NOTE: Only ever accessed from the owning thread (declared #[thread_local])
+unsafe impl<T> Sync for __LocalKeyInner<T> { }
+
+impl<T> __LocalKeyInner<T>
+{
+	#[doc(hidden)]
+	pub const fn new() -> __LocalKeyInner<T> {
+		__LocalKeyInner { value: UnsafeCell::new(None) }
+	}
+}
+
+impl<T: 'static> LocalKey<T>
+{
+	#[doc(hidden)]
+	pub const fn new(inner: fn() -> &'static __LocalKeyInner<T>, init: fn() -> T) -> LocalKey<T> {
+		LocalKey { inner: inner, init: init }
+	}
+
+	/// Obtain a reference to this thread's value (initialising it if needed)
+	pub fn with<F, R>(&'static self, f: F) -> R
+	where
+		F: FnOnce(&T) -> R
+	{
+		let slot = (self.inner)();
+		// SAFE: Slot is only accessed by this thread, and the value isn't moved/dropped until the thread exits
+		unsafe {
+			if (*slot.value.get()).is_none() {
+				let v = (self.init)();
+				*slot.value.get() = Some(v);
+				register_dtor(slot as *const _ as *mut u8, destroy_value::<T>);
+			}
+			f( (*slot.value.get()).as_ref().unwrap() )
+		}
+	}
+}
+
+unsafe fn destroy_value<T>(ptr: *mut u8) {
+	let slot = &*(ptr as *const __LocalKeyInner<T>);
+	drop( (*slot.value.get()).take() );
+}
+
+struct DtorList(UnsafeCell<Option<Vec<(*mut u8, unsafe fn(*mut u8))>>>);
+unsafe impl Sync for DtorList { }
+#[thread_local]
+static S_DTORS: DtorList = DtorList(UnsafeCell::new(None));
+
+unsafe fn register_dtor(ptr: *mut u8, dtor: unsafe fn(*mut u8)) {
+	let list = &mut *S_DTORS.0.get();
+	if list.is_none() {
+		*list = Some(Vec::new());
+	}
+	list.as_mut().unwrap().push( (ptr, dtor) );
+}
+fn run_tls_destructors() {
+	// SAFE: Thread-local list, called as the thread exits
+	unsafe {
+		// NOTE: Destructors may register new values, so loop until the list is empty
+		while let Some(list) = (*S_DTORS.0.get()).take() {
+			for (ptr, dtor) in list {
+				dtor(ptr);
+			}
+		}
+	}
+}
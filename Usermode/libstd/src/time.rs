@@ -0,0 +1,192 @@
+//
+//
+//
+//! Temporal quantification
+//!
+//! `Instant` uses the kernel's monotonic clock (millisecond resolution), `SystemTime` uses the wall-clock time
+use core::ops::{Add,Sub,AddAssign,SubAssign};
+use core::fmt;
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+const NANOS_PER_MILLI: u32 = 1_000_000;
+const MILLIS_PER_SEC: u64 = 1_000;
+
+/// A span of time
+#[derive(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug,Default)]
+pub struct Duration
+{
+	secs: u64,
+	nanos: u32,
+}
+
+impl Duration
+{
+	pub fn new(secs: u64, nanos: u32) -> Duration {
+		let secs = secs.checked_add((nanos / NANOS_PER_SEC) as u64).expect("overflow in Duration::new");
+		Duration { secs: secs, nanos: nanos % NANOS_PER_SEC }
+	}
+	pub fn from_secs(secs: u64) -> Duration {
+		Duration { secs: secs, nanos: 0 }
+	}
+	pub fn from_millis(millis: u64) -> Duration {
+		Duration { secs: millis / MILLIS_PER_SEC, nanos: (millis % MILLIS_PER_SEC) as u32 * NANOS_PER_MILLI }
+	}
+
+	pub fn as_secs(&self) -> u64 {
+		self.secs
+	}
+	pub fn subsec_nanos(&self) -> u32 {
+		self.nanos
+	}
+
+	/// Total length in milliseconds (rounded down, saturating)
+	fn as_millis_sat(&self) -> u64 {
+		self.secs.saturating_mul(MILLIS_PER_SEC).saturating_add( (self.nanos / NANOS_PER_MILLI) as u64 )
+	}
+
+	pub fn checked_add(self, rhs: Duration) -> Option<Duration> {
+		let mut secs = match self.secs.checked_add(rhs.secs) { Some(v) => v, None => return None };
+		let mut nanos = self.nanos + rhs.nanos;
+		if nanos >= NANOS_PER_SEC {
+			nanos -= NANOS_PER_SEC;
+			secs = match secs.checked_add(1) { Some(v) => v, None => return None };
+		}
+		Some(Duration { secs: secs, nanos: nanos })
+	}
+	pub fn checked_sub(self, rhs: Duration) -> Option<Duration> {
+		let mut secs = match self.secs.checked_sub(rhs.secs) { Some(v) => v, None => return None };
+		let nanos = if self.nanos >= rhs.nanos {
+				self.nanos - rhs.nanos
+			}
+			else {
+				secs = match secs.checked_sub(1) { Some(v) => v, None => return None };
+				self.nanos + NANOS_PER_SEC - rhs.nanos
+			};
+		Some(Duration { secs: secs, nanos: nanos })
+	}
+}
+impl Add for Duration {
+	type Output = Duration;
+	fn add(self, rhs: Duration) -> Duration {
+		self.checked_add(rhs).expect("overflow when adding durations")
+	}
+}
+impl Sub for Duration {
+	type Output = Duration;
+	fn sub(self, rhs: Duration) -> Duration {
+		self.checked_sub(rhs).expect("overflow when subtracting durations")
+	}
+}
+impl AddAssign for Duration {
+	fn add_assign(&mut self, rhs: Duration) {
+		*self = *self + rhs;
+	}
+}
+impl SubAssign for Duration {
+	fn sub_assign(&mut self, rhs: Duration) {
+		*self = *self - rhs;
+	}
+}
+
+
+/// A measurement of the monotonic clock, only useful when compared with another `Instant`
+#[derive(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct Instant(u64);
+
+impl Instant
+{
+	pub fn now() -> Instant {
+		Instant( ::syscalls::threads::get_time() )
+	}
+	/// Amount of time elapsed between `earlier` and `self` (panics if `earlier` is later than `self`)
+	pub fn duration_since(&self, earlier: Instant) -> Duration {
+		assert!(earlier.0 <= self.0, "Instant::duration_since - `earlier` is later than self");
+		Duration::from_millis(self.0 - earlier.0)
+	}
+	pub fn elapsed(&self) -> Duration {
+		Instant::now().duration_since(*self)
+	}
+}
+impl Add<Duration> for Instant {
+	type Output = Instant;
+	fn add(self, rhs: Duration) -> Instant {
+		Instant( self.0.checked_add(rhs.as_millis_sat()).expect("overflow when adding duration to instant") )
+	}
+}
+impl Sub<Duration> for Instant {
+	type Output = Instant;
+	fn sub(self, rhs: Duration) -> Instant {
+		Instant( self.0.checked_sub(rhs.as_millis_sat()).expect("overflow when subtracting duration from instant") )
+	}
+}
+impl Sub<Instant> for Instant {
+	type Output = Duration;
+	fn sub(self, rhs: Instant) -> Duration {
+		self.duration_since(rhs)
+	}
+}
+
+
+/// A measurement of the wall-clock (milliseconds since the UNIX epoch)
+///
+/// NOTE: Not monotonic, the kernel may adjust the clock at any time.
+#[derive(Copy,Clone,PartialEq,Eq,PartialOrd,Ord,Hash,Debug)]
+pub struct SystemTime(u64);
+
+/// The UNIX epoch (1970-01-01 00:00:00 UTC)
+pub const UNIX_EPOCH: SystemTime = SystemTime(0);
+
+/// Error returned by `SystemTime::duration_since` when the argument is later than `self`
+#[derive(Clone,Debug)]
+pub struct SystemTimeError(Duration);
+
+impl SystemTime
+{
+	/// Obtain the current wall-clock time
+	///
+	/// Panics if the kernel doesn't know the wall-clock time (no RTC or firmware time), see `try_now`
+	pub fn now() -> SystemTime {
+		SystemTime::try_now().expect("SystemTime::now - wall-clock time not available")
+	}
+	/// Obtain the current wall-clock time, `None` if the kernel doesn't know it
+	pub fn try_now() -> Option<SystemTime> {
+		::syscalls::threads::get_realtime().map(SystemTime)
+	}
+	pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+		if earlier.0 <= self.0 {
+			Ok( Duration::from_millis(self.0 - earlier.0) )
+		}
+		else {
+			Err( SystemTimeError(Duration::from_millis(earlier.0 - self.0)) )
+		}
+	}
+	pub fn elapsed(&self) -> Result<Duration, SystemTimeError> {
+		SystemTime::now().duration_since(*self)
+	}
+}
+impl Add<Duration> for SystemTime {
+	type Output = SystemTime;
+	fn add(self, rhs: Duration) -> SystemTime {
+		SystemTime( self.0.checked_add(rhs.as_millis_sat()).expect("overflow when adding duration to time") )
+	}
+}
+impl Sub<Duration> for SystemTime {
+	type Output = SystemTime;
+	fn sub(self, rhs: Duration) -> SystemTime {
+		SystemTime( self.0.checked_sub(rhs.as_millis_sat()).expect("overflow when subtracting duration from time") )
+	}
+}
+
+impl SystemTimeError
+{
+	/// The amount of time by which the second time was later than the first
+	pub fn duration(&self) -> Duration {
+		self.0
+	}
+}
+impl fmt::Display for SystemTimeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "second time provided was later than self")
+	}
+}
+
@@ -23,6 +23,20 @@ macro_rules! log_debug {
 mod aeabi_unwind;
 
 
+pub fn thread_pointer() -> usize {
+	// SAFE: Reads TPIDRURO
+	unsafe {
+		let tp: usize;
+		asm!("mrc p15,0, $0, c13,c0,3" : "=r" (tp));
+		tp
+	}
+}
+
+/// Initial stack pointer for a new thread, given the top of its stack
+pub fn initial_sp(top: usize) -> usize {
+	top & !7
+}
+
 pub struct Backtrace(aeabi_unwind::UnwindState);
 impl Backtrace {
 	pub fn new() -> Backtrace {
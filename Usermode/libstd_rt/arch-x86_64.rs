@@ -1,4 +1,19 @@
 
+pub fn thread_pointer() -> usize {
+	// SAFE: Reads the self pointer from the TLS control block
+	unsafe {
+		let tp: usize;
+		asm!("mov %fs:0, $0" : "=r" (tp));
+		tp
+	}
+}
+
+/// Initial stack pointer for a new thread, given the top of its stack
+pub fn initial_sp(top: usize) -> usize {
+	// Entered as if called (return address slot pushed, leaving the stack misaligned by one word)
+	(top & !15) - 8
+}
+
 pub struct Backtrace(usize);
 impl Backtrace {
 	pub fn new() -> Backtrace {
@@ -0,0 +1,89 @@
+// Tifflin OS - Standard Library Runtime
+// - By John Hodge (thePowersGang)
+//
+// Process control notification handler
+//! Process control notification handler
+//!
+//! Starts a dedicated thread that waits on the process's control queue (exit requests, child exits,
+//! closed windows, user notifications), and passes each notification to a handler function. This lets
+//! a process that is blocked elsewhere (e.g. in a GUI event loop) respond to the session asking it to exit.
+use core::sync::atomic::{AtomicBool,Ordering,ATOMIC_BOOL_INIT};
+use syscalls::threads::{S_THIS_PROCESS,Notification};
+
+/// Control notification handler function
+pub type Handler = fn(Notification);
+
+/// Size of the handler thread's memory (TLS block and stack)
+const HANDLER_AREA_SIZE: usize = 0x8000;
+
+/// Memory for the handler thread (static, as this crate has no heap)
+static mut S_HANDLER_AREA: [u8; HANDLER_AREA_SIZE] = [0; HANDLER_AREA_SIZE];
+static S_HANDLER_STARTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+#[derive(Debug)]
+pub enum Error
+{
+	/// A handler thread is already running (only one can be started)
+	AlreadyStarted,
+	/// The TLS block is too large for the handler thread
+	TlsTooLarge,
+	/// The kernel refused to start the thread
+	StartFailed(u32),
+}
+
+/// Start the handler thread, calling `handler` for each notification
+pub fn start_handler(handler: Handler) -> Result<(), Error>
+{
+	if S_HANDLER_STARTED.swap(true, Ordering::SeqCst) {
+		return Err( Error::AlreadyStarted );
+	}
+	let template = ::tls::TlsTemplate::current();
+	let tls_size = template.block_size();
+	if tls_size > HANDLER_AREA_SIZE / 2 {
+		S_HANDLER_STARTED.store(false, Ordering::SeqCst);
+		return Err( Error::TlsTooLarge );
+	}
+	// SAFE: The area is only used by the handler thread (guarded by S_HANDLER_STARTED), which never exits
+	let rv = unsafe {
+		let base = S_HANDLER_AREA.as_mut_ptr();
+		let tp = template.init_block(base, tls_size);
+		let sp = ::arch::initial_sp(base as usize + HANDLER_AREA_SIZE);
+		::syscalls::threads::start_thread(handler_root as usize, sp, tp, handler as usize)
+		};
+	match rv
+	{
+	// - Handle dropped, detaching the thread
+	Ok(_) => Ok( () ),
+	Err(e) => {
+		S_HANDLER_STARTED.store(false, Ordering::SeqCst);
+		Err( Error::StartFailed(e) )
+		},
+	}
+}
+
+/// Default handler: Exits the process when asked to, logs and ignores everything else
+pub fn default_handler(n: Notification)
+{
+	match n
+	{
+	Notification::TerminateRequest => {
+		kernel_log!("Exit requested, terminating");
+		::syscalls::threads::exit(0);
+		},
+	_ => kernel_log!("Control notification {:?} ignored", n),
+	}
+}
+
+extern "C" fn handler_root(arg: usize) -> !
+{
+	// SAFE: `arg` is the `Handler` passed to `start_handler`
+	let handler: Handler = unsafe { ::core::mem::transmute(arg) };
+	loop
+	{
+		while let Some(n) = S_THIS_PROCESS.recv_control()
+		{
+			handler(n);
+		}
+		::syscalls::threads::wait(&mut [S_THIS_PROCESS.wait_control()], !0);
+	}
+}
@@ -42,6 +42,12 @@ mod arch;
 #[path="arch-armv7.rs"]
 mod arch;
 
+mod tls;
+pub use tls::{TlsTemplate,TCB_SIZE,thread_pointer};
+pub use arch::initial_sp;
+
+pub mod control;
+
 pub fn begin_panic<M: ::core::any::Any+Send+'static>(msg: M, file_line: &(&'static str, u32)) -> ! {
 	begin_unwind(msg, file_line)
 }
@@ -0,0 +1,78 @@
+// Tifflin OS - Standard Library Runtime
+// - By John Hodge (thePowersGang)
+//
+// Thread-local storage blocks
+//! Thread-local storage blocks
+//!
+//! Each thread's TLS block is laid out as specified by the platform ELF ABI (variant II on amd64, with the
+//! data immediately before the thread pointer; variant I on ARM, with the data after a two-word control
+//! block). The thread pointer addresses the control block, the first word of which is a self pointer and
+//! the second points to the template (so new threads can be created without asking the loader).
+//!
+//! The template covers the TLS segments of every loaded image (built by the loader), each at a fixed offset
+//! from the thread pointer.
+
+/// Thread-local storage template
+///
+/// The image ends at the thread pointer on amd64, and starts immediately after the control block elsewhere
+/// (any padding required by the images' alignment is part of the image).
+#[repr(C)]
+#[derive(Copy,Clone,Debug,Default)]
+pub struct TlsTemplate
+{
+	/// Address of the initialisation image
+	pub base: usize,
+	/// Size of the initialisation image
+	pub file_size: usize,
+	/// Total size of the TLS data (the remainder is zeroed)
+	pub mem_size: usize,
+	/// Required alignment of the TLS data
+	pub align: usize,
+}
+
+/// Size of the thread control block (self pointer and template pointer)
+pub const TCB_SIZE: usize = 2 * ::core::mem::size_of::<usize>();
+
+impl TlsTemplate
+{
+	/// Template for the current thread (set by `init_block`)
+	pub fn current() -> &'static TlsTemplate {
+		// SAFE: The thread pointer addresses a control block, the second word of which is the template
+		unsafe { &**(thread_pointer() as *const *const TlsTemplate).offset(1) }
+	}
+
+	fn align(&self) -> usize {
+		::core::cmp::max(self.align, TCB_SIZE)
+	}
+	/// Size of buffer required for `init_block` (includes space for alignment)
+	pub fn block_size(&self) -> usize {
+		self.mem_size + 2 * self.align() + TCB_SIZE
+	}
+
+	/// Initialise a thread's TLS block in the provided buffer, returning the thread pointer
+	pub unsafe fn init_block(&'static self, buf: *mut u8, buf_len: usize) -> usize {
+		assert!(buf_len >= self.block_size());
+		let align = self.align();
+		let round_up = |v: usize| (v + align - 1) / align * align;
+		let start = buf as usize;
+		let (tp, data) = if cfg!(arch="amd64") {
+				let tp = round_up(start + round_up(self.mem_size));
+				(tp, tp - round_up(self.mem_size))
+			}
+			else {
+				let tp = round_up(start);
+				(tp, tp + TCB_SIZE)
+			};
+		::core::ptr::copy_nonoverlapping(self.base as *const u8, data as *mut u8, self.file_size);
+		::core::ptr::write_bytes((data + self.file_size) as *mut u8, 0, self.mem_size - self.file_size);
+		let tcb = tp as *mut usize;
+		*tcb = tp;
+		*tcb.offset(1) = self as *const _ as usize;
+		tp
+	}
+}
+
+/// Current thread pointer (TLS base)
+pub fn thread_pointer() -> usize {
+	::arch::thread_pointer()
+}
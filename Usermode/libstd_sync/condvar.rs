@@ -0,0 +1,77 @@
+//
+//
+//
+//! Condition variable
+use core::sync::atomic::{AtomicUsize,Ordering};
+use mutex::HeldMutex;
+
+/// Condition variable, used to block a thread until an event happens (while releasing a mutex)
+///
+/// Waiters sleep on a sequence number that is incremented on each notification.
+pub struct Condvar
+{
+	seq: AtomicUsize,
+}
+
+/// Result of `Condvar::wait_timeout_ms`
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub struct WaitTimeoutResult(bool);
+impl WaitTimeoutResult
+{
+	/// Returns true if the wait ended because the timeout expired
+	pub fn timed_out(&self) -> bool {
+		self.0
+	}
+}
+
+impl Condvar
+{
+	pub const fn new() -> Condvar {
+		Condvar {
+			seq: ::core::sync::atomic::ATOMIC_USIZE_INIT,
+		}
+	}
+
+	/// Release the mutex and wait for a notification, re-locking the mutex before returning
+	///
+	/// NOTE: Spurious wakeups are possible, callers should re-check their condition
+	pub fn wait<'a, T>(&self, guard: HeldMutex<'a, T>) -> HeldMutex<'a, T> {
+		self.wait_until(guard, !0).0
+	}
+
+	/// Wait for a notification, with a timeout (in milliseconds)
+	pub fn wait_timeout_ms<'a, T>(&self, guard: HeldMutex<'a, T>, ms: u64) -> (HeldMutex<'a, T>, WaitTimeoutResult) {
+		let wake_time = ::syscalls::threads::get_time().saturating_add(ms);
+		self.wait_until(guard, wake_time)
+	}
+
+	fn wait_until<'a, T>(&self, guard: HeldMutex<'a, T>, wake_time_mono: u64) -> (HeldMutex<'a, T>, WaitTimeoutResult) {
+		let mutex = HeldMutex::mutex(&guard);
+		// NOTE: The sequence number is read before releasing the mutex, so a notification after the release isn't lost
+		let seq = self.seq.load(Ordering::SeqCst);
+		drop(guard);
+		let timed_out = match ::syscalls::sync::futex_wait(&self.seq, seq, wake_time_mono)
+			{
+			Err(::syscalls::sync::FutexError::TimedOut) => true,
+			_ => false,
+			};
+		(mutex.lock(), WaitTimeoutResult(timed_out))
+	}
+
+	/// Wake one waiting thread
+	pub fn notify_one(&self) {
+		self.seq.fetch_add(1, Ordering::SeqCst);
+		::syscalls::sync::futex_wake(&self.seq, 1);
+	}
+	/// Wake all waiting threads
+	pub fn notify_all(&self) {
+		self.seq.fetch_add(1, Ordering::SeqCst);
+		::syscalls::sync::futex_wake(&self.seq, !0);
+	}
+}
+
+impl Default for Condvar {
+	fn default() -> Condvar {
+		Condvar::new()
+	}
+}
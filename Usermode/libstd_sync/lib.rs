@@ -5,11 +5,15 @@
 #![feature(const_fn)]
 #![no_std]
 
+extern crate syscalls;
+
 pub use mutex::Mutex;
 pub use rwlock::RwLock;
+pub use condvar::Condvar;
 
 pub mod mutex;
 pub mod rwlock;
+pub mod condvar;
 
 pub use core::sync::atomic;
 
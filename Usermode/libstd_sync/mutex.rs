@@ -44,19 +44,41 @@ impl<T> Mutex<T>
 			// While the last seen value wasn't "unlocked"
 			while cur != STATE_UNLOCKED {
 				// Go to sleep if still contended when wait starts
-				//::syscalls::sync::futex_wait(&self.locked, STATE_CONTENDED);
-				panic!("TODO: Mutex::lock() - futex_wait()");
+				let _ = ::syscalls::sync::futex_wait(&self.locked, STATE_CONTENDED, !0);
 				cur = self.locked.swap(STATE_CONTENDED, Ordering::Acquire)
 			}
 		}
 		HeldMutex { ptr: self }
 	}
+
+	/// Attempt to lock the mutex without blocking
+	pub fn try_lock(&self) -> Option<HeldMutex<T>> {
+		if self.locked.compare_and_swap(STATE_UNLOCKED, STATE_UNCONTENDED, Ordering::Acquire) == STATE_UNLOCKED {
+			Some(HeldMutex { ptr: self })
+		}
+		else {
+			None
+		}
+	}
+
+	pub fn get_mut(&mut self) -> &mut T {
+		// SAFE: &mut to the mutex, so no handles can exist
+		unsafe { &mut *self.data.get() }
+	}
 }
 
 pub struct HeldMutex<'a, T: 'a>
 {
 	ptr: &'a Mutex<T>,
 }
+impl<'a, T: 'a> HeldMutex<'a, T>
+{
+	/// Obtain the mutex this handle locks (used by `Condvar` to re-acquire the lock)
+	#[doc(hidden)]
+	pub fn mutex(this: &Self) -> &'a Mutex<T> {
+		this.ptr
+	}
+}
 
 impl<'a, T: 'a> ops::Deref for HeldMutex<'a, T> {
 	type Target = T;
@@ -78,8 +100,7 @@ impl<'a, T> ::core::ops::Drop for HeldMutex<'a, T> {
 			// - Set to unlocked state
 			self.ptr.locked.store(STATE_UNLOCKED, Ordering::Release);
 			// - And wake one waiter
-			//::syscalls::sync::futex_wake(&self.ptr.locked, 1);
-			panic!("TODO: Release contended mutex");
+			::syscalls::sync::futex_wake(&self.ptr.locked, 1);
 		}
 		// In unlocked state
 	}
@@ -7,6 +7,8 @@ use mutex::Mutex;
 pub struct RwLock<T: ?Sized>
 {
 	int: ::mutex::Mutex<Inner>,
+	/// Futex word, incremented whenever a waiter should re-check the state
+	seq: AtomicUsize,
 	data: UnsafeCell<T>,
 }
 unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
@@ -15,7 +17,9 @@ unsafe impl<T: ?Sized + Send> Sync for RwLock<T> {}
 struct Inner
 {
 	readers: usize,
-	writers: usize,
+	writer: bool,
+	waiting_readers: usize,
+	waiting_writers: usize,
 }
 
 impl<T> RwLock<T>
@@ -24,8 +28,11 @@ impl<T> RwLock<T>
 		RwLock {
 			int: Mutex::new(Inner {
 				readers: 0,
-				writers: 0,
+				writer: false,
+				waiting_readers: 0,
+				waiting_writers: 0,
 				}),
+			seq: ::core::sync::atomic::ATOMIC_USIZE_INIT,
 			data: UnsafeCell::new(v),
 			}
 	}
@@ -34,41 +41,49 @@ impl<T> RwLock<T>
 impl<T: ?Sized> RwLock<T>
 {
 	pub fn write(&self) -> Write<T> {
-		loop {
-			let mut lh = self.int.lock();
-			if lh.readers > 0 {
-				panic!("TODO: RwLock::write - wait for readers to release");
-			}
-			else {
-				lh.writers += 1;
-				if lh.writers > 1 {
-					panic!("TODO: RwLock::write - wait for other writer to release");
-				}
-				return Write { p: self };
-			}
+		let mut lh = self.int.lock();
+		while lh.writer || lh.readers > 0
+		{
+			lh.waiting_writers += 1;
+			lh = self.sleep(lh);
+			lh.waiting_writers -= 1;
 		}
+		lh.writer = true;
+		Write { p: self }
 	}
 	pub fn read(&self) -> Read<T> {
-		loop {
-			let mut lh = self.int.lock();
-			if lh.readers > 0 {
-				lh.readers += 1;
-				return Read { p: self };
-			}
-			else if lh.writers > 0 {
-				panic!("TODO: RwLock::read - wait for writer to release");
-			}
-			else {
-				lh.readers += 1;
-				return Read { p: self };
-			}
+		let mut lh = self.int.lock();
+		// Writers are preferred: new readers wait while a writer is waiting
+		while lh.writer || lh.waiting_writers > 0
+		{
+			lh.waiting_readers += 1;
+			lh = self.sleep(lh);
+			lh.waiting_readers -= 1;
 		}
+		lh.readers += 1;
+		Read { p: self }
 	}
 
 	pub fn get_mut(&mut self) -> &mut T {
 		// SAFE: mut handle to UnsafeCell
 		unsafe { &mut *self.data.get() }
 	}
+
+	/// Release the state lock and wait until woken by a release
+	fn sleep<'a>(&'a self, lh: ::mutex::HeldMutex<'a, Inner>) -> ::mutex::HeldMutex<'a, Inner> {
+		// NOTE: Sequence is read with the state lock held, so a wakeup between the unlock and the wait isn't lost
+		let seq = self.seq.load(Ordering::SeqCst);
+		drop(lh);
+		let _ = ::syscalls::sync::futex_wait(&self.seq, seq, !0);
+		self.int.lock()
+	}
+	/// Wake all waiters (if there are any), they will re-check the state
+	fn wake_waiters(&self, lh: &Inner) {
+		if lh.waiting_readers > 0 || lh.waiting_writers > 0 {
+			self.seq.fetch_add(1, Ordering::SeqCst);
+			::syscalls::sync::futex_wake(&self.seq, !0);
+		}
+	}
 }
 
 pub struct Read<'a, T: ?Sized + 'a> {
@@ -85,8 +100,8 @@ impl<'a, T: 'a + ?Sized> ops::Drop for Read<'a, T> {
 	fn drop(&mut self) {
 		let mut lh = self.p.int.lock();
 		lh.readers -= 1;
-		if lh.readers == 0 && lh.writers > 0 {
-			panic!("TODO: rwlock::Read::drop - Wake writers");
+		if lh.readers == 0 {
+			self.p.wake_waiters(&lh);
 		}
 	}
 }
@@ -110,17 +125,7 @@ impl<'a, T: 'a + ?Sized> ops::DerefMut for Write<'a, T> {
 impl<'a, T: 'a + ?Sized> ops::Drop for Write<'a, T> {
 	fn drop(&mut self) {
 		let mut lh = self.p.int.lock();
-		lh.writers -= 1;
-		if lh.writers > 0 {
-			panic!("TODO: rwlock::Write::drop - Wake writers");
-		}
-		else if lh.readers > 0 {
-			panic!("TODO: rwlock::Write::drop - Wake readers");
-		}
-		else {
-			// Uncontended release
-		}
+		lh.writer = false;
+		self.p.wake_waiters(&lh);
 	}
 }
-
-
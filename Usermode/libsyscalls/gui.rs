@@ -125,6 +125,23 @@ impl Window
 		unsafe { self.0.call_2(::values::GUI_WIN_SETDIMS, dims.w as usize, dims.h as usize); }
 	}
 
+	/// System-wide window ID (as reported by `threads::Notification::WindowClosed`)
+	pub fn get_id(&self) -> u32 {
+		// SAFE: No side-effect syscall
+		unsafe { self.0.call_0(::values::GUI_WIN_GETID) as u32 }
+	}
+
+	/// Make this window a modal dialog of another window in the same group (input to the owner goes to this
+	/// window while it exists)
+	pub fn set_modal_owner(&self, owner_id: u32) -> Result<(),()> {
+		// SAFE: Syscall
+		match unsafe { self.0.call_1(::values::GUI_WIN_SETOWNER, owner_id as usize) }
+		{
+		0 => Ok( () ),
+		_ => Err( () ),
+		}
+	}
+
 	pub fn get_pos(&self) -> (u32, u32) {
 		// SAFE: No side-effect syscall
 		let v = unsafe { self.0.call_0(::values::GUI_WIN_GETPOS) };
@@ -30,6 +30,7 @@ impl ::Object for RpcChannel
 }
 define_waits!{ RpcChannelWaits => (
 	rx:has_rx = ::values::EV_IPC_RPC_RECV,
+	tx:has_tx = ::values::EV_IPC_RPC_SEND,
 )}
 impl RpcChannel
 {
@@ -47,29 +48,61 @@ impl RpcChannel
 		}
 	}
 
-	pub fn send(&self, message: RpcMessage) {
+	/// Send a message, fails if the other end hasn't received the previous message
+	pub fn send(&self, message: RpcMessage) -> Result<(), SendError> {
 		// SAFE: Syscall
-		unsafe { self.0.call_2(::values::IPC_RPC_SEND, &message as *const _ as usize, 0); }
+		let rv = unsafe { self.0.call_2(::values::IPC_RPC_SEND, &message as *const _ as usize, 0) };
+		Self::send_result(rv)
 	}
-	pub fn send_obj<T: ::Object>(&self, message: RpcMessage, object: T) {
+	/// Send a message with an attached object (the object is dropped if sending fails)
+	pub fn send_obj<T: ::Object>(&self, message: RpcMessage, object: T) -> Result<(), SendError> {
+		self.try_send_obj(message, object).map_err(|(e, _)| e)
+	}
+	/// Send a message with an attached object, returning the object if sending fails
+	pub fn try_send_obj<T: ::Object>(&self, message: RpcMessage, object: T) -> Result<(), (SendError, T)> {
 		// SAFE: Syscall
-		unsafe { self.0.call_2(::values::IPC_RPC_SEND, &message as *const _ as usize, object.into_handle().into_raw() as usize); }
+		let rv = unsafe { self.0.call_2(::values::IPC_RPC_SEND, &message as *const _ as usize, object.handle().0 as usize) };
+		match Self::send_result(rv)
+		{
+		Ok( () ) => {
+			// The kernel now owns the object
+			object.into_handle().into_raw();
+			Ok( () )
+			},
+		Err(e) => Err( (e, object) ),
+		}
+	}
+	fn send_result(rv: u64) -> Result<(), SendError> {
+		match super::to_result(rv as usize)
+		{
+		Ok(_) => Ok( () ),
+		Err(::values::IPC_RPC_ERR_FULL) => Err(SendError::Full),
+		Err(::values::IPC_RPC_ERR_OBJECT) => Err(SendError::BadObject),
+		Err(::values::IPC_RPC_ERR_CLOSED) => Err(SendError::ConnectionClosed),
+		Err(e) => panic!("RpcChannel::send - Unknown error {}", e),
+		}
 	}
 	pub fn try_receive(&self) -> Result< (RpcMessage, Option<::AnyObject>), RxError> {
 		let mut msg: RpcMessage = Default::default();
 		// SAFE: Syscall
 		let rv = unsafe { self.0.call_1(::values::IPC_RPC_RECV, &mut msg as *mut _ as usize) };
-		if rv < 0x1000 {
-			Ok( (msg, if rv > 0 { Some(::AnyObject(::ObjectHandle(rv as u32))) } else { None }) )
-		}
-		else {
-			unimplemented!()
+		match super::to_result(rv as usize)
+		{
+		Ok(0) => Ok( (msg, None) ),
+		Ok(h) => Ok( (msg, Some(::AnyObject(::ObjectHandle(h)))) ),
+		Err(::values::IPC_RPC_ERR_EMPTY) => Err(RxError::NoMessage),
+		Err(::values::IPC_RPC_ERR_CLOSED) => Err(RxError::ConnectionClosed),
+		Err(e) => panic!("RpcChannel::try_receive - Unknown error {}", e),
 		}
 	}
 
 	pub fn wait_rx(&self) -> ::WaitItem {
 		::values::WaitItem { object: self.0 .0, flags: ::values::EV_IPC_RPC_RECV }
 	}
+	/// Wait for the other end to receive the previous message (i.e. `send` won't fail with `SendError::Full`)
+	pub fn wait_tx(&self) -> ::WaitItem {
+		::values::WaitItem { object: self.0 .0, flags: ::values::EV_IPC_RPC_SEND }
+	}
 }
 
 #[derive(Debug)]
@@ -82,3 +115,260 @@ pub enum RxError
 #[derive(Debug)]
 pub struct NewError( () );
 
+
+/// Message channel endpoint
+///
+/// Carries variable-length messages (up to `MAX_DATA` bytes) with up to `MAX_OBJECTS` attached objects. Each
+/// direction has a bounded queue; `try_send` fails with `SendError::Full` when the queue is full, while `send`
+/// waits for space.
+pub struct Channel(::ObjectHandle);
+
+/// Maximum size of a message's data
+pub const MAX_DATA: usize = ::values::IPC_CHAN_MAX_DATA;
+/// Maximum number of objects attached to a message
+pub const MAX_OBJECTS: usize = ::values::IPC_CHAN_MAX_OBJECTS;
+
+impl ::Object for Channel
+{
+	const CLASS: u16 = ::values::CLASS_IPC_CHANNEL;
+	fn class() -> u16 { Self::CLASS }
+	fn from_handle(handle: ::ObjectHandle) -> Self {
+		Channel(handle)
+	}
+	fn into_handle(self) -> ::ObjectHandle {
+		self.0
+	}
+	fn handle(&self) -> &::ObjectHandle {
+		&self.0
+	}
+
+	type Waits = ChannelWaits;
+}
+define_waits!{ ChannelWaits => (
+	rx:has_rx = ::values::EV_IPC_CHAN_RECV,
+	tx:has_tx = ::values::EV_IPC_CHAN_SEND,
+)}
+
+/// Error from `Channel::try_send` and `RpcChannel::send`
+#[derive(Debug)]
+pub enum SendError
+{
+	/// The queue is full
+	Full,
+	/// The other end has been closed
+	ConnectionClosed,
+	/// The message data or object list is too large
+	TooLarge,
+	/// An attached object couldn't be sent
+	BadObject,
+}
+/// Error from `Channel::try_recv`
+#[derive(Debug)]
+pub enum RecvError
+{
+	/// No message is waiting
+	NoMessage,
+	/// The other end has been closed (and all messages have been received)
+	ConnectionClosed,
+	/// The buffers are too small for the next message (size and object count are provided)
+	TooSmall(usize, usize),
+	/// There's no space for the attached objects
+	NoObjectSpace,
+	/// The message is shorter than the requested type (from `try_recv_typed`, the message is consumed)
+	WrongSize(usize),
+}
+
+/// Plain data that can be sent as a typed message (see `Channel::send_typed`)
+///
+/// UNSAFE: Implementors must be valid for any bit pattern, and contain no pointers, references or handles
+pub unsafe trait Pod: Copy {}
+macro_rules! impl_pod {
+	( $($t:ty),* ) => { $( unsafe impl Pod for $t {} )* };
+}
+impl_pod!{ u8, u16, u32, u64, usize, i8, i16, i32, i64, isize }
+
+/// Information about a received message
+#[derive(Debug)]
+pub struct Received
+{
+	/// Length of the message data
+	pub len: usize,
+	/// Number of attached objects (stored in the first entries of the object buffer)
+	pub objects: usize,
+}
+
+impl Channel
+{
+	pub fn new_pair() -> Result< (Channel, Channel), NewError > {
+		// SAFE: Zero-operand syscall
+		let rv = unsafe { syscall!(IPC_NEWCHANNEL) };
+		if rv == !0 {
+			Err( NewError(()) )
+		}
+		else {
+			let l = super::ObjectHandle::new( (rv & 0xFFFFFFFF) as usize ).expect("Channel::new_pair - left bad");
+			let r = super::ObjectHandle::new( (rv >> 32) as usize ).expect("Channel::new_pair - right bad");
+
+			Ok( (Channel(l), Channel(r)) )
+		}
+	}
+
+	/// Send a message (without objects), failing if the queue is full
+	pub fn try_send(&self, data: &[u8]) -> Result<(), SendError> {
+		self.try_send_objects(data, &mut [])
+	}
+	/// Send a message with attached objects, failing if the queue is full
+	///
+	/// On success, the objects are taken out of `objects` (leaving `None`). On failure they are left in place.
+	pub fn try_send_objects(&self, data: &[u8], objects: &mut [Option<::AnyObject>]) -> Result<(), SendError> {
+		if objects.len() > MAX_OBJECTS {
+			return Err(SendError::TooLarge);
+		}
+		let mut handles = [0u32; MAX_OBJECTS];
+		for (h, o) in handles.iter_mut().zip(objects.iter()) {
+			*h = o.as_ref().expect("Channel::try_send_objects - Object already sent").0 .0;
+		}
+		let handles = &handles[.. objects.len()];
+		// SAFE: Syscall with valid buffers
+		let rv = unsafe { self.0.call_4(::values::IPC_CHAN_SEND, data.as_ptr() as usize, data.len(), handles.as_ptr() as usize, handles.len()) };
+		match super::to_result(rv as usize)
+		{
+		Ok(_) => {
+			// The kernel now owns the objects, forget the handles
+			for o in objects.iter_mut() {
+				::core::mem::forget( o.take() );
+			}
+			Ok( () )
+			},
+		Err(::values::IPC_CHAN_ERR_FULL) => Err(SendError::Full),
+		Err(::values::IPC_CHAN_ERR_CLOSED) => Err(SendError::ConnectionClosed),
+		Err(::values::IPC_CHAN_ERR_TOOLARGE) => Err(SendError::TooLarge),
+		Err(::values::IPC_CHAN_ERR_OBJECT) => Err(SendError::BadObject),
+		Err(e) => panic!("Channel::try_send_objects - Unknown error {}", e),
+		}
+	}
+	/// Send a message, waiting for space in the queue
+	pub fn send(&self, data: &[u8]) -> Result<(), SendError> {
+		self.send_objects(data, &mut [])
+	}
+	/// Send a message with attached objects, waiting for space in the queue
+	pub fn send_objects(&self, data: &[u8], objects: &mut [Option<::AnyObject>]) -> Result<(), SendError> {
+		loop
+		{
+			match self.try_send_objects(data, objects)
+			{
+			Err(SendError::Full) => {
+				::threads::wait(&mut [self.wait_tx()], !0);
+				},
+			v @ _ => return v,
+			}
+		}
+	}
+
+	/// Send a typed message (the raw bytes of `msg`), failing if the queue is full
+	pub fn try_send_typed<T: Pod>(&self, msg: &T) -> Result<(), SendError> {
+		self.try_send( Self::typed_bytes(msg) )
+	}
+	/// Send a typed message, waiting for space in the queue
+	pub fn send_typed<T: Pod>(&self, msg: &T) -> Result<(), SendError> {
+		self.send( Self::typed_bytes(msg) )
+	}
+	fn typed_bytes<T: Pod>(msg: &T) -> &[u8] {
+		// SAFE: Pod types are plain data, size is that of the type
+		unsafe { ::core::slice::from_raw_parts(msg as *const T as *const u8, ::core::mem::size_of::<T>()) }
+	}
+
+	/// Get the data length and object count of the next message (without receiving it)
+	pub fn peek(&self) -> Result<Received, RecvError> {
+		// SAFE: Syscall
+		let rv = unsafe { self.0.call_0(::values::IPC_CHAN_PEEK) };
+		Self::decode_recv(rv)
+	}
+	/// Receive a message if one is waiting
+	///
+	/// Attached objects are stored in the first entries of `objects`
+	pub fn try_recv(&self, data: &mut [u8], objects: &mut [Option<::AnyObject>]) -> Result<Received, RecvError> {
+		let mut handles = [0u32; MAX_OBJECTS];
+		let n_handles = ::core::cmp::min(objects.len(), MAX_OBJECTS);
+		// SAFE: Syscall with valid buffers
+		let rv = unsafe { self.0.call_4(::values::IPC_CHAN_RECV, data.as_mut_ptr() as usize, data.len(), handles.as_mut_ptr() as usize, n_handles) };
+		let info = match Self::decode_recv(rv)
+			{
+			// Report the size of the waiting message
+			Err(RecvError::TooSmall(..)) => {
+				let next = try!(self.peek());
+				return Err(RecvError::TooSmall(next.len, next.objects));
+				},
+			v @ _ => try!(v),
+			};
+		for (o, &h) in objects.iter_mut().zip(handles[.. info.objects].iter()) {
+			*o = if h == !0 { None } else { Some(::AnyObject(::ObjectHandle(h))) };
+		}
+		Ok(info)
+	}
+	/// Receive a message, waiting for one to arrive
+	pub fn recv(&self, data: &mut [u8], objects: &mut [Option<::AnyObject>]) -> Result<Received, RecvError> {
+		loop
+		{
+			match self.try_recv(data, objects)
+			{
+			Err(RecvError::NoMessage) => {
+				::threads::wait(&mut [self.wait_rx()], !0);
+				},
+			v @ _ => return v,
+			}
+		}
+	}
+
+	/// Receive a typed message if one is waiting
+	///
+	/// Fails with `RecvError::WrongSize` if the message is shorter than `T` (or `TooSmall` if it's longer)
+	pub fn try_recv_typed<T: Pod>(&self) -> Result<T, RecvError> {
+		// SAFE: Pod types are valid for any bit pattern
+		let mut rv: T = unsafe { ::core::mem::zeroed() };
+		let info = {
+			// SAFE: Pod types are plain data, size is that of the type
+			let buf = unsafe { ::core::slice::from_raw_parts_mut(&mut rv as *mut T as *mut u8, ::core::mem::size_of::<T>()) };
+			try!(self.try_recv(buf, &mut []))
+			};
+		if info.len != ::core::mem::size_of::<T>() {
+			Err( RecvError::WrongSize(info.len) )
+		}
+		else {
+			Ok(rv)
+		}
+	}
+	/// Receive a typed message, waiting for one to arrive
+	pub fn recv_typed<T: Pod>(&self) -> Result<T, RecvError> {
+		loop
+		{
+			match self.try_recv_typed()
+			{
+			Err(RecvError::NoMessage) => {
+				::threads::wait(&mut [self.wait_rx()], !0);
+				},
+			v @ _ => return v,
+			}
+		}
+	}
+
+	fn decode_recv(rv: u64) -> Result<Received, RecvError> {
+		match super::to_result(rv as usize)
+		{
+		Ok(v) => Ok(Received { len: (v & 0xFFFF) as usize, objects: (v >> 16) as usize }),
+		Err(::values::IPC_CHAN_ERR_EMPTY) => Err(RecvError::NoMessage),
+		Err(::values::IPC_CHAN_ERR_CLOSED) => Err(RecvError::ConnectionClosed),
+		// - Sizes are filled by `try_recv`
+		Err(::values::IPC_CHAN_ERR_TOOLARGE) => Err(RecvError::TooSmall(0, 0)),
+		Err(::values::IPC_CHAN_ERR_OBJECT) => Err(RecvError::NoObjectSpace),
+		Err(e) => panic!("Channel::try_recv - Unknown error {}", e),
+		}
+	}
+
+	pub fn wait_rx(&self) -> ::WaitItem {
+		self.0.get_wait(::values::EV_IPC_CHAN_RECV)
+	}
+	pub fn wait_tx(&self) -> ::WaitItem {
+		self.0.get_wait(::values::EV_IPC_CHAN_SEND)
+	}
+}
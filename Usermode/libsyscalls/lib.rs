@@ -165,6 +165,14 @@ impl ObjectHandle
 	def_call!{ call_5,call_5_v => syscall_5(a1, a2, a3, a4, a5) }
 	def_call!{ call_6,call_6_v => syscall_6(a1, a2, a3, a4, a5, a6) }
 
+	#[allow(dead_code)]
+	#[inline]
+	unsafe fn call_1l(&self, call: u16, a1: u64) -> u64 {
+		#[cfg(target_pointer_width="64")]
+		{ return ::raw::syscall_1( self.call_value(call), a1 as usize ) }
+		#[cfg(target_pointer_width="32")]
+		{ return ::raw::syscall_2( self.call_value(call), (a1 & 0xFFFFFFFF) as usize, (a1 >> 32) as usize ) }
+	}
 	#[allow(dead_code)]
 	#[inline]
 	unsafe fn call_2l(&self, call: u16, a1: u64, a2: usize) -> u64 {
@@ -190,6 +198,21 @@ impl ObjectHandle
 		#[cfg(target_pointer_width="32")]
 		return ::raw::syscall_5( self.call_value(call), (a1 & 0xFFFFFFFF) as usize, (a1 >> 32) as usize, a2, a3, a4 );
 	}
+
+	#[inline]
+	unsafe fn call_2ll(&self, call: u16, a1: u64, a2: u64) -> u64 {
+		#[cfg(target_pointer_width="64")]
+		{ return ::raw::syscall_2( self.call_value(call), a1 as usize, a2 as usize ) }
+		#[cfg(target_pointer_width="32")]
+		{ return ::raw::syscall_4( self.call_value(call), (a1 & 0xFFFFFFFF) as usize, (a1 >> 32) as usize, (a2 & 0xFFFFFFFF) as usize, (a2 >> 32) as usize ) }
+	}
+	#[inline]
+	unsafe fn call_3ll(&self, call: u16, a1: u64, a2: u64, a3: usize) -> u64 {
+		#[cfg(target_pointer_width="64")]
+		{ return ::raw::syscall_3( self.call_value(call), a1 as usize, a2 as usize, a3 ) }
+		#[cfg(target_pointer_width="32")]
+		{ return ::raw::syscall_5( self.call_value(call), (a1 & 0xFFFFFFFF) as usize, (a1 >> 32) as usize, (a2 & 0xFFFFFFFF) as usize, (a2 >> 32) as usize, a3 ) }
+	}
 }
 impl Drop for ObjectHandle {
 	fn drop(&mut self) {
@@ -204,6 +227,10 @@ impl Drop for ObjectHandle {
 pub struct AnyObject(::ObjectHandle);
 impl AnyObject
 {
+	/// Type-erase an object (e.g. to attach it to an IPC message)
+	pub fn new<T: ::Object>(obj: T) -> AnyObject {
+		AnyObject(obj.into_handle())
+	}
 	pub fn downcast<T: ::Object>(self) -> Result<T, Self> {
 		if self.0.get_class() == Ok(T::CLASS) {
 			Ok( T::from_handle(self.0) )
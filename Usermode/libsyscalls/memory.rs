@@ -12,25 +12,108 @@ pub enum ProtectionMode
 	ReadWriteExecute = 3,
 }
 
-#[derive(Debug)]
-pub struct Error;
+#[derive(Debug,PartialEq)]
+pub enum Error
+{
+	/// The requested range is already in use
+	RangeInUse,
+	/// The system is out of physical memory
+	OutOfMemory,
+	/// The process's page quota would be exceeded
+	QuotaExceeded,
+	/// The address was not valid for the operation
+	BadAddress,
+	/// A writable mapping of a read-only shared memory object was requested
+	ReadOnly,
+}
+impl Error
+{
+	fn from_code(v: u32) -> Error {
+		match v
+		{
+		::values::MEM_ERR_INUSE => Error::RangeInUse,
+		::values::MEM_ERR_OUTOFMEMORY => Error::OutOfMemory,
+		::values::MEM_ERR_QUOTA => Error::QuotaExceeded,
+		::values::MEM_ERR_READONLY => Error::ReadOnly,
+		_ => Error::BadAddress,
+		}
+	}
+}
 
 #[inline]
 pub unsafe fn allocate(addr: usize, count: usize) -> Result<(), Error> {
 	super::to_result( syscall!(MEM_ALLOCATE, addr, count) as usize )
 		.map(|_| ())
-		.map_err(|_| Error)
+		.map_err(Error::from_code)
 }
 #[inline]
 pub unsafe fn reprotect(addr: usize, protection: ProtectionMode) -> Result<(), Error> {
 	super::to_result( syscall!(MEM_REPROTECT, addr, protection as u8 as usize) as usize )
 		.map(|_| ())
-		.map_err(|_| Error)
+		.map_err(|_| Error::BadAddress)
 }
 #[inline]
 pub unsafe fn deallocate(addr: usize) -> Result<(), Error> {
 	super::to_result( syscall!(MEM_DEALLOCATE, addr) as usize )
 		.map(|_| ())
-		.map_err(|_| Error)
+		.map_err(|_| Error::BadAddress)
 }
 
+
+/// Anonymous shared memory
+///
+/// Every mapping (in this or any other process the object is sent to) refers to the same memory. Pages are
+/// unmapped individually using `deallocate`.
+pub struct SharedMemory(::ObjectHandle);
+impl ::Object for SharedMemory
+{
+	const CLASS: u16 = ::values::CLASS_MEM_SHARED;
+	fn class() -> u16 { Self::CLASS }
+	fn from_handle(handle: ::ObjectHandle) -> Self {
+		SharedMemory(handle)
+	}
+	fn into_handle(self) -> ::ObjectHandle {
+		self.0
+	}
+	fn handle(&self) -> &::ObjectHandle {
+		&self.0
+	}
+
+	type Waits = ();
+}
+impl SharedMemory
+{
+	/// Create a new (zero-filled) shared memory object `page_count` pages long
+	pub fn new(page_count: usize) -> Result<SharedMemory, Error> {
+		// SAFE: Syscall
+		let rv = unsafe { syscall!(MEM_NEWSHARED, page_count) };
+		::ObjectHandle::new(rv as usize)
+			.map(|h| SharedMemory(h))
+			.map_err(Error::from_code)
+	}
+	/// Size of the object in pages
+	pub fn page_count(&self) -> usize {
+		// SAFE: Syscall
+		unsafe { self.0.call_0(::values::MEM_SHARED_GETSIZE) as usize }
+	}
+	/// Map the entire object at `addr`
+	///
+	/// UNSAFE: The memory can be changed by other processes at any time
+	pub unsafe fn map(&self, addr: usize, protection: ProtectionMode) -> Result<(), Error> {
+		super::to_result( self.0.call_2(::values::MEM_SHARED_MAP, addr, protection as u8 as usize) as usize )
+			.map(|_| ())
+			.map_err(Error::from_code)
+	}
+	/// Obtain a handle that only allows read-only (or executable) mappings
+	pub fn clone_readonly(&self) -> SharedMemory {
+		// SAFE: Syscall
+		let rv = unsafe { self.0.call_0(::values::MEM_SHARED_CLONERO) };
+		SharedMemory( ::ObjectHandle::new(rv as usize).expect("SharedMemory::clone_readonly") )
+	}
+}
+impl Clone for SharedMemory
+{
+	fn clone(&self) -> SharedMemory {
+		SharedMemory( self.0.try_clone().expect("SharedMemory::clone") )
+	}
+}
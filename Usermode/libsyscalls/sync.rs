@@ -6,6 +6,50 @@ use core::ops;
 use core::sync::atomic::{AtomicUsize,Ordering};
 use core::cell::UnsafeCell;
 
+/// Error from `futex_wait`
+#[derive(Debug,Copy,Clone,PartialEq,Eq)]
+pub enum FutexError
+{
+	/// The word didn't hold the expected value (the thread didn't sleep)
+	Changed,
+	/// The timeout expired before the thread was woken
+	TimedOut,
+}
+
+/// Sleep until woken by `futex_wake`, provided that `word` still contains `value`
+///
+/// `wake_time_mono` is an absolute time (see `threads::get_time`), or `!0` to wait forever.
+pub fn futex_wait(word: &AtomicUsize, value: usize, wake_time_mono: u64) -> Result<(), FutexError> {
+	// SAFE: Syscall
+	let rv = unsafe {
+		#[cfg(target_pointer_width="64")]
+		let rv = syscall!(CORE_FUTEXWAIT, word as *const _ as usize, value, wake_time_mono as usize);
+		#[cfg(target_pointer_width="32")]
+		let rv = syscall!(CORE_FUTEXWAIT, word as *const _ as usize, value, (wake_time_mono & 0xFFFFFFFF) as usize, (wake_time_mono >> 32) as usize);
+		rv
+		};
+	match ::to_result(rv as usize)
+	{
+	Ok(_) => Ok( () ),
+	Err(::values::FUTEX_ERR_CHANGED) => Err( FutexError::Changed ),
+	Err(::values::FUTEX_ERR_TIMEDOUT) => Err( FutexError::TimedOut ),
+	Err(e) => panic!("futex_wait - Unknown error {}", e),
+	}
+}
+/// Wake up to `count` threads waiting on `word`, returns the number woken
+pub fn futex_wake(word: &AtomicUsize, count: usize) -> usize {
+	// SAFE: Syscall
+	unsafe { syscall!(CORE_FUTEXWAKE, word as *const _ as usize, count) as usize }
+}
+
+// NOTE: Unlock code requires these exact values
+/// Lock is unlocked
+const STATE_UNLOCKED   : usize = 0;
+/// Locked, with nothing waiting
+const STATE_UNCONTENDED: usize = 1;
+/// Locked, and maybe something waiting
+const STATE_CONTENDED  : usize = 2;
+
 /// Primitive Mutex
 pub struct Mutex<T>(AtomicUsize, UnsafeCell<T>);
 unsafe impl<T: Send> Sync for Mutex<T> {}
@@ -13,28 +57,37 @@ unsafe impl<T: Send> Send for Mutex<T> {}
 impl<T> Mutex<T>
 {
 	pub const fn new(v: T) -> Mutex<T> {
-		Mutex( AtomicUsize::new(1), UnsafeCell::new(v) )
+		Mutex( AtomicUsize::new(STATE_UNLOCKED), UnsafeCell::new(v) )
 	}
 
 	pub fn lock(&self) -> HeldMutex<T> {
-		if self.0.fetch_sub(1, Ordering::Acquire) == 1 {
-			HeldMutex { _ptr: self, }
-		}
-		else {
-			panic!("TODO: Acquire Mutex when contended");
+		let mut cur = self.0.compare_and_swap(STATE_UNLOCKED, STATE_UNCONTENDED, Ordering::Acquire);
+		if cur != STATE_UNLOCKED
+		{
+			// Mark as contended (so the holder wakes us), then sleep until it's released
+			if cur != STATE_CONTENDED {
+				cur = self.0.swap(STATE_CONTENDED, Ordering::Acquire);
+			}
+			while cur != STATE_UNLOCKED {
+				let _ = futex_wait(&self.0, STATE_CONTENDED, !0);
+				cur = self.0.swap(STATE_CONTENDED, Ordering::Acquire);
+			}
 		}
+		HeldMutex { _ptr: self, }
 	}
 
 	pub fn unwrap(self) -> T {
-		assert_eq!( self.0.load(Ordering::Relaxed), 0 );
+		assert_eq!( self.0.load(Ordering::Relaxed), STATE_UNLOCKED );
 		// SAFE: By-value self, so no aliasing
 		unsafe { self.1.into_inner() }
 	}
 
 	/// UNSAFE: User needs to ensure that resources are no longer borrowed
 	pub unsafe fn unlock(&self) {
-		if self.0.fetch_add(1, Ordering::Release) != 0 {
-			panic!("TODO: Release Mutex when contended");
+		// If the lock was contended, fully release it and wake a waiter
+		if self.0.fetch_sub(1, Ordering::Release) != STATE_UNCONTENDED {
+			self.0.store(STATE_UNLOCKED, Ordering::Release);
+			futex_wake(&self.0, 1);
 		}
 	}
 }
@@ -61,9 +114,7 @@ impl<'a, T: 'a> ops::DerefMut for HeldMutex<'a, T>
 impl<'a, T: 'a> ops::Drop for HeldMutex<'a, T>
 {
 	fn drop(&mut self) {
-		if self._ptr.0.fetch_add(1, Ordering::Release) != 0 {
-			panic!("TODO: Release Mutex when contended");
-		}
+		// SAFE: This handle owns the lock, and is being dropped
+		unsafe { self._ptr.unlock() }
 	}
 }
-
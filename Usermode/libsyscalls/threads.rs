@@ -3,6 +3,23 @@
 //
 //! Thread management system calls
 
+pub use values::{FaultInfo, FaultImage, EXIT_STATUS_FAULT, FAULT_NREGS, FAULT_MAX_IMAGES, FAULT_IMAGE_NAME_LEN};
+pub use values::{FAULT_KIND_PAGE, FAULT_KIND_PROTECTION, FAULT_KIND_INVALIDOPCODE, FAULT_KIND_OTHER};
+
+/// Process control notification (see `ThisProcess::recv_control`)
+#[derive(Copy,Clone,Debug,PartialEq,Eq)]
+pub enum Notification
+{
+	/// Another process (e.g. the session manager) asked this process to exit cleanly
+	TerminateRequest,
+	/// A child process exited (PID, exit status)
+	ChildExited(u32, u32),
+	/// A window owned by this process was closed by the window manager (see `gui::Window::get_id`)
+	WindowClosed(u32),
+	/// Application-defined notification (see `Process::notify`)
+	User(u32),
+}
+
 #[derive(Debug)]
 pub enum RecvObjectError
 {
@@ -19,24 +36,79 @@ impl ::core::fmt::Display for RecvObjectError {
 	}
 }
 
+/// Start a new thread in this process, with the provided entrypoint, stack pointer, and TLS base
+///
+/// `arg` is passed as the first argument to the entrypoint
 #[inline]
-pub unsafe fn start_thread(ip: usize, sp: usize, tlsbase: usize) -> Result<u32, u32> {
-	::to_result( syscall!(CORE_STARTTHREAD, ip, sp, tlsbase) as usize )
+pub unsafe fn start_thread(ip: usize, sp: usize, tlsbase: usize, arg: usize) -> Result<Thread, u32> {
+	::ObjectHandle::new( syscall!(CORE_STARTTHREAD, ip, sp, tlsbase, arg) as usize ).map(|v| Thread(v))
 }
+/// Terminate the current thread, with the provided exit value (see `Thread::join`)
 #[inline]
-pub fn exit_thread() -> ! {
+pub fn exit_thread(value: u32) -> ! {
 	// SAFE: Syscall
 	unsafe {
-		syscall!(CORE_EXITTHREAD);
+		syscall!(CORE_EXITTHREAD, value as usize);
 		::core::intrinsics::unreachable();
 	}
 }
+/// Set the current thread's TLS base (thread pointer)
+#[inline]
+pub unsafe fn set_tls_base(base: usize) {
+	syscall!(CORE_SETTLSBASE, base);
+}
+
+define_waits!{ ThreadWaits => (
+	terminate:get_terminate = ::values::EV_THREAD_TERMINATED,
+)}
+/// Handle to a thread started with `start_thread` (dropping it detaches the thread)
+pub struct Thread(::ObjectHandle);
+impl Thread {
+	/// Read the thread's exit value (`None` if it's still running)
+	#[inline]
+	pub fn get_exit_value(&self) -> Option<u32> {
+		// SAFE: Syscall
+		match unsafe { self.0.call_0(::values::CORE_THREAD_GETEXITVALUE) }
+		{
+		!0 => None,
+		v => Some(v as u32),
+		}
+	}
+
+	#[inline]
+	pub fn wait_terminate(&self) -> ::values::WaitItem {
+		self.0.get_wait(::values::EV_THREAD_TERMINATED)
+	}
+
+	/// Block until the thread terminates, returning its exit value
+	pub fn join(self) -> u32 {
+		loop
+		{
+			if let Some(value) = self.get_exit_value() {
+				return value;
+			}
+			wait(&mut [self.wait_terminate()], !0);
+		}
+	}
+}
+impl ::Object for Thread {
+	const CLASS: u16 = ::values::CLASS_CORE_THREAD;
+	fn class() -> u16 { Self::CLASS }
+	fn from_handle(handle: ::ObjectHandle) -> Self {
+		Thread(handle)
+	}
+	fn into_handle(self) -> ::ObjectHandle { self.0 }
+	fn handle(&self) -> &::ObjectHandle { &self.0 }
+	
+	type Waits = ThreadWaits;
+}
 
 // Object 0 : This process
 /// Current process handle
 pub static S_THIS_PROCESS: ThisProcess = ThisProcess;//( ::ObjectHandle(0) );
 
 define_waits!{ ThisProcessWaits => (
+	control:get_control = ::values::EV_THISPROCESS_CONTROL,
 )}
 
 /// 
@@ -64,6 +136,34 @@ impl ThisProcess
 			}
 		)
 	}
+
+	#[inline]
+	/// Wait item that fires when a control notification is queued
+	pub fn wait_control(&self) -> ::values::WaitItem {
+		::values::WaitItem { object: 0, flags: ::values::EV_THISPROCESS_CONTROL }
+	}
+	/// Take the next control notification (`None` if there are none queued)
+	pub fn recv_control(&self) -> Option<Notification> {
+		let mut msg = ::values::ControlMessage::default();
+		// SAFE: Syscall with a valid output pointer
+		let rv = self.with_obj(|obj| unsafe { obj.call_1(::values::CORE_THISPROCESS_RECVCONTROL, &mut msg as *mut _ as usize) });
+		if rv == 0 {
+			return None;
+		}
+		Some( match msg.kind
+			{
+			::values::CONTROL_KIND_TERMINATE => Notification::TerminateRequest,
+			::values::CONTROL_KIND_CHILDEXITED => Notification::ChildExited(msg.source, msg.value),
+			::values::CONTROL_KIND_WINDOWCLOSED => Notification::WindowClosed(msg.source),
+			_ => Notification::User(msg.value),
+			} )
+	}
+
+	/// Record the images loaded in this process, so fault reports can be symbolised (used by the loader)
+	pub fn set_images(&self, images: &[FaultImage]) {
+		// SAFE: Syscall with a valid input slice
+		self.with_obj(|obj| unsafe { obj.call_2(::values::CORE_THISPROCESS_SETIMAGES, images.as_ptr() as usize, images.len()) });
+	}
 }
 impl ::Object for ThisProcess {
 	const CLASS: u16 = ::values::CLASS_CORE_THISPROCESS;
@@ -119,6 +219,17 @@ impl ProtoProcess
 		// SAFE: Syscall
 		unsafe { self.0.call_2l(::values::CORE_PROTOPROCESS_SENDOBJ, ::values::FixedStr8::from(tag).into(), oh as usize); }
 	}
+
+	#[inline]
+	/// Limit the number of pages the child process can allocate (must not exceed this process's own quota)
+	pub fn set_page_quota(&self, pages: usize) -> Result<(),()> {
+		// SAFE: Syscall
+		match super::to_result( unsafe { self.0.call_1(::values::CORE_PROTOPROCESS_SETQUOTA, pages) } as usize )
+		{
+		Ok(_) => Ok( () ),
+		Err(_) => Err( () ),
+		}
+	}
  
  	#[inline]
 	pub fn start(self, entry: usize, stack: usize) -> Process {
@@ -143,6 +254,60 @@ impl Process {
 	pub fn wait_terminate(&self) -> ::values::WaitItem {
 		self.0.get_wait(::values::EV_PROCESS_TERMINATED)
 	}
+
+	/// Ask the process to exit cleanly (delivers `Notification::TerminateRequest`)
+	///
+	/// Returns false if the process has already exited
+	#[inline]
+	pub fn request_exit(&self) -> bool {
+		// SAFE: Syscall
+		unsafe { self.0.call_2(::values::CORE_PROCESS_NOTIFY, ::values::CONTROL_KIND_TERMINATE as usize, 0) == 0 }
+	}
+	/// Send an application-defined notification (`Notification::User`)
+	///
+	/// Returns false if the process has exited, or its notification queue is full
+	#[inline]
+	pub fn notify(&self, value: u32) -> bool {
+		// SAFE: Syscall
+		unsafe { self.0.call_2(::values::CORE_PROCESS_NOTIFY, ::values::CONTROL_KIND_USER as usize, value as usize) == 0 }
+	}
+
+	/// Read the process's exit status (`None` if it's still running)
+	///
+	/// Statuses of processes killed by the kernel (or via `terminate`) are the `EXIT_STATUS_*` values
+	#[inline]
+	pub fn get_exit_status(&self) -> Option<u32> {
+		// SAFE: Syscall
+		match unsafe { self.0.call_0(::values::CORE_PROCESS_GETEXITSTATUS) }
+		{
+		!0 => None,
+		v => Some(v as u32),
+		}
+	}
+
+	/// Read the report of the fault that terminated the process
+	///
+	/// Returns `None` if the process is still running, or exited without faulting
+	pub fn get_fault(&self) -> Option<FaultInfo> {
+		let mut info = FaultInfo::default();
+		// SAFE: Syscall with a valid output pointer
+		match unsafe { self.0.call_1(::values::CORE_PROCESS_GETFAULT, &mut info as *mut _ as usize) }
+		{
+		0 => None,
+		_ => Some(info),
+		}
+	}
+
+	/// Block until the process terminates, returning its exit status
+	pub fn wait(&self) -> u32 {
+		loop
+		{
+			if let Some(status) = self.get_exit_status() {
+				return status;
+			}
+			wait(&mut [self.wait_terminate()], !0);
+		}
+	}
 }
 impl ::Object for Process {
 	const CLASS: u16 = ::values::CLASS_CORE_PROCESS;
@@ -186,3 +351,69 @@ pub fn wait(items: &mut [WaitItem], wake_time_mono: u64) -> u32 {
 	}
 }
 
+/// Read the monotonic timer (milliseconds since an arbitary point, usually system startup)
+#[inline]
+pub fn get_time() -> u64 {
+	// SAFE: Syscall
+	unsafe { syscall!(CORE_GETTIME) }
+}
+
+/// Read the wall-clock time, in milliseconds since the UNIX epoch (`None` if the kernel doesn't know it)
+pub fn get_realtime() -> Option<u64> {
+	// SAFE: Syscall
+	match unsafe { syscall!(CORE_GETREALTIME) }
+	{
+	!0 => None,
+	v => Some(v),
+	}
+}
+
+/// Scheduling priority class
+#[derive(Copy,Clone,Debug,PartialEq,Eq,PartialOrd,Ord)]
+pub enum Priority
+{
+	/// Only runs when nothing else is runnable
+	Idle,
+	/// Default class
+	Normal,
+}
+impl Priority
+{
+	fn from_raw(v: u32) -> Priority {
+		match v
+		{
+		::values::PRIORITY_IDLE => Priority::Idle,
+		_ => Priority::Normal,
+		}
+	}
+}
+
+/// Lower the current process's priority class (affects all threads in the process)
+///
+/// Returns the previous class, or `Err` with the current class if the request would raise it
+pub fn lower_priority(prio: Priority) -> Result<Priority,Priority> {
+	let class = match prio
+		{
+		Priority::Idle => ::values::PRIORITY_IDLE,
+		Priority::Normal => ::values::PRIORITY_NORMAL,
+		};
+	// SAFE: Syscall
+	match ::to_result( unsafe { syscall!(CORE_SETPRIORITY, class as usize) } as usize )
+	{
+	Ok(v) => Ok(Priority::from_raw(v)),
+	Err(v) => Err(Priority::from_raw(v)),
+	}
+}
+
+/// Read the CPU time used by the current thread, in milliseconds
+#[inline]
+pub fn get_cpu_time() -> u64 {
+	// SAFE: Syscall
+	unsafe { syscall!(CORE_GETCPUTIME) }
+}
+
+/// Block the current thread for (at least) the specified number of milliseconds
+pub fn sleep(ms: u64) {
+	wait(&mut [], get_time() + ms);
+}
+
@@ -11,13 +11,29 @@ pub struct File(super::ObjectHandle, u64);
 pub struct Dir(super::ObjectHandle);
 /// Directory iterator
 pub struct DirIter(::ObjectHandle);
+/// Directory change watch
+pub struct DirWatch(::ObjectHandle);
 /// Symbolic link
 pub struct Symlink(super::ObjectHandle);
+/// Capability to mount and unmount volumes
+pub struct MountCap(super::ObjectHandle);
+
+/// Path resolution flags for `Dir::open_child_path_ex`
+#[derive(Debug,Default,Copy,Clone)]
+pub struct OpenFlags
+{
+	/// Open a symbolic link as the final component, instead of its target
+	pub no_follow: bool,
+	/// Fail if the path would leave the directory (via `..` or an absolute symbolic link)
+	pub beneath: bool,
+}
 
 pub use ::values::VFSError as Error;
 pub use ::values::VFSNodeType as NodeType;
 pub use ::values::VFSFileOpenMode as FileOpenMode;
 pub use ::values::VFSMemoryMapMode as MemoryMapMode;
+pub use ::values::VFSChangeKind as ChangeKind;
+pub use ::values::VFSMountError as MountError;
 
 pub static ROOT: Dir = Dir( ::ObjectHandle(2) );
 
@@ -39,6 +55,13 @@ impl Node
 		// SAFE: Syscall with no side-effects
 		NodeType::try_from( unsafe { self.0.call_0(::values::VFS_NODE_GETTYPE) } as u32 ).expect("Bad VFS Node Type")
 	}
+	/// Owning (user, group) of the node
+	#[inline]
+	pub fn owner(&self) -> (u32, u32) {
+		// SAFE: Syscall with no side-effects
+		let v = unsafe { self.0.call_0(::values::VFS_NODE_GETOWNER) };
+		((v >> 32) as u32, v as u32)
+	}
 
 	/// Convert handle to a directory handle
 	#[inline]
@@ -83,6 +106,14 @@ impl File
 		unsafe { self.0.call_0(::values::VFS_FILE_GETSIZE) }
 	}
 
+	/// Set the size of the file (truncating, or extending with zeroes)
+	#[inline]
+	pub fn set_size(&self, size: u64) -> Result<(),Error> {
+		// SAFE: Syscall with no memory arguments
+		to_result( unsafe { self.0.call_1l(::values::VFS_FILE_SETSIZE, size) } as usize )
+			.map( |_| () )
+	}
+
 	/// Query the current cursor position
 	#[inline]
 	pub fn get_cursor(&self) -> u64 { self.1 }
@@ -121,7 +152,32 @@ impl File
 		to_result( unsafe { self.0.call_4l(::values::VFS_FILE_MEMMAP, ofs, read_size, mem_addr as usize, mode as u8 as usize) } as usize )
 			.map( |_| () )
 	}
+
+	/// Acquire an advisory lock on a range of the file
+	///
+	/// Returns `Err(Error::FileLocked)` if another handle holds a conflicting lock, wait on `wait_lock_release` to retry.
+	#[inline]
+	pub fn lock_range(&self, ofs: u64, len: u64, exclusive: bool) -> Result<(),Error> {
+		// SAFE: Syscall with no memory arguments
+		to_result( unsafe { self.0.call_3ll(::values::VFS_FILE_LOCKRANGE, ofs, len, exclusive as usize) } as usize )
+			.map( |_| () )
+	}
+	/// Release advisory locks held by this handle within a range of the file
+	#[inline]
+	pub fn unlock_range(&self, ofs: u64, len: u64) -> Result<(),Error> {
+		// SAFE: Syscall with no memory arguments
+		to_result( unsafe { self.0.call_2ll(::values::VFS_FILE_UNLOCKRANGE, ofs, len) } as usize )
+			.map( |_| () )
+	}
+	/// Wait item that fires when any advisory lock on the file is released
+	#[inline]
+	pub fn wait_lock_release(&self) -> ::values::WaitItem {
+		self.0.get_wait(::values::EV_VFS_FILE_LOCKRELEASE)
+	}
 }
+define_waits!{ FileWaits => (
+	lock_release:has_lock_release = ::values::EV_VFS_FILE_LOCKRELEASE,
+)}
 impl ::Object for File {
 	const CLASS: u16 = ::values::CLASS_VFS_FILE;
 	fn class() -> u16 { Self::CLASS }
@@ -131,7 +187,7 @@ impl ::Object for File {
 	fn into_handle(self) -> ::ObjectHandle { self.0 }
 	fn handle(&self) -> &::ObjectHandle { &self.0 }
 
-	type Waits = ();
+	type Waits = FileWaits;
 }
 
 
@@ -159,12 +215,49 @@ impl Dir
 		}
 	}
 
+	/// Create a new empty file in this directory
+	pub fn create_file<P: ?Sized+AsRef<[u8]>>(&self, name: &P) -> Result<Node, Error> {
+		let name = name.as_ref();
+		// SAFE: Syscall
+		match super::ObjectHandle::new( unsafe { self.0.call_2(::values::VFS_DIR_CREATEFILE, name.as_ptr() as usize, name.len()) } as usize )
+		{
+		Ok(rv) => Ok( Node(rv) ),
+		Err(code) => Err( Error::try_from(code).expect("Bad VFS Error") ),
+		}
+	}
+
+	/// Rename an entry in this directory
+	pub fn rename<P: ?Sized+AsRef<[u8]>, Q: ?Sized+AsRef<[u8]>>(&self, old_name: &P, new_name: &Q) -> Result<(), Error> {
+		let old_name = old_name.as_ref();
+		let new_name = new_name.as_ref();
+		// SAFE: Syscall
+		to_result( unsafe { self.0.call_4(::values::VFS_DIR_RENAME, old_name.as_ptr() as usize, old_name.len(), new_name.as_ptr() as usize, new_name.len()) } as usize )
+			.map(|_| ())
+	}
+
+	/// Obtain a handle that reports changes to this directory's entries
+	pub fn watch(&self) -> Result<DirWatch, Error> {
+		// SAFE: Syscall
+		match super::ObjectHandle::new( unsafe { self.0.call_0(::values::VFS_DIR_WATCH) } as usize )
+		{
+		Ok(rv) => Ok( DirWatch(rv) ),
+		Err(code) => Err( Error::try_from(code).expect("Bad VFS Error") ),
+		}
+	}
+
 	/// Open a path relative to this directory
 	#[inline]
 	pub fn open_child_path<P: ?Sized+AsRef<[u8]>>(&self, path: &P) -> Result<Node, Error> {
+		self.open_child_path_ex(path, Default::default())
+	}
+	/// Open a path relative to this directory, with resolution flags
+	#[inline]
+	pub fn open_child_path_ex<P: ?Sized+AsRef<[u8]>>(&self, path: &P, flags: OpenFlags) -> Result<Node, Error> {
 		let name = path.as_ref();
+		let flags = if flags.no_follow { ::values::VFS_OPENPATH_NOFOLLOW } else { 0 }
+			| if flags.beneath { ::values::VFS_OPENPATH_BENEATH } else { 0 };
 		// SAFE: Syscall
-		match super::ObjectHandle::new( unsafe { self.0.call_2(::values::VFS_DIR_OPENPATH, name.as_ptr() as usize, name.len()) } as usize )
+		match super::ObjectHandle::new( unsafe { self.0.call_3(::values::VFS_DIR_OPENPATH, name.as_ptr() as usize, name.len(), flags as usize) } as usize )
 		{
 		Ok(rv) => Ok( Node(rv) ),
 		Err(code) => Err( Error::try_from(code).expect("Bad VFS Error") ),
@@ -216,6 +309,49 @@ impl ::Object for DirIter {
 }
 
 
+impl DirWatch
+{
+	/// Read the next change record (returning the kind of change and the length of the affected name)
+	///
+	/// If the name doesn't fit in `namebuf`, the record stays queued and `Err(required_len)` is returned.
+	#[inline]
+	pub fn read_change(&mut self, namebuf: &mut [u8]) -> Result<Option<(ChangeKind, usize)>, usize> {
+		// SAFE: Syscall
+		let v = unsafe { self.0.call_2(::values::VFS_WATCH_READ, namebuf.as_ptr() as usize, namebuf.len()) };
+		let len = (v >> 8) as usize;
+		if v == 0 {
+			Ok(None)
+		}
+		else if v & 0xFF == 0 {
+			Err(len)
+		}
+		else {
+			let kind = ChangeKind::try_from(v as u8).expect("Bad VFS change kind");
+			Ok(Some( (kind, len) ))
+		}
+	}
+	/// Wait item that fires when there are unread change records
+	#[inline]
+	pub fn wait_change(&self) -> ::values::WaitItem {
+		self.0.get_wait(::values::EV_VFS_WATCH_CHANGE)
+	}
+}
+define_waits!{ DirWatchWaits => (
+	change:has_change = ::values::EV_VFS_WATCH_CHANGE,
+)}
+impl ::Object for DirWatch {
+	const CLASS: u16 = ::values::CLASS_VFS_WATCH;
+	fn class() -> u16 { Self::CLASS }
+	fn from_handle(handle: ::ObjectHandle) -> Self {
+		DirWatch(handle)
+	}
+	fn into_handle(self) -> ::ObjectHandle { self.0 }
+	fn handle(&self) -> &::ObjectHandle { &self.0 }
+
+	type Waits = DirWatchWaits;
+}
+
+
 impl Symlink
 {
 	/// Read the target path from the link
@@ -240,3 +376,75 @@ impl ::Object for Symlink {
 
 	type Waits = ();
 }
+
+
+impl MountCap
+{
+	/// Obtain the mount capability (only available to privileged processes)
+	pub fn get() -> Result<MountCap,()> {
+		// SAFE: Syscall
+		match super::ObjectHandle::new( unsafe { syscall!(VFS_GETMOUNTCAP) } as usize )
+		{
+		Ok(rv) => Ok( MountCap(rv) ),
+		Err(_) => Err( () ),
+		}
+	}
+
+	/// Read the name of the `idx`th logical volume, returns `None` past the last volume
+	///
+	/// If the buffer is not long enough, the name is truncated.
+	#[inline]
+	pub fn volume_name<'a>(&self, idx: usize, buf: &'a mut [u8]) -> Option<&'a [u8]> {
+		// SAFE: Syscall
+		let len = unsafe { self.0.call_3(::values::VFS_MOUNTCAP_ENUMVOLS, idx, buf.as_mut_ptr() as usize, buf.len()) };
+		if len == !0 {
+			None
+		}
+		else {
+			let len = ::core::cmp::min(len as usize, buf.len());
+			Some( &buf[.. len] )
+		}
+	}
+
+	/// Mount the named volume at the provided path
+	///
+	/// `options` is a comma-separated list (e.g. `ro,noexec`). `fs=NAME` selects a driver instead of detecting it.
+	#[inline]
+	pub fn mount<P: ?Sized+AsRef<[u8]>>(&self, volume: &str, mountpoint: &P, options: &str) -> Result<(),MountError> {
+		let mountpoint = mountpoint.as_ref();
+		// SAFE: Syscall
+		let rv = unsafe { self.0.call_6(::values::VFS_MOUNTCAP_MOUNT,
+			volume.as_ptr() as usize, volume.len(),
+			mountpoint.as_ptr() as usize, mountpoint.len(),
+			options.as_ptr() as usize, options.len()
+			) };
+		match super::to_result(rv as usize)
+		{
+		Ok(_) => Ok( () ),
+		Err(code) => Err( MountError::try_from(code).expect("Bad VFS mount error") ),
+		}
+	}
+	/// Unmount the volume mounted at the provided path
+	#[inline]
+	pub fn unmount<P: ?Sized+AsRef<[u8]>>(&self, mountpoint: &P) -> Result<(),MountError> {
+		let mountpoint = mountpoint.as_ref();
+		// SAFE: Syscall
+		let rv = unsafe { self.0.call_2(::values::VFS_MOUNTCAP_UNMOUNT, mountpoint.as_ptr() as usize, mountpoint.len()) };
+		match super::to_result(rv as usize)
+		{
+		Ok(_) => Ok( () ),
+		Err(code) => Err( MountError::try_from(code).expect("Bad VFS mount error") ),
+		}
+	}
+}
+impl ::Object for MountCap {
+	const CLASS: u16 = ::values::CLASS_VFS_MOUNTCAP;
+	fn class() -> u16 { Self::CLASS }
+	fn from_handle(handle: ::ObjectHandle) -> Self {
+		MountCap(handle)
+	}
+	fn into_handle(self) -> ::ObjectHandle { self.0 }
+	fn handle(&self) -> &::ObjectHandle { &self.0 }
+
+	type Waits = ();
+}
@@ -12,7 +12,17 @@ pub struct Process(::syscalls::threads::Process);
 impl Process
 {
 	pub fn spawn<S: AsRef<[u8]>>(path: S) -> Process {
-		match loader::new_process(path.as_ref(), &[])
+		Process::spawn_env(path, &[], &[])
+	}
+	/// Spawn a process with arguments and an environment
+	pub fn spawn_env<S: AsRef<[u8]>>(path: S, args: &[&[u8]], env: &[(&[u8], &[u8])]) -> Process {
+		let path = path.as_ref();
+		let fh = match ::syscalls::vfs::ROOT.open_child_path(path).and_then(|n| n.into_file(::syscalls::vfs::FileOpenMode::Execute))
+			{
+			Ok(v) => v,
+			Err(e) => panic!("Couldn't open executable - {:?}", e),
+			};
+		match loader::new_process_env(fh, path, args, env)
 		{
 		Ok(v) => Process(v.start()),
 		Err(e) => panic!("Couldn't start process - {:?}", e),
@@ -30,6 +30,8 @@ pub mod scrollbar;
 
 pub mod decorator;
 
+pub mod listview;
+
 pub use surface::Colour;
 
 /// Re-export GUI events for users of the library
@@ -107,6 +109,8 @@ pub type ScrollbarH = scrollbar::Widget<scrollbar::Horizontal>;
 
 pub use text::Label;
 
+pub use listview::ListView;
+
 /// Initialise the WTK library with a window group handle sent by the parent process
 pub fn initialise()
 {
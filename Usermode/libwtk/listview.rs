@@ -1,9 +1,9 @@
 //
 //
 //
-///
-use wtk::Colour;
-use wtk::geom::Rect;
+//! Multi-column list widget (used by the file browser and file picker)
+use surface::Colour;
+use geom::Rect;
 use std::cell::Cell;
 
 pub trait Row
@@ -77,6 +77,15 @@ impl<Titles: Row, R: Row> ListView<Titles, R>
 	pub fn append_item(&self, item: R) {
 		self.items.borrow_mut().push(item);
 	}
+	/// Remove all items matching the predicate
+	pub fn remove_items<F: FnMut(&R)->bool>(&self, mut f: F) {
+		let mut items = self.items.borrow_mut();
+		items.retain(|i| !f(i));
+		if self.selected_id.get() >= items.len() {
+			self.selected_id.set( items.len().saturating_sub(1) );
+		}
+		self.items_replaced.set(true);
+	}
 
 	fn row_height(&self) -> u32 {
 		16+1
@@ -106,22 +115,22 @@ impl<Titles: Row, R: Row> ListView<Titles, R>
 impl<Titles: Row, R: Row> ListView<Titles, R>
 {
 	// NOTE: VERY VERY EVIL - The passed closure returns Option<OtherCLosure> where OtherClosure can mutate the item list
-	pub fn handle_event<F,C>(&self, event: ::wtk::InputEvent, mut open_cb: F) -> bool
+	pub fn handle_event<F,C>(&self, event: ::InputEvent, mut open_cb: F) -> bool
 	where
 		F: FnMut(&R)->Option<C>,
 		C: FnOnce()
 	{
 		match event
 		{
-		//::wtk::InputEvent::MouseClick(x,y,b) =>
-		//::wtk::InputEvent::MouseUp(x,y,b) => {
+		//::InputEvent::MouseClick(x,y,b) =>
+		//::InputEvent::MouseUp(x,y,b) => {
 		//
 		//	},
 
-		::wtk::InputEvent::KeyUp(key) =>
+		::InputEvent::KeyUp(key) =>
 			match key
 			{
-			::wtk::KeyCode::UpArrow =>
+			::KeyCode::UpArrow =>
 				if self.selected_id.get() > 0 {
 					self.selected_id.set( self.selected_id.get() - 1 );
 					true
@@ -129,7 +138,7 @@ impl<Titles: Row, R: Row> ListView<Titles, R>
 				else {
 					false
 				},
-			::wtk::KeyCode::DownArrow =>
+			::KeyCode::DownArrow =>
 				if self.selected_id.get() < self.items.borrow().len() {
 					self.selected_id.set( self.selected_id.get() + 1 );
 					true
@@ -137,7 +146,7 @@ impl<Titles: Row, R: Row> ListView<Titles, R>
 				else {
 					false
 				},
-			::wtk::KeyCode::Return => {
+			::KeyCode::Return => {
 				let c = open_cb( &self.items.borrow()[self.selected_id.get()]);
 				if let Some(c) = c {
 					c();
@@ -152,7 +161,7 @@ impl<Titles: Row, R: Row> ListView<Titles, R>
 		_ => false,
 		}
 	}
-	pub fn render(&self, surface: ::wtk::surface::SurfaceView, force: bool)
+	pub fn render(&self, surface: ::surface::SurfaceView, force: bool)
 	{
 		// 1. Render the header
 		if force || self.header_dirty.get() || self.widths_dirty.get()
@@ -161,7 +170,7 @@ impl<Titles: Row, R: Row> ListView<Titles, R>
 			surface.fill_rect(row_rect, self.colour_header_bg());
 			let mut x = 0;
 			let y = 0;	// constant
-			for (title, &width) in ::iterx::zip( RowItemIter::new(&self.column_titles), &self.column_widths )
+			for (title, &width) in Iterator::zip( RowItemIter::new(&self.column_titles), &self.column_widths )
 			{
 				if x > 0 {
 					surface.fill_rect(Rect::new(x+1, y, 1, self.row_height()), self.colour_header_fg());
@@ -172,7 +181,7 @@ impl<Titles: Row, R: Row> ListView<Titles, R>
 		}
 		// 2. Render each item
 		let mut max_y = self.row_height();
-		for (idx, (row, y)) in (0 .. ).zip( ::iterx::zip( self.items.borrow().iter(), (1 .. ).map(|x| x*self.row_height()) ) )
+		for (idx, (row, y)) in (0 .. ).zip( Iterator::zip( self.items.borrow().iter(), (1 .. ).map(|x| x*self.row_height()) ) )
 		{
 			if force || self.widths_dirty.get() || true //row.is_dirty()
 			{
@@ -186,7 +195,7 @@ impl<Titles: Row, R: Row> ListView<Titles, R>
 				let row_view = surface.slice( Rect::new(0, y, !0, self.row_height()) );
 				row_view.fill_rect(Rect::new(0,0,!0,!0), c_bg);
 				let mut x = 0;
-				for (value, &width) in ::iterx::zip( RowItemIter::new(row), &self.column_widths )
+				for (value, &width) in Iterator::zip( RowItemIter::new(row), &self.column_widths )
 				{
 					if x > 0 {
 						row_view.fill_rect(Rect::new(x+1, 0, 1, !0), c_fg);
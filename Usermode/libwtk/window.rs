@@ -173,11 +173,25 @@ impl<'a, D: 'a + Decorator> Window<'a, D>
 	pub fn rerender(&mut self)  {
 		WindowTrait::rerender(self)
 	}
+	/// Re-render the window and ask the server to redraw it (for updates made outside of event handling)
+	pub fn redraw(&mut self) {
+		WindowTrait::rerender(self);
+		self.win.redraw();
+	}
 
 	/// Obtain the states of all "modifier" keys
 	pub fn get_modifiers(&self) -> &ModifierStates {
 		WindowTrait::get_modifiers(self)
 	}
+
+	/// System-wide window ID (e.g. to make another process's dialog modal to this window)
+	pub fn get_id(&self) -> u32 {
+		self.win.get_id()
+	}
+	/// Make this window a modal dialog of another window (by ID)
+	pub fn set_modal_owner(&mut self, owner_id: u32) -> Result<(),()> {
+		self.win.set_modal_owner(owner_id)
+	}
 }
 
 impl<'a, D: 'a + Decorator> WindowTrait<'a> for Window<'a, D>
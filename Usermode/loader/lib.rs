@@ -10,6 +10,7 @@ extern crate std_rt;
 extern crate syscalls;
 
 use core::result::Result;
+use core::cell::Cell;
 
 #[derive(Debug)]
 pub enum Error
@@ -21,8 +22,24 @@ pub enum Error
 	BadArguments,
 }
 
+/// Tag for the current working directory (a `vfs::Dir`), used by std to resolve relative paths
+pub const CWD_TAG: &'static str = "cwd";
+/// Tag for the inherited handles manifest (an `ipc::Channel` with a single NUL-separated list of tags)
+///
+/// Sent by `ProtoProcess::start`, and received by std (see `std::env::inherited_handles`)
+pub const HANDLES_TAG: &'static str = "hndlst";
+
+/// Size of the inherited handles manifest (16 tags of at most 6 bytes, plus NUL terminators)
+const MANIFEST_SIZE: usize = 16 * (6+1);
+
 /// Process still being initialised (not yet running)
-pub struct ProtoProcess( ::syscalls::threads::ProtoProcess );
+pub struct ProtoProcess
+{
+	inner: ::syscalls::threads::ProtoProcess,
+	/// NUL-separated list of tags sent with `send_obj`
+	manifest: Cell<[u8; MANIFEST_SIZE]>,
+	manifest_len: Cell<usize>,
+}
 
 mod int {
 	use core::result::Result;
@@ -34,36 +51,85 @@ mod int {
 		// - Required data for spawning a new process:
 		//  > Binary path
 		//  > Arguments
-		//  > ? Environment (could this be transferred using IPC during init?)
-		//  > ? Handles (same thing really, send them over an IPC channel)
-		pub fn new_process(executable_handle: ::syscalls::vfs::File, process_name: &[u8], args: &[&[u8]]) -> Result<::syscalls::threads::ProtoProcess,super::Error>;
+		//  > Environment (key/value pairs, stored alongside the arguments)
+		//  > Handles are sent using `ProtoProcess::send_obj`, and listed in a manifest sent during `ProtoProcess::start`
+		pub fn new_process(executable_handle: ::syscalls::vfs::File, process_name: &[u8], args: &[&[u8]], env: &[(&[u8], &[u8])]) -> Result<::syscalls::threads::ProtoProcess,super::Error>;
 
 		pub fn start_process(handle: ::syscalls::threads::ProtoProcess) -> ::syscalls::threads::Process;
+
+		pub fn log_fault(executable: Option<&::syscalls::vfs::File>, info: &::syscalls::threads::FaultInfo);
 	}
 }
 
 impl ProtoProcess
 {
 	pub fn from_syscall(v: ::syscalls::threads::ProtoProcess) -> ProtoProcess {
-		ProtoProcess(v)
+		ProtoProcess {
+			inner: v,
+			manifest: Cell::new([0; MANIFEST_SIZE]),
+			manifest_len: Cell::new(0),
+			}
 	}
 
+	/// Send an object to the new process, and record the tag in the inherited handles manifest
 	pub fn send_obj<T: ::syscalls::Object>(&self, tag: &str, obj: T) {
-		self.0.send_obj( tag, obj );
+		self.inner.send_obj( tag, obj );
+
+		let mut manifest = self.manifest.get();
+		let ofs = self.manifest_len.get();
+		if ofs + tag.len() + 1 <= MANIFEST_SIZE {
+			manifest[ofs .. ofs + tag.len()].copy_from_slice( tag.as_bytes() );
+			manifest[ofs + tag.len()] = 0;
+			self.manifest.set(manifest);
+			self.manifest_len.set(ofs + tag.len() + 1);
+		}
+		// - If the manifest is full, the object is still sent (just not listed)
+	}
+
+	/// Set the new process's working directory (see `CWD_TAG`)
+	pub fn set_cwd(&self, dir: ::syscalls::vfs::Dir) {
+		self.send_obj( CWD_TAG, dir );
 	}
 
 	pub fn start(self) -> ::syscalls::threads::Process {
+		// Send the inherited handles manifest (the message is kept after the local end is closed)
+		// - On failure, the child just sees no manifest
+		if let Ok( (local, remote) ) = ::syscalls::ipc::Channel::new_pair()
+		{
+			let len = self.manifest_len.get();
+			if local.try_send( &self.manifest.get()[..len] ).is_ok() {
+				self.inner.send_obj( HANDLES_TAG, remote );
+			}
+		}
 		// SAFE: FFI into rust code
 		unsafe {
-			int::start_process(self.0)
+			int::start_process(self.inner)
 		}
 	}
 }
 
+/// Spawn a new process with an empty environment
 pub fn new_process(binary_file: ::syscalls::vfs::File, binary: &[u8], args: &[&[u8]]) -> Result<ProtoProcess,Error> {
+	new_process_env(binary_file, binary, args, &[])
+}
+
+/// Spawn a new process with the provided environment (readable by the child using `std::env::var`)
+///
+/// Keys must not be empty or contain '='. At most 16 arguments and 16 environment entries are supported.
+pub fn new_process_env(binary_file: ::syscalls::vfs::File, binary: &[u8], args: &[&[u8]], env: &[(&[u8], &[u8])]) -> Result<ProtoProcess,Error> {
 	// SAFE: Call is actually to rust
 	unsafe {
-		int::new_process(binary_file, binary, args).map( |v| ProtoProcess(v) )
+		int::new_process(binary_file, binary, args, env).map( |v| ProtoProcess::from_syscall(v) )
 	}
 }
 
+/// Log a child process's fault report (see `Process::get_fault`) to the kernel log
+///
+/// Backtrace entries are printed as `fn+offset`, using the symbol table of `executable` (which must be a
+/// fixed-address image). Addresses outside of it are printed raw.
+pub fn log_fault(executable: Option<&::syscalls::vfs::File>, info: &::syscalls::threads::FaultInfo) {
+	// SAFE: Call is actually to rust
+	unsafe {
+		int::log_fault(executable, info)
+	}
+}
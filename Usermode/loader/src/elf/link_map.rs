@@ -0,0 +1,575 @@
+// Tifflin OS - Userland loader
+// - By John Hodge (thePowersGang)
+//
+// elf/link_map.rs
+// - List of loaded images, and global symbol resolution
+//!
+//! The link map is in search order: the executable, then libraries in breadth-first order of their DT_NEEDED
+//! entries. Symbols not found in any image are looked up in the loader's own exports.
+use std::ffi::OsStr;
+use super::{Error, LinkObject, RelocationState, SymbolHash};
+use super::{STB_GLOBAL, STB_WEAK, STV_DEFAULT, STV_PROTECTED, SHN_ABS, STT_TLS};
+
+/// Maximum number of loaded images (including the executable)
+const MAX_OBJECTS: usize = 16;
+/// Maximum number of DT_NEEDED entries in a single image
+pub const MAX_NEEDED: usize = 8;
+
+/// Region used for library images (between the top of the heap and the loader)
+#[cfg(arch="armv7")]
+pub const LIBRARY_REGION: (usize, usize) = (0x7000_0000, 0x7f00_0000);
+#[cfg(not(arch="armv7"))]
+pub const LIBRARY_REGION: (usize, usize) = (0x7000_0000_0000, 0x7fff_0000_0000);
+/// Region used for position-independent executables (below the heap)
+#[cfg(arch="armv7")]
+const EXECUTABLE_REGION: (usize, usize) = (0x0100_0000, 0x1000_0000);
+#[cfg(not(arch="armv7"))]
+const EXECUTABLE_REGION: (usize, usize) = (0x0100_0000_0000, 0x1000_0000_0000);
+/// Libraries start at a random page within the first 1/LIBRARY_SLIDE_FRACTION of the library region
+const LIBRARY_SLIDE_FRACTION: usize = 16;
+
+// NOTE: These are only accessed by the main thread, before the executable is started
+static mut S_OBJECTS: [Option<LinkObject>; MAX_OBJECTS] = [None; MAX_OBJECTS];
+static mut S_OBJECT_COUNT: usize = 0;
+static mut S_NEXT_LIBRARY_BASE: usize = LIBRARY_REGION.0;
+/// Total size of the TLS blocks (amd64: bytes below the thread pointer, otherwise: end offset including the TCB)
+static mut S_TLS_SIZE: usize = 0;
+static mut S_TLS_ALIGN: usize = 1;
+
+/// Empty the link map
+///
+/// NOTE: Must be called before loading, as this image may have been cloned from a process with different libraries
+pub fn clear()
+{
+	// SAFE: Single-threaded at this point (executable hasn't started)
+	unsafe {
+		S_OBJECTS = [None; MAX_OBJECTS];
+		S_OBJECT_COUNT = 0;
+		let slide_pages = (LIBRARY_REGION.1 - LIBRARY_REGION.0) / LIBRARY_SLIDE_FRACTION / ::PAGE_SIZE;
+		S_NEXT_LIBRARY_BASE = LIBRARY_REGION.0 + random_below(slide_pages) * ::PAGE_SIZE;
+	}
+}
+
+/// Pick a random load bias for a position-independent executable spanning `min`..`max` (file addresses)
+///
+/// NOTE: Leaves room for the extra GOT page after the image
+pub fn random_executable_base(min: usize, max: usize) -> Result<usize, Error>
+{
+	let min = min / ::PAGE_SIZE * ::PAGE_SIZE;
+	let size = (max - min + ::PAGE_SIZE-1) / ::PAGE_SIZE * ::PAGE_SIZE + ::PAGE_SIZE;
+	let region_size = EXECUTABLE_REGION.1 - EXECUTABLE_REGION.0;
+	if size > region_size {
+		kernel_log!("Executable too large ({:#x} bytes)", size);
+		return Err(Error::Unsupported);
+	}
+	let addr = EXECUTABLE_REGION.0 + random_below((region_size - size) / ::PAGE_SIZE + 1) * ::PAGE_SIZE;
+	Ok(addr - min)
+}
+
+/// Generate a random number in 0..`limit`
+///
+/// TODO: The only entropy source avaliable is the timer, use a better one when the kernel provides it.
+fn random_below(limit: usize) -> usize
+{
+	static mut S_COUNTER: u64 = 0;
+	// SAFE: Single-threaded at this point (executable hasn't started)
+	let counter = unsafe { S_COUNTER += 1; S_COUNTER };
+	// splitmix64 finaliser, to spread the (low-entropy) timer across all bits
+	let mut v = ::syscalls::threads::get_time().wrapping_add(counter.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+	v = (v ^ (v >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+	v = (v ^ (v >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+	v = v ^ (v >> 31);
+	if limit == 0 { 0 } else { (v % limit as u64) as usize }
+}
+
+/// Add an image to the end of the link map
+pub fn push(obj: LinkObject) -> Result<(), Error>
+{
+	// SAFE: Single-threaded at this point (executable hasn't started)
+	unsafe {
+		if S_OBJECT_COUNT == MAX_OBJECTS {
+			kernel_log!("Too many loaded images (max {})", MAX_OBJECTS);
+			return Err(Error::Unsupported);
+		}
+		S_OBJECTS[S_OBJECT_COUNT] = Some(obj);
+		S_OBJECT_COUNT += 1;
+	}
+	Ok( () )
+}
+
+fn objects() -> &'static [Option<LinkObject>]
+{
+	// SAFE: Single-threaded at this point (executable hasn't started)
+	unsafe { &S_OBJECTS[..S_OBJECT_COUNT] }
+}
+fn get(idx: usize) -> &'static LinkObject
+{
+	objects()[idx].as_ref().expect("Unpopulated link map entry")
+}
+
+/// Number of images in the link map
+pub fn count() -> usize
+{
+	objects().len()
+}
+
+/// Obtain the `idx`th DT_NEEDED name of the `obj`th image
+pub fn get_needed(obj: usize, idx: usize) -> Option<&'static OsStr>
+{
+	let obj = get(obj);
+	if idx < obj.needed_count {
+		obj.strtab.get(obj.needed[idx])
+	}
+	else {
+		None
+	}
+}
+
+/// Returns true if a library with this name is already loaded
+pub fn is_loaded(name: &OsStr) -> bool
+{
+	objects().iter().filter_map(|o| o.as_ref()).any(|o| o.name == name)
+}
+
+/// Reserve address space for a library image (plus a page for extra GOT entries), returns the base address
+pub fn alloc_region(size: usize) -> Result<usize, Error>
+{
+	let size = (size + ::PAGE_SIZE-1) / ::PAGE_SIZE * ::PAGE_SIZE + ::PAGE_SIZE;
+	// SAFE: Single-threaded at this point (executable hasn't started)
+	unsafe {
+		let base = S_NEXT_LIBRARY_BASE;
+		if size > LIBRARY_REGION.1 - base {
+			kernel_log!("Library region exhausted ({:#x} bytes requested)", size);
+			return Err(Error::Unsupported);
+		}
+		S_NEXT_LIBRARY_BASE += size;
+		Ok(base)
+	}
+}
+
+/// Look up a symbol in the global scope, returns the address and size
+///
+/// The first global definition wins; if only weak definitions exist, the first of those is used. Definitions in the
+/// image loaded at `skip` are ignored (used for COPY relocations).
+pub fn lookup(name: &OsStr, version: Option<&OsStr>, skip: Option<usize>) -> Option<(usize, usize)>
+{
+	match find_definition(name, version, skip)
+	{
+	Some((idx, sym)) => Some( (get(idx).base + sym.st_value, sym.st_size) ),
+	None => ::load::lookup_symbol(name),
+	}
+}
+
+/// Look up a thread-local symbol in the global scope, returns the defining image's index and the offset in its TLS block
+pub fn lookup_tls(name: &OsStr, version: Option<&OsStr>) -> Option<(usize, usize)>
+{
+	match find_definition(name, version, None)
+	{
+	Some((idx, ref sym)) if sym.sym_type() == STT_TLS => Some( (idx, sym.st_value) ),
+	_ => None,
+	}
+}
+
+/// Locate the definition of a symbol (see `lookup`), returns the image index and symbol
+fn find_definition(name: &OsStr, version: Option<&OsStr>, skip: Option<usize>) -> Option<(usize, super::Symbol)>
+{
+	let mut weak = None;
+	for (idx, obj) in objects().iter().enumerate()
+	{
+		let obj = match *obj { Some(ref v) => v, None => continue };
+		if Some(obj.base) == skip {
+			continue ;
+		}
+		if let Some(sym) = obj.find_symbol(name, version)
+		{
+			match sym.binding()
+			{
+			STB_GLOBAL => return Some( (idx, sym) ),
+			STB_WEAK => if weak.is_none() { weak = Some( (idx, sym) ); },
+			_ => {},
+			}
+		}
+	}
+	weak
+}
+
+/// Assign each image's TLS block an offset from the thread pointer
+///
+/// Blocks are in link map order, so the executable's block is at a fixed offset (for the local-exec model).
+pub fn layout_tls()
+{
+	let round_up = |v: usize, a: usize| (v + a - 1) / a * a;
+	// SAFE: Single-threaded at this point (executable hasn't started)
+	unsafe {
+		let mut size = if cfg!(arch="amd64") { 0 } else { ::std::rt::TCB_SIZE };
+		let mut align = 1;
+		for obj in S_OBJECTS[..S_OBJECT_COUNT].iter_mut().filter_map(|o| o.as_mut())
+		{
+			let tls = match obj.tls { Some(v) => v, None => continue };
+			let a = ::std::cmp::max(tls.align, 1);
+			// amd64 (variant II) blocks are below the thread pointer, others (variant I) after the TCB
+			obj.tls_offset = if cfg!(arch="amd64") {
+					size = round_up(size + tls.mem_size, a);
+					0usize.wrapping_sub(size)
+				}
+				else {
+					size = round_up(size, a);
+					let ofs = size;
+					size += tls.mem_size;
+					ofs
+				};
+			align = ::std::cmp::max(align, a);
+		}
+		S_TLS_SIZE = size;
+		S_TLS_ALIGN = align;
+	}
+}
+
+/// Offset (wrapping) of an image's TLS block from the thread pointer
+pub fn tls_offset(idx: usize) -> usize
+{
+	get(idx).tls_offset
+}
+
+/// Build the combined TLS template for all images (after `layout_tls` and relocation)
+///
+/// The image is allocated in the library region, as it must outlive the loader's use of it.
+pub fn build_tls_template() -> Result<::std::rt::TlsTemplate, Error>
+{
+	use std::rt::TCB_SIZE;
+	// SAFE: Single-threaded at this point (executable hasn't started)
+	let (size, align) = unsafe { (S_TLS_SIZE, S_TLS_ALIGN) };
+	// - The image must end at the thread pointer on amd64, so is padded to the alignment `init_block` uses
+	let (image_size, image_start) = if cfg!(arch="amd64") {
+			let a = ::std::cmp::max(align, TCB_SIZE);
+			let s = (size + a - 1) / a * a;
+			(s, 0usize.wrapping_sub(s))
+		}
+		else {
+			(size - TCB_SIZE, TCB_SIZE)
+		};
+	if image_size == 0 {
+		return Ok( Default::default() );
+	}
+
+	let base = try!( alloc_region(image_size) );
+	// SAFE: Region was reserved above, and source ranges were checked to be within the images
+	unsafe {
+		try!( ::syscalls::memory::allocate(base, (image_size + ::PAGE_SIZE-1) / ::PAGE_SIZE) );
+		for obj in objects().iter().filter_map(|o| o.as_ref())
+		{
+			if let Some(ref tls) = obj.tls {
+				let dst = base + obj.tls_offset.wrapping_sub(image_start);
+				::std::ptr::copy_nonoverlapping(tls.base as *const u8, dst as *mut u8, tls.file_size);
+			}
+		}
+	}
+	Ok(::std::rt::TlsTemplate {
+		base: base,
+		file_size: image_size,
+		mem_size: image_size,
+		align: align,
+		})
+}
+
+/// Argument to `__tls_get_addr` (a pair of GOT entries filled by DTPMOD and DTPOFF relocations)
+#[repr(C)]
+pub struct TlsIndex
+{
+	module: usize,
+	offset: usize,
+}
+/// Dynamic TLS access, exported to images as `__tls_get_addr`
+///
+/// Every block is allocated with the thread (there is no run-time loading), so this is an offset from the thread
+/// pointer. NOTE: Called after the executable has started, the link map is read-only by then.
+pub extern "C" fn tls_get_addr(ti: &TlsIndex) -> *mut u8
+{
+	::std::rt::thread_pointer().wrapping_add(tls_offset(ti.module - 1)).wrapping_add(ti.offset) as *mut u8
+}
+
+/// Register the loaded images with the kernel, so that the parent can symbolise a fault report
+pub fn register_fault_images()
+{
+	use syscalls::threads::{FaultImage, FAULT_MAX_IMAGES};
+	let mut images = [FaultImage::default(); FAULT_MAX_IMAGES];
+	let mut count = 0;
+	for (dst, obj) in images.iter_mut().zip( objects().iter().filter_map(|o| o.as_ref()) )
+	{
+		let (start, end) = obj.ranges.extent();
+		dst.start = start;
+		dst.end = end;
+		dst.bias = obj.base;
+		// - Names too long to fit can't be opened by the parent, so are left empty
+		let name = obj.name.as_bytes();
+		if name.len() < dst.name.len() {
+			dst.name[..name.len()].copy_from_slice(name);
+		}
+		count += 1;
+	}
+	::syscalls::threads::S_THIS_PROCESS.set_images(&images[..count]);
+}
+
+/// Apply relocations for all images
+pub fn relocate_all() -> Result<(), Error>
+{
+	// Libraries are relocated first (last loaded first), so data copied by COPY relocations is already relocated
+	for i in (0 .. count()).rev()
+	{
+		let obj = get(i);
+		let state = RelocationState {
+			obj: obj,
+			idx: i,
+			got_used: ::std::cell::Cell::new(0),
+			};
+		try!( state.apply_relocs( obj.rel.iter().chain(obj.rela.iter()).chain(obj.plt.iter()) ) );
+		// Initialiser pointers are only valid after relocation
+		for &addr in obj.info.init_array
+		{
+			if addr != 0 && addr != !0 && !obj.ranges.contains_code(addr) {
+				kernel_log!("Malformed ELF - DT_INIT_ARRAY entry {:#x} not in an executable segment", addr);
+				return Err(Error::Malformed);
+			}
+		}
+	}
+	Ok( () )
+}
+
+/// Run DT_INIT and DT_INIT_ARRAY for all images (dependencies first)
+///
+/// NOTE: Must be called after TLS is initialised, as the initialisers are normal code
+pub fn run_initialisers()
+{
+	for i in (0 .. count()).rev()
+	{
+		let obj = get(i);
+		// SAFE: (uncheckable) Addresses are checked to be in the image's code by `relocate_all`
+		unsafe {
+			if obj.info.init != 0 {
+				let f: extern "C" fn() = ::std::mem::transmute(obj.info.init);
+				f();
+			}
+			for &addr in obj.info.init_array
+			{
+				// 0 and -1 are used as placeholders by some toolchains
+				if addr != 0 && addr != !0 {
+					let f: extern "C" fn() = ::std::mem::transmute(addr);
+					f();
+				}
+			}
+		}
+	}
+}
+
+unsafe fn read_u16(addr: usize) -> u16 {
+	::std::ptr::read(addr as *const u16)
+}
+unsafe fn read_u32(addr: usize) -> u32 {
+	::std::ptr::read(addr as *const u32)
+}
+
+/// Hash function for DT_HASH tables
+fn sysv_hash(name: &[u8]) -> u32
+{
+	let mut h: u32 = 0;
+	for &c in name
+	{
+		h = (h << 4).wrapping_add(c as u32);
+		let g = h & 0xF000_0000;
+		if g != 0 {
+			h ^= g >> 24;
+		}
+		h &= !g;
+	}
+	h
+}
+/// Hash function for DT_GNU_HASH tables
+fn gnu_hash(name: &[u8]) -> u32
+{
+	name.iter().fold(5381u32, |h, &c| h.wrapping_mul(33).wrapping_add(c as u32))
+}
+
+impl LinkObject
+{
+	/// Locate an exported definition of a symbol (using the image's hash table if present)
+	fn find_symbol(&self, name: &OsStr, version: Option<&OsStr>) -> Option<super::Symbol>
+	{
+		match self.hash
+		{
+		SymbolHash::None => {
+			(1 .. self.symtab.len()).filter_map(|idx| self.check_export(idx, name, version)).next()
+			},
+		SymbolHash::SysV { buckets, chains } => {
+			let mut idx = buckets[sysv_hash(name.as_bytes()) as usize % buckets.len()] as usize;
+			// - Bounded by the chain length, in case of a loop
+			for _ in 0 .. chains.len()
+			{
+				if idx == 0 || idx >= chains.len() {
+					break ;
+				}
+				if let Some(sym) = self.check_export(idx, name, version) {
+					return Some(sym);
+				}
+				idx = chains[idx] as usize;
+			}
+			None
+			},
+		SymbolHash::Gnu { symoffset, bloom_shift, bloom, buckets, chains } => {
+			let h = gnu_hash(name.as_bytes());
+			// Bloom filter, quickly rejects most names not defined by this image
+			let bits = 8 * ::std::mem::size_of::<usize>();
+			let word = bloom[(h as usize / bits) % bloom.len()];
+			let mask = 1usize << (h as usize % bits) | 1usize << ((h >> bloom_shift) as usize % bits);
+			if word & mask != mask {
+				return None;
+			}
+			// Chain entries are the hashes of consecutive symbols, with the low bit set on the last of the chain
+			let mut idx = buckets[h as usize % buckets.len()] as usize;
+			if idx < symoffset {
+				return None;
+			}
+			while idx - symoffset < chains.len()
+			{
+				let chain_hash = chains[idx - symoffset];
+				if chain_hash | 1 == h | 1 {
+					if let Some(sym) = self.check_export(idx, name, version) {
+						return Some(sym);
+					}
+				}
+				if chain_hash & 1 != 0 {
+					break ;
+				}
+				idx += 1;
+			}
+			None
+			},
+		}
+	}
+	/// Check if a symbol is an exported definition with the specified name and version
+	fn check_export(&self, idx: usize, name: &OsStr, version: Option<&OsStr>) -> Option<super::Symbol>
+	{
+		let sym = match self.symtab.get(idx) { Some(v) => v, None => return None };
+		if !sym.is_defined() || (sym.binding() != STB_GLOBAL && sym.binding() != STB_WEAK) {
+			return None;
+		}
+		if sym.visibility() != STV_DEFAULT && sym.visibility() != STV_PROTECTED {
+			return None;
+		}
+		if !self.symbol_in_image(&sym) {
+			return None;
+		}
+		if self.strtab.get(sym.st_name) != Some(name) {
+			return None;
+		}
+		if !self.version_matches(idx, version) {
+			return None;
+		}
+		Some(sym)
+	}
+
+	/// Check that a defined symbol refers to memory within the image (absolute and TLS symbols aren't addresses)
+	pub fn symbol_in_image(&self, sym: &super::Symbol) -> bool
+	{
+		sym.st_shndx == SHN_ABS || sym.sym_type() == STT_TLS || self.ranges.contains(self.base.wrapping_add(sym.st_value), sym.st_size)
+	}
+
+	/// DT_VERSYM entry for a symbol
+	fn versym(&self, idx: usize) -> Option<u16>
+	{
+		if self.info.versym == 0 || idx >= self.symtab.len() {
+			None
+		}
+		else {
+			// SAFE: Index checked against the symbol table, which has the same number of entries
+			Some( unsafe { read_u16(self.info.versym + idx * 2) } )
+		}
+	}
+
+	/// Version required by a symbol reference (from DT_VERNEED)
+	pub fn required_version(&self, idx: usize) -> Option<&'static OsStr>
+	{
+		let ndx = match self.versym(idx) { Some(v) => v & 0x7FFF, None => return None };
+		// 0 = local, 1 = global (unversioned)
+		if ndx <= 1 || self.info.verneed == 0 {
+			return None;
+		}
+		// SAFE: Each structure is checked to be within the image before being read
+		unsafe {
+			// Elf_Verneed: vn_version:u16, vn_cnt:u16, vn_file:u32, vn_aux:u32, vn_next:u32
+			// Elf_Vernaux: vna_hash:u32, vna_flags:u16, vna_other:u16, vna_name:u32, vna_next:u32
+			let mut vn = self.info.verneed;
+			loop
+			{
+				if !self.ranges.contains(vn, 16) {
+					return None;
+				}
+				let mut aux = vn.wrapping_add(read_u32(vn + 8) as usize);
+				for _ in 0 .. read_u16(vn + 2)
+				{
+					if !self.ranges.contains(aux, 16) {
+						return None;
+					}
+					if read_u16(aux + 6) == ndx {
+						return self.strtab.get(read_u32(aux + 8) as usize);
+					}
+					aux = aux.wrapping_add(read_u32(aux + 12) as usize);
+				}
+				match read_u32(vn + 12)
+				{
+				0 => return None,
+				next => vn = vn.wrapping_add(next as usize),
+				}
+			}
+		}
+	}
+
+	/// Version of a symbol definition (from DT_VERDEF), and if it is hidden (i.e. not the default version)
+	fn defined_version(&self, idx: usize) -> (Option<&'static OsStr>, bool)
+	{
+		let vs = match self.versym(idx) { Some(v) => v, None => return (None, false) };
+		let (ndx, hidden) = (vs & 0x7FFF, vs & 0x8000 != 0);
+		if ndx <= 1 || self.info.verdef == 0 {
+			return (None, hidden);
+		}
+		// SAFE: Each structure is checked to be within the image before being read
+		unsafe {
+			// Elf_Verdef: vd_version:u16, vd_flags:u16, vd_ndx:u16, vd_cnt:u16, vd_hash:u32, vd_aux:u32, vd_next:u32
+			// Elf_Verdaux: vda_name:u32, vda_next:u32
+			let mut vd = self.info.verdef;
+			loop
+			{
+				if !self.ranges.contains(vd, 20) {
+					return (None, hidden);
+				}
+				if read_u16(vd + 4) == ndx {
+					let aux = vd.wrapping_add(read_u32(vd + 12) as usize);
+					if !self.ranges.contains(aux, 8) {
+						return (None, hidden);
+					}
+					return (self.strtab.get(read_u32(aux) as usize), hidden);
+				}
+				match read_u32(vd + 16)
+				{
+				0 => return (None, hidden),
+				next => vd = vd.wrapping_add(next as usize),
+				}
+			}
+		}
+	}
+
+	fn version_matches(&self, idx: usize, required: Option<&OsStr>) -> bool
+	{
+		let (version, hidden) = self.defined_version(idx);
+		match required
+		{
+		// Unversioned references only bind to the default version
+		None => !hidden,
+		Some(req) => match version
+			{
+			Some(v) => v == req,
+			// Images without version information satisfy any version
+			None => self.info.versym == 0,
+			},
+		}
+	}
+}
@@ -11,6 +11,8 @@ use std::io::{Read,Seek,SeekFrom};
 
 use load::{Segment,SegmentProt};
 
+pub mod link_map;
+
 #[derive(Debug)]
 pub enum Error
 {
@@ -18,7 +20,10 @@ pub enum Error
 	Unsupported,
 	Malformed,
 	UndefinedSymbol,
+	/// A library named by DT_NEEDED couldn't be found
+	MissingLibrary,
 	Vfs(VfsError),
+	Memory(::syscalls::memory::Error),
 	Byteorder(::byteorder::Error),
 	Io(::std::io::Error),
 }
@@ -26,6 +31,9 @@ impl_from! {
 	From<VfsError>(e) for Error {
 		Error::Vfs(e)
 	}
+	From<::syscalls::memory::Error>(e) for Error {
+		Error::Memory(e)
+	}
 	From<::byteorder::Error>(e) for Error {
 		Error::Byteorder(e)
 	}
@@ -38,11 +46,19 @@ pub struct ElfModuleHandle<R: Read+Seek>
 {
 	file: R,
 	header: Header,
+	file_size: u64,
 }
 
+/// Maximum number of program headers accepted
+const MAX_PHENTS: u16 = 64;
+/// Maximum number of PT_LOAD segments in an image
+const MAX_LOAD_SEGMENTS: usize = 16;
+
 pub fn load_executable(mut fh: File) -> Result<ElfModuleHandle<File>,Error>
 {
 	// 1. Open file
+	// - Rewind, as the handle may have already been read (e.g. validated by `new_process`)
+	try!(fh.seek(SeekFrom::Start(0)));
 	let elf_ident = {
 		let mut hdr: [u8; 16] = [0; 16];
 		if try!(fh.read(&mut hdr)) != 16 {
@@ -61,10 +77,14 @@ pub fn load_executable(mut fh: File) -> Result<ElfModuleHandle<File>,Error>
 	kernel_log!("Text only");
 	kernel_log!("elf_ident = {:?}", elf_ident);
 	kernel_log!("hdr = {:?}", hdr);
-	Ok(ElfModuleHandle{
+	let file_size = try!(fh.seek(SeekFrom::End(0)));
+	let mut rv = ElfModuleHandle{
 		file: fh,
 		header: hdr,
-		})
+		file_size: file_size,
+		};
+	try!(rv.validate());
+	Ok(rv)
 }
 	
 impl<R: Read+Seek> ElfModuleHandle<R>
@@ -78,6 +98,146 @@ impl<R: Read+Seek> ElfModuleHandle<R>
 			entry_size: self.header.e_phentsize,
 			}
 	}
+	/// Check the header and program headers against the file and the address space
+	///
+	/// After this, iterating the program headers can't fail (short of IO errors) and all segments are mappable
+	fn validate(&mut self) -> Result<(),Error> {
+		if !self.header.machine.is_native() || self.header.object_size != Size::native() {
+			kernel_log!("ELF not for this machine - {:?} {:?}", self.header.machine, self.header.object_size);
+			return Err(Error::Unsupported);
+		}
+		let is_fixed = match self.header.object_type
+			{
+			ObjectType::Exec => true,
+			ObjectType::Dyn => false,
+			t @ _ => {
+				kernel_log!("ELF object type {:?} not loadable", t);
+				return Err(Error::Unsupported);
+				},
+			};
+		let min_phentsize = match self.header.object_size
+			{
+			Size::Elf32 => 32,
+			Size::Elf64 => 56,
+			};
+		if self.header.e_phentsize < min_phentsize || self.header.e_phentsize > 64 {
+			kernel_log!("Malformed ELF - e_phentsize invalid ({})", self.header.e_phentsize);
+			return Err(Error::Malformed);
+		}
+		if self.header.e_phnum == 0 || self.header.e_phnum > MAX_PHENTS {
+			kernel_log!("Malformed ELF - e_phnum invalid ({})", self.header.e_phnum);
+			return Err(Error::Malformed);
+		}
+		let ph_size = self.header.e_phnum as u64 * self.header.e_phentsize as u64;
+		if !range_in(self.header.e_phoff, ph_size, self.file_size) {
+			kernel_log!("Malformed ELF - Program headers outside file ({:#x}+{:#x})", self.header.e_phoff, ph_size);
+			return Err(Error::Malformed);
+		}
+
+		let file_size = self.file_size;
+		let entry = self.header.e_entry;
+		let mut loads = ImageRanges::default();
+		let mut entry_found = false;
+		let (mut n_dynamic, mut n_tls) = (0, 0);
+		let mut tls_range = (0, 0);
+		{
+			let mut it = self.phents();
+			for _ in 0 .. it.remaining_ents
+			{
+				let e = try!(it.read_entry());
+				match e.p_type
+				{
+				PT_LOAD => {
+					let prot = match segment_prot(e.p_flags)
+						{
+						Some(v) => v,
+						None => {
+							kernel_log!("Unsupported segment flags {:#x}", e.p_flags);
+							return Err(Error::Unsupported);
+							},
+						};
+					if e.p_filesz > e.p_memsz || !range_in(e.p_offset, e.p_filesz as u64, file_size) {
+						kernel_log!("Malformed ELF - Segment file range invalid {:?}", e);
+						return Err(Error::Malformed);
+					}
+					let end = match e.p_vaddr.checked_add(e.p_memsz)
+						{
+						Some(v) if e.p_memsz > 0 => v,
+						_ => {
+							kernel_log!("Malformed ELF - Segment memory range invalid {:?}", e);
+							return Err(Error::Malformed);
+							},
+						};
+					// The mapping code requires the file offset and address to have the same page offset
+					if e.p_vaddr % ::PAGE_SIZE != (e.p_offset % ::PAGE_SIZE as u64) as usize {
+						kernel_log!("Unsupported segment alignment {:?}", e);
+						return Err(Error::Unsupported);
+					}
+					// Segments are mapped in whole pages, so must not share pages
+					let page_range = (e.p_vaddr / ::PAGE_SIZE * ::PAGE_SIZE, (end + ::PAGE_SIZE-1) / ::PAGE_SIZE * ::PAGE_SIZE);
+					if loads.overlaps(page_range.0, page_range.1) {
+						kernel_log!("Malformed ELF - Overlapping segments {:?}", e);
+						return Err(Error::Malformed);
+					}
+					let is_exec = match prot { SegmentProt::Execute => true, _ => false };
+					if !loads.push(e.p_vaddr, end, is_exec) {
+						kernel_log!("Too many PT_LOAD segments (max {})", MAX_LOAD_SEGMENTS);
+						return Err(Error::Unsupported);
+					}
+					if is_exec && e.p_vaddr <= entry && entry < end {
+						entry_found = true;
+					}
+					},
+				PT_DYNAMIC => {
+					n_dynamic += 1;
+					if !range_in(e.p_offset, e.p_filesz as u64, file_size) {
+						kernel_log!("Malformed ELF - PT_DYNAMIC outside file {:?}", e);
+						return Err(Error::Malformed);
+					}
+					},
+				PT_TLS => {
+					n_tls += 1;
+					if e.p_filesz > e.p_memsz || (e.p_align != 0 && !e.p_align.is_power_of_two()) || e.p_vaddr.checked_add(e.p_memsz).is_none() {
+						kernel_log!("Malformed ELF - PT_TLS invalid {:?}", e);
+						return Err(Error::Malformed);
+					}
+					tls_range = (e.p_vaddr, e.p_memsz);
+					},
+				_ => {},
+				}
+			}
+		}
+		if loads.count == 0 || n_dynamic > 1 || n_tls > 1 {
+			kernel_log!("Malformed ELF - {} PT_LOAD, {} PT_DYNAMIC, {} PT_TLS", loads.count, n_dynamic, n_tls);
+			return Err(Error::Malformed);
+		}
+		if n_tls == 1 && !loads.contains(tls_range.0, tls_range.1) {
+			kernel_log!("Malformed ELF - PT_TLS not within a loaded segment");
+			return Err(Error::Malformed);
+		}
+		// NOTE: Libraries don't have a meaningful entrypoint
+		if !entry_found && (is_fixed || entry != 0) {
+			kernel_log!("Malformed ELF - Entrypoint {:#x} is not in an executable segment", entry);
+			return Err(Error::Malformed);
+		}
+		if is_fixed {
+			let (min, max) = loads.extent();
+			if min < ::PAGE_SIZE || max > link_map::LIBRARY_REGION.0 {
+				kernel_log!("Fixed-address image {:#x}-{:#x} overlaps reserved memory", min, max);
+				return Err(Error::Unsupported);
+			}
+		}
+		Ok( () )
+	}
+	/// Address ranges of the PT_LOAD segments, once loaded at `base`
+	fn image_ranges(&mut self, base: usize) -> ImageRanges {
+		let mut rv = ImageRanges::default();
+		for e in self.phents().filter(|e| e.p_type == PT_LOAD) {
+			let is_exec = match segment_prot(e.p_flags) { Some(SegmentProt::Execute) => true, _ => false };
+			rv.push(base + e.p_vaddr, base + e.p_vaddr + e.p_memsz, is_exec);
+		}
+		rv
+	}
 	fn dyntab(&mut self, ofs: u64, len: usize) -> DtEntIterator<R> {
 		self.file.seek(SeekFrom::Start(ofs)).expect("Unable to seek to dynamic table offset");
 		DtEntIterator {
@@ -94,45 +254,88 @@ impl<R: Read+Seek> ElfModuleHandle<R>
 // TODO: Make this part of a trait
 impl<R: Read+Seek> ElfModuleHandle<R>
 {
-	pub fn get_entrypoint(&self) -> usize {
-		self.header.e_entry
+	/// Release the file handle (e.g. after just validating the image)
+	pub fn into_file(self) -> R {
+		self.file
+	}
+	/// Entrypoint, for an image loaded at `base` (zero for fixed-address executables)
+	pub fn get_entrypoint(&self, base: usize) -> usize {
+		base + self.header.e_entry
 	}
 	pub fn load_segments(&mut self) -> LoadSegments<R> {
 		LoadSegments( self.phents() )
 	}
+	/// Obtain the image's thread-local storage segment (from PT_TLS), if present
+	///
+	/// NOTE: The image is within a PT_LOAD segment, so is only valid once segments are loaded
+	fn get_tls_template(&mut self, base: usize) -> Option<::std::rt::TlsTemplate> {
+		self.phents().find(|e| e.p_type == PT_TLS).map(|e| ::std::rt::TlsTemplate {
+			base: base + e.p_vaddr,
+			file_size: e.p_filesz,
+			mem_size: e.p_memsz,
+			align: e.p_align,
+			})
+	}
 	
-	pub fn do_relocation(&mut self) -> Result<(),Error> {
-		// 1. Locate the PT_DYN section
-		let pt_dyn = match self.phents().find(|e| e.p_type == PT_DYNAMIC)
-			{
-			Some(e) => e,
-			None => return Ok( () ),	// No PT_DYN, nothing to do
+	/// Extent of the loaded image (lowest and highest virtual addresses, before relocation)
+	pub fn get_load_extent(&mut self) -> (usize, usize) {
+		self.phents().filter(|e| e.p_type == PT_LOAD)
+			.fold( (!0, 0), |(min,max), e| (::std::cmp::min(min, e.p_vaddr), ::std::cmp::max(max, e.p_vaddr + e.p_memsz)) )
+	}
+	/// Returns true if the file is a shared object (position-independent)
+	pub fn is_shared_object(&self) -> bool {
+		match self.header.object_type
+		{
+		ObjectType::Dyn => true,
+		_ => false,
+		}
+	}
+	
+	/// Parse the dynamic section and add the image (loaded at `base`) to the link map
+	///
+	/// NOTE: Segments must already be loaded, as the tables are used in-place
+	pub fn register(&mut self, base: usize, name: &'static ::std::ffi::OsStr) -> Result<(),Error> {
+		let image_end = {
+			let (_, max) = self.get_load_extent();
+			base + (max + ::PAGE_SIZE-1) / ::PAGE_SIZE * ::PAGE_SIZE
 			};
+		// 1. Locate the PT_DYN section
+		// - If there isn't one, the image is still added (with empty tables) so the link map order is consistent
+		let pt_dyn = self.phents().find(|e| e.p_type == PT_DYNAMIC);
 		kernel_log!("pt_dyn = {:?}", pt_dyn);
+		let (dyn_ofs, dyn_size) = pt_dyn.map(|e| (e.p_offset, e.p_filesz)).unwrap_or( (0, 0) );
 		// 2. Parse to locate the symbol table, string table, and Rel/Rela sections
 		let (mut symtab_addr,mut symtab_esz) = (None, None);
 		let (mut strtab_addr,mut strtab_len) = (None, None);
 		let (mut rel_addr, mut rel_sz, mut rel_esz) = Default::default();
 		let (mut rela_addr, mut rela_sz, mut rela_esz) = Default::default();
 		let (mut plt_addr, mut plt_sz, mut plt_type) = (None, None, RelocType::RelA);
-		for ent in self.dyntab(pt_dyn.p_offset, pt_dyn.p_filesz)
+		let mut hash_addr = None;
+		let mut gnu_hash_addr = None;
+		let mut info = DynInfo::default();
+		let (mut init_array_addr, mut init_array_sz) = (0, 0);
+		let mut needed = [0; link_map::MAX_NEEDED];
+		let mut needed_count = 0;
+		for ent in self.dyntab(dyn_ofs, dyn_size)
 		{
 			match ent
 			{
-			DtEnt::SymTab(addr) => symtab_addr = Some(addr as *const _),
+			DtEnt::SymTab(addr) => symtab_addr = Some((base + addr as usize) as *const _),
 			DtEnt::SymEntSz(count) => symtab_esz = Some(count),
-			DtEnt::StrTab(addr) => strtab_addr = Some(addr as *const _),
+			DtEnt::StrTab(addr) => strtab_addr = Some((base + addr as usize) as *const _),
 			DtEnt::StrSz(count) => strtab_len = Some(count),
+			DtEnt::Hash(addr) => hash_addr = Some(base + addr),
+			DtEnt::GnuHash(addr) => gnu_hash_addr = Some(base + addr),
 			
-			DtEnt::RelA(addr) => rela_addr = Some(addr),
+			DtEnt::RelA(addr) => rela_addr = Some((base + addr as usize) as *const _),
 			DtEnt::RelASz(size) => rela_sz = Some(size),
 			DtEnt::RelAEnt(size) => rela_esz = Some(size),
 			
-			DtEnt::Rel(addr) => rel_addr = Some(addr),
+			DtEnt::Rel(addr) => rel_addr = Some((base + addr as usize) as *const _),
 			DtEnt::RelSz(size) => rel_sz = Some(size),
 			DtEnt::RelEnt(size) => rel_esz = Some(size),
 			
-			DtEnt::Plt(addr) => plt_addr = Some(addr),
+			DtEnt::Plt(addr) => plt_addr = Some((base + addr as usize) as *const _),
 			DtEnt::PltRel(ty) => plt_type = match ty {
 				 7 => RelocType::RelA,	// DT_RELA
 				17 => RelocType::Rel,	// DT_REL
@@ -142,106 +345,244 @@ impl<R: Read+Seek> ElfModuleHandle<R>
 					},
 				},
 			DtEnt::PltRelSz(size) => plt_sz = Some(size),
-			DtEnt::Needed(_) => {/* do nothing */},
+			DtEnt::PltGot(addr) => info.pltgot = base + addr as usize,
+			DtEnt::Needed(ofs) => {
+				if needed_count == needed.len() {
+					kernel_log!("Too many DT_NEEDED entries (max {})", needed.len());
+					return Err(Error::Unsupported);
+				}
+				needed[needed_count] = ofs;
+				needed_count += 1;
+				},
+			DtEnt::Init(addr) => info.init = base + addr,
+			DtEnt::InitArray(addr) => init_array_addr = base + addr,
+			DtEnt::InitArraySz(size) => init_array_sz = size,
+			DtEnt::VerSym(addr) => info.versym = base + addr,
+			DtEnt::VerDef(addr) => info.verdef = base + addr,
+			DtEnt::VerNeed(addr) => info.verneed = base + addr,
 			//v @ _ => kernel_log!("- ?{:?}", v),
 			_ => {},
 			}
 		}
 		kernel_log!("symtab_ofs = {:?}, strtab_ofs = {:?}", symtab_addr, strtab_addr);
-		// SAFE: (well, as can be) These addresses should be pointing to within the program's image
+		// 3. Check that all tables are within the loaded image
+		let ranges = self.image_ranges(base);
+		let ptr_size = ::std::mem::size_of::<usize>();
+		try!(check_range(&ranges, "DT_STRTAB", strtab_addr.map(|a| a as usize), strtab_len.unwrap_or(0)));
+		try!(check_range(&ranges, "DT_HASH", hash_addr, 8));
+		try!(check_range(&ranges, "DT_GNU_HASH", gnu_hash_addr, 16));
+		try!(check_range(&ranges, "DT_REL", rel_addr.map(|a| a as usize), rel_sz.unwrap_or(0)));
+		try!(check_range(&ranges, "DT_RELA", rela_addr.map(|a| a as usize), rela_sz.unwrap_or(0)));
+		try!(check_range(&ranges, "DT_JMPREL", plt_addr.map(|a| a as usize), plt_sz.unwrap_or(0)));
+		try!(check_range(&ranges, "DT_PLTGOT", nonzero(info.pltgot), ptr_size));
+		try!(check_range(&ranges, "DT_VERDEF", nonzero(info.verdef), 20));
+		try!(check_range(&ranges, "DT_VERNEED", nonzero(info.verneed), 16));
+		if init_array_sz % ptr_size != 0 || init_array_addr % ptr_size != 0 {
+			kernel_log!("Malformed ELF - DT_INIT_ARRAY misaligned ({:#x}+{})", init_array_addr, init_array_sz);
+			return Err(Error::Malformed);
+		}
+		// - An empty array can have any address (including none)
+		try!(check_range(&ranges, "DT_INIT_ARRAY", if init_array_sz > 0 { Some(init_array_addr) } else { None }, init_array_sz));
+		if info.init != 0 && !ranges.contains_code(info.init) {
+			kernel_log!("Malformed ELF - DT_INIT {:#x} not in an executable segment", info.init);
+			return Err(Error::Malformed);
+		}
+		if needed[..needed_count].iter().any(|&ofs| ofs >= strtab_len.unwrap_or(0)) {
+			kernel_log!("Malformed ELF - DT_NEEDED name outside string table");
+			return Err(Error::Malformed);
+		}
+		// Symbol count is taken from DT_HASH's chain count if present, otherwise assume that the string table follows the symbol table
+		let symtab_len = match hash_addr
+			{
+			// SAFE: Range checked above
+			Some(a) => Some( unsafe { ::std::ptr::read((a + 4) as *const u32) } as usize * SymbolTable::ent_size_st(self.header.object_size) ),
+			None => match (strtab_addr, symtab_addr)
+				{
+				(Some(st), Some(sy)) => match (st as usize).checked_sub(sy as usize)
+					{
+					Some(v) => Some(v),
+					None => {
+						kernel_log!("Malformed ELF - No DT_HASH, and string table is before the symbol table");
+						return Err(Error::Malformed);
+						},
+					},
+				_ => strtab_addr.map(|_| 0),
+				},
+			};
+		try!(check_range(&ranges, "DT_SYMTAB", symtab_addr.map(|a| a as usize), symtab_len.unwrap_or(0)));
+		let symtab_count = symtab_len.unwrap_or(0) / SymbolTable::ent_size_st(self.header.object_size);
+		try!(check_range(&ranges, "DT_VERSYM", nonzero(info.versym), symtab_count * 2));
+		// SAFE: Headers range checked above, tables are checked by `SymbolHash::new_*`
+		let hash = unsafe {
+			match (gnu_hash_addr, hash_addr)
+			{
+			(Some(a), _) => try!(SymbolHash::new_gnu(&ranges, a, symtab_count)),
+			(None, Some(a)) => try!(SymbolHash::new_sysv(&ranges, a)),
+			(None, None) => SymbolHash::None,
+			}
+			};
+		// SAFE: Ranges checked above
 		let (strtab, symtab, rel, rela, plt) = unsafe {
 			let strtab = try!(StringTable::new(strtab_addr,strtab_len));
-			// TODO: Check assumption that symtab_addr < strtab_addr
-			let symtab = try!(SymbolTable::new(self.header.get_format(), symtab_addr, strtab_addr.map(|x| x as usize - symtab_addr.unwrap_or(x as *const _) as usize), symtab_esz));
+			let symtab = try!(SymbolTable::new(self.header.get_format(), symtab_addr, symtab_len, symtab_esz));
 			let rel  = try!(RelocTable::new(self.header.get_format(), rel_addr , rel_sz , rel_esz , RelocType::Rel ));
 			let rela = try!(RelocTable::new(self.header.get_format(), rela_addr, rela_sz, rela_esz, RelocType::RelA));
 			let plt  = try!(RelocTable::new(self.header.get_format(), plt_addr, plt_sz, None, plt_type));
 			(strtab, symtab, rel, rela, plt)
 			};
+		info.init_array = if init_array_sz == 0 {
+				&[]
+			}
+			else {
+				// SAFE: Range and alignment checked above
+				unsafe { ::std::slice::from_raw_parts(init_array_addr as *const usize, init_array_sz / ptr_size) }
+			};
 		
 		kernel_log!("strtab = {:?}", ::std::ffi::OsStr::new(strtab.0));
-		// Have symbol table - Nice, can't relocate it due to the way it's yielded though
-		// - Should really get a structure that allows random access to it, for resolution
 		for sym in symtab.iter()
 		{
 			kernel_log!("- {:?}", sym);
 		}
-		
-		// 1. Locate DT_NEEDED entries and load the relevant libraries
-		for ent in self.dyntab(pt_dyn.p_offset, pt_dyn.p_filesz)
-		{
-			if let DtEnt::Needed(ofs) = ent {
-				if let Some(name) = strtab.get(ofs) {
-					kernel_log!("DT_NEEDED '{:?}'", name);
-					if name != "libloader_dyn.so" {
-						//::load::load_library(name);
-						panic!("TODO: Load library '{:?}'", name);
-					}
-				}
-				else {
-				}
-			}
+		for r in rel.iter().chain(rela.iter()).chain(plt.iter()) {
+			kernel_log!("{:?}", r);
 		}
-		
-		// 2. Iterate the Rel/RelA/PLT relocation lists and apply
-		kernel_log!("Relocations:");
-		for r in rel.iter() {
-			kernel_log!("REL {:?}", r);
-		}
-		for r in rela.iter() {
-			kernel_log!("RELA {:?}", r);
-		}
-		for r in plt.iter() {
-			kernel_log!("PLT {:?}", r);
-		}
-		
-		kernel_log!("Applying relocations:");
-		{
-			let rs = RelocationState {
-				base: 0,
-				machine: self.header.machine,
-				strtab: strtab,
-				symtab: symtab,
-				};
-			try!( rs.apply_relocs( rel.iter().chain(rela.iter()).chain(plt.iter()) ) );
-		}
-		
+
+		let tls = self.get_tls_template(base);
+		try!(link_map::push(LinkObject {
+			name: name,
+			base: base,
+			machine: self.header.machine,
+			strtab: strtab,
+			symtab: symtab,
+			hash: hash,
+			rel: rel,
+			rela: rela,
+			plt: plt,
+			info: info,
+			needed: needed,
+			needed_count: needed_count,
+			extra_got: image_end,
+			ranges: ranges,
+			tls: tls,
+			tls_offset: 0,
+			}));
 		Ok( () )
 	}
 }
 
-struct RelocationState<'a>
+/// A loaded image in the link map
+#[derive(Copy,Clone)]
+struct LinkObject
 {
+	/// Name used to match DT_NEEDED entries (empty for the executable)
+	name: &'static ::std::ffi::OsStr,
+	/// Offset from the file's virtual addresses to the loaded addresses
 	base: usize,
 	machine: Machine,
-	symtab: SymbolTable<'a>,
-	strtab: StringTable<'a>,
+	strtab: StringTable<'static>,
+	symtab: SymbolTable<'static>,
+	hash: SymbolHash,
+	rel: RelocTable<'static>,
+	rela: RelocTable<'static>,
+	plt: RelocTable<'static>,
+	info: DynInfo,
+	/// String table offsets of DT_NEEDED names
+	needed: [usize; link_map::MAX_NEEDED],
+	needed_count: usize,
+	/// Page after the image, used for GOT entries created by GOT32 relocations
+	extra_got: usize,
+	/// Loaded segments, for bounds checks
+	ranges: ImageRanges,
+	/// Thread-local storage segment
+	tls: Option<::std::rt::TlsTemplate>,
+	/// Offset of this image's TLS block from the thread pointer (wrapping, set by `link_map::layout_tls`)
+	tls_offset: usize,
+}
+/// Miscellaneous dynamic section values (all addresses are relocated, zero if not present)
+#[derive(Copy,Clone,Default)]
+struct DynInfo
+{
+	pltgot: usize,
+	init: usize,
+	init_array: &'static [usize],
+	versym: usize,
+	verdef: usize,
+	verneed: usize,
+}
+
+struct RelocationState<'a>
+{
+	obj: &'a LinkObject,
+	/// Index of the image in the link map (TLS module ID - 1)
+	idx: usize,
+	/// Number of slots used in the image's extra GOT page (for GOT32 relocations)
+	got_used: ::std::cell::Cell<usize>,
 }
 
 impl<'a> RelocationState<'a>
 {
 	fn apply_relocs<I: Iterator<Item=Reloc>>(&self, iter: I) -> Result<(), Error>
 	{
-		match self.machine
+		match self.obj.machine
 		{
 		Machine::X8664 => for r in iter { try!(self.apply_reloc_x86_64(r)); },
 		Machine::ARM => for r in iter { try!(self.apply_reloc_arm(r)); },
-		_ => todo!("apply_reloc - Machine {:?}", self.machine),
+		_ => {
+			kernel_log!("Relocations for {:?} not supported", self.obj.machine);
+			return Err(Error::Unsupported);
+			},
 		}
 		Ok( () )
 	}
 	fn apply_reloc_arm(&self, r: Reloc) -> Result<(), Error> {
 		const R_ARM_NONE: u16 = 0;
 		//const R_ARM_PC24: u16 = 1;	// ((S + A) | T) - P
+		const R_ARM_ABS32: u16 = 2;	// (S + A) | T
+		const R_ARM_REL32: u16 = 3;	// ((S + A) | T) - P
+		const R_ARM_TLS_DTPMOD32: u16 = 17;	// Module[S]
+		const R_ARM_TLS_DTPOFF32: u16 = 18;	// S + A - TLS
+		const R_ARM_TLS_TPOFF32: u16 = 19;	// S + A - tp
+		const R_ARM_COPY: u16 = 20;
+		const R_ARM_GLOB_DAT: u16 = 21;	// (S + A) | T
 		const R_ARM_JUMP_SLOT: u16 = 22;	// (S + A) | T
+		const R_ARM_RELATIVE: u16 = 23;	// B + A
+		let p = self.obj.base.wrapping_add(r.addr);
 		match r.ty
 		{
 		R_ARM_NONE => {},
-		R_ARM_JUMP_SLOT => {
-			let (addr,_size) = try!( self.get_symbol_r(r.sym as usize) );
-			self.relocate_32(r.addr, |_val| addr as u32);
+		R_ARM_ABS32 => {
+			let (addr,_size) = try!( self.get_symbol(r.sym as usize) );
+			try!( self.relocate_32(p, |val| addr.wrapping_add(r.addend.unwrap_or(val as usize)) as u32) );
+			},
+		R_ARM_REL32 => {
+			let (addr,_size) = try!( self.get_symbol(r.sym as usize) );
+			try!( self.relocate_32(p, |val| addr.wrapping_add(r.addend.unwrap_or(val as usize)).wrapping_sub(p) as u32) );
+			},
+		R_ARM_COPY => try!( self.copy_symbol(r.sym as usize, p) ),
+		R_ARM_GLOB_DAT | R_ARM_JUMP_SLOT => {
+			let (addr,_size) = try!( self.get_symbol(r.sym as usize) );
+			try!( self.relocate_32(p, |_val| addr as u32) );
+			},
+		R_ARM_RELATIVE => {
+			try!( self.relocate_32(p, |val| self.obj.base.wrapping_add(r.addend.unwrap_or(val as usize)) as u32) );
+			},
+		R_ARM_TLS_DTPMOD32 => {
+			let (module, _ofs) = try!( self.get_tls_symbol(r.sym as usize) );
+			try!( self.relocate_32(p, |_val| (module + 1) as u32) );
+			},
+		R_ARM_TLS_DTPOFF32 => {
+			let (_module, ofs) = try!( self.get_tls_symbol(r.sym as usize) );
+			try!( self.relocate_32(p, |val| ofs.wrapping_add(r.addend.unwrap_or(val as usize)) as u32) );
+			},
+		R_ARM_TLS_TPOFF32 => {
+			let (module, ofs) = try!( self.get_tls_symbol(r.sym as usize) );
+			let tp_ofs = link_map::tls_offset(module).wrapping_add(ofs);
+			try!( self.relocate_32(p, |val| tp_ofs.wrapping_add(r.addend.unwrap_or(val as usize)) as u32) );
+			},
+		v @ _ => {
+			kernel_log!("Unsupported ARM relocation type {}", v);
+			return Err(Error::Unsupported);
 			},
-		v @ _ => todo!("apply_reloc_arm - ty={}", v),
 		}
 		Ok( () )
 	}
@@ -256,86 +597,227 @@ impl<'a> RelocationState<'a>
 		const R_X86_64_GLOB_DAT : u16 = 6;	// 64, S
 		const R_X86_64_JUMP_SLOT: u16 = 7;	// 64, S
 		const R_X86_64_RELATIVE : u16 = 8;	// 64, B + A
+		const R_X86_64_DTPMOD64: u16 = 16;	// 64, Module ID
+		const R_X86_64_DTPOFF64: u16 = 17;	// 64, Offset in the module's TLS block
+		const R_X86_64_TPOFF64 : u16 = 18;	// 64, Offset from the thread pointer
 
+		let p = self.obj.base.wrapping_add(r.addr);
 		match r.ty
 		{
 		R_X86_64_NONE => {},
 		R_X86_64_64 => {
-			let (addr,_size) = try!( self.get_symbol_r(r.sym as usize) );
-			self.relocate_64(r.addr, |val| (addr + r.addend.unwrap_or(val as usize)) as u64);
+			let (addr,_size) = try!( self.get_symbol(r.sym as usize) );
+			try!( self.relocate_64(p, |val| addr.wrapping_add(r.addend.unwrap_or(val as usize)) as u64) );
 			},
-		R_X86_64_PC32 => {
-			let (addr,_size) = try!( self.get_symbol_r(r.sym as usize) );
-			self.relocate_32(r.addr, |val| (addr + r.addend.unwrap_or(val as usize) - r.addr) as u32);
+		// NOTE: No PLT stubs are generated, so PLT32 is resolved directly to the symbol
+		R_X86_64_PC32 | R_X86_64_PLT32 => {
+			let (addr,_size) = try!( self.get_symbol(r.sym as usize) );
+			try!( self.relocate_32(p, |val| addr.wrapping_add(r.addend.unwrap_or(val as usize)).wrapping_sub(p) as u32) );
 			},
-		R_X86_64_GOT32 => todo!("apply_reloc_x86_64 - GOT32"),
-		R_X86_64_PLT32 => todo!("apply_reloc_x86_64 - PLT32"),
-		R_X86_64_COPY => todo!("apply_reloc_x86_64 - COPY"),
-		R_X86_64_GLOB_DAT => {
-			let (addr,_size) = try!( self.get_symbol_r(r.sym as usize) );
-			self.relocate_64(r.addr, |_val| addr as u64);
+		R_X86_64_GOT32 => {
+			let (addr,_size) = try!( self.get_symbol(r.sym as usize) );
+			let slot = try!( self.alloc_got_slot(addr) );
+			if self.obj.info.pltgot == 0 {
+				kernel_log!("Malformed ELF - GOT32 relocation with no DT_PLTGOT");
+				return Err(Error::Malformed);
+			}
+			let g = slot.wrapping_sub(self.obj.info.pltgot);
+			try!( self.relocate_32(p, |val| g.wrapping_add(r.addend.unwrap_or(val as usize)) as u32) );
 			},
-		R_X86_64_JUMP_SLOT => {
-			let (addr,_size) = try!( self.get_symbol_r(r.sym as usize) );
-			self.relocate_64(r.addr, |_val| addr as u64);
+		R_X86_64_COPY => try!( self.copy_symbol(r.sym as usize, p) ),
+		R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => {
+			let (addr,_size) = try!( self.get_symbol(r.sym as usize) );
+			try!( self.relocate_64(p, |_val| addr as u64) );
 			},
 		R_X86_64_RELATIVE => {
-			self.relocate_64(r.addr, |val| (self.base + r.addend.unwrap_or(val as usize)) as u64);
+			try!( self.relocate_64(p, |val| self.obj.base.wrapping_add(r.addend.unwrap_or(val as usize)) as u64) );
+			},
+		R_X86_64_DTPMOD64 => {
+			let (module, _ofs) = try!( self.get_tls_symbol(r.sym as usize) );
+			try!( self.relocate_64(p, |_val| (module + 1) as u64) );
+			},
+		R_X86_64_DTPOFF64 => {
+			let (_module, ofs) = try!( self.get_tls_symbol(r.sym as usize) );
+			try!( self.relocate_64(p, |val| ofs.wrapping_add(r.addend.unwrap_or(val as usize)) as u64) );
+			},
+		R_X86_64_TPOFF64 => {
+			let (module, ofs) = try!( self.get_tls_symbol(r.sym as usize) );
+			let tp_ofs = link_map::tls_offset(module).wrapping_add(ofs);
+			try!( self.relocate_64(p, |val| tp_ofs.wrapping_add(r.addend.unwrap_or(val as usize)) as u64) );
+			},
+		v @ _ => {
+			kernel_log!("Unsupported x86_64 relocation type {}", v);
+			return Err(Error::Unsupported);
 			},
-		v @ _ => todo!("apply_reloc_x86_64 - ty={}", v),
 		}
 		Ok( () )
 	}
 	
-	fn get_symbol(&self, idx: usize) -> Option<(usize, usize)> {
-		if let Some(sym) = self.symtab.get(idx)
+	/// Resolve a symbol referenced by this image (returns the address and size)
+	///
+	/// Local symbols resolve to this image, others are looked up in the link map (so the executable can override
+	/// library definitions). Undefined weak symbols resolve to zero.
+	fn get_symbol(&self, idx: usize) -> Result<(usize, usize), Error> {
+		if idx == 0 {
+			return Ok( (0, 0) );
+		}
+		let sym = match self.obj.symtab.get(idx)
+			{
+			Some(v) => v,
+			None => { kernel_log!("Malformed ELF, symbol {} out of range", idx); return Err(Error::Malformed); },
+			};
+		if sym.binding() == STB_LOCAL {
+			return if self.obj.symbol_in_image(&sym) {
+					Ok( (self.obj.base + sym.st_value, sym.st_size) )
+				}
+				else {
+					kernel_log!("Malformed ELF, symbol {} outside the image", idx);
+					Err(Error::Malformed)
+				};
+		}
+		let name = match self.obj.strtab.get(sym.st_name)
+			{
+			Some(v) => v,
+			None => { kernel_log!("Malformed ELF, symbol {} name {} invalid", idx, sym.st_name); return Err(Error::Malformed); },
+			};
+		let version = self.obj.required_version(idx);
+		kernel_log!("get_symbol: #{} = {:?} {:?} (version {:?})", idx, sym, name, version);
+		match link_map::lookup(name, version, None)
+		{
+		Some(v) => Ok(v),
+		None if sym.binding() == STB_WEAK => Ok( (0, 0) ),
+		None => {
+			kernel_log!("Undefined symbol {:?} (version {:?})", name, version);
+			Err(Error::UndefinedSymbol)
+			},
+		}
+	}
+	/// Resolve a thread-local symbol, returns the defining image's link map index and the offset in its TLS block
+	///
+	/// Symbol zero refers to this image's own block (used by local-dynamic accesses)
+	fn get_tls_symbol(&self, idx: usize) -> Result<(usize, usize), Error> {
+		let sym = if idx == 0 {
+				None
+			}
+			else {
+				match self.obj.symtab.get(idx)
+				{
+				Some(v) => Some(v),
+				None => { kernel_log!("Malformed ELF, symbol {} out of range", idx); return Err(Error::Malformed); },
+				}
+			};
+		match sym
 		{
-			let name = match self.strtab.get(sym.st_name)
+		Some(ref sym) if sym.binding() != STB_LOCAL => {
+			let name = match self.obj.strtab.get(sym.st_name)
 				{
 				Some(v) => v,
-				None => { kernel_log!("Malformed ELF, symbol {} name {} invalid", idx, sym.st_name); return None; },
+				None => { kernel_log!("Malformed ELF, symbol {} name {} invalid", idx, sym.st_name); return Err(Error::Malformed); },
 				};
-			kernel_log!("get_symbol: #{} = {:?} {:?}", idx, sym, name);
-			if sym.st_shndx == 0 {
-				::load::lookup_symbol(name)
+			let version = self.obj.required_version(idx);
+			match link_map::lookup_tls(name, version)
+			{
+			Some(v) => Ok(v),
+			None => {
+				kernel_log!("Undefined thread-local symbol {:?} (version {:?})", name, version);
+				Err(Error::UndefinedSymbol)
+				},
 			}
-			else {
-				Some( (self.base + sym.st_value, sym.st_size) )
+			},
+		// Local symbols are in this image's block
+		_ => {
+			let ofs = sym.as_ref().map(|s| s.st_value).unwrap_or(0);
+			match self.obj.tls
+			{
+			Some(ref tls) if ofs <= tls.mem_size => Ok( (self.idx, ofs) ),
+			_ => {
+				kernel_log!("Malformed ELF, TLS relocation against symbol {} outside the TLS segment", idx);
+				Err(Error::Malformed)
+				},
 			}
+			},
 		}
-		else {
-			// TODO: This is kinda... not correct, as it actually means a malformed file
-			None
+	}
+	/// Handle a COPY relocation, copying the symbol's initial value from the library that defines it
+	fn copy_symbol(&self, idx: usize, dest: usize) -> Result<(), Error> {
+		let sym = match self.obj.symtab.get(idx)
+			{
+			Some(v) => v,
+			None => { kernel_log!("Malformed ELF, symbol {} out of range", idx); return Err(Error::Malformed); },
+			};
+		let name = try!( self.obj.strtab.get(sym.st_name).ok_or(Error::Malformed) );
+		// - The definition in this image is the destination, so must be skipped
+		let (src, size) = match link_map::lookup(name, self.obj.required_version(idx), Some(self.obj.base))
+			{
+			Some(v) => v,
+			None => {
+				kernel_log!("Undefined symbol {:?} (for COPY)", name);
+				return Err(Error::UndefinedSymbol);
+				},
+			};
+		if size != sym.st_size {
+			kernel_log!("NOTICE: Size mismatch for COPY of {:?} - {} != {}", name, size, sym.st_size);
+		}
+		try!(self.check_target(dest, ::std::cmp::min(size, sym.st_size)));
+		// SAFE: (uncheckable) Both addresses are within loaded images
+		unsafe {
+			::std::ptr::copy_nonoverlapping(src as *const u8, dest as *mut u8, ::std::cmp::min(size, sym.st_size));
 		}
+		Ok( () )
 	}
-	fn get_symbol_r(&self, idx: usize) -> Result<(usize, usize), Error> {
-		match self.get_symbol(idx)
-		{
-		Some(v) => Ok(v),
-		None => Err(Error::UndefinedSymbol),
+	/// Allocate an entry in the extra GOT page (placed after the image), returns the entry's address
+	fn alloc_got_slot(&self, value: usize) -> Result<usize, Error> {
+		let idx = self.got_used.get();
+		if (idx + 1) * ::std::mem::size_of::<usize>() > ::PAGE_SIZE {
+			kernel_log!("Too many GOT32 relocations");
+			return Err(Error::Unsupported);
 		}
+		if idx == 0 {
+			// SAFE: Address is reserved for this purpose (just after the image)
+			if let Err(e) = unsafe { ::syscalls::memory::allocate(self.obj.extra_got, 1) } {
+				kernel_log!("Unable to allocate GOT page at {:#x} - {:?}", self.obj.extra_got, e);
+				return Err(Error::Unsupported);
+			}
+		}
+		let addr = self.obj.extra_got + idx * ::std::mem::size_of::<usize>();
+		// SAFE: Address was allocated above
+		unsafe { *(addr as *mut usize) = value; }
+		self.got_used.set(idx + 1);
+		Ok(addr)
 	}
 	
-	fn relocate_64<F: FnOnce(u64)->u64>(&self, addr: usize, fcn: F) {
-		// SAFE: (uncheckable) Assumes that the file is valid
+	fn check_target(&self, addr: usize, len: usize) -> Result<(), Error> {
+		if self.obj.ranges.contains(addr, len) {
+			Ok( () )
+		}
+		else {
+			kernel_log!("Malformed ELF - Relocation target {:#x}+{} not within a loaded segment", addr, len);
+			Err(Error::Malformed)
+		}
+	}
+	fn relocate_64<F: FnOnce(u64)->u64>(&self, addr: usize, fcn: F) -> Result<(), Error> {
+		try!(self.check_target(addr, 8));
+		// SAFE: Address checked to be within the image
 		unsafe {
-			// TODO: Ensure that address is valid (i.e. within the newly loaded sections)
 			let ptr = addr as *mut u64;
 			// TODO: Ensure that endianness is native endian
 			*ptr = fcn(*ptr);
 		}
+		Ok( () )
 	}
-	fn relocate_32<F: FnOnce(u32)->u32>(&self, addr: usize, fcn: F) {
-		// SAFE: (uncheckable) Assumes that the file is valid
+	fn relocate_32<F: FnOnce(u32)->u32>(&self, addr: usize, fcn: F) -> Result<(), Error> {
+		try!(self.check_target(addr, 4));
+		// SAFE: Address checked to be within the image
 		unsafe {
-			// TODO: Ensure that address is valid
 			let ptr = addr as *mut u32;
 			// TODO: Ensure that endianness is native endian
 			*ptr = fcn(*ptr);
 		}
+		Ok( () )
 	}
 }
 
+#[derive(Copy,Clone)]
 struct StringTable<'a>(&'a [u8]);
 impl<'a> StringTable<'a>
 {
@@ -351,7 +833,7 @@ impl<'a> StringTable<'a>
 		Ok( StringTable(strtab) )
 	}
 	
-	fn get(&self, ofs: usize) -> Option<&::std::ffi::OsStr> {
+	fn get(&self, ofs: usize) -> Option<&'a ::std::ffi::OsStr> {
 		if ofs >= self.0.len() {
 			None
 		}
@@ -388,6 +870,7 @@ impl_fmt! {
 }
 const PT_LOAD: u32 = 1;
 const PT_DYNAMIC: u32 = 2;
+const PT_TLS: u32 = 7;
 impl PHEnt
 {
 	fn parse_64<R: Read>(file: &mut R) -> Result<PHEnt,Error>
@@ -432,7 +915,7 @@ impl<'a, R: 'a+Read>  PhEntIterator<'a, R> {
 		assert!(self.entry_size as usize <= data.len(), "Allocation {} insufficent for {}", data.len(), self.entry_size);
 		let data = &mut data[.. self.entry_size as usize];
 		if try!(self.file.read(data)) != self.entry_size as usize {
-			panic!("TODO");
+			Err(Error::Malformed)
 		}
 		else {
 			match self.object_size
@@ -452,6 +935,7 @@ impl<'a, R: 'a+Read> ::std::iter::Iterator for PhEntIterator<'a, R> {
 		}
 		else {
 			self.remaining_ents -= 1;
+			// NOTE: The program headers are checked by `validate`, so this can only fail on IO errors
 			Some( self.read_entry().expect("Error reading ELF PHEnt") )
 		}
 	}
@@ -471,7 +955,14 @@ enum DtEnt {
 	StrSz(usize),
 	SymEntSz(usize),
 	Rel(*const u8), RelSz(usize), RelEnt(usize),
-	Unknown(u8, u64),
+	Init(usize),
+	SoName(usize),
+	InitArray(usize), InitArraySz(usize),
+	VerSym(usize),
+	VerDef(usize), VerDefNum(usize),
+	VerNeed(usize), VerNeedNum(usize),
+	GnuHash(usize),
+	Unknown(u64, u64),
 }
 impl_from! {
 	From<[u32; 2]>(v) for DtEnt {
@@ -493,9 +984,9 @@ impl_from! {
 		9 => DtEnt::RelAEnt(val),
 		10 => DtEnt::StrSz(val),
 		11 => DtEnt::SymEntSz(val),
-		//12 = DT_INIT
+		12 => DtEnt::Init(val),
 		//13 = DT_FINI
-		//14 = DT_SONAME
+		14 => DtEnt::SoName(val),
 		//15 = DT_RPATH
 		//16 = DT_SYMBOLIC
 		17 => DtEnt::Rel(val as *const _),
@@ -505,7 +996,15 @@ impl_from! {
 		//21 = DT_DEBUG
 		//22 = DT_TEXTREL
 		23 => DtEnt::Plt(val as *const _),
-		t @ _ => DtEnt::Unknown(t as u8, v[1]),
+		25 => DtEnt::InitArray(val),
+		27 => DtEnt::InitArraySz(val),
+		0x6fff_fef5 => DtEnt::GnuHash(val),
+		0x6fff_fff0 => DtEnt::VerSym(val),
+		0x6fff_fffc => DtEnt::VerDef(val),
+		0x6fff_fffd => DtEnt::VerDefNum(val),
+		0x6fff_fffe => DtEnt::VerNeed(val),
+		0x6fff_ffff => DtEnt::VerNeedNum(val),
+		t @ _ => DtEnt::Unknown(t, v[1]),
 		}
 	}}
 }
@@ -564,6 +1063,80 @@ impl<'a, R: 'a+Read> Iterator for DtEntIterator<'a, R>
 	}
 }
 
+fn segment_prot(flags: u32) -> Option<SegmentProt> {
+	match flags & 7
+	{
+	0x4 => Some(SegmentProt::ReadOnly),
+	0x5 => Some(SegmentProt::Execute),
+	0x6 => Some(SegmentProt::ReadWrite),
+	_ => None,
+	}
+}
+/// Check that a dynamic table (if present) is within the loaded image
+fn check_range(ranges: &ImageRanges, name: &str, addr: Option<usize>, len: usize) -> Result<(), Error> {
+	match addr
+	{
+	Some(a) if !ranges.contains(a, len) => {
+		kernel_log!("Malformed ELF - {} {:#x}+{:#x} not within a loaded segment", name, a, len);
+		Err(Error::Malformed)
+		},
+	_ => Ok( () ),
+	}
+}
+fn nonzero(v: usize) -> Option<usize> {
+	if v == 0 { None } else { Some(v) }
+}
+/// Returns true if `ofs+len` is within a file of size `size`
+fn range_in(ofs: u64, len: u64, size: u64) -> bool {
+	match ofs.checked_add(len)
+	{
+	Some(end) => end <= size,
+	None => false,
+	}
+}
+
+/// Address ranges covered by an image's PT_LOAD segments, used to bounds-check accesses
+#[derive(Copy,Clone,Default)]
+struct ImageRanges
+{
+	/// (start, end, is_executable)
+	ranges: [(usize, usize, bool); MAX_LOAD_SEGMENTS],
+	count: usize,
+}
+impl ImageRanges
+{
+	fn push(&mut self, start: usize, end: usize, is_exec: bool) -> bool {
+		if self.count == MAX_LOAD_SEGMENTS {
+			false
+		}
+		else {
+			self.ranges[self.count] = (start, end, is_exec);
+			self.count += 1;
+			true
+		}
+	}
+	fn iter(&self) -> ::std::slice::Iter<(usize, usize, bool)> {
+		self.ranges[..self.count].iter()
+	}
+	fn overlaps(&self, start: usize, end: usize) -> bool {
+		self.iter().any(|r| start < r.1 && r.0 < end)
+	}
+	fn extent(&self) -> (usize, usize) {
+		self.iter().fold( (!0, 0), |(min,max), r| (::std::cmp::min(min, r.0), ::std::cmp::max(max, r.1)) )
+	}
+	/// Check that `addr+len` is entirely within one segment
+	fn contains(&self, addr: usize, len: usize) -> bool {
+		match addr.checked_add(len)
+		{
+		Some(end) => self.iter().any(|r| r.0 <= addr && end <= r.1),
+		None => false,
+		}
+	}
+	fn contains_code(&self, addr: usize) -> bool {
+		self.iter().any(|r| r.2 && r.0 <= addr && addr < r.1)
+	}
+}
+
 pub struct LoadSegments<'a, R: 'a + Read>(PhEntIterator<'a,R>);
 impl<'a, R: 'a + Read> ::load::SegmentIterator<R> for LoadSegments<'a, R>
 {
@@ -578,17 +1151,11 @@ impl<'a, R: 'a+Read> ::std::iter::Iterator for LoadSegments<'a, R> {
 			if e.p_type == PT_LOAD
 			{
 				return Some(Segment {
-					load_addr: e.p_paddr,
+					load_addr: e.p_vaddr,
 					file_addr: e.p_offset,
 					file_size: e.p_filesz,
 					mem_size: e.p_memsz,
-					protection: match e.p_flags & 7
-						{
-						0x4 => SegmentProt::ReadOnly,
-						0x5 => SegmentProt::Execute,
-						0x6 => SegmentProt::ReadWrite,
-						v @ _ => panic!("TODO: Unknown ELF segment flags {}", v),
-						},
+					protection: segment_prot(e.p_flags).expect("Segment flags not checked by validate"),
 					})
 			}
 		}
@@ -607,6 +1174,30 @@ struct Symbol
 	st_shndx: u16,
 }
 
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STB_WEAK: u8 = 2;
+const STV_DEFAULT: u8 = 0;
+const STV_PROTECTED: u8 = 3;
+const SHN_ABS: u16 = 0xFFF1;
+const STT_TLS: u8 = 6;
+impl Symbol
+{
+	fn sym_type(&self) -> u8 {
+		self.st_info & 0xF
+	}
+	fn binding(&self) -> u8 {
+		self.st_info >> 4
+	}
+	fn visibility(&self) -> u8 {
+		self.st_other & 3
+	}
+	fn is_defined(&self) -> bool {
+		self.st_shndx != 0
+	}
+}
+
+#[derive(Copy,Clone)]
 struct SymbolTable<'a>(&'a [u8], Format);
 impl<'a> SymbolTable<'a>
 {
@@ -706,6 +1297,77 @@ impl<'a> Iterator for SymbolIterator<'a>
 	}
 }
 
+/// Symbol hash table, used to find exported definitions without scanning the whole symbol table
+#[derive(Copy,Clone)]
+enum SymbolHash
+{
+	/// No hash table, the symbol table is searched linearly
+	None,
+	/// DT_HASH (chains are indexed by symbol)
+	SysV {
+		buckets: &'static [u32],
+		chains: &'static [u32],
+	},
+	/// DT_GNU_HASH (only symbols from `symoffset` are hashed, chain values are indexed from there)
+	Gnu {
+		symoffset: usize,
+		bloom_shift: u32,
+		bloom: &'static [usize],
+		buckets: &'static [u32],
+		chains: &'static [u32],
+	},
+}
+impl SymbolHash
+{
+	/// Parse a DT_HASH table (the header must be within the image)
+	unsafe fn new_sysv(ranges: &ImageRanges, addr: usize) -> Result<SymbolHash,Error> {
+		let nbucket = ::std::ptr::read(addr as *const u32) as usize;
+		let nchain  = ::std::ptr::read((addr + 4) as *const u32) as usize;
+		let len = 4 * (nbucket as u64 + nchain as u64);
+		if addr % 4 != 0 || nbucket == 0 || len > usize::max_value() as u64 {
+			kernel_log!("Malformed ELF - DT_HASH invalid (addr={:#x}, nbucket={}, nchain={})", addr, nbucket, nchain);
+			return Err(Error::Malformed);
+		}
+		try!(check_range(ranges, "DT_HASH", Some(addr + 8), len as usize));
+		Ok(SymbolHash::SysV {
+			buckets: ::std::slice::from_raw_parts((addr + 8) as *const u32, nbucket),
+			chains: ::std::slice::from_raw_parts((addr + 8 + nbucket * 4) as *const u32, nchain),
+			})
+	}
+	/// Parse a DT_GNU_HASH table (the header must be within the image)
+	///
+	/// The chain array isn't sized in the table, so extends to the end of the symbol table
+	unsafe fn new_gnu(ranges: &ImageRanges, addr: usize, symtab_count: usize) -> Result<SymbolHash,Error> {
+		let ptr_size = ::std::mem::size_of::<usize>();
+		let nbuckets    = ::std::ptr::read(addr as *const u32) as usize;
+		let symoffset   = ::std::ptr::read((addr + 4) as *const u32) as usize;
+		let bloom_size  = ::std::ptr::read((addr + 8) as *const u32) as usize;
+		let bloom_shift = ::std::ptr::read((addr + 12) as *const u32);
+		if addr % ptr_size != 0 || nbuckets == 0 || bloom_size == 0 || bloom_shift >= 32 || symoffset > symtab_count {
+			kernel_log!("Malformed ELF - DT_GNU_HASH invalid (addr={:#x}, nbuckets={}, symoffset={}, bloom_size={}, bloom_shift={})",
+				addr, nbuckets, symoffset, bloom_size, bloom_shift);
+			return Err(Error::Malformed);
+		}
+		let nchains = symtab_count - symoffset;
+		let len = 16 + bloom_size as u64 * ptr_size as u64 + 4 * (nbuckets as u64 + nchains as u64);
+		if len > usize::max_value() as u64 {
+			kernel_log!("Malformed ELF - DT_GNU_HASH too large");
+			return Err(Error::Malformed);
+		}
+		try!(check_range(ranges, "DT_GNU_HASH", Some(addr), len as usize));
+		let bloom_addr = addr + 16;
+		let buckets_addr = bloom_addr + bloom_size * ptr_size;
+		let chains_addr = buckets_addr + nbuckets * 4;
+		Ok(SymbolHash::Gnu {
+			symoffset: symoffset,
+			bloom_shift: bloom_shift,
+			bloom: ::std::slice::from_raw_parts(bloom_addr as *const usize, bloom_size),
+			buckets: ::std::slice::from_raw_parts(buckets_addr as *const u32, nbuckets),
+			chains: ::std::slice::from_raw_parts(chains_addr as *const u32, nchains),
+			})
+	}
+}
+
 #[derive(Copy,Clone,PartialEq,Debug)]
 enum RelocType {
 	Rel,
@@ -736,6 +1398,7 @@ impl Reloc {
 		}
 	}
 }
+#[derive(Copy,Clone)]
 struct RelocTable<'a> {
 	data: &'a [u8],
 	format: Format,
@@ -885,6 +1548,24 @@ enum Machine {
 	X8664,
 	Unk(u16)
 }
+impl Machine
+{
+	#[cfg(arch="armv7")]
+	fn is_native(&self) -> bool {
+		match *self { Machine::ARM => true, _ => false }
+	}
+	#[cfg(not(arch="armv7"))]
+	fn is_native(&self) -> bool {
+		match *self { Machine::X8664 => true, _ => false }
+	}
+}
+impl Size
+{
+	#[cfg(target_pointer_width="32")]
+	fn native() -> Size { Size::Elf32 }
+	#[cfg(target_pointer_width="64")]
+	fn native() -> Size { Size::Elf64 }
+}
 impl_from! {
 	From<u16>(v) for ObjectType {
 		match v
@@ -909,6 +1590,7 @@ impl_from! {
 	}
 }
 
+#[derive(Copy,Clone)]
 struct Format
 {
 	size: Size,
@@ -962,12 +1644,15 @@ impl Header
 			2 => Endian::Big,
 			_ => return Err(Error::Unsupported),
 			};
-		assert_eq!(endian, Endian::Little);
+		if endian != Endian::Little {
+			kernel_log!("Big-endian ELF not supported");
+			return Err(Error::Unsupported);
+		}
 		
 		match objsize
 		{
 		Size::Elf32 => {
-			let data = { let mut d = [0; 36]; try!(file.read(&mut d)); d };
+			let data = { let mut d = [0; 36]; if try!(file.read(&mut d)) != d.len() { return Err(Error::NotElf); } d };
 			let mut data = &data[..];
 
 			let objtype = ObjectType::from( try!(data.read_u16::<LittleEndian>()) );
@@ -999,7 +1684,7 @@ impl Header
 				})
 			},
 		Size::Elf64 => {
-			let data = { let mut d = [0; 48]; try!(file.read(&mut d)); d };
+			let data = { let mut d = [0; 48]; if try!(file.read(&mut d)) != d.len() { return Err(Error::NotElf); } d };
 			let mut data = &data[..];
 
 			let objtype = ObjectType::from( try!(data.read_u16::<LittleEndian>()) );
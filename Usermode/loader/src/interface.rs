@@ -11,6 +11,7 @@ extern "C" {
 	static init_path: [u8; 0];
 	static init_path_end: [u8; 0];
 	static mut arg_count: u32;
+	static mut env_count: u32;
 }
 
 static S_BUFFER_LOCK: ::syscalls::sync::Mutex<()> = ::syscalls::sync::Mutex::new( () );
@@ -19,11 +20,23 @@ impl_from! {
 	From<NullStringBuilderError>(_v) for loader::Error {
 		loader::Error::BadArguments
 	}
+	From<::elf::Error>(v) for loader::Error {
+		match v
+		{
+		::elf::Error::NotElf => loader::Error::NotExecutable,
+		::elf::Error::Unsupported => loader::Error::BadFormat,
+		::elf::Error::Vfs(_) | ::elf::Error::Io(_) => loader::Error::NotFound,
+		_ => loader::Error::CorruptExecutable,
+		}
+	}
 }
 
+/// Maximum number of arguments or environment entries (the capacity of `::FixedVec`)
+const MAX_LIST_ENTRIES: usize = 16;
+
 #[no_mangle]
-/// Spawn a new process using the provided binary and arguments
-pub extern "C" fn new_process(executable_handle: ::syscalls::vfs::File, process_name: &[u8], args: &[&[u8]]) -> Result<::syscalls::threads::ProtoProcess,loader::Error>
+/// Spawn a new process using the provided binary, arguments, and environment
+pub extern "C" fn new_process(executable_handle: ::syscalls::vfs::File, process_name: &[u8], args: &[&[u8]], env: &[(&[u8], &[u8])]) -> Result<::syscalls::threads::ProtoProcess,loader::Error>
 {
 	extern "C" {
 		static BASE: [u8; 0];
@@ -32,6 +45,18 @@ pub extern "C" fn new_process(executable_handle: ::syscalls::vfs::File, process_
 	
 	kernel_log!("new_process({:?}, ...)", ::std::ffi::OsStr::new(process_name));
 	
+	if args.len() > MAX_LIST_ENTRIES || env.len() > MAX_LIST_ENTRIES {
+		return Err(loader::Error::BadArguments);
+	}
+	// Environment keys are stored as `KEY=VALUE`, so can't contain '='
+	if env.iter().any(|&(k,_)| k.len() == 0 || k.contains(&b'=')) {
+		return Err(loader::Error::BadArguments);
+	}
+	
+	// Check the headers before spawning, so errors are reported to the caller (instead of a panic in the new process)
+	// - Dynamic sections and libraries are only checked once loaded
+	let executable_handle = try!( ::elf::load_executable(executable_handle) ).into_file();
+	
 	// Acquire the global buffer lock and start the new process
 	let proto_proc = {
 		// Lock loader until after 'start_process', allowing global memory to be used as buffer for binary and arguments
@@ -42,6 +67,7 @@ pub extern "C" fn new_process(executable_handle: ::syscalls::vfs::File, process_
 		// SAFE: Locked
 		unsafe {
 			arg_count = (args.len() + 1) as u32;
+			env_count = env.len() as u32;
 		}
 		// SAFE: Locked (so access is unique), and pointers are valid
 		let buf = unsafe {
@@ -57,6 +83,9 @@ pub extern "C" fn new_process(executable_handle: ::syscalls::vfs::File, process_
 		for arg in args {
 			try!( builder.push(arg) );
 		}
+		for &(key, value) in env {
+			try!( builder.push_parts(&[key, b"=", value]) );
+		}
 		
 		let name = ::std::str::from_utf8(process_name).unwrap_or("BADSTR");
 
@@ -87,6 +116,73 @@ pub extern "C" fn start_process(pp: ::syscalls::threads::ProtoProcess) -> ::sysc
 	pp.start( new_process_entry as usize, unsafe { init_stack_end.as_ptr() as usize } )
 }
 
+#[cfg(arch="armv7")]
+const REG_NAMES: [&'static str; ::syscalls::threads::FAULT_NREGS] = [
+	"R0 ", "R1 ", "R2 ", "R3 ", "R4 ", "R5 ", "R6 ", "R7 ",
+	"R8 ", "R9 ", "R10", "R11", "R12", "SP ", "LR ", "PC ",
+	];
+#[cfg(not(arch="armv7"))]
+const REG_NAMES: [&'static str; ::syscalls::threads::FAULT_NREGS] = [
+	"RAX", "RCX", "RDX", "RBX", "RSP", "RBP", "RSI", "RDI",
+	"R8 ", "R9 ", "R10", "R11", "R12", "R13", "R14", "R15",
+	];
+
+#[no_mangle]
+/// Log a child process's fault report, resolving the backtrace against the symbol tables of its images
+///
+/// Images are located using the load addresses registered by the child's loader, libraries are opened from
+/// `/sysroot/lib` by name.
+pub extern "C" fn log_fault(executable: Option<&::syscalls::vfs::File>, info: &::syscalls::threads::FaultInfo)
+{
+	let kind = match info.kind
+		{
+		::syscalls::threads::FAULT_KIND_PAGE => "Page fault",
+		::syscalls::threads::FAULT_KIND_PROTECTION => "Protection fault",
+		::syscalls::threads::FAULT_KIND_INVALIDOPCODE => "Invalid opcode",
+		_ => "Fault",
+		};
+	kernel_log!("{} (code {:#x}) at {:#x} - address {:#x}, SP {:#x}", kind, info.code, info.ip, info.address, info.sp);
+	for (names, values) in REG_NAMES.chunks(4).zip( info.regs.chunks(4) ) {
+		kernel_log!("{} {:#x}  {} {:#x}  {} {:#x}  {} {:#x}",
+			names[0], values[0], names[1], values[1], names[2], values[2], names[3], values[3]);
+	}
+	
+	let images = &info.images[.. ::std::cmp::min(info.image_count, info.images.len())];
+	let count = ::std::cmp::min(info.backtrace_len, info.backtrace.len());
+	for (i, &addr) in info.backtrace[..count].iter().enumerate()
+	{
+		let image = images.iter().find(|im| im.start <= addr && addr < im.end);
+		// - An empty name is the executable
+		let lib_file = match image
+			{
+			Some(im) if im.name[0] != 0 => {
+				let len = im.name.iter().position(|&b| b == 0).unwrap_or(im.name.len());
+				::open_library(::std::ffi::OsStr::new(&im.name[..len]))
+				},
+			_ => None,
+			};
+		let file = match image
+			{
+			Some(im) if im.name[0] == 0 => executable,
+			Some(_) => lib_file.as_ref(),
+			None => None,
+			};
+		let syms = file.and_then(::symbolise::FileSymbols::new);
+		match (image, syms.as_ref())
+		{
+		(Some(im), Some(s)) => match s.lookup(addr.wrapping_sub(im.bias))
+			{
+			Some( (name_ofs, ofs) ) => {
+				let mut buf = [0u8; 256];
+				kernel_log!("#{} {:#x} {}+{:#x}", i, addr, ::symbolise::Demangle(s.name(name_ofs, &mut buf)), ofs);
+				},
+			None => kernel_log!("#{} {:#x} ?", i, addr),
+			},
+		_ => kernel_log!("#{} {:#x} ?", i, addr),
+		}
+	}
+}
+
 /// Entrypoint for new processes, runs with a clean stack
 fn new_process_entry() -> !
 {
@@ -97,46 +193,45 @@ fn new_process_entry() -> !
 		S_BUFFER_LOCK.unlock();
 	}
 	// SAFE: Valid memory from linker script
-	let (arg_slice, argc) = unsafe {
+	let (arg_slice, argc, envc) = unsafe {
 		assert!(arg_count > 0);
 		let s = ::std::slice::from_raw_parts( init_path.as_ptr(), init_path_end.as_ptr() as usize - init_path.as_ptr() as usize );
-		(s, arg_count)
+		(s, arg_count as usize, env_count as usize)
 		};
 	
-	// Parse command line stored in data area (including image path)
-	let mut arg_iter = NullStringList(arg_slice).map(::std::ffi::OsStr::new);
-	let process_name = arg_iter.next().expect("No binary was passed");
+	// Parse command line stored in data area (including image path), followed by the environment
+	let mut string_iter = NullStringList(arg_slice).map(::std::ffi::OsStr::new);
+	let process_name = string_iter.next().expect("No binary was passed");
 	kernel_log!("Binary = {:?}", process_name);
-	let arg_iter = (0 .. argc).zip(arg_iter);
-	for (i,arg) in arg_iter {
-		kernel_log!("Arg {}: {:?}", i, arg);
+	let mut args = super::FixedVec::new();
+	for arg in string_iter.by_ref().take(argc - 1) {
+		args.push(arg).unwrap();
+	}
+	let mut env = super::FixedVec::new();
+	for ent in string_iter.take(envc) {
+		env.push(ent).unwrap();
 	}
-
-	let arg_iter = NullStringList(arg_slice).map(::std::ffi::OsStr::new).skip(1);
-	let arg_iter = (0 .. argc).zip(arg_iter);
-	
-	
 	
 	let fh: ::syscalls::vfs::File = ::syscalls::threads::S_THIS_PROCESS.receive_object("exec").expect("Could not receive the executable vfs::File object");
 	let root: ::syscalls::vfs::Dir = ::syscalls::threads::S_THIS_PROCESS.receive_object("ro:/").expect("Could not receive the root");
 	::std::mem::forget(root);
-	let entrypoint = ::load_binary(process_name, fh);
-	
-	// TODO: Coordinate with the parent process and receive an initial set of objects (e.g. WM root)?
-	// - Could possibly leave this up to user code, or at least std
+	let entrypoint = match ::load_binary(process_name, fh)
+		{
+		Ok(v) => v,
+		Err(e) => panic!("ERROR: Binary '{:?}' cannot be loaded: {:?}", process_name, e),
+		};
 	
-	// Populate arguments
-	let mut args = super::FixedVec::new();
-	for (_,arg) in arg_iter {
-		args.push(arg).unwrap();
-	}
+	// NOTE: Objects sent by the parent (and the inherited handles manifest) are left for std to receive
 	kernel_log!("args = {:?}", &*args);
 	
+	::init_main_tls();
+	::elf::link_map::run_initialisers();
+	
 	// TODO: Switch stacks into a larger dynamically-allocated stack
 	// SAFE: Entrypoint assumed to have this signature
-	let ep: fn(&[&::std::ffi::OsStr]) -> ! = unsafe { ::std::mem::transmute(entrypoint) };
+	let ep: fn(&[&::std::ffi::OsStr], &[&::std::ffi::OsStr]) -> ! = unsafe { ::std::mem::transmute(entrypoint) };
 	kernel_log!("Calling entry {:p} for {:?}", ep as *const (), process_name);
-	ep(&args);
+	ep(&args, &env);
 }
 
 
@@ -174,26 +269,30 @@ struct NullStringBuilder<'a>(&'a mut [u8]);
 impl<'a> NullStringBuilder<'a>
 {
 	fn push(&mut self, bytes: &[u8]) -> Result<(), NullStringBuilderError> {
-		if bytes.contains(&0) {
+		self.push_parts(&[bytes])
+	}
+	/// Push the concatenation of several byte strings as a single entry
+	fn push_parts(&mut self, parts: &[&[u8]]) -> Result<(), NullStringBuilderError> {
+		let len = parts.iter().fold(0, |sum, p| sum + p.len());
+		if parts.iter().any(|p| p.contains(&0)) {
 			Err( NullStringBuilderError::ContainsNull )
 		}
-		else if bytes.len() > self.0.len() {
+		else if len > self.0.len() {
 			Err( NullStringBuilderError::InsufficientSpace )
 		}
 		else {
-			let rem: *mut [u8] = if bytes.len() == self.0.len() {
-					let (dst, rem) = self.0.split_at_mut(bytes.len());
-					for (d,s) in dst.iter_mut().zip( bytes.iter() ) {
-						*d = *s;
-					}
-					rem
-				} else {
-					let (dst, rem) = self.0.split_at_mut(bytes.len()+1);
-					for (d,s) in dst.iter_mut().zip( bytes.iter() ) {
-						*d = *s;
-					}
-					dst[bytes.len()] = b'\0';
-					rem
+			let rem: *mut [u8] = {
+				// Only NUL terminate if there's space (the final entry can fill the buffer)
+				let (dst, rem) = if len == self.0.len() { self.0.split_at_mut(len) } else { self.0.split_at_mut(len+1) };
+				let mut ofs = 0;
+				for p in parts {
+					dst[ofs .. ofs + p.len()].copy_from_slice(p);
+					ofs += p.len();
+				}
+				if ofs < dst.len() {
+					dst[ofs] = b'\0';
+				}
+				rem
 				};
 			// SAFE: (Fuck you borrowck)
 			self.0 = unsafe { ::std::mem::transmute(rem) };
@@ -44,15 +44,17 @@ impl_fmt! {
 }
 
 
-/// Look up a symbol in the global symbol namespace
+/// Look up a symbol exported by the loader itself (stand-in for `libloader_dyn.so`)
 ///
-/// TODO: Needs support for weak symbols, and multiple namespaces (or preferential namespaces)
+/// NOTE: Only consulted after all loaded images, see `elf::link_map::lookup`
 pub fn lookup_symbol(name: &::std::ffi::OsStr) -> Option<(usize, usize)> {
 	match name.as_bytes()
 	{
 	b"new_process" => Some( (::interface::new_process as usize, 0) ),
 	b"start_process" => Some( (::interface::start_process as usize, 0) ),
-	_ => todo!("lookup_symbol({:?})", name),
+	b"log_fault" => Some( (::interface::log_fault as usize, 0) ),
+	b"__tls_get_addr" => Some( (::elf::link_map::tls_get_addr as usize, 0) ),
+	_ => None,
 	}
 }
 
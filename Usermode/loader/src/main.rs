@@ -21,6 +21,9 @@ extern crate macros;
 mod elf;
 pub mod interface;
 mod load;
+mod symbolise;
+
+use elf::Error;
 
 #[cfg(arch="armv7")]
 const PAGE_SIZE: usize = 0x2000;
@@ -57,7 +60,11 @@ pub extern "C" fn loader_main(cmdline: *mut u8, cmdline_len: usize) -> !
 	
 	// 3. Spin up init
 	let fh: ::syscalls::vfs::File = ::syscalls::object_from_raw(1).expect("Unable to open object #1 as init");
-	let entrypoint = load_binary(init_path, fh);
+	let entrypoint = match load_binary(init_path, fh)
+		{
+		Ok(v) => v,
+		Err(e) => panic!("ERROR: Binary '{:?}' cannot be loaded: {:?}", init_path, e),
+		};
 	
 	// Populate arguments
 	let mut args = FixedVec::new();
@@ -67,11 +74,15 @@ pub extern "C" fn loader_main(cmdline: *mut u8, cmdline_len: usize) -> !
 	}
 	kernel_log!("args = {:?}", &*args);
 	
+	init_main_tls();
+	::elf::link_map::run_initialisers();
+	
 	// TODO: Switch stacks into a larger dynamically-allocated stack
 	// SAFE: Entrypoint assumed to have this format... will likely crash if it isn't
-	let ep: fn(&[&::std::ffi::OsStr]) = unsafe { ::std::mem::transmute(entrypoint) };
+	// - INIT is started with an empty environment
+	let ep: fn(&[&::std::ffi::OsStr], &[&::std::ffi::OsStr]) = unsafe { ::std::mem::transmute(entrypoint) };
 	kernel_log!("Calling entry {:p} for INIT {:?}", ep as *const (), init_path);
-	ep(&args);
+	ep(&args, &[]);
 	kernel_log!("User entrypoint returned");
 	::syscalls::threads::exit(!0);
 }
@@ -109,100 +120,193 @@ impl<T> ::std::ops::DerefMut for FixedVec<T> {
 	}
 }
 
-/// Panics if it fails to load, returns the entrypoint
-fn load_binary(path: &::std::ffi::OsStr, fh: ::syscalls::vfs::File) -> usize
+/// TLS template for the loaded executable (written by `load_binary`)
+static mut S_TLS_TEMPLATE: ::std::rt::TlsTemplate = ::std::rt::TlsTemplate { base: 0, file_size: 0, mem_size: 0, align: 0 };
+
+/// Set up the main thread's TLS block (in space reserved in the loader's image)
+fn init_main_tls()
+{
+	extern "C" {
+		static init_tls_base: [u8; 0];
+		static init_tls_end: [u8; 0];
+	}
+	// SAFE: The reserved area is only used for this (once per process), and the template was written by load_binary
+	unsafe {
+		let base = init_tls_base.as_ptr() as *mut u8;
+		let len = init_tls_end.as_ptr() as usize - base as usize;
+		if S_TLS_TEMPLATE.block_size() > len {
+			panic!("Executable's TLS segment is too large ({:#x} bytes, max {:#x})", S_TLS_TEMPLATE.mem_size, len);
+		}
+		let tp = S_TLS_TEMPLATE.init_block(base, len);
+		::syscalls::threads::set_tls_base(tp);
+	}
+}
+
+/// Load an executable (and its libraries), returns the entrypoint
+///
+/// Libraries are loaded from `/sysroot/lib`
+fn load_binary(path: &::std::ffi::OsStr, fh: ::syscalls::vfs::File) -> Result<usize, Error>
 {
 	kernel_log!("load_binary({:?})", path);
-	// - Open the init path passed in `cmdline`
-	let mut handle = match ::elf::load_executable(fh)
-		{
-		Ok(v) => v,
-		Err(e) => {
-			panic!("ERROR: Binary '{:?}' cannot be loaded: {:?}", ::std::ffi::OsStr::new(path), e);
-			},
-		};
+	// NOTE: Always cleared, as this image may have been cloned from a process with different libraries loaded
+	::elf::link_map::clear();
+
+	let mut handle = try!( ::elf::load_executable(fh) );
 	
-	let entrypoint = handle.get_entrypoint();
-	kernel_log!("- entrypoint = {:#x}", entrypoint);
+	// Position-independent executables are loaded at a random address, others at their link address
+	let base = if handle.is_shared_object() {
+			let (min, max) = handle.get_load_extent();
+			try!( ::elf::link_map::random_executable_base(min, max) )
+		}
+		else {
+			0
+		};
+	let entrypoint = handle.get_entrypoint(base);
+	kernel_log!("- base = {:#x}, entrypoint = {:#x}", base, entrypoint);
 	
-	let mut found_segment_for_entry = false;
-	// I would love to use a for loop here, but getting access the file is hard using that
+	try!( map_segments(&mut handle, base) );
+	try!( handle.register(base, ::std::ffi::OsStr::new("")) );
+	try!( load_dependencies() );
+	// TLS offsets are needed by TPOFF relocations
+	::elf::link_map::layout_tls();
+	try!( ::elf::link_map::relocate_all() );
+
+	// NOTE: Always overwritten, as this image may have been cloned from a process with a different executable
+	let tls = try!( ::elf::link_map::build_tls_template() );
+	kernel_log!("- TLS template = {:?}", tls);
+	// SAFE: Single-threaded at this point (executable hasn't started)
+	unsafe { S_TLS_TEMPLATE = tls; }
+	::elf::link_map::register_fault_images();
+
+	// TODO: Have a cleaner way of handling this, than just forgetting the handle
+	// - Probably unwrap the handle into a raw file handle - THEN forget that (or even store it)
+	::std::mem::forget(handle);
+		
+	Ok(entrypoint)
+}
+
+/// Load the libraries named by DT_NEEDED entries of all images in the link map (breadth-first)
+fn load_dependencies() -> Result<(), Error>
+{
+	let mut i = 0;
+	// NOTE: The link map grows as libraries are loaded
+	while i < ::elf::link_map::count()
 	{
-		let mut segments_it = handle.load_segments();
-		while let Some(segment) = segments_it.next()
+		let mut j = 0;
+		while let Some(name) = ::elf::link_map::get_needed(i, j)
 		{
-			use syscalls::vfs::MemoryMapMode;
-			use syscalls::memory::ProtectionMode;
-			kernel_log!("segment = {:?}", segment);
-			
-			if segment.load_addr <= entrypoint && entrypoint < segment.load_addr + segment.mem_size {
-				found_segment_for_entry = true;
-			}
-			
-			assert!(segment.file_size <= segment.mem_size);
-			// Split the segment into three regions: (reverse)
-			// - Page-aligned resident data
-			// - Non-resident data
-			// - Tailing resident data
-			let extra = segment.mem_size - segment.file_size;
-			let tail    = segment.file_size % PAGE_SIZE;
-			let aligned = segment.file_size - tail;
-			let map_mode = match segment.protection
-				{
-				::load::SegmentProt::Execute   => MemoryMapMode::Execute,
-				::load::SegmentProt::ReadWrite => MemoryMapMode::COW,
-				::load::SegmentProt::ReadOnly  => MemoryMapMode::ReadOnly,
-				};
-			let alloc_mode = match segment.protection
-				{
-				::load::SegmentProt::Execute   => ProtectionMode::Executable,
-				::load::SegmentProt::ReadWrite => ProtectionMode::ReadWrite,	// Allocates as read-write
-				::load::SegmentProt::ReadOnly  => ProtectionMode::ReadOnly,
-				};
-			let fp = segments_it.get_file();
-			if aligned > 0 {
-				let mm = fp.memory_map(segment.file_addr, aligned, segment.load_addr as *mut _, map_mode);
-				::std::mem::forget(mm);
-			}
-			if tail > 0 {
-				// SAFE: Trusing addresses to be valid
-				unsafe {
-					let destslice = ::std::slice::from_raw_parts_mut((segment.load_addr + aligned) as *mut u8, tail);
-					// - Allocate space
-					::syscalls::memory::allocate(destslice.as_ptr() as usize, 1).expect("tail alloc");
-					// - Read data
-					fp.read_at(segment.file_addr + aligned as u64, destslice).expect("Failure reading file data for end of .segment");
-					// - Reprotect to the real mode, not bothering if the desired is Read-Write
-					if alloc_mode != ProtectionMode::ReadWrite {
-						::syscalls::memory::reprotect(destslice.as_ptr() as usize, alloc_mode).expect("reprotect");
-					}
-				}
-			}
-			if extra > PAGE_SIZE - tail {
-				let addr = segment.load_addr + aligned + PAGE_SIZE;
-				let pages = (extra - (PAGE_SIZE - tail) + PAGE_SIZE-1) / PAGE_SIZE;
-				// SAFE: Just allocating at a known free place
-				unsafe { ::syscalls::memory::allocate(addr, pages).expect("extra alloc"); }
+			j += 1;
+			// The loader provides the symbols from this library itself
+			if name.as_bytes() == b"libloader_dyn.so" || ::elf::link_map::is_loaded(name) {
+				continue ;
 			}
+			try!( load_library(name) );
 		}
+		i += 1;
 	}
-	
-	if !found_segment_for_entry {
-		panic!("Entrypoint {:#x} is not located in a loaded segment", entrypoint);
+	Ok( () )
+}
+
+fn load_library(name: &'static ::std::ffi::OsStr) -> Result<(), Error>
+{
+	kernel_log!("load_library({:?})", name);
+	let fh = try!( open_library(name).ok_or(Error::MissingLibrary) );
+	let mut handle = try!( ::elf::load_executable(fh) );
+	if !handle.is_shared_object() {
+		kernel_log!("Library {:?} is not a shared object", name);
+		return Err(Error::Unsupported);
 	}
+	let (min, max) = handle.get_load_extent();
+	let min = min / PAGE_SIZE * PAGE_SIZE;
+	let base = try!( ::elf::link_map::alloc_region(max - min) ) - min;
+	kernel_log!("- base = {:#x}", base);
+	try!( map_segments(&mut handle, base) );
+	try!( handle.register(base, name) );
+	// TODO: See load_binary
+	::std::mem::forget(handle);
+	Ok( () )
+}
+
+fn open_library(name: &::std::ffi::OsStr) -> Option<::syscalls::vfs::File>
+{
+	use syscalls::vfs::FileOpenMode;
+	const SYSTEM_LIB_DIR: &'static [u8] = b"/sysroot/lib/";
 	
-	match handle.do_relocation()
-	{
-	Ok(_) => {},
-	Err(e) => {
-		panic!("Error relocating executable: {:?}", e);
-		},
+	// NOTE: No allocation in the loader, so build the path on the stack
+	let mut path = [0u8; 256];
+	let len = SYSTEM_LIB_DIR.len() + name.as_bytes().len();
+	if len > path.len() {
+		return None;
 	}
+	path[..SYSTEM_LIB_DIR.len()].copy_from_slice(SYSTEM_LIB_DIR);
+	path[SYSTEM_LIB_DIR.len()..len].copy_from_slice(name.as_bytes());
+	::syscalls::vfs::ROOT.open_child_path(&path[..len]).and_then(|n| n.into_file(FileOpenMode::Execute)).ok()
+}
 
-	// TODO: Have a cleaner way of handling this, than just forgetting the handle
-	// - Probably unwrap the handle into a raw file handle - THEN forget that (or even store it)
-	::std::mem::forget(handle);
+/// Map an image's segments into memory, offset by `base`
+fn map_segments(handle: &mut ::elf::ElfModuleHandle<::syscalls::vfs::File>, base: usize) -> Result<(), Error>
+{
+	// I would love to use a for loop here, but getting access the file is hard using that
+	let mut segments_it = handle.load_segments();
+	while let Some(mut segment) = segments_it.next()
+	{
+		use syscalls::vfs::MemoryMapMode;
+		use syscalls::memory::ProtectionMode;
+		kernel_log!("segment = {:?}", segment);
+		
+		// Relocate, and expand to start on a page boundary
+		segment.load_addr += base;
+		let misalign = segment.load_addr % PAGE_SIZE;
+		segment.load_addr -= misalign;
+		segment.file_addr -= misalign as u64;
+		segment.file_size += misalign;
+		segment.mem_size  += misalign;
 		
-	entrypoint
+		// Split the segment into three regions: (reverse)
+		// - Page-aligned resident data
+		// - Non-resident data
+		// - Tailing resident data
+		let extra = segment.mem_size - segment.file_size;
+		let tail    = segment.file_size % PAGE_SIZE;
+		let aligned = segment.file_size - tail;
+		let map_mode = match segment.protection
+			{
+			::load::SegmentProt::Execute   => MemoryMapMode::Execute,
+			::load::SegmentProt::ReadWrite => MemoryMapMode::COW,
+			::load::SegmentProt::ReadOnly  => MemoryMapMode::ReadOnly,
+			};
+		let alloc_mode = match segment.protection
+			{
+			::load::SegmentProt::Execute   => ProtectionMode::Executable,
+			::load::SegmentProt::ReadWrite => ProtectionMode::ReadWrite,	// Allocates as read-write
+			::load::SegmentProt::ReadOnly  => ProtectionMode::ReadOnly,
+			};
+		let fp = segments_it.get_file();
+		if aligned > 0 {
+			try!( fp.memory_map(segment.file_addr, aligned, segment.load_addr as *mut _, map_mode) );
+		}
+		if tail > 0 {
+			// SAFE: Address range checked by the ELF parser to not overlap other segments
+			unsafe {
+				let destslice = ::std::slice::from_raw_parts_mut((segment.load_addr + aligned) as *mut u8, tail);
+				// - Allocate space
+				try!( ::syscalls::memory::allocate(destslice.as_ptr() as usize, 1) );
+				// - Read data
+				if try!( fp.read_at(segment.file_addr + aligned as u64, destslice) ) != tail {
+					return Err(Error::Malformed);
+				}
+				// - Reprotect to the real mode, not bothering if the desired is Read-Write
+				if alloc_mode != ProtectionMode::ReadWrite {
+					try!( ::syscalls::memory::reprotect(destslice.as_ptr() as usize, alloc_mode) );
+				}
+			}
+		}
+		if extra > PAGE_SIZE - tail {
+			let addr = segment.load_addr + aligned + PAGE_SIZE;
+			let pages = (extra - (PAGE_SIZE - tail) + PAGE_SIZE-1) / PAGE_SIZE;
+			// SAFE: Just allocating at a known free place
+			try!( unsafe { ::syscalls::memory::allocate(addr, pages) } );
+		}
+	}
+	Ok( () )
 }
-
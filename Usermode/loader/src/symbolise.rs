@@ -0,0 +1,212 @@
+// Tifflin OS - Userland loader
+// - By John Hodge (thePowersGang)
+//
+// symbolise.rs
+// - Address symbolisation for fault reports
+use syscalls::vfs::File;
+
+const SHT_SYMTAB: u64 = 2;
+const SHT_DYNSYM: u64 = 11;
+const STT_FUNC: u8 = 2;
+
+/// Native ELF structure layouts, as (offset, size) pairs
+#[cfg(target_pointer_width="64")]
+mod layout {
+	pub const EHDR_SIZE: usize = 64;
+	pub const E_SHOFF: (usize,usize) = (40, 8);
+	pub const E_SHENTSIZE: (usize,usize) = (58, 2);
+	pub const E_SHNUM: (usize,usize) = (60, 2);
+
+	pub const SHDR_SIZE: usize = 64;
+	pub const SH_TYPE: (usize,usize) = (4, 4);
+	pub const SH_OFFSET: (usize,usize) = (24, 8);
+	pub const SH_SIZE: (usize,usize) = (32, 8);
+	pub const SH_LINK: (usize,usize) = (40, 4);
+
+	pub const SYM_SIZE: usize = 24;
+	pub const ST_NAME: (usize,usize) = (0, 4);
+	pub const ST_INFO: usize = 4;
+	pub const ST_SHNDX: (usize,usize) = (6, 2);
+	pub const ST_VALUE: (usize,usize) = (8, 8);
+	pub const ST_SIZE: (usize,usize) = (16, 8);
+}
+#[cfg(target_pointer_width="32")]
+mod layout {
+	pub const EHDR_SIZE: usize = 52;
+	pub const E_SHOFF: (usize,usize) = (32, 4);
+	pub const E_SHENTSIZE: (usize,usize) = (46, 2);
+	pub const E_SHNUM: (usize,usize) = (48, 2);
+
+	pub const SHDR_SIZE: usize = 40;
+	pub const SH_TYPE: (usize,usize) = (4, 4);
+	pub const SH_OFFSET: (usize,usize) = (16, 4);
+	pub const SH_SIZE: (usize,usize) = (20, 4);
+	pub const SH_LINK: (usize,usize) = (24, 4);
+
+	pub const SYM_SIZE: usize = 16;
+	pub const ST_NAME: (usize,usize) = (0, 4);
+	pub const ST_INFO: usize = 12;
+	pub const ST_SHNDX: (usize,usize) = (14, 2);
+	pub const ST_VALUE: (usize,usize) = (4, 4);
+	pub const ST_SIZE: (usize,usize) = (8, 4);
+}
+
+/// Read a little-endian field
+fn field(buf: &[u8], (ofs, size): (usize, usize)) -> u64 {
+	buf[ofs .. ofs + size].iter().rev().fold(0, |v, &b| (v << 8) | b as u64)
+}
+
+/// Symbol table of an image, read from the file on demand (the loader has no heap)
+pub struct FileSymbols<'a>
+{
+	file: &'a File,
+	symtab: (u64, u64),
+	strtab: (u64, u64),
+}
+
+impl<'a> FileSymbols<'a>
+{
+	/// Locate the symbol table of an image
+	///
+	/// Uses the static symbol table if present, otherwise (for stripped files) the dynamic symbol table, which only has
+	/// exported symbols. Symbol values are file addresses, so the load bias must be removed before calling `lookup`.
+	pub fn new(file: &File) -> Option<FileSymbols> {
+		let mut ehdr = [0u8; layout::EHDR_SIZE];
+		match file.read_at(0, &mut ehdr)
+		{
+		Ok(v) if v == ehdr.len() => {},
+		_ => return None,
+		}
+		if ehdr[..4] != b"\x7FELF"[..] {
+			return None;
+		}
+		let shoff = field(&ehdr, layout::E_SHOFF);
+		let shentsize = field(&ehdr, layout::E_SHENTSIZE);
+		let shnum = field(&ehdr, layout::E_SHNUM);
+		if shoff == 0 || (shentsize as usize) < layout::SHDR_SIZE {
+			return None;
+		}
+
+		let read_shdr = |idx: u64| -> Option<[u8; layout::SHDR_SIZE]> {
+			let mut shdr = [0u8; layout::SHDR_SIZE];
+			match file.read_at(shoff + idx * shentsize, &mut shdr)
+			{
+			Ok(v) if v == shdr.len() => Some(shdr),
+			_ => None,
+			}
+			};
+		let mut found = None;
+		for i in 0 .. shnum
+		{
+			let shdr = match read_shdr(i) { Some(v) => v, None => return None };
+			match field(&shdr, layout::SH_TYPE)
+			{
+			SHT_SYMTAB => { found = Some(shdr); break; },
+			SHT_DYNSYM => if found.is_none() { found = Some(shdr); },
+			_ => {},
+			}
+		}
+		let shdr = match found { Some(v) => v, None => return None };
+		let link = field(&shdr, layout::SH_LINK);
+		if link >= shnum {
+			return None;
+		}
+		let strhdr = match read_shdr(link) { Some(v) => v, None => return None };
+		Some(FileSymbols {
+			file: file,
+			symtab: (field(&shdr, layout::SH_OFFSET), field(&shdr, layout::SH_SIZE)),
+			strtab: (field(&strhdr, layout::SH_OFFSET), field(&strhdr, layout::SH_SIZE)),
+			})
+	}
+
+	/// Find the function containing `addr` (a file address), returning the symbol's name offset and the offset into the function
+	///
+	/// If no sized symbol contains the address, the nearest unsized symbol below it is used.
+	pub fn lookup(&self, addr: usize) -> Option<(u64, usize)> {
+		const CHUNK: usize = 64;
+		let mut buf = [0u8; CHUNK * layout::SYM_SIZE];
+		let mut best: Option<(u64, usize)> = None;
+		let mut pos = 0;
+		while pos < self.symtab.1
+		{
+			let len = ::std::cmp::min(buf.len() as u64, self.symtab.1 - pos) as usize;
+			let len = match self.file.read_at(self.symtab.0 + pos, &mut buf[..len])
+				{
+				Ok(v) => v - v % layout::SYM_SIZE,
+				Err(_) => return best,
+				};
+			if len == 0 {
+				break ;
+			}
+			pos += len as u64;
+			for sym in buf[..len].chunks(layout::SYM_SIZE)
+			{
+				if sym[layout::ST_INFO] & 0xF != STT_FUNC || field(sym, layout::ST_SHNDX) == 0 {
+					continue ;
+				}
+				// - Clear the Thumb bit (no-op elsewhere, functions are at least 2-byte aligned)
+				let base = field(sym, layout::ST_VALUE) as usize & !1;
+				let size = field(sym, layout::ST_SIZE) as usize;
+				if base == 0 || base > addr {
+					continue ;
+				}
+				let ofs = addr - base;
+				if size > 0 {
+					if ofs < size {
+						return Some( (field(sym, layout::ST_NAME), ofs) );
+					}
+				}
+				else if best.map(|(_,o)| ofs < o).unwrap_or(true) {
+					best = Some( (field(sym, layout::ST_NAME), ofs) );
+				}
+			}
+		}
+		best
+	}
+
+	/// Read a symbol name into the provided buffer (truncated if too long)
+	pub fn name<'b>(&self, ofs: u64, buf: &'b mut [u8]) -> &'b str {
+		if ofs >= self.strtab.1 {
+			return "#BADSTR#";
+		}
+		let len = ::std::cmp::min(buf.len() as u64, self.strtab.1 - ofs) as usize;
+		let len = self.file.read_at(self.strtab.0 + ofs, &mut buf[..len]).unwrap_or(0);
+		let bytes = buf[..len].split(|&b| b == 0).next().unwrap();
+		::std::str::from_utf8(bytes).unwrap_or("#UTF8#")
+	}
+}
+
+/// Display wrapper that demangles (legacy) rust symbol names
+pub struct Demangle<'a>(pub &'a str);
+impl<'a> ::std::fmt::Display for Demangle<'a> {
+	fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+		if self.0.starts_with("_ZN") {
+			let mut s = &self.0[3..];
+			let mut first = true;
+			loop
+			{
+				let digits = s.bytes().take_while(|b| b'0' <= *b && *b <= b'9').count();
+				let n = match s[..digits].parse::<usize>() { Ok(v) => v, Err(_) => break };
+				// - Lengths are in bytes, so may not end on a character boundary in a corrupt name
+				if n == 0 || digits + n > s.len() || !s.is_char_boundary(digits + n) {
+					break ;
+				}
+				let component = &s[digits .. digits + n];
+				s = &s[digits + n ..];
+				// Skip the trailing hash component
+				if s == "E" && component.starts_with("h") && component.len() == 17 {
+					break ;
+				}
+				if !first {
+					try!(write!(f, "::"));
+				}
+				try!(write!(f, "{}", component));
+				first = false;
+			}
+			Ok( () )
+		}
+		else {
+			write!(f, "{}", self.0)
+		}
+	}
+}
@@ -0,0 +1,415 @@
+#!/usr/bin/env python3
+# Tifflin OS - Userland loader
+# - By John Hodge (thePowersGang)
+#
+# test_corpus/gen_corpus.py
+# - Generates a corpus of malformed (amd64) ELF files for the loader's validation
+#
+# Each file is a small mutation of a minimal valid image. `expected.txt` lists the `loader::Error` each file should be
+# rejected with (the `control_*` files should be accepted). Re-run this script after changing a case, and check the
+# results with `make test_loader` (see runner.rs).
+import os
+import struct
+
+PAGE = 0x1000
+BASE = 0x40_0000
+
+PT_LOAD = 1
+PT_DYNAMIC = 2
+PT_TLS = 7
+
+DT_NULL, DT_NEEDED, DT_HASH, DT_STRTAB, DT_SYMTAB, DT_RELA, DT_RELASZ, DT_RELAENT, DT_STRSZ, DT_SYMENT = 0, 1, 4, 5, 6, 7, 8, 9, 10, 11
+DT_INIT_ARRAY, DT_INIT_ARRAYSZ = 25, 27
+
+R_X86_64_RELATIVE = 8
+
+# Layout of the base image (all in one read+execute page, plus a read-write page)
+OFS_PHDRS = 0x40
+OFS_CODE = 0x200
+OFS_DYNAMIC = 0x400
+OFS_STRTAB = 0x600
+OFS_SYMTAB = 0x700
+OFS_HASH = 0x780
+OFS_RELA = 0x800
+OFS_DATA = 0x1000
+
+# `mov eax, 60; xor edi, edi; syscall; jmp $` - Not a real Tifflin exit, just needs to be code
+CODE = bytes.fromhex("b83c00000031ff0f05ebfe")
+
+
+class Image:
+	def __init__(self, etype=2, machine=62):
+		self.ident = bytearray(b"\x7fELF" + bytes([2, 1, 1]) + bytes(9))
+		self.etype = etype
+		self.machine = machine
+		self.entry = BASE + OFS_CODE
+		self.phentsize = 56
+		self.phoff = OFS_PHDRS
+		self.phdrs = []
+		self.dynamic = None
+		self.extra = {}
+		self.size = OFS_DATA + PAGE
+
+	def add_phdr(self, p_type, flags, offset, vaddr, filesz, memsz, align=PAGE):
+		self.phdrs.append([p_type, flags, offset, vaddr, vaddr, filesz, memsz, align])
+
+	def build(self):
+		data = bytearray(self.size)
+		struct.pack_into("<16sHHIQQQIHHHHHH", data, 0,
+			bytes(self.ident), self.etype, self.machine, 1, self.entry, self.phoff, 0,
+			0, 64, self.phentsize, len(self.phdrs), 0, 0, 0)
+		for i, ph in enumerate(self.phdrs):
+			ofs = self.phoff + i * 56
+			if ofs + 56 <= len(data):
+				struct.pack_into("<IIQQQQQQ", data, ofs, *ph)
+		data[OFS_CODE:OFS_CODE+len(CODE)] = CODE
+		if self.dynamic is not None:
+			for i, (tag, val) in enumerate(self.dynamic + [(DT_NULL, 0)]):
+				struct.pack_into("<QQ", data, OFS_DYNAMIC + i*16, tag, val & 0xFFFF_FFFF_FFFF_FFFF)
+		for ofs, blob in self.extra.items():
+			data[ofs:ofs+len(blob)] = blob
+		return bytes(data)
+
+
+def base_exec():
+	img = Image()
+	img.add_phdr(PT_LOAD, 5, 0, BASE, OFS_DATA, OFS_DATA)
+	img.add_phdr(PT_LOAD, 6, OFS_DATA, BASE + OFS_DATA, PAGE, PAGE)
+	return img
+
+def base_dynamic(base=BASE):
+	"""Executable with a minimal dynamic section (string table, and a symbol table with one entry after the null symbol)"""
+	img = base_exec()
+	img.add_phdr(PT_DYNAMIC, 6, OFS_DYNAMIC, base + OFS_DYNAMIC, 0x100, 0x100)
+	img.extra[OFS_STRTAB] = b"\0libfoo.so\0"
+	img.extra[OFS_SYMTAB] = bytes(24) * 2
+	img.extra[OFS_HASH] = struct.pack("<IIII", 1, 2, 0, 0)
+	img.dynamic = [
+		(DT_STRTAB, base + OFS_STRTAB), (DT_STRSZ, 11),
+		(DT_SYMTAB, base + OFS_SYMTAB), (DT_SYMENT, 24),
+		(DT_HASH, base + OFS_HASH),
+		]
+	return img
+
+def with_rela(img, offset, ty, addend=0, base=BASE):
+	img.extra[OFS_RELA] = struct.pack("<QQq", offset, ty, addend)
+	img.dynamic += [(DT_RELA, base + OFS_RELA), (DT_RELASZ, 24), (DT_RELAENT, 24)]
+	return img
+
+
+CASES = []
+def case(name, expected):
+	def wrap(fcn):
+		CASES.append((name, expected, fcn))
+		return fcn
+	return wrap
+
+
+@case("control_exec", "Ok")
+def _():
+	return base_exec().build()
+
+@case("control_pie", "Ok")
+def _():
+	img = Image(etype=3)
+	img.entry = OFS_CODE
+	img.add_phdr(PT_LOAD, 5, 0, 0, OFS_DATA, OFS_DATA)
+	img.add_phdr(PT_LOAD, 6, OFS_DATA, OFS_DATA, PAGE, PAGE)
+	img.add_phdr(PT_DYNAMIC, 6, OFS_DYNAMIC, OFS_DYNAMIC, 0x100, 0x100)
+	img.dynamic = []
+	with_rela(img, OFS_DATA, R_X86_64_RELATIVE, OFS_CODE, base=0)
+	return img.build()
+
+@case("truncated_header", "NotElf")
+def _():
+	return base_exec().build()[:20]
+
+@case("bad_magic", "NotElf")
+def _():
+	return b"\x7fELG" + base_exec().build()[4:]
+
+@case("big_endian", "Unsupported")
+def _():
+	img = base_exec()
+	img.ident[5] = 2
+	return img.build()
+
+@case("wrong_class", "Unsupported")
+def _():
+	img = base_exec()
+	img.ident[4] = 3
+	return img.build()
+
+@case("wrong_machine", "Unsupported")
+def _():
+	img = base_exec()
+	img.machine = 40
+	return img.build()
+
+@case("relocatable_object", "Unsupported")
+def _():
+	img = base_exec()
+	img.etype = 1
+	return img.build()
+
+@case("phentsize_too_small", "Malformed")
+def _():
+	img = base_exec()
+	img.phentsize = 32
+	return img.build()
+
+@case("phentsize_too_large", "Malformed")
+def _():
+	img = base_exec()
+	img.phentsize = 0x200
+	return img.build()
+
+@case("no_phdrs", "Malformed")
+def _():
+	img = base_exec()
+	img.phdrs = []
+	return img.build()
+
+@case("phoff_past_eof", "Malformed")
+def _():
+	img = base_exec()
+	img.phoff = 0x10_0000
+	return img.build()
+
+@case("phoff_overflow", "Malformed")
+def _():
+	img = base_exec()
+	img.phoff = 0xFFFF_FFFF_FFFF_FFF0
+	return img.build()
+
+@case("filesz_gt_memsz", "Malformed")
+def _():
+	img = base_exec()
+	img.phdrs[1][5] = PAGE * 2
+	return img.build()
+
+@case("segment_past_eof", "Malformed")
+def _():
+	img = base_exec()
+	img.phdrs[1][2] = 0x10_0000
+	return img.build()
+
+@case("segment_offset_overflow", "Malformed")
+def _():
+	img = base_exec()
+	img.phdrs[1][2] = 0xFFFF_FFFF_FFFF_F000
+	return img.build()
+
+@case("segment_vaddr_overflow", "Malformed")
+def _():
+	img = base_exec()
+	img.phdrs[1][3] = img.phdrs[1][4] = 0xFFFF_FFFF_FFFF_F000
+	img.phdrs[1][6] = PAGE * 2
+	return img.build()
+
+@case("segment_empty", "Malformed")
+def _():
+	img = base_exec()
+	img.phdrs[1][5] = img.phdrs[1][6] = 0
+	return img.build()
+
+@case("segment_rwx", "Unsupported")
+def _():
+	img = base_exec()
+	img.phdrs[0][1] = 7
+	return img.build()
+
+@case("segment_no_permissions", "Unsupported")
+def _():
+	img = base_exec()
+	img.phdrs[1][1] = 0
+	return img.build()
+
+@case("segment_misaligned", "Unsupported")
+def _():
+	img = base_exec()
+	img.phdrs[1][3] = img.phdrs[1][4] = BASE + OFS_DATA + 0x10
+	return img.build()
+
+@case("segments_overlap", "Malformed")
+def _():
+	img = base_exec()
+	img.phdrs[1][3] = img.phdrs[1][4] = BASE + OFS_DATA - PAGE
+	return img.build()
+
+@case("too_many_segments", "Unsupported")
+def _():
+	img = base_exec()
+	for i in range(16):
+		img.add_phdr(PT_LOAD, 4, OFS_DATA, BASE + (i+4) * PAGE, 0x10, 0x10)
+	img.size = OFS_DATA + PAGE
+	img.phoff = OFS_DATA
+	return img.build()
+
+@case("null_page", "Unsupported")
+def _():
+	img = Image()
+	img.entry = OFS_CODE
+	img.add_phdr(PT_LOAD, 5, 0, 0, OFS_DATA, OFS_DATA)
+	return img.build()
+
+@case("exec_in_library_region", "Unsupported")
+def _():
+	img = Image()
+	img.entry = 0x7000_0000_0000 + OFS_CODE
+	img.add_phdr(PT_LOAD, 5, 0, 0x7000_0000_0000, OFS_DATA, OFS_DATA)
+	return img.build()
+
+@case("entry_in_data", "Malformed")
+def _():
+	img = base_exec()
+	img.entry = BASE + OFS_DATA
+	return img.build()
+
+@case("entry_outside_image", "Malformed")
+def _():
+	img = base_exec()
+	img.entry = 0x1234
+	return img.build()
+
+@case("two_dynamic", "Malformed")
+def _():
+	img = base_dynamic()
+	img.add_phdr(PT_DYNAMIC, 6, OFS_DYNAMIC, BASE + OFS_DYNAMIC, 0x100, 0x100)
+	return img.build()
+
+@case("dynamic_past_eof", "Malformed")
+def _():
+	img = base_dynamic()
+	img.phdrs[2][2] = 0x10_0000
+	return img.build()
+
+@case("tls_outside_image", "Malformed")
+def _():
+	img = base_exec()
+	img.add_phdr(PT_TLS, 4, OFS_DATA, 0x10_0000, 0x10, 0x10, 8)
+	return img.build()
+
+@case("tls_bad_align", "Malformed")
+def _():
+	img = base_exec()
+	img.add_phdr(PT_TLS, 4, OFS_DATA, BASE + OFS_DATA, 0x10, 0x10, 3)
+	return img.build()
+
+@case("strtab_outside_image", "Malformed")
+def _():
+	img = base_dynamic()
+	img.dynamic[0] = (DT_STRTAB, 0x10_0000)
+	return img.build()
+
+@case("strtab_size_overflow", "Malformed")
+def _():
+	img = base_dynamic()
+	img.dynamic[1] = (DT_STRSZ, 0xFFFF_FFFF_FFFF_FFF0)
+	return img.build()
+
+@case("hash_outside_image", "Malformed")
+def _():
+	img = base_dynamic()
+	img.dynamic[4] = (DT_HASH, 0x10_0000)
+	return img.build()
+
+@case("hash_nchain_huge", "Malformed")
+def _():
+	img = base_dynamic()
+	img.extra[OFS_HASH] = struct.pack("<II", 1, 0x100_0000)
+	return img.build()
+
+@case("symtab_after_strtab_no_hash", "Malformed")
+def _():
+	img = base_dynamic()
+	img.dynamic = [e for e in img.dynamic if e[0] != DT_HASH]
+	img.dynamic[0] = (DT_STRTAB, BASE + OFS_SYMTAB - 0x80)
+	return img.build()
+
+@case("symtab_bad_entsize", "Malformed")
+def _():
+	img = base_dynamic()
+	img.dynamic[3] = (DT_SYMENT, 16)
+	return img.build()
+
+@case("needed_outside_strtab", "Malformed")
+def _():
+	img = base_dynamic()
+	img.dynamic.append((DT_NEEDED, 0x1000))
+	return img.build()
+
+@case("needed_missing_library", "MissingLibrary")
+def _():
+	img = base_dynamic()
+	img.dynamic.append((DT_NEEDED, 1))
+	return img.build()
+
+@case("rela_outside_image", "Malformed")
+def _():
+	img = with_rela(base_dynamic(), 0, R_X86_64_RELATIVE)
+	img.dynamic[-3] = (DT_RELA, 0x10_0000)
+	return img.build()
+
+@case("rela_bad_entsize", "Malformed")
+def _():
+	img = with_rela(base_dynamic(), BASE + OFS_DATA, R_X86_64_RELATIVE)
+	img.dynamic[-1] = (DT_RELAENT, 16)
+	return img.build()
+
+@case("reloc_target_outside_image", "Malformed")
+def _():
+	return with_rela(base_dynamic(), 0x10_0000, R_X86_64_RELATIVE).build()
+
+@case("reloc_target_straddles_segment", "Malformed")
+def _():
+	return with_rela(base_dynamic(), BASE + OFS_DATA + PAGE - 4, R_X86_64_RELATIVE).build()
+
+@case("reloc_unknown_type", "Unsupported")
+def _():
+	return with_rela(base_dynamic(), BASE + OFS_DATA, 200).build()
+
+@case("reloc_symbol_out_of_range", "Malformed")
+def _():
+	return with_rela(base_dynamic(), BASE + OFS_DATA, (50 << 32) | 1).build()
+
+@case("reloc_undefined_symbol", "UndefinedSymbol")
+def _():
+	img = with_rela(base_dynamic(), BASE + OFS_DATA, (1 << 32) | 1)
+	# Global undefined symbol named "libfoo.so"
+	img.extra[OFS_SYMTAB + 24] = struct.pack("<IBBHQQ", 1, 0x10, 0, 0, 0, 0)
+	return img.build()
+
+@case("init_array_outside_image", "Malformed")
+def _():
+	img = base_dynamic()
+	img.dynamic += [(DT_INIT_ARRAY, 0x10_0000), (DT_INIT_ARRAYSZ, 8)]
+	return img.build()
+
+@case("init_array_bad_size", "Malformed")
+def _():
+	img = base_dynamic()
+	img.dynamic += [(DT_INIT_ARRAY, BASE + OFS_DATA), (DT_INIT_ARRAYSZ, 5)]
+	return img.build()
+
+@case("init_array_entry_in_data", "Malformed")
+def _():
+	img = base_dynamic()
+	img.extra[OFS_DATA] = struct.pack("<Q", BASE + OFS_DATA + 8)
+	img.dynamic += [(DT_INIT_ARRAY, BASE + OFS_DATA), (DT_INIT_ARRAYSZ, 8)]
+	return img.build()
+
+
+def main():
+	outdir = os.path.dirname(os.path.abspath(__file__))
+	lines = []
+	for name, expected, fcn in CASES:
+		with open(os.path.join(outdir, name + ".elf"), "wb") as fp:
+			fp.write(fcn())
+		lines.append("%s.elf\t%s\n" % (name, expected))
+	with open(os.path.join(outdir, "expected.txt"), "w") as fp:
+		fp.write("# File\tExpected result (generated by gen_corpus.py)\n")
+		fp.writelines(lines)
+
+if __name__ == "__main__":
+	main()
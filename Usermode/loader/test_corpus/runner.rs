@@ -0,0 +1,301 @@
+// Tifflin OS - Userland loader
+// - By John Hodge (thePowersGang)
+//
+// test_corpus/runner.rs
+// - Host-side driver for the malformed ELF corpus
+//!
+//! Builds the loader's `elf` and `load` modules against small host stand-ins for `syscalls` and the parts of `std`
+//! that differ from the host's, then loads each file listed in `expected.txt` the same way `load_binary` does, and
+//! checks the resulting `elf::Error` variant.
+//!
+//! Usage: `runner <corpus dir>` (set `LOADER_LOG` to see the loader's log output)
+//!
+//! NOTE: Images are mapped at their link addresses, so this must be built position-independent (the default) and
+//! only run on amd64 hosts.
+#![no_std]
+
+#[macro_use]
+extern crate std as host_std;
+
+#[macro_use(impl_from, impl_fmt, try, todo)]
+extern crate macros;
+
+macro_rules! kernel_log {
+	($($t:tt)*) => {
+		if ::log_enabled() { eprintln!($($t)*); }
+	};
+}
+
+#[path="../../libbyteorder/src/lib.rs"]
+mod byteorder;
+#[path="../src/elf/mod.rs"]
+mod elf;
+#[path="../src/load/mod.rs"]
+mod load;
+
+use load::SegmentIterator;
+use elf::Error;
+
+const PAGE_SIZE: usize = 0x1000;
+
+fn log_enabled() -> bool {
+	host_std::env::var_os("LOADER_LOG").is_some()
+}
+
+/// Host `std`, with the Tifflin-specific `ffi` and `rt` items the loader uses
+mod std {
+	pub use host_std::*;
+
+	pub mod io {
+		pub use host_std::io::{Error, Result, Seek, SeekFrom};
+		/// Tifflin's `Read` (without the methods `byteorder` provides itself)
+		pub trait Read {
+			fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+		}
+		impl<T: ?Sized + host_std::io::Read> Read for T {
+			fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+				host_std::io::Read::read(self, buf)
+			}
+		}
+	}
+
+	pub mod ffi {
+		/// Byte string (as Tifflin's `OsStr`, which unlike the host's can be built from any byte slice)
+		pub struct OsStr([u8]);
+		impl OsStr {
+			pub fn new<'a, S: AsRef<[u8]>+?Sized+'a>(s: &'a S) -> &'a OsStr {
+				// SAFE: OsStr is [u8]
+				unsafe { ::std::mem::transmute(s.as_ref()) }
+			}
+			pub fn as_bytes(&self) -> &[u8] {
+				&self.0
+			}
+		}
+		impl PartialEq for OsStr {
+			fn eq(&self, other: &OsStr) -> bool {
+				self.0 == other.0
+			}
+		}
+		impl_fmt! {
+			Debug(self, f) for OsStr {
+				write!(f, "{:?}", ::host_std::string::String::from_utf8_lossy(&self.0))
+			}
+		}
+	}
+
+	pub mod rt {
+		#[derive(Copy,Clone,Debug,Default)]
+		pub struct TlsTemplate
+		{
+			pub base: usize,
+			pub file_size: usize,
+			pub mem_size: usize,
+			pub align: usize,
+		}
+		pub const TCB_SIZE: usize = 2 * ::std::mem::size_of::<usize>();
+		pub fn thread_pointer() -> usize {
+			unimplemented!("thread_pointer");
+		}
+	}
+}
+
+/// Host stand-ins for the system calls used by `elf`
+mod syscalls {
+	pub fn log_write<S: ?Sized+AsRef<[u8]>>(msg: &S) {
+		kernel_log!("{}", ::host_std::string::String::from_utf8_lossy(msg.as_ref()));
+	}
+
+	pub mod vfs {
+		#[derive(Debug)]
+		pub enum Error {}
+		/// Corpus file (read into memory)
+		pub type File = ::host_std::io::Cursor<::host_std::vec::Vec<u8>>;
+	}
+
+	pub mod memory {
+		use host_std::cell::RefCell;
+		use host_std::vec::Vec;
+
+		#[derive(Debug)]
+		pub enum Error {
+			RangeInUse,
+		}
+
+		const PROT_READ: i32 = 1;
+		const PROT_WRITE: i32 = 2;
+		const MAP_PRIVATE: i32 = 0x02;
+		const MAP_ANONYMOUS: i32 = 0x20;
+		const MAP_FIXED_NOREPLACE: i32 = 0x10_0000;
+		extern "C" {
+			fn mmap(addr: *mut u8, len: usize, prot: i32, flags: i32, fd: i32, ofs: isize) -> *mut u8;
+			fn munmap(addr: *mut u8, len: usize) -> i32;
+		}
+
+		thread_local! {
+			static S_ALLOCATIONS: RefCell<Vec<(usize, usize)>> = RefCell::new(Vec::new());
+		}
+
+		/// Allocate zeroed read-write pages at a fixed address
+		pub unsafe fn allocate(addr: usize, count: usize) -> Result<(), Error> {
+			let len = try!( count.checked_mul(::PAGE_SIZE).ok_or(Error::RangeInUse) );
+			let rv = mmap(addr as *mut u8, len, PROT_READ|PROT_WRITE, MAP_PRIVATE|MAP_ANONYMOUS|MAP_FIXED_NOREPLACE, -1, 0);
+			if rv as usize != addr {
+				if rv as isize != -1 {
+					munmap(rv, len);
+				}
+				return Err(Error::RangeInUse);
+			}
+			S_ALLOCATIONS.with(|a| a.borrow_mut().push( (addr, len) ));
+			Ok( () )
+		}
+
+		/// Find a free address range of `len` bytes (not reserved)
+		pub fn find_free(len: usize) -> usize {
+			// SAFE: Mapping is immediately released
+			unsafe {
+				let rv = mmap(0 as *mut u8, len, 0, MAP_PRIVATE|MAP_ANONYMOUS, -1, 0);
+				assert!(rv as isize != -1, "Unable to find {:#x} bytes of free address space", len);
+				munmap(rv, len);
+				rv as usize
+			}
+		}
+
+		/// Release everything allocated since the last call
+		pub fn release_all() {
+			S_ALLOCATIONS.with(|a| for (addr, len) in a.borrow_mut().drain(..) {
+				// SAFE: Only ranges allocated by `allocate` (and no longer referenced) are released
+				unsafe { munmap(addr as *mut u8, len); }
+			});
+		}
+	}
+
+	pub mod threads {
+		pub const FAULT_MAX_IMAGES: usize = 16;
+		#[derive(Copy,Clone,Default,Debug)]
+		pub struct FaultImage {
+			pub start: usize,
+			pub end: usize,
+			pub bias: usize,
+			pub name: [u8; 32],
+		}
+		pub struct ThisProcess;
+		pub static S_THIS_PROCESS: ThisProcess = ThisProcess;
+		impl ThisProcess {
+			pub fn set_images(&self, _images: &[FaultImage]) {
+			}
+		}
+
+		pub fn get_time() -> u64 {
+			0
+		}
+	}
+}
+
+/// Functions exported by the loader (only their addresses are used by `load::lookup_symbol`)
+mod interface {
+	pub fn new_process() {}
+	pub fn start_process() {}
+	pub fn log_fault() {}
+}
+
+/// Load and link an image, as `load_binary` does (without TLS setup or initialisers)
+fn load(file: syscalls::vfs::File) -> Result<(), Error>
+{
+	elf::link_map::clear();
+	let mut handle = try!( elf::load_executable(file) );
+	let base = if handle.is_shared_object() {
+			let (min, max) = handle.get_load_extent();
+			let min = min / PAGE_SIZE * PAGE_SIZE;
+			syscalls::memory::find_free(max - min + 2 * PAGE_SIZE) - min
+		}
+		else {
+			0
+		};
+	try!( map_segments(&mut handle, base) );
+	try!( handle.register(base, std::ffi::OsStr::new("")) );
+	// Dependencies: there are no libraries in the corpus, and the loader's own exports are always present
+	let mut i = 0;
+	while i < elf::link_map::count()
+	{
+		let mut j = 0;
+		while let Some(name) = elf::link_map::get_needed(i, j)
+		{
+			j += 1;
+			if name.as_bytes() != b"libloader_dyn.so" {
+				return Err(Error::MissingLibrary);
+			}
+		}
+		i += 1;
+	}
+	elf::link_map::layout_tls();
+	try!( elf::link_map::relocate_all() );
+	host_std::mem::forget(handle);
+	Ok( () )
+}
+
+/// Copy an image's segments into freshly allocated (read-write) memory, offset by `base`
+fn map_segments(handle: &mut elf::ElfModuleHandle<syscalls::vfs::File>, base: usize) -> Result<(), Error>
+{
+	let mut segments_it = handle.load_segments();
+	while let Some(segment) = segments_it.next()
+	{
+		let addr = segment.load_addr + base;
+		let start = addr / PAGE_SIZE * PAGE_SIZE;
+		let pages = (addr + segment.mem_size - start + PAGE_SIZE-1) / PAGE_SIZE;
+		// SAFE: Range was checked to not overlap other segments, and allocate fails if the host is using it
+		unsafe {
+			try!( syscalls::memory::allocate(start, pages) );
+			let data = segments_it.get_file().get_ref();
+			let src = &data[segment.file_addr as usize ..][.. segment.file_size];
+			host_std::ptr::copy_nonoverlapping(src.as_ptr(), addr as *mut u8, src.len());
+		}
+	}
+	Ok( () )
+}
+
+fn main()
+{
+	use host_std::io::Read;
+
+	let dir = host_std::path::PathBuf::from( host_std::env::args_os().nth(1).expect("Usage: runner <corpus dir>") );
+	let mut expected = host_std::string::String::new();
+	host_std::fs::File::open(dir.join("expected.txt")).and_then(|mut f| f.read_to_string(&mut expected)).expect("Unable to read expected.txt");
+
+	let mut n_failed = 0;
+	let mut n_total = 0;
+	for line in expected.lines().filter(|l| !l.starts_with('#') && !l.is_empty())
+	{
+		let mut cols = line.split('\t');
+		let (name, exp) = match (cols.next(), cols.next())
+			{
+			(Some(n), Some(e)) => (n, e),
+			_ => panic!("Malformed line in expected.txt: {:?}", line),
+			};
+		let mut data = host_std::vec::Vec::new();
+		host_std::fs::File::open(dir.join(name)).and_then(|mut f| f.read_to_end(&mut data)).expect(name);
+
+		let res = match load(host_std::io::Cursor::new(data))
+			{
+			Ok(()) => host_std::string::String::from("Ok"),
+			Err(e) => {
+				// Just the variant name (e.g. `Io(...)` -> `Io`)
+				let s = format!("{:?}", e);
+				s.split('(').next().unwrap().into()
+				},
+			};
+		syscalls::memory::release_all();
+
+		n_total += 1;
+		if res == exp {
+			println!("PASS {}: {}", name, res);
+		}
+		else {
+			println!("FAIL {}: expected {}, got {}", name, exp, res);
+			n_failed += 1;
+		}
+	}
+	println!("{}/{} passed", n_total - n_failed, n_total);
+	if n_failed > 0 {
+		host_std::process::exit(1);
+	}
+}
@@ -148,6 +148,9 @@ fn open_exe(path: &str) -> Result<::syscalls::vfs::File, ::syscalls::vfs::Error>
 	}
 }
 
+/// Time (in milliseconds) session processes are given to exit once the session ends
+const EXIT_TIMEOUT_MS: u64 = 3000;
+
 fn spawn_console_and_wait(path: &str)
 {
 	let (hs_chan, cli_chan) = ::syscalls::ipc::RpcChannel::new_pair().expect("Coudn't create new RPC Channel");
@@ -158,6 +161,7 @@ fn spawn_console_and_wait(path: &str)
 		let pp = loader::new_process(fh, path.as_bytes(), &[]).expect("Could not spawn handle server");
 		pp.send_obj( "RwRoot", VFS_ROOT.clone() );
 		pp.send_obj( "HsChan", hs_chan );
+		pp.send_obj( "guigrp", ::syscalls::gui::clone_group_handle() );
 		pp.start()
 		};
 	// TODO: I need something more elegant than this.
@@ -175,6 +179,17 @@ fn spawn_console_and_wait(path: &str)
 		pp.start()
 		};
 	::syscalls::threads::wait(&mut [console.wait_terminate()], !0);
-	//::syscalls::threads::wait(&mut [console.wait_terminate(), handle_server.wait_terminate()], !0);
+	if let Some(fault) = console.get_fault() {
+		kernel_log!("'{}' terminated by a fault", path);
+		::loader::log_fault(open_exe(path).ok().as_ref(), &fault);
+	}
+
+	// Session ended, ask the handle server to exit (killing it if it doesn't)
+	handle_server.request_exit();
+	::syscalls::threads::wait(&mut [handle_server.wait_terminate()], ::syscalls::threads::get_time() + EXIT_TIMEOUT_MS);
+	if handle_server.get_exit_status().is_none() {
+		kernel_log!("Handle server didn't exit, killing");
+		handle_server.terminate();
+	}
 }
 
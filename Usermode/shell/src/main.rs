@@ -9,57 +9,69 @@ extern crate async;
 extern crate syscalls;
 
 extern crate loader;
+extern crate handle_server;
 
 use syscalls::gui::KeyCode;
+use syscalls::threads::Process;
 use wtk::ModifierKey;
+use std::cell::RefCell;
 
 macro_rules! imgpath {
 		($p:expr) => {concat!("/system/Tifflin/shared/images/",$p)};
 }
 
-fn start_app_console() {
-	start_app(&["/sysroot/bin/simple_console", "--windowed"], |app| {
-		//app.send_obj( "vfs", ::syscalls::vfs::ROOT.clone() );
-		});
-}
-fn start_app_filebrowser() {
-	start_app(&["/sysroot/bin/filebrowser"], |app| {
-		app.send_obj( "ro:/", ::syscalls::vfs::ROOT.clone() );
-		});
-}
-fn start_app_editor() {
-	let path = "/system/1.txt";
-	let f = ::syscalls::vfs::ROOT.open_child_path(path.as_bytes()).expect("Couldn't open file")
-		.into_file(::syscalls::vfs::FileOpenMode::ReadOnly).expect("Couldn't open file as readonly");
-	start_app(&["/sysroot/bin/fileviewer", path], |app| {
-		app.send_obj( "file", f );
-		});
+/// Application listed in the system menu (from the handle server's manifests)
+struct AppInfo
+{
+	id: String,
+	name: String,
+	args: Vec<Vec<u8>>,
+	env: Vec<(Vec<u8>, Vec<u8>)>,
+	handles: Vec<Vec<u8>>,
 }
 
+/// Keyboard shortcuts for applications (by ID)
+const APP_SHORTCUTS: &'static [(&'static str, KeyCode, &'static str)] = &[
+	("console", KeyCode::T, "Win-T"),
+	("filebrowser", KeyCode::E, "Win-E"),
+	];
+
+/// Time (in milliseconds) applications are given to exit on logout, before they're killed
+const LOGOUT_TIMEOUT_MS: u64 = 3000;
+
 fn main()
 {
 	::wtk::initialise();
 
+	let hs = ::handle_server::Connection::rx_new();
+	let hs = &hs;
+	let apps = load_applications(hs);
+	// Processes started from the menus (asked to exit on logout)
+	let running = RefCell::new(Vec::new());
+	let running = &running;
 
 	let power_menu = {
 		use wtk::menu::{Menu,Entry,Spacer};
 		Menu::new("Power Menu", (
 			Entry::new("Lock", 0, "", || {}),
-			Entry::new("Logout", 1, "", || {}),
+			Entry::new("Logout", 1, "", || logout(running)),
 			Spacer,
 			Entry::new("Restart", 0, "", || {}),
 			Entry::new("Shut Down", 0, "", || {}),
 			))
 		};
 	//power_menu.set_pos( Pos::new(0,20) );
+	// NOTE: All launchers are the same closure type, so can be referenced by the menu entries
+	let launchers: Vec<_> = apps.iter().map(|app| move || start_app(hs, app, running)).collect();
 	let system_menu = {
-		use wtk::menu::{Menu,Entry,Spacer};
-		Menu::new("System Menu", (
-			Entry::new("CLI", 0, "Win-T", || start_app_console()),
-			Spacer,
-			Entry::new("Filesystem", 0, "Win-E", || start_app_filebrowser()),
-			Entry::new("Text Editor", 5, "", || start_app_editor()),
-			))
+		use wtk::menu::{Menu,Entry,AnyItem};
+		Menu::new("System Menu", apps.iter().zip(launchers.iter())
+			.map(|(app, launch)| {
+				let shortcut = APP_SHORTCUTS.iter().find(|s| s.0 == app.id).map(|s| s.2).unwrap_or("");
+				AnyItem::Entry( Entry::new(&app.name[..], 0, shortcut, launch as &Fn()) )
+				})
+			.collect::<Vec<_>>()
+			)
 		};
 	system_menu.set_pos( ::wtk::geom::Pos::new(0,20) );
 	
@@ -103,8 +115,12 @@ fn main()
 	
 	win_menu.add_shortcut_1( KeyCode::LeftGui, || system_menu.show() );
 	win_menu.add_shortcut_1( KeyCode::RightGui, || system_menu.show() );
-	win_menu.add_shortcut_2( ModifierKey::Gui, KeyCode::T, || start_app_console() );
-	win_menu.add_shortcut_2( ModifierKey::Gui, KeyCode::E, || start_app_filebrowser() );
+	for &(id, key, _) in APP_SHORTCUTS
+	{
+		if let Some(app) = apps.iter().find(|a| a.id == id) {
+			win_menu.add_shortcut_2( ModifierKey::Gui, key, move || start_app(hs, app, running) );
+		}
+	}
 
 	win_background.show();
 	win_menu.show();
@@ -118,34 +134,121 @@ fn main()
 
 }
 
-fn start_app<F>(args: &[&str], cb: F)
-where
-	F: FnOnce(&mut ::loader::ProtoProcess)
+/// Read the manifests of all applications registered with the handle server
+fn load_applications(hs: &::handle_server::Connection) -> Vec<AppInfo>
 {
-	kernel_log!("start_app(args={:?})", args);
-	let fh = open_exec(args[0]);
-	// SAFE: &str and &[u8] have the same representation
-	let byte_args: &[&[u8]] = unsafe { ::std::mem::transmute(&args[1..]) };
-	match ::loader::new_process(fh, args[0].as_bytes(), byte_args)
+	let mut rv = Vec::new();
+	for app in hs.applications()
 	{
-	Ok(mut app) => {
-		app.send_obj( "guigrp", ::syscalls::gui::clone_group_handle() );
-		cb(&mut app);
-		app.start();
-		},
-	Err(_e) => {},
+		let file = app.manifest_file();
+		let mut data = vec![0; file.get_size() as usize];
+		match file.read_at(0, &mut data)
+		{
+		Ok(len) => data.truncate(len),
+		Err(e) => {
+			kernel_log!("Unable to read manifest for '{}' - {:?}", app.id(), e);
+			continue ;
+			},
+		}
+		match ::handle_server::manifest::Manifest::parse(&data)
+		{
+		Ok(m) => rv.push(AppInfo {
+			id: String::from(app.id()),
+			name: String::from(m.name()),
+			args: m.args().map(|a| a.to_vec()).collect(),
+			env: m.env().map(|e| match e.iter().position(|&b| b == b'=')
+				{
+				Some(p) => (e[..p].to_vec(), e[p+1..].to_vec()),
+				None => (e.to_vec(), Vec::new()),
+				}).collect(),
+			handles: m.handles().map(|h| h.to_vec()).collect(),
+			}),
+		Err(e) => kernel_log!("Invalid manifest for '{}' - {:?}", app.id(), e),
+		}
 	}
+	rv
 }
 
-fn open_exec(path: &str) -> ::syscalls::vfs::File
+fn start_app(hs: &::handle_server::Connection, app: &AppInfo, running: &RefCell<Vec<Process>>)
 {
-	match ::syscalls::vfs::ROOT.open_child_path(path.as_bytes())
+	// Ask the user for any files before starting the process
+	let mut file = None;
+	for h in app.handles.iter()
 	{
-	Ok(v) => match v.into_file(::syscalls::vfs::FileOpenMode::Execute)
+		match &h[..]
+		{
+		b"file" => match hs.select_file_ro(None, &app.name)
+			{
+			Ok(v) => file = Some(v),
+			Err(e) => {
+				kernel_log!("No file selected for '{}' - {:?}", app.id, e);
+				return ;
+				},
+			},
+		b"guigrp" | b"ro:/" | b"cwd" => {},
+		_ => {
+			kernel_log!("Unable to start '{}' - Unknown handle {:?}", app.id, ::std::ffi::OsStr::new(&h[..]));
+			return ;
+			},
+		}
+	}
+
+	let fh = match hs.open_executable(&app.id)
 		{
 		Ok(v) => v,
-		Err(e) => panic!("Couldn't open '{}' as an executable file - {:?}", path, e),
+		Err(e) => {
+			kernel_log!("Couldn't open executable for '{}' - {:?}", app.id, e);
+			return ;
+			},
+		};
+	let args: Vec<&[u8]> = app.args.iter().map(|a| &a[..]).collect();
+	let env: Vec<(&[u8], &[u8])> = app.env.iter().map(|&(ref k, ref v)| (&k[..], &v[..])).collect();
+	match ::loader::new_process_env(fh, app.id.as_bytes(), &args, &env)
+	{
+	Ok(app_proc) => {
+		app_proc.send_obj( "guigrp", ::syscalls::gui::clone_group_handle() );
+		for h in app.handles.iter()
+		{
+			match &h[..]
+			{
+			b"ro:/" => app_proc.send_obj( "ro:/", ::syscalls::vfs::ROOT.clone() ),
+			// - Relative paths are resolved from the (read-only) root
+			b"cwd" => app_proc.set_cwd( ::syscalls::vfs::ROOT.clone() ),
+			b"file" => if let Some(f) = file.take() { app_proc.send_obj( "file", f ) },
+			_ => {},
+			}
+		}
+		let p = app_proc.start();
+		let mut running = running.borrow_mut();
+		running.retain(|p| p.get_exit_status().is_none());
+		running.push(p);
 		},
-	Err(e) => panic!("Couldn't open executable '{}' - {:?}", path, e),
+	Err(e) => kernel_log!("Couldn't start '{}' - {:?}", app.id, e),
+	}
+}
+
+/// End the session: Ask all started applications to exit, kill any that don't within the timeout, then exit
+fn logout(running: &RefCell<Vec<Process>>) -> !
+{
+	let running = running.borrow();
+	kernel_log!("Logging out, requesting exit of {} applications", running.len());
+	for p in running.iter()
+	{
+		p.request_exit();
+	}
+	let deadline = ::syscalls::threads::get_time() + LOGOUT_TIMEOUT_MS;
+	loop
+	{
+		let mut waits: Vec<_> = running.iter().filter(|p| p.get_exit_status().is_none()).map(|p| p.wait_terminate()).collect();
+		if waits.is_empty() || ::syscalls::threads::get_time() >= deadline {
+			break ;
+		}
+		::syscalls::threads::wait(&mut waits, deadline);
+	}
+	for p in running.iter().filter(|p| p.get_exit_status().is_none())
+	{
+		kernel_log!("Application didn't exit in time, killing");
+		p.terminate();
 	}
+	::syscalls::threads::exit(0);
 }
@@ -55,6 +55,8 @@ fn main()
 	}
 	
 	::wtk::initialise();
+	// Exit when the session ends
+	let _ = ::std::rt::control::start_handler(::std::rt::control::default_handler);
 
 	let mut shell = ShellState::new();
 	let mut input = input::InputStack::new();
@@ -0,0 +1,16 @@
+// Tifflin OS Project
+// - By John Hodge (thePowersGang)
+//
+// exitstatus.inc.rs
+// - Process exit statuses set by the kernel
+//
+// Included by syscalls.inc.rs and Kernel/Core/threads/mod.rs
+
+/// Process exit status: Terminated by the kernel after an invalid system call
+pub const EXIT_STATUS_BADSYSCALL: u32 = 0x8000_0000;
+/// Process exit status: Killed by the kernel to free memory
+pub const EXIT_STATUS_OOM: u32 = 0x8000_0001;
+/// Process exit status: Killed using CORE_PROCESS_KILL
+pub const EXIT_STATUS_KILLED: u32 = 0x8000_0002;
+/// Process exit status: Terminated by an unhandled CPU fault (details from CORE_PROCESS_GETFAULT)
+pub const EXIT_STATUS_FAULT: u32 = 0x8000_0003;
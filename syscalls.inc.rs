@@ -36,16 +36,128 @@ def_grp!( 0: GROUP_CORE = {
 	=2: CORE_EXITPROCESS,
 	/// Request a text string from the kernel
 	=3: CORE_TEXTINFO,
-	/// Terminate the current thread
+	/// Terminate the current thread (with an exit value, see CORE_THREAD_GETEXITVALUE)
 	=4: CORE_EXITTHREAD,
 	/// Start a new process (loader only, use loader API instead)
 	=5: CORE_STARTPROCESS,
-	/// Start a new thread in the current process
+	/// Start a new thread in the current process (IP, SP, TLS base, argument), returns a thread object
 	=6: CORE_STARTTHREAD,
 	/// Wait for any of a set of events
 	=7: CORE_WAIT,
+	/// Read the monotonic clock (milliseconds since an arbitary point, usually boot)
+	=8: CORE_GETTIME,
+	/// Read the wall-clock time (milliseconds since the UNIX epoch, !0 if unknown)
+	=9: CORE_GETREALTIME,
+	/// Lower the current process's scheduling priority class (see PRIORITY_*)
+	=10: CORE_SETPRIORITY,
+	/// Read the CPU time used by the current thread (milliseconds)
+	=11: CORE_GETCPUTIME,
+	/// Sleep until woken, if the word at an address equals the passed value (with a timeout)
+	=12: CORE_FUTEXWAIT,
+	/// Wake up to N threads waiting on an address
+	=13: CORE_FUTEXWAKE,
+	/// Set the current thread's TLS base (FS base on amd64, TPIDRURO on ARM)
+	=14: CORE_SETTLSBASE,
 });
 
+/// Priority class for CORE_SETPRIORITY: Only run when nothing else is runnable
+pub const PRIORITY_IDLE: u32 = 0;
+/// Priority class for CORE_SETPRIORITY: Default class
+pub const PRIORITY_NORMAL: u32 = 1;
+
+/// CORE_FUTEXWAIT error: The word didn't hold the expected value (the thread didn't sleep)
+pub const FUTEX_ERR_CHANGED: u32 = 0;
+/// CORE_FUTEXWAIT error: The timeout expired before the thread was woken
+pub const FUTEX_ERR_TIMEDOUT: u32 = 1;
+
+/// MEM_ALLOCATE/CORE_STARTPROCESS error: The requested range is already in use
+pub const MEM_ERR_INUSE: u32 = 0;
+/// MEM_ALLOCATE/CORE_STARTPROCESS error: Out of physical memory
+pub const MEM_ERR_OUTOFMEMORY: u32 = 1;
+/// MEM_ALLOCATE/CORE_STARTPROCESS error: The process's page quota would be exceeded
+pub const MEM_ERR_QUOTA: u32 = 2;
+
+// Process exit statuses (EXIT_STATUS_*), shared with the kernel core
+include!("exitstatus.inc.rs");
+
+/// FaultInfo kind: Page fault (amd64) or data/prefetch abort (ARM)
+pub const FAULT_KIND_PAGE: u32 = 0;
+/// FaultInfo kind: General protection fault
+pub const FAULT_KIND_PROTECTION: u32 = 1;
+/// FaultInfo kind: Undefined instruction
+pub const FAULT_KIND_INVALIDOPCODE: u32 = 2;
+/// FaultInfo kind: Any other exception
+pub const FAULT_KIND_OTHER: u32 = 3;
+/// Number of registers in `FaultInfo::regs`
+pub const FAULT_NREGS: usize = 16;
+/// Maximum number of entries in `FaultInfo::backtrace`
+pub const FAULT_BACKTRACE_LEN: usize = 16;
+/// Maximum number of entries in `FaultInfo::images`
+pub const FAULT_MAX_IMAGES: usize = 16;
+/// Size of `FaultImage::name`
+pub const FAULT_IMAGE_NAME_LEN: usize = 32;
+
+#[repr(C)]
+#[derive(Copy,Clone,Default,Debug)]
+/// Image loaded in a process (registered by the loader using CORE_THISPROCESS_SETIMAGES)
+pub struct FaultImage {
+	/// Start of the image's address range
+	pub start: usize,
+	/// End of the image's address range (exclusive)
+	pub end: usize,
+	/// Load bias (run-time address minus file address)
+	pub bias: usize,
+	/// Library name, NUL padded (empty for the executable)
+	pub name: [u8; FAULT_IMAGE_NAME_LEN],
+}
+
+#[repr(C)]
+#[derive(Copy,Clone,Default,Debug)]
+/// Report of the fault that terminated a process, read using CORE_PROCESS_GETFAULT
+pub struct FaultInfo {
+	/// Fault class (FAULT_KIND_*)
+	pub kind: u32,
+	/// Architecture-specific code (amd64: vector << 16 | error code, ARM: fault status register)
+	pub code: u32,
+	/// Faulting data address (zero if not applicable)
+	pub address: usize,
+	/// Instruction pointer
+	pub ip: usize,
+	/// Stack pointer
+	pub sp: usize,
+	/// General purpose registers (amd64: RAX,RCX,RDX,RBX,RSP,RBP,RSI,RDI,R8-R15, ARM: R0-R15)
+	pub regs: [usize; FAULT_NREGS],
+	/// Number of valid entries in `backtrace`
+	pub backtrace_len: usize,
+	/// Return addresses walked from the user stack (innermost first, starting with `ip`)
+	pub backtrace: [usize; FAULT_BACKTRACE_LEN],
+	/// Number of valid entries in `images`
+	pub image_count: usize,
+	/// Images loaded in the process, used to symbolise `backtrace`
+	pub images: [FaultImage; FAULT_MAX_IMAGES],
+}
+
+/// ControlMessage kind: Another process asked this process to exit cleanly (CORE_PROCESS_NOTIFY)
+pub const CONTROL_KIND_TERMINATE: u32 = 0;
+/// ControlMessage kind: A child process exited (`source` is the PID, `value` the exit status)
+pub const CONTROL_KIND_CHILDEXITED: u32 = 1;
+/// ControlMessage kind: The window manager closed a window owned by this process (`source` is the window ID)
+pub const CONTROL_KIND_WINDOWCLOSED: u32 = 2;
+/// ControlMessage kind: Application-defined notification (`value` is from CORE_PROCESS_NOTIFY)
+pub const CONTROL_KIND_USER: u32 = 3;
+
+#[repr(C)]
+#[derive(Copy,Clone,Default,Debug)]
+/// Process control notification, read using CORE_THISPROCESS_RECVCONTROL
+pub struct ControlMessage {
+	/// Notification class (CONTROL_KIND_*)
+	pub kind: u32,
+	/// Originating object (child PID, window ID), zero if not applicable
+	pub source: u32,
+	/// Kind-specific value (exit status, user value)
+	pub value: u32,
+}
+
 /// Value for `get_text_info`'s `unit` argument, indicating kernel core
 pub const TEXTINFO_KERNEL: u32 = 0;
 
@@ -76,12 +188,54 @@ def_grp!( 2: GROUP_MEM = {
 	=0: MEM_ALLOCATE,
 	=1: MEM_REPROTECT,
 	=2: MEM_DEALLOCATE,
+	/// Create an anonymous shared memory object (page count). Returns an object handle, or a MEM_ERR_* code
+	=3: MEM_NEWSHARED,
 });
 
+/// Maximum size (in pages) of a shared memory object
+pub const MEM_SHARED_MAX_PAGES: usize = 0x1000;
+/// MEM_SHARED_MAP error: The object is read-only, and a writable mapping was requested
+pub const MEM_ERR_READONLY: u32 = 3;
+
 /// Process memory management
 def_grp!( 3: GROUP_IPC = {
 	/// Allocate a handle pair (returns two object handles)
 	=0: IPC_NEWPAIR,
+	/// Allocate a message channel pair (returns two object handles, as for IPC_NEWPAIR)
+	=1: IPC_NEWCHANNEL,
+});
+
+/// Maximum size of the data in a single IPC_CHAN_SEND message
+pub const IPC_CHAN_MAX_DATA: usize = 0x1000;
+/// Maximum number of objects attached to a single IPC_CHAN_SEND message
+pub const IPC_CHAN_MAX_OBJECTS: usize = 8;
+/// Number of messages that can be queued (in each direction) before IPC_CHAN_SEND fails with IPC_CHAN_ERR_FULL
+pub const IPC_CHAN_QUEUE_DEPTH: usize = 16;
+
+/// IPC_CHAN_RECV/IPC_CHAN_PEEK error: No messages are waiting
+pub const IPC_CHAN_ERR_EMPTY: u32 = 0;
+/// IPC_CHAN_SEND error: The queue is full (wait for EV_IPC_CHAN_SEND then retry)
+pub const IPC_CHAN_ERR_FULL: u32 = 1;
+/// IPC_CHAN_* error: The other end has been closed (returned by receive only once the queue is empty)
+pub const IPC_CHAN_ERR_CLOSED: u32 = 2;
+/// IPC_CHAN_SEND error: Message exceeds the limits. IPC_CHAN_RECV error: The provided buffers are too small
+pub const IPC_CHAN_ERR_TOOLARGE: u32 = 3;
+/// IPC_CHAN_SEND error: An attached object handle was invalid, in use, or an endpoint of the same channel. IPC_CHAN_RECV error: No space for attached objects
+pub const IPC_CHAN_ERR_OBJECT: u32 = 4;
+
+/// IPC_RPC_SEND error: The other end hasn't received the previous message (wait for EV_IPC_RPC_SEND then retry)
+pub const IPC_RPC_ERR_FULL: u32 = 0;
+/// IPC_RPC_SEND error: The attached object handle was invalid (or in use)
+pub const IPC_RPC_ERR_OBJECT: u32 = 1;
+/// IPC_RPC_RECV error: No message is waiting
+pub const IPC_RPC_ERR_EMPTY: u32 = 2;
+/// IPC_RPC_* error: The other end has been closed (returned by receive only once the pending message is taken)
+pub const IPC_RPC_ERR_CLOSED: u32 = 3;
+
+/// Filesystem management
+def_grp!( 4: GROUP_VFS = {
+	/// Obtain a handle allowing volumes to be mounted/unmounted (requires capability, init only usually)
+	=0: VFS_GETMOUNTCAP,
 });
 
 pub fn get_class_name(class_idx: u16) -> &'static str {
@@ -139,6 +293,8 @@ def_classes! {
 		/// Give the process one of this process's objects
 		/// This method blocks if the child process hasn't popped the previous object
 		=0: CORE_PROTOPROCESS_SENDOBJ,
+		/// Set the maximum number of pages the process can allocate (can't exceed the caller's own quota)
+		=1: CORE_PROTOPROCESS_SETQUOTA,
 		--
 		/// Start the process executing
 		=0: CORE_PROTOPROCESS_START,
@@ -148,6 +304,12 @@ def_classes! {
 	=1: CLASS_CORE_PROCESS = {
 		/// Request that the process be terminated
 		=0: CORE_PROCESS_KILL,
+		/// Read the process's exit status (!0 if still running)
+		=1: CORE_PROCESS_GETEXITSTATUS,
+		/// Read the fault report (into a FaultInfo), returns 1 if the process was terminated by a fault (0 otherwise)
+		=2: CORE_PROCESS_GETFAULT,
+		/// Queue a control notification (CONTROL_KIND_TERMINATE or CONTROL_KIND_USER, value). Returns 0, or 1 if it was dropped
+		=3: CORE_PROCESS_NOTIFY,
 		--
 	}|{
 		/// Wakes if the child process terminates
@@ -157,12 +319,20 @@ def_classes! {
 	=2: CLASS_CORE_THISPROCESS = {
 		/// Receive a sent object
 		=0: CORE_THISPROCESS_RECVOBJ,
+		/// Dequeue a control notification (into a ControlMessage), returns 1 if one was read (0 if the queue is empty)
+		=1: CORE_THISPROCESS_RECVCONTROL,
+		/// Record the images loaded in this process (a slice of FaultImage), included in the fault report
+		=2: CORE_THISPROCESS_SETIMAGES,
 		--
 	}|{
+		/// Fires when a control notification is queued
+		=0: EV_THISPROCESS_CONTROL,
 	},
 	/// Opened node
 	=3: CLASS_VFS_NODE = {
 		=0: VFS_NODE_GETTYPE,
+		/// Get the owning user and group (user in the high 32 bits, group in the low)
+		=1: VFS_NODE_GETOWNER,
 		--
 		=0: VFS_NODE_TOFILE,
 		=1: VFS_NODE_TODIR,
@@ -173,14 +343,22 @@ def_classes! {
 	=4: CLASS_VFS_FILE = {
 		/// Get the size of the file (maximum addressable byte + 1)
 		=0: VFS_FILE_GETSIZE,
-		/// Read data from the specified position in the file
+		/// Read data from the specified position in the file (at most 1GiB per call), returns the byte count
 		=1: VFS_FILE_READAT,
-		/// Write to the specified position in the file
+		/// Write to the specified position in the file (at most 1GiB per call), returns the byte count
 		=2: VFS_FILE_WRITEAT,
 		/// Map part of the file into the current address space
 		=3: VFS_FILE_MEMMAP,
+		/// Acquire an advisory lock on a byte range (offset, length, exclusive)
+		=4: VFS_FILE_LOCKRANGE,
+		/// Release advisory locks held by this handle within a byte range (offset, length)
+		=5: VFS_FILE_UNLOCKRANGE,
+		/// Set the size of the file (truncating, or extending with zeroes)
+		=6: VFS_FILE_SETSIZE,
 		--
 	}|{
+		/// Fires when an advisory lock on the file is released
+		=0: EV_VFS_FILE_LOCKRELEASE,
 	},
 	/// Opened directory
 	=5: CLASS_VFS_DIR = {
@@ -188,8 +366,14 @@ def_classes! {
 		=0: VFS_DIR_ENUMERATE,
 		/// Open a child node
 		=1: VFS_DIR_OPENCHILD,
-		/// Open a sub-path
+		/// Open a sub-path (path, VFS_OPENPATH_* flags)
 		=2: VFS_DIR_OPENPATH,
+		/// Create a watch handle for changes to this directory
+		=3: VFS_DIR_WATCH,
+		/// Create a new empty file (name), returning a node handle
+		=4: VFS_DIR_CREATEFILE,
+		/// Rename an entry within this directory (old name, new name)
+		=5: VFS_DIR_RENAME,
 		--
 	}|{
 	},
@@ -236,6 +420,10 @@ def_classes! {
 		=7: GUI_WIN_GETPOS,
 		/// Set window position (will be clipped to visible area)
 		=8: GUI_WIN_SETPOS,
+		/// Obtain the window's ID (as reported by CONTROL_KIND_WINDOWCLOSED)
+		=9: GUI_WIN_GETID,
+		/// Make this window a modal dialog of another window in the same group (by ID). Returns 0, or 1 if the owner isn't valid
+		=10: GUI_WIN_SETOWNER,
 		--
 	}|{
 		/// Fires when the input queue is non-empty
@@ -246,14 +434,78 @@ def_classes! {
 
 	/// Remote procedure call channel
 	=10: CLASS_IPC_RPC = {
-		/// Send a message over the channel (RpcMessage, limited size)
+		/// Send a message over the channel (RpcMessage, limited size) with an optional object (0 for none). Returns 0, or an IPC_RPC_ERR_* code
 		=0: IPC_RPC_SEND,
-		/// Receive a message
+		/// Receive a message. Returns the attached object's handle (0 if none), or an IPC_RPC_ERR_* code
 		=1: IPC_RPC_RECV,
 	--
 	}|{
-		/// Fires when the channel has a message waiting
+		/// Fires when the channel has a message waiting (or the other end has closed)
 		=0: EV_IPC_RPC_RECV,
+		/// Fires when the other end has received the previous message (or has closed)
+		=1: EV_IPC_RPC_SEND,
+	},
+
+	/// Directory change watch
+	=11: CLASS_VFS_WATCH = {
+		/// Read a change record. Returns 0 if there are none, otherwise the VFSChangeKind and name length (name_len << 8)
+		///
+		/// If the name doesn't fit in the buffer, the record is left queued and the kind is 0 (with the required length)
+		=0: VFS_WATCH_READ,
+		--
+	}|{
+		/// Fires when there are unread change records
+		=0: EV_VFS_WATCH_CHANGE,
+	},
+
+	/// Capability to mount and unmount volumes
+	=12: CLASS_VFS_MOUNTCAP = {
+		/// Read the name of a logical volume (index, name buffer). Returns the name length, or !0 past the last volume
+		=0: VFS_MOUNTCAP_ENUMVOLS,
+		/// Mount a volume (volume name, mountpoint, comma-separated options). The option `fs=NAME` selects a filesystem driver instead of detecting it
+		=1: VFS_MOUNTCAP_MOUNT,
+		/// Unmount the volume mounted at the provided path
+		=2: VFS_MOUNTCAP_UNMOUNT,
+		--
+	}|{
+	},
+
+	/// Handle to a thread started with CORE_STARTTHREAD (dropping it detaches the thread)
+	=13: CLASS_CORE_THREAD = {
+		/// Read the thread's exit value (!0 if still running)
+		=0: CORE_THREAD_GETEXITVALUE,
+		--
+	}|{
+		/// Wakes when the thread terminates
+		=0: EV_THREAD_TERMINATED,
+	},
+
+	/// Message channel endpoint (variable-length messages with attached objects, queued in each direction)
+	=14: CLASS_IPC_CHANNEL = {
+		/// Queue a message (data buffer, object handle array). Returns 0, or an IPC_CHAN_ERR_* code
+		=0: IPC_CHAN_SEND,
+		/// Dequeue a message (data buffer, object handle buffer). Returns the data length, with the object count in bits 16-23
+		=1: IPC_CHAN_RECV,
+		/// Get the size of the next message without dequeueing it (same return format as IPC_CHAN_RECV)
+		=2: IPC_CHAN_PEEK,
+		--
+	}|{
+		/// Fires when a message is waiting (or the other end has closed)
+		=0: EV_IPC_CHAN_RECV,
+		/// Fires when there is space to send (or the other end has closed)
+		=1: EV_IPC_CHAN_SEND,
+	},
+
+	/// Anonymous shared memory
+	=15: CLASS_MEM_SHARED = {
+		/// Get the size of the object (in pages)
+		=0: MEM_SHARED_GETSIZE,
+		/// Map the object into the current address space (address, protection mode). Returns 0, or a MEM_ERR_* code
+		=1: MEM_SHARED_MAP,
+		/// Obtain a read-only handle to the same memory
+		=2: MEM_SHARED_CLONERO,
+		--
+	}|{
 	}
 }
 
@@ -302,6 +554,37 @@ enum_to_from!{ VFSError => u32:
 	PermissionDenied = 2,
 	FileLocked = 3,
 	MalformedPath = 4,
+	InvalidParameter = 5,
+	ReadOnlyFilesystem = 6,
+	/// Too many symbolic links were followed while resolving a path
+	SymlinkLoop = 7,
+	/// The item already exists
+	AlreadyExists = 8,
+	/// The underlying storage device reported an error
+	IoError = 9,
+	/// The filesystem driver found an inconsistency on the volume
+	InconsistentFilesystem = 10,
+	/// The volume is full
+	OutOfSpace = 11,
+	/// The kernel (or the process's page quota) ran out of memory
+	OutOfMemory = 12,
+	/// A transient error occurred, the operation can be retried
+	TransientError = 13,
+	/// Any other error (details in the kernel log)
+	Unknown = 14,
+}
+enum_to_from!{ VFSMountError => u32:
+	/// The named volume does not exist (or is already in use)
+	NoSuchVolume = 0,
+	/// No filesystem driver could be found for the volume
+	UnknownFilesystem = 1,
+	InvalidMountpoint = 2,
+	MountpointUsed = 3,
+	/// The filesystem driver rejected the volume
+	CallFailed = 4,
+	BadOption = 5,
+	/// Nodes on the volume are still open
+	Busy = 6,
 }
 enum_to_from!{ VFSNodeType => u32:
 	File = 0,
@@ -318,6 +601,21 @@ enum_to_from!{ VFSFileOpenMode => u8:
 	Unsynch  = 6,
 }
 
+/// `VFS_DIR_OPENPATH` flag: Don't follow a symbolic link as the final path component
+pub const VFS_OPENPATH_NOFOLLOW: u32 = 1 << 0;
+/// `VFS_DIR_OPENPATH` flag: Fail if the path would leave the directory (via `..` or an absolute symbolic link)
+pub const VFS_OPENPATH_BENEATH: u32 = 1 << 1;
+
+enum_to_from!{ VFSChangeKind => u8:
+	Created = 1,
+	Removed = 2,
+	RenamedFrom = 3,
+	RenamedTo = 4,
+	SizeChanged = 5,
+	/// Change records were lost, re-enumerate the directory
+	Overflow = 6,
+}
+
 enum_to_from!{ VFSMemoryMapMode => u8:
 	// /// Read-only mapping of a file
 	ReadOnly = 0,